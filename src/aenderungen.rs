@@ -0,0 +1,646 @@
+//! Inhaltliche Änderungen an Grundbuchblättern
+//!
+//! Ein Blatt wird in einzelne Einträge zerlegt (Abteilung, lfd. Nr., Text),
+//! dieselbe Darstellung wird auch für den Suchindex verwendet. Beim Vergleich
+//! der alten und neuen `.gbx` ergeben sich daraus je Abteilung die
+//! hinzugefügten, entfernten und geänderten Einträge, die Abonnenten mit der
+//! Detailstufe `details` per Webhook bzw. E-Mail erhalten. Über die Art der
+//! Änderungen (`AenderungsArt`) werden außerdem die Filter der Abonnements
+//! ausgewertet.
+
+use crate::models::{BvEintrag, PdfFile};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Abteilungen, nach denen Abonnements gefiltert werden können
+pub const ABTEILUNGEN: &[&str] = &["bv", "abt1", "abt2", "abt3"];
+
+/// Ein Eintrag eines Grundbuchblatts, `abteilung` z.B. `bv`, `abt2` oder `abt3-loeschungen`
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct BlattEintrag {
+    pub abteilung: String,
+    pub lfd_nr: String,
+    pub text: String,
+    pub geroetet: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct EintragText {
+    pub lfd_nr: String,
+    pub text: String,
+    /// Eintrag ist gerötet
+    #[serde(default)]
+    pub geroetet: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct EintragGeaendert {
+    pub lfd_nr: String,
+    pub alt: String,
+    pub neu: String,
+    /// Eintrag wurde mit dieser Änderung gerötet
+    #[serde(default)]
+    pub geroetet: bool,
+}
+
+/// Art einer Änderung, für die Filter der Abonnements
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum AenderungsArt {
+    /// Neuer Eintrag (lfd. Nr.) in einer Abteilung
+    #[serde(rename = "neu")]
+    Neu,
+    /// Rötung eines Eintrags oder Eintrag in den Löschungen / Abschreibungen
+    #[serde(rename = "loeschung")]
+    Loeschung,
+    /// Eintrag in den Veränderungen / Zuschreibungen oder geänderter Text
+    #[serde(rename = "veraenderung")]
+    Veraenderung,
+}
+
+impl AenderungsArt {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AenderungsArt::Neu => "neu",
+            AenderungsArt::Loeschung => "loeschung",
+            AenderungsArt::Veraenderung => "veraenderung",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.trim() {
+            "neu" => Some(AenderungsArt::Neu),
+            "loeschung" => Some(AenderungsArt::Loeschung),
+            "veraenderung" => Some(AenderungsArt::Veraenderung),
+            _ => None,
+        }
+    }
+}
+
+/// Liest eine kommagetrennte Liste von Abteilungen (`"abt2, abt3"`), leer = alle
+pub fn abteilungen_lesen(s: &str) -> Result<Vec<String>, String> {
+    let mut abteilungen = Vec::new();
+    for a in s.split(',').map(|a| a.trim().to_lowercase()).filter(|a| !a.is_empty()) {
+        if !ABTEILUNGEN.contains(&a.as_str()) {
+            return Err(format!(
+                "Ungültige Abteilung: {a} (erlaubt: {})",
+                ABTEILUNGEN.join(", ")
+            ));
+        }
+        if !abteilungen.contains(&a) {
+            abteilungen.push(a);
+        }
+    }
+    Ok(abteilungen)
+}
+
+/// Liest eine kommagetrennte Liste von Änderungsarten (`"neu, loeschung"`), leer = alle
+pub fn aenderungsarten_lesen(s: &str) -> Result<Vec<AenderungsArt>, String> {
+    let mut arten = Vec::new();
+    for a in s.split(',').map(|a| a.trim()).filter(|a| !a.is_empty()) {
+        let art = AenderungsArt::from_str(a).ok_or_else(|| {
+            format!("Ungültige Änderungsart: {a} (erlaubt: neu, loeschung, veraenderung)")
+        })?;
+        if !arten.contains(&art) {
+            arten.push(art);
+        }
+    }
+    Ok(arten)
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct AbteilungAenderungen {
+    pub hinzugefuegt: Vec<EintragText>,
+    pub entfernt: Vec<EintragText>,
+    pub geaendert: Vec<EintragGeaendert>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct AenderungsAutor {
+    pub name: String,
+    pub email: String,
+}
+
+/// Detaillierte Beschreibung einer Änderung an einem Blatt
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct AenderungsDetails {
+    pub titel: String,
+    pub beschreibung: Vec<String>,
+    pub autor: AenderungsAutor,
+    /// Abteilung => Änderungen, nur Abteilungen mit Änderungen
+    pub abteilungen: BTreeMap<String, AbteilungAenderungen>,
+}
+
+/// Normalisiert ein Flurstück `Gemarkung/Flur/Flurstück` (z.B. `Schenkenberg/1/26`
+/// oder mit Nenner `Schenkenberg/1/26/3`) für den Vergleich, `None` wenn ungültig
+pub fn flurstueck_normalisieren(s: &str) -> Option<String> {
+    let teile = s
+        .splitn(3, '/')
+        .map(|t| t.split_whitespace().collect::<Vec<_>>().join(" ").to_lowercase())
+        .collect::<Vec<_>>();
+    if teile.len() != 3 || teile.iter().any(|t| t.is_empty()) {
+        return None;
+    }
+    Some(teile.join("/"))
+}
+
+/// Alle nicht geröteten Flurstücke des Bestandsverzeichnisses (normalisiert)
+pub fn get_flurstuecke(pdf: &PdfFile) -> BTreeSet<String> {
+    pdf.analysiert
+        .bestandsverzeichnis
+        .eintraege
+        .iter()
+        .filter(|bv| !bv.ist_geroetet())
+        .filter_map(|bv| match bv {
+            BvEintrag::Flurstueck(bvf) => flurstueck_normalisieren(&format!(
+                "{}/{}/{}",
+                bvf.gemarkung
+                    .clone()
+                    .unwrap_or(pdf.analysiert.titelblatt.grundbuch_von.clone()),
+                bvf.flur,
+                bvf.flurstueck,
+            )),
+            BvEintrag::Recht(_) => None,
+        })
+        .collect()
+}
+
+/// Abteilung für die Filter (`bv`, `abt1`, `abt2`, `abt3`), z.B. `abt2` für `abt2-loeschungen`
+pub fn get_hauptabteilung(abteilung: &str) -> &str {
+    abteilung.split('-').next().unwrap_or(abteilung)
+}
+
+/// Alle (Abteilung, Art) der Änderungen, siehe `vergleichen`
+pub fn get_aenderungsarten(
+    abteilungen: &BTreeMap<String, AbteilungAenderungen>,
+) -> BTreeSet<(String, AenderungsArt)> {
+    let mut arten = BTreeSet::new();
+
+    for (abteilung, a) in abteilungen.iter() {
+        // Art eines neuen Eintrags in diesem Teil der Abteilung
+        let art_neu = match abteilung.split_once('-').map(|(_, teil)| teil) {
+            Some("loeschungen") | Some("abschreibungen") => AenderungsArt::Loeschung,
+            Some("veraenderungen") | Some("zuschreibungen") => AenderungsArt::Veraenderung,
+            _ => AenderungsArt::Neu,
+        };
+        let hauptabteilung = get_hauptabteilung(abteilung).to_string();
+
+        for e in a.hinzugefuegt.iter() {
+            let art = if e.geroetet { AenderungsArt::Loeschung } else { art_neu };
+            arten.insert((hauptabteilung.clone(), art));
+        }
+        for e in a.geaendert.iter() {
+            let art = if e.geroetet || art_neu == AenderungsArt::Loeschung {
+                AenderungsArt::Loeschung
+            } else {
+                AenderungsArt::Veraenderung
+            };
+            arten.insert((hauptabteilung.clone(), art));
+        }
+        if !a.entfernt.is_empty() {
+            let art = match art_neu {
+                AenderungsArt::Loeschung => AenderungsArt::Loeschung,
+                _ => AenderungsArt::Veraenderung,
+            };
+            arten.insert((hauptabteilung.clone(), art));
+        }
+    }
+
+    arten
+}
+
+/// Bezeichnung einer Abteilung für E-Mails
+pub fn get_abteilung_name(abteilung: &str) -> &'static str {
+    match abteilung {
+        "bv" => "Bestandsverzeichnis",
+        "bv-herrschvermerke" => "Bestandsverzeichnis (Herrschvermerke)",
+        "bv-zuschreibungen" => "Bestandsverzeichnis (Zuschreibungen)",
+        "bv-abschreibungen" => "Bestandsverzeichnis (Abschreibungen)",
+        "abt1" => "Abteilung 1",
+        "abt1-grundlagen-eintragungen" => "Abteilung 1 (Grundlagen der Eintragungen)",
+        "abt1-veraenderungen" => "Abteilung 1 (Veränderungen)",
+        "abt1-loeschungen" => "Abteilung 1 (Löschungen)",
+        "abt2" => "Abteilung 2",
+        "abt2-veraenderungen" => "Abteilung 2 (Veränderungen)",
+        "abt2-loeschungen" => "Abteilung 2 (Löschungen)",
+        "abt3" => "Abteilung 3",
+        "abt3-veraenderungen" => "Abteilung 3 (Veränderungen)",
+        "abt3-loeschungen" => "Abteilung 3 (Löschungen)",
+        _ => "Sonstige",
+    }
+}
+
+/// Vergleicht zwei Fassungen eines Blatts, Einträge werden über
+/// Abteilung und lfd. Nr. zugeordnet
+pub fn vergleichen(alt: &PdfFile, neu: &PdfFile) -> BTreeMap<String, AbteilungAenderungen> {
+    eintraege_vergleichen(get_eintraege(alt), get_eintraege(neu))
+}
+
+/// Wie `vergleichen` für ein neu angelegtes Blatt: alle Einträge sind hinzugefügt
+pub fn vergleichen_neu(neu: &PdfFile) -> BTreeMap<String, AbteilungAenderungen> {
+    eintraege_vergleichen(Vec::new(), get_eintraege(neu))
+}
+
+fn eintraege_vergleichen(
+    alt: Vec<BlattEintrag>,
+    neu: Vec<BlattEintrag>,
+) -> BTreeMap<String, AbteilungAenderungen> {
+    let mut alt_nach_nr = BTreeMap::<(String, String), Vec<(String, bool)>>::new();
+    for e in alt {
+        alt_nach_nr.entry((e.abteilung, e.lfd_nr)).or_default().push((e.text, e.geroetet));
+    }
+    let mut neu_nach_nr = BTreeMap::<(String, String), Vec<(String, bool)>>::new();
+    for e in neu {
+        neu_nach_nr.entry((e.abteilung, e.lfd_nr)).or_default().push((e.text, e.geroetet));
+    }
+
+    let mut schluessel = alt_nach_nr.keys().cloned().collect::<Vec<_>>();
+    schluessel.extend(neu_nach_nr.keys().cloned());
+    schluessel.sort();
+    schluessel.dedup();
+
+    let mut abteilungen = BTreeMap::<String, AbteilungAenderungen>::new();
+
+    for (abteilung, lfd_nr) in schluessel {
+        let k = (abteilung.clone(), lfd_nr.clone());
+        let mut alt_texte = alt_nach_nr.remove(&k).unwrap_or_default();
+        let mut neu_texte = neu_nach_nr.remove(&k).unwrap_or_default();
+
+        // Unveränderte Einträge (auch bei mehreren Einträgen mit derselben lfd. Nr.)
+        alt_texte.retain(|t| match neu_texte.iter().position(|n| n == t) {
+            Some(p) => {
+                neu_texte.remove(p);
+                false
+            }
+            None => true,
+        });
+
+        if alt_texte.is_empty() && neu_texte.is_empty() {
+            continue;
+        }
+
+        let a = abteilungen.entry(abteilung).or_default();
+        let mut alt_texte = alt_texte.into_iter();
+        let mut neu_texte = neu_texte.into_iter();
+        loop {
+            match (alt_texte.next(), neu_texte.next()) {
+                (Some((alt, alt_geroetet)), Some((neu, neu_geroetet))) => {
+                    a.geaendert.push(EintragGeaendert {
+                        lfd_nr: lfd_nr.clone(),
+                        alt,
+                        neu,
+                        geroetet: neu_geroetet && !alt_geroetet,
+                    })
+                }
+                (Some((text, geroetet)), None) => a.entfernt.push(EintragText {
+                    lfd_nr: lfd_nr.clone(),
+                    text,
+                    geroetet,
+                }),
+                (None, Some((text, geroetet))) => a.hinzugefuegt.push(EintragText {
+                    lfd_nr: lfd_nr.clone(),
+                    text,
+                    geroetet,
+                }),
+                (None, None) => break,
+            }
+        }
+    }
+
+    abteilungen
+}
+
+/// Alle Einträge eines Blatts in der Reihenfolge der Abteilungen
+pub fn get_eintraege(pdf: &PdfFile) -> Vec<BlattEintrag> {
+    let mut eintraege = Vec::new();
+    let mut push = |abteilung: &str, lfd_nr: String, text: String, geroetet: bool| {
+        eintraege.push(BlattEintrag {
+            abteilung: abteilung.to_string(),
+            lfd_nr,
+            text,
+            geroetet,
+        })
+    };
+
+    for bv in pdf.analysiert.bestandsverzeichnis.eintraege.iter() {
+        match bv {
+            BvEintrag::Flurstueck(bvf) => push(
+                "bv",
+                format!("{}", bvf.lfd_nr),
+                format!(
+                    "BV lfd. Nr. {}, Gemarkung {} Flur {} Flurstück {}: {}Größe: {} m²",
+                    bvf.lfd_nr,
+                    bvf.gemarkung
+                        .clone()
+                        .unwrap_or(pdf.analysiert.titelblatt.grundbuch_von.clone()),
+                    bvf.flur,
+                    bvf.flurstueck,
+                    bvf.bezeichnung
+                        .as_ref()
+                        .map(|s| s.text_clean() + ", ")
+                        .unwrap_or_default(),
+                    bvf.groesse.get_m2(),
+                ),
+                bv.ist_geroetet(),
+            ),
+            BvEintrag::Recht(bvr) => push(
+                "bv-herrschvermerke",
+                format!("{}", bvr.lfd_nr),
+                format!(
+                    "BV lfd. Nr. {} (zu lfd. Nr. {}): {}",
+                    bvr.lfd_nr,
+                    bvr.zu_nr.text_clean(),
+                    bvr.text.text_clean(),
+                ),
+                bv.ist_geroetet(),
+            ),
+        }
+    }
+
+    for bvz in pdf.analysiert.bestandsverzeichnis.zuschreibungen.iter() {
+        push(
+            "bv-zuschreibungen",
+            bvz.bv_nr.lines().join(" "),
+            format!(
+                "BV-Zuschreibung zu lfd. Nr. {}: {}",
+                bvz.bv_nr.lines().join(" "),
+                bvz.text.text_clean()
+            ),
+            bvz.ist_geroetet(),
+        );
+    }
+
+    for bva in pdf.analysiert.bestandsverzeichnis.abschreibungen.iter() {
+        push(
+            "bv-abschreibungen",
+            bva.bv_nr.lines().join(" "),
+            format!(
+                "BV-Abschreibung zu lfd. Nr. {}: {}",
+                bva.bv_nr.lines().join(" "),
+                bva.text.text_clean()
+            ),
+            bva.ist_geroetet(),
+        );
+    }
+
+    for abt1 in pdf.analysiert.abt1.eintraege.iter() {
+        push(
+            "abt1",
+            format!("{}", abt1.get_lfd_nr()),
+            format!(
+                "Abteilung 1, lfd. Nr. {}: {}",
+                abt1.get_lfd_nr(),
+                abt1.get_eigentuemer().text_clean()
+            ),
+            abt1.ist_geroetet(),
+        );
+    }
+
+    for abt1 in pdf.analysiert.abt1.grundlagen_eintragungen.iter() {
+        push(
+            "abt1-grundlagen-eintragungen",
+            format!("{}", abt1.bv_nr.lines().join(" ")),
+            format!(
+                "Abteilung 1, lfd. Nr. {}: {}",
+                abt1.bv_nr.lines().join(" "),
+                abt1.text.text_clean()
+            ),
+            abt1.ist_geroetet(),
+        );
+    }
+
+    for bvz in pdf.analysiert.abt1.veraenderungen.iter() {
+        push(
+            "abt1-veraenderungen",
+            bvz.lfd_nr.lines().join(" "),
+            format!(
+                "Abteilung 1 Veränderung von lfd. Nr. {}: {}",
+                bvz.lfd_nr.lines().join(" "),
+                bvz.text.text_clean()
+            ),
+            bvz.ist_geroetet(),
+        );
+    }
+
+    for bva in pdf.analysiert.abt1.loeschungen.iter() {
+        push(
+            "abt1-loeschungen",
+            bva.lfd_nr.lines().join(" "),
+            format!(
+                "Abteilung 1 Löschung von lfd. Nr. {}: {}",
+                bva.lfd_nr.lines().join(" "),
+                bva.text.text_clean()
+            ),
+            bva.ist_geroetet(),
+        );
+    }
+
+    for abt2 in pdf.analysiert.abt2.eintraege.iter() {
+        push(
+            "abt2",
+            format!("{}", abt2.lfd_nr),
+            format!(
+                "Abteilung 2, lfd. Nr. {}: {}",
+                abt2.lfd_nr,
+                abt2.text.text_clean()
+            ),
+            abt2.ist_geroetet(),
+        );
+    }
+
+    for bvz in pdf.analysiert.abt2.veraenderungen.iter() {
+        push(
+            "abt2-veraenderungen",
+            bvz.lfd_nr.lines().join(" "),
+            format!(
+                "Abteilung 2 Veränderung von lfd. Nr. {}: {}",
+                bvz.lfd_nr.lines().join(" "),
+                bvz.text.text_clean()
+            ),
+            bvz.ist_geroetet(),
+        );
+    }
+
+    for bva in pdf.analysiert.abt2.loeschungen.iter() {
+        push(
+            "abt2-loeschungen",
+            bva.lfd_nr.lines().join(" "),
+            format!(
+                "Abteilung 2 Löschung von lfd. Nr. {}: {}",
+                bva.lfd_nr.lines().join(" "),
+                bva.text.text_clean()
+            ),
+            bva.ist_geroetet(),
+        );
+    }
+
+    for abt3 in pdf.analysiert.abt3.eintraege.iter() {
+        push(
+            "abt3",
+            format!("{}", abt3.lfd_nr),
+            format!(
+                "Abteilung 3, lfd. Nr. {}: {}",
+                abt3.lfd_nr,
+                abt3.text.text_clean()
+            ),
+            abt3.ist_geroetet(),
+        );
+    }
+
+    for bvz in pdf.analysiert.abt3.veraenderungen.iter() {
+        push(
+            "abt3-veraenderungen",
+            bvz.lfd_nr.lines().join(" "),
+            format!(
+                "Abteilung 3 Veränderung von lfd. Nr. {}: {}",
+                bvz.lfd_nr.lines().join(" "),
+                bvz.text.text_clean()
+            ),
+            bvz.ist_geroetet(),
+        );
+    }
+
+    for bva in pdf.analysiert.abt3.loeschungen.iter() {
+        push(
+            "abt3-loeschungen",
+            bva.lfd_nr.lines().join(" "),
+            format!(
+                "Abteilung 3 Löschung von lfd. Nr. {}: {}",
+                bva.lfd_nr.lines().join(" "),
+                bva.text.text_clean()
+            ),
+            bva.ist_geroetet(),
+        );
+    }
+
+    eintraege
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eintrag(abteilung: &str, lfd_nr: &str, text: &str, geroetet: bool) -> BlattEintrag {
+        BlattEintrag {
+            abteilung: abteilung.to_string(),
+            lfd_nr: lfd_nr.to_string(),
+            text: text.to_string(),
+            geroetet,
+        }
+    }
+
+    #[test]
+    fn vergleichen_unveraendert() {
+        let alt = vec![eintrag("abt2", "1", "Wegerecht", false), eintrag("abt3", "1", "Grundschuld", false)];
+        assert!(eintraege_vergleichen(alt.clone(), alt).is_empty());
+    }
+
+    #[test]
+    fn vergleichen_hinzugefuegt_entfernt_geaendert() {
+        let alt = vec![
+            eintrag("abt2", "1", "Wegerecht", false),
+            eintrag("abt2", "2", "Leitungsrecht", false),
+            eintrag("abt3", "1", "Grundschuld 10.000 EUR", false),
+        ];
+        let neu = vec![
+            eintrag("abt2", "1", "Wegerecht", false),
+            eintrag("abt3", "1", "Grundschuld 20.000 EUR", false),
+            eintrag("abt3", "2", "Hypothek", false),
+        ];
+
+        let abteilungen = eintraege_vergleichen(alt, neu);
+        assert_eq!(abteilungen.keys().collect::<Vec<_>>(), vec!["abt2", "abt3"]);
+
+        let abt2 = &abteilungen["abt2"];
+        assert!(abt2.hinzugefuegt.is_empty() && abt2.geaendert.is_empty());
+        assert_eq!(
+            abt2.entfernt,
+            vec![EintragText { lfd_nr: "2".to_string(), text: "Leitungsrecht".to_string(), geroetet: false }]
+        );
+
+        let abt3 = &abteilungen["abt3"];
+        assert!(abt3.entfernt.is_empty());
+        assert_eq!(
+            abt3.geaendert,
+            vec![EintragGeaendert {
+                lfd_nr: "1".to_string(),
+                alt: "Grundschuld 10.000 EUR".to_string(),
+                neu: "Grundschuld 20.000 EUR".to_string(),
+                geroetet: false,
+            }]
+        );
+        assert_eq!(
+            abt3.hinzugefuegt,
+            vec![EintragText { lfd_nr: "2".to_string(), text: "Hypothek".to_string(), geroetet: false }]
+        );
+    }
+
+    #[test]
+    fn vergleichen_roetung() {
+        let alt = vec![eintrag("abt2", "1", "Wegerecht", false)];
+        let neu = vec![eintrag("abt2", "1", "Wegerecht", true)];
+
+        let abteilungen = eintraege_vergleichen(alt, neu);
+        assert_eq!(abteilungen["abt2"].geaendert.len(), 1);
+        assert!(abteilungen["abt2"].geaendert[0].geroetet);
+        assert_eq!(
+            get_aenderungsarten(&abteilungen).into_iter().collect::<Vec<_>>(),
+            vec![("abt2".to_string(), AenderungsArt::Loeschung)]
+        );
+    }
+
+    #[test]
+    fn vergleichen_gleiche_lfd_nr_mehrfach() {
+        // Nur der tatsächlich neue Eintrag zählt, Reihenfolge ist egal
+        let alt = vec![eintrag("abt2-veraenderungen", "1", "A", false)];
+        let neu = vec![
+            eintrag("abt2-veraenderungen", "1", "B", false),
+            eintrag("abt2-veraenderungen", "1", "A", false),
+        ];
+
+        let abteilungen = eintraege_vergleichen(alt, neu);
+        let a = &abteilungen["abt2-veraenderungen"];
+        assert!(a.geaendert.is_empty() && a.entfernt.is_empty());
+        assert_eq!(a.hinzugefuegt.len(), 1);
+        assert_eq!(a.hinzugefuegt[0].text, "B");
+        assert_eq!(
+            get_aenderungsarten(&abteilungen).into_iter().collect::<Vec<_>>(),
+            vec![("abt2".to_string(), AenderungsArt::Veraenderung)]
+        );
+    }
+
+    #[test]
+    fn aenderungsarten_nach_abteilung() {
+        let neu = vec![
+            eintrag("bv", "3", "Flurstück", false),
+            eintrag("abt3-loeschungen", "1", "Gelöscht", false),
+        ];
+
+        let arten = get_aenderungsarten(&eintraege_vergleichen(Vec::new(), neu));
+        assert!(arten.contains(&("bv".to_string(), AenderungsArt::Neu)));
+        assert!(arten.contains(&("abt3".to_string(), AenderungsArt::Loeschung)));
+        assert_eq!(arten.len(), 2);
+    }
+
+    #[test]
+    fn flurstueck_normalisiert() {
+        assert_eq!(flurstueck_normalisieren("Schenkenberg/1/26"), Some("schenkenberg/1/26".to_string()));
+        assert_eq!(
+            flurstueck_normalisieren(" Groß  Luckow / 1 / 26/3 "),
+            Some("groß luckow/1/26/3".to_string())
+        );
+        assert_eq!(flurstueck_normalisieren("Schenkenberg/1"), None);
+        assert_eq!(flurstueck_normalisieren("Schenkenberg//26"), None);
+    }
+
+    #[test]
+    fn abteilungen_und_arten_lesen() {
+        assert_eq!(abteilungen_lesen(" ABT2, abt3,abt2 ").unwrap(), vec!["abt2", "abt3"]);
+        assert!(abteilungen_lesen("").unwrap().is_empty());
+        assert!(abteilungen_lesen("abt4").is_err());
+        assert_eq!(
+            aenderungsarten_lesen("neu, loeschung, neu").unwrap(),
+            vec![AenderungsArt::Neu, AenderungsArt::Loeschung]
+        );
+        assert!(aenderungsarten_lesen("sonstiges").is_err());
+    }
+}
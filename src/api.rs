@@ -1,28 +1,84 @@
+use crate::api_schluessel::{ApiScope, API_SCHLUESSEL_PRAEFIX};
+use crate::config::Konfiguration;
 use crate::models::BenutzerInfo;
 use crate::AppState;
 use actix_web::{HttpRequest, HttpResponse};
 use std::collections::BTreeMap;
 
+/// Anmeldung nur per Session, API-Schlüssel werden abgelehnt
 async fn get_benutzer_from_httpauth(
+    konfiguration: &Konfiguration,
     req: &HttpRequest,
 ) -> Result<(String, BenutzerInfo), HttpResponse> {
+    let result = get_benutzer_from_httpauth_inner(konfiguration, req).await.and_then(|(token, benutzer)| {
+        match benutzer.api_schluessel.as_ref() {
+            Some(_) => Err(format!("API-Schlüssel sind für diesen Endpunkt nicht zugelassen")),
+            None => Ok((token, benutzer)),
+        }
+    });
+    result.map_err(httpauth_fehler)
+}
+
+/// Anmeldung per Session oder API-Schlüssel mit dem angegebenen Scope
+async fn get_benutzer_from_httpauth_scope(
+    konfiguration: &Konfiguration,
+    req: &HttpRequest,
+    scope: ApiScope,
+) -> Result<(String, BenutzerInfo), HttpResponse> {
+    let result = get_benutzer_from_httpauth_inner(konfiguration, req).await.and_then(|(token, benutzer)| {
+        benutzer.api_scope_pruefen(scope)?;
+        Ok((token, benutzer))
+    });
+    result.map_err(httpauth_fehler)
+}
+
+fn httpauth_fehler(e: String) -> HttpResponse {
     use self::upload::{UploadChangesetResponse, UploadChangesetResponseError};
-    get_benutzer_from_httpauth_inner(req).await.map_err(|e| {
-        let json = serde_json::to_string_pretty(&UploadChangesetResponse::StatusError(
-            UploadChangesetResponseError {
-                code: 0,
-                text: format!("Fehler bei Authentifizierung: {e}"),
-            },
-        ))
-        .unwrap_or_default();
+    let json = serde_json::to_string_pretty(&UploadChangesetResponse::StatusError(
+        UploadChangesetResponseError {
+            code: 0,
+            text: format!("Fehler bei Authentifizierung: {e}"),
+        },
+    ))
+    .unwrap_or_default();
 
-        return HttpResponse::Ok()
-            .content_type("application/json")
-            .body(json);
-    })
+    HttpResponse::Ok()
+        .content_type("application/json")
+        .body(json)
+}
+
+/// Wie `get_benutzer_from_httpauth`, aber auch wenn die vorgeschriebene
+/// Zwei-Faktor-Authentifizierung noch fehlt (für `/konto`, wo sie eingerichtet wird, und `/logout`)
+async fn get_benutzer_from_httpauth_konto(
+    konfiguration: &Konfiguration,
+    req: &HttpRequest,
+) -> Result<(String, BenutzerInfo), HttpResponse> {
+    let result = get_benutzer_ohne_zwei_faktor(konfiguration, req).await.and_then(|(token, benutzer)| {
+        match benutzer.api_schluessel.as_ref() {
+            Some(_) => Err(format!("API-Schlüssel sind für diesen Endpunkt nicht zugelassen")),
+            None => Ok((token, benutzer)),
+        }
+    });
+    result.map_err(httpauth_fehler)
 }
 
 async fn get_benutzer_from_httpauth_inner(
+    konfiguration: &Konfiguration,
+    req: &HttpRequest,
+) -> Result<(String, BenutzerInfo), String> {
+    let (token, benutzer) = get_benutzer_ohne_zwei_faktor(konfiguration, req).await?;
+
+    if crate::zwei_faktor::einrichtung_erforderlich(konfiguration, &benutzer) {
+        return Err(format!(
+            "Zwei-Faktor-Authentifizierung ist vorgeschrieben, aber noch nicht eingerichtet"
+        ));
+    }
+
+    Ok((token, benutzer))
+}
+
+async fn get_benutzer_ohne_zwei_faktor(
+    konfiguration: &Konfiguration,
     req: &HttpRequest,
 ) -> Result<(String, BenutzerInfo), String> {
     use actix_web::FromRequest;
@@ -38,21 +94,33 @@ async fn get_benutzer_from_httpauth_inner(
         }
     };
 
-    let user = crate::db::get_user_from_token(&token)?;
+    if !token.starts_with(API_SCHLUESSEL_PRAEFIX) {
+        let user = crate::db::get_user_from_token(konfiguration, &token)?;
+        return Ok((token, user));
+    }
+
+    let user = crate::api_schluessel::get_user_from_api_schluessel(konfiguration, &token)?;
+    if let Some(app_state) = req.app_data::<actix_web::web::Data<AppState>>() {
+        crate::api_schluessel::zuletzt_benutzt_aktualisieren(&user, app_state).await;
+    }
 
     Ok((token, user))
 }
 
+/// Schreibt eine Änderung in die Benutzerdatenbank (lokal oder über den Sync-Server).
+/// `akteur` ist die E-Mail des auslösenden Benutzers und landet im Audit-Log.
 pub(crate) async fn write_to_root_db(
     change: commit::DbChangeOp,
+    akteur: &str,
     app_state: &AppState,
 ) -> Result<(), String> {
-    use crate::api::commit::{CommitResponse, CommitResponseOk};
+    use crate::api::commit::{CommitResponse, CommitResponseOk, DbChangeAnfrage};
 
+    let konfiguration = app_state.konfiguration();
     if !app_state.k8s_aktiv() {
-        let result = crate::api::commit::db_change_inner(&change, app_state);
+        let result = crate::api::commit::db_change_inner(&change, akteur, app_state);
 
-        crate::db::pull_db().await.map_err(|e| {
+        crate::db::pull_db(&konfiguration).await.map_err(|e| {
             format!(
                 "Fehler beim Synchronisieren der Datenbanken (pull): {}: {}",
                 e.code, e.text
@@ -70,8 +138,17 @@ pub(crate) async fn write_to_root_db(
 
     let client = reqwest::Client::new();
     let res = client
-        .post(&format!("http://{k8s_sync_server_ip}:8081/db"))
-        .body(serde_json::to_string(&change).unwrap_or_default())
+        .post(&format!(
+            "http://{k8s_sync_server_ip}:{}/db",
+            konfiguration.ports.intern
+        ))
+        .body(
+            serde_json::to_string(&DbChangeAnfrage {
+                akteur: akteur.to_string(),
+                op: change,
+            })
+            .unwrap_or_default(),
+        )
         .send()
         .await
         .map_err(|e| format!("Fehler beim Senden an /db: {e}"))?;
@@ -87,7 +164,7 @@ pub(crate) async fn write_to_root_db(
         }
     }
 
-    crate::db::pull_db().await.map_err(|e| {
+    crate::db::pull_db(&konfiguration).await.map_err(|e| {
         format!(
             "Fehler beim Synchronisieren der Datenbanken (pull): {}: {}",
             e.code, e.text
@@ -106,8 +183,32 @@ pub(crate) async fn write_to_root_db(
     }
 }
 
+/// Schreibt Einsichten in Grundbuchblätter in das Einsichtsprotokoll. Die Einsichten
+/// werden lokal gesammelt und im Hintergrund übertragen, ein Fehler wird nur geloggt
+/// und verhindert den Download nicht.
+pub(crate) fn einsichten_protokollieren(
+    benutzer: &BenutzerInfo,
+    einsichten: Vec<crate::einsicht::Einsicht>,
+    app_state: &AppState,
+) {
+    if einsichten.is_empty() {
+        return;
+    }
+
+    let eintrag = crate::einsicht::EinsichtenNeu {
+        benutzer: benutzer.email.clone(),
+        zeitpunkt: chrono::Utc::now().to_rfc3339(),
+        einsichten,
+    };
+
+    if let Err(e) = crate::einsicht::einreihen(&app_state.konfiguration(), &eintrag) {
+        log::error!("Einsicht von {} nicht protokolliert: {e}", benutzer.email);
+    }
+}
+
 /// HTML für `/` und `/api` Seite
 pub mod index {
+    use crate::config::Konfiguration;
     use crate::AppState;
     use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
     use serde_derive::{Deserialize, Serialize};
@@ -137,6 +238,16 @@ pub mod index {
             .body(include_str!("../web/zugriff.html").replace("<!-- CSS -->", &css))
     }
 
+    // Statusseite einer Zugriffsanfrage (Link aus der Eingangsbestätigung)
+    #[get("/zugriff/status")]
+    async fn zugriff_status(_: HttpRequest) -> impl Responder {
+        let css = crate::get_css();
+        let css = format!("<style type='text/css'>{css}</style>");
+        HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .body(include_str!("../web/zugriff-status.html").replace("<!-- CSS -->", &css))
+    }
+
     #[derive(Debug, Clone, Serialize, Deserialize)]
     #[serde(tag = "action", content = "data")]
     pub enum ZugriffJsonPost {
@@ -148,6 +259,14 @@ pub mod index {
         GetBlaetter(ZugriffJsonGetBlaetter),
         #[serde(rename = "anfrage")]
         Anfrage(ZugriffJsonAnfrage),
+        #[serde(rename = "verlaengern")]
+        Verlaengern(ZugriffJsonVerlaengern),
+        #[serde(rename = "status")]
+        Status(ZugriffJsonStatus),
+        #[serde(rename = "bestaetigen")]
+        Bestaetigen(ZugriffJsonBestaetigen),
+        #[serde(rename = "zurueckziehen")]
+        Zurueckziehen(ZugriffJsonZurueckziehen),
     }
 
     #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -177,12 +296,94 @@ pub mod index {
         blaetter: Vec<ZugriffJsonAnfrageBlatt>,
     }
 
+    /// Verlängerung eines bestehenden Zugriffs (Link aus der Erinnerungs-E-Mail)
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct ZugriffJsonVerlaengern {
+        id: String,
+        #[serde(default)]
+        grund: String,
+        /// Signatur aus dem Link der Erinnerungs-E-Mail
+        #[serde(default)]
+        signatur: String,
+    }
+
+    /// Status aller Blätter einer Anfrage (Link aus der Eingangsbestätigung)
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct ZugriffJsonStatus {
+        anfrage: String,
+    }
+
+    /// Bestätigung der E-Mail-Adresse (signierter Link aus der Bestätigungs-E-Mail)
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct ZugriffJsonBestaetigen {
+        anfrage: String,
+        /// Ablauf des Links (Unix-Zeitstempel), leer = unbegrenzt
+        #[serde(default)]
+        bis: String,
+        signatur: String,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct ZugriffJsonZurueckziehen {
+        anfrage: String,
+        ids: Vec<String>,
+    }
+
     #[derive(Debug, Clone, Serialize, Deserialize)]
     pub struct ZugriffJsonAnfrageBlatt {
         land: String,
         amtsgericht: String,
         grundbuchbezirk: String,
         blatt: String,
+        /// Letztes Blatt eines Bereichs (einschließlich), z.B. `blatt = "100"`, `blatt_bis = "250"`
+        #[serde(default)]
+        blatt_bis: Option<String>,
+    }
+
+    impl ZugriffJsonAnfrageBlatt {
+        /// Prüft Platzhalter (`ALLE_*`) und Bereiche und gibt den bereinigten `blatt_bis` zurück
+        fn pruefen(&self) -> Result<Option<String>, String> {
+            let ebenen = [
+                (self.land.trim(), "ALLE_BUNDESLAENDER"),
+                (self.amtsgericht.trim(), "ALLE_AMTSGERICHTE"),
+                (self.grundbuchbezirk.trim(), "ALLE_GRUNDBUCHBEZIRKE"),
+                (self.blatt.trim(), "ALLE_BLAETTER"),
+            ];
+
+            // Nach einem Platzhalter müssen auch alle folgenden Ebenen Platzhalter sein
+            let mut alle = false;
+            for (wert, platzhalter) in ebenen.iter() {
+                if wert.is_empty() {
+                    return Err(format!("Unvollständige Angabe für Blatt"));
+                }
+                if alle && wert != platzhalter {
+                    return Err(format!(
+                        "Ungültige Angabe \"{wert}\": nach einem Platzhalter muss \"{platzhalter}\" angegeben werden"
+                    ));
+                }
+                alle = wert == platzhalter;
+            }
+
+            let blatt_bis = match self.blatt_bis.as_deref().map(|s| s.trim()) {
+                None | Some("") => return Ok(None),
+                Some(s) => s,
+            };
+
+            if alle {
+                return Err(format!("Bereich von Blättern nur für einen einzelnen Grundbuchbezirk möglich"));
+            }
+
+            match (
+                crate::db::blatt_nummer(self.blatt.trim()),
+                crate::db::blatt_nummer(blatt_bis),
+            ) {
+                (Some(von), Some(bis)) if von <= bis => Ok(Some(blatt_bis.to_string())),
+                _ => Err(format!(
+                    "Ungültiger Bereich von Blatt {} bis {blatt_bis}",
+                    self.blatt.trim()
+                )),
+            }
+        }
     }
 
     #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -205,6 +406,14 @@ pub mod index {
         GetBlaetter(ZugriffJsonGetBlaetterResponseOk),
         #[serde(rename = "anfrage")]
         Anfrage(ZugriffJsonAnfrageResponseOk),
+        #[serde(rename = "verlaengern")]
+        Verlaengern(ZugriffJsonAnfrageResponseOk),
+        #[serde(rename = "status")]
+        Status(ZugriffJsonStatusResponseOk),
+        #[serde(rename = "bestaetigen")]
+        Bestaetigen(ZugriffJsonStatusResponseOk),
+        #[serde(rename = "zurueckziehen")]
+        Zurueckziehen(ZugriffJsonStatusResponseOk),
     }
 
     #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -225,6 +434,69 @@ pub mod index {
     #[derive(Debug, Clone, Serialize, Deserialize)]
     struct ZugriffJsonAnfrageResponseOk {}
 
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct ZugriffJsonStatusResponseOk {
+        name: String,
+        email: String,
+        angefragt: String,
+        blaetter: Vec<ZugriffJsonStatusBlatt>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct ZugriffJsonStatusBlatt {
+        id: String,
+        land: String,
+        amtsgericht: String,
+        grundbuchbezirk: String,
+        blatt: String,
+        blatt_bis: Option<String>,
+        /// `unbestaetigt`, `offen`, `gewaehrt`, `abgelehnt`, `zurueckgezogen`, `widerrufen` oder `abgelaufen`
+        status: String,
+        /// Zeitpunkt der letzten Statusänderung (RFC 3339)
+        am: Option<String>,
+        /// Rolle des Bearbeiters, z.B. "Administrator"
+        bearbeitet_von: Option<String>,
+        ablehnungsgrund: Option<String>,
+        gueltig_bis: Option<String>,
+    }
+
+    impl ZugriffJsonStatusBlatt {
+        fn neu(konfiguration: &Konfiguration, z: &crate::db::ZugriffInfo, jetzt: &str) -> Self {
+            let bearbeiter = z.gewaehrt_von.as_deref().or(z.abgelehnt_von.as_deref());
+            let (status, am) = if let Some(d) = z.zurueckgezogen_am.as_ref() {
+                ("zurueckgezogen", Some(d.clone()))
+            } else if let Some(d) = z.widerrufen_am.as_ref() {
+                ("widerrufen", Some(d.clone()))
+            } else if z.gewaehrt_von.is_some() {
+                match z.gueltig_bis.as_deref() {
+                    Some(bis) if bis <= jetzt => ("abgelaufen", Some(bis.to_string())),
+                    _ => ("gewaehrt", z.am.clone()),
+                }
+            } else if z.abgelehnt_von.is_some() {
+                ("abgelehnt", z.am.clone())
+            } else if z.bestaetigt_am.is_none() {
+                ("unbestaetigt", None)
+            } else {
+                ("offen", None)
+            };
+
+            Self {
+                id: z.id.clone(),
+                land: z.land.clone(),
+                amtsgericht: z.amtsgericht.clone(),
+                grundbuchbezirk: z.bezirk.clone(),
+                blatt: z.blatt.clone(),
+                blatt_bis: z.blatt_bis.clone(),
+                status: status.to_string(),
+                am,
+                bearbeitet_von: bearbeiter
+                    .and_then(|b| crate::rollen::get_rollen_bezeichnung_fuer_benutzer(konfiguration, b)),
+                ablehnungsgrund: z.ablehnungsgrund.clone(),
+                gueltig_bis: z.gueltig_bis.clone(),
+            }
+        }
+    }
+
     #[derive(Debug, Clone, Serialize, Deserialize)]
     struct ZugriffJsonResponseError {
         code: usize,
@@ -250,6 +522,15 @@ pub mod index {
         pub fn from_str(s: &str) -> Option<Self> {
             serde_json::from_str(s).ok()
         }
+        /// Rolle, die ein neuer Benutzer nach Genehmigung des Zugriffs bekommt
+        pub fn get_rolle(&self) -> &'static str {
+            match self {
+                ZugriffTyp::Gast => "gast",
+                ZugriffTyp::Mitarbeiter => "mitarbeiter",
+                ZugriffTyp::Bearbeiter => "bearbeiter",
+                ZugriffTyp::Sonstige => crate::rollen::STANDARD_ROLLE,
+            }
+        }
     }
 
     // Login-Seite
@@ -257,9 +538,10 @@ pub mod index {
     pub async fn zugriff_post(
         app_state: web::Data<AppState>,
         json: web::Json<ZugriffJsonPost>,
-        _: HttpRequest,
+        req: HttpRequest,
     ) -> impl Responder {
-        let response = zugriff_post_inner(&*app_state, &*json).await;
+        let ip = crate::ratenbegrenzung::get_ip(&app_state.konfiguration(), &req);
+        let response = zugriff_post_inner(&*app_state, &*json, &ip).await;
 
         HttpResponse::Ok()
             .content_type("application/json")
@@ -280,25 +562,27 @@ pub mod index {
     async fn zugriff_post_inner(
         app_state: &AppState,
         json: &ZugriffJsonPost,
+        ip: &str,
     ) -> Result<ZugriffJsonResponseOk, String> {
         use self::ZugriffJsonPost::*;
 
+        let konfiguration = app_state.konfiguration();
         match json {
             GetAmtsgerichte(ga) => {
-                let amtsgerichte = crate::db::get_amtsgerichte_for_bundesland(&ga.land)?;
+                let amtsgerichte = crate::db::get_amtsgerichte_for_bundesland(&konfiguration, &ga.land)?;
                 Ok(ZugriffJsonResponseOk::GetAmtsgerichte(
                     ZugriffJsonGetAmtsgerichteResponseOk { amtsgerichte },
                 ))
             }
             GetBezirke(gb) => {
-                let bezirke = crate::db::get_bezirke_for_amtsgericht(&gb.amtsgericht)?;
+                let bezirke = crate::db::get_bezirke_for_amtsgericht(&konfiguration, &gb.amtsgericht)?;
                 Ok(ZugriffJsonResponseOk::GetBezirke(
                     ZugriffJsonGetBezirkeResponseOk { bezirke },
                 ))
             }
             GetBlaetter(gb) => {
                 let blaetter =
-                    crate::db::get_blaetter_for_bezirk(&gb.land, &gb.amtsgericht, &gb.bezirk)?;
+                    crate::db::get_blaetter_for_bezirk(&konfiguration, &gb.land, &gb.amtsgericht, &gb.bezirk)?;
                 Ok(ZugriffJsonResponseOk::GetBlaetter(
                     ZugriffJsonGetBlaetterResponseOk { blaetter },
                 ))
@@ -330,25 +614,98 @@ pub mod index {
                     }
                 };
 
+                let bereiche = a
+                    .blaetter
+                    .iter()
+                    .map(|b| b.pruefen())
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                // Begrenzung pro E-Mail und IP, jede angefragte Zeile zählt einmal,
+                // auch Bereiche und Platzhalter (ALLE_*). Wie viele Blätter sie
+                // umfassen, sehen die Administratoren bei der Entscheidung.
+                let anzahl = a.blaetter.len();
+                crate::ratenbegrenzung::zugriff_anfrage_pruefen(&konfiguration, a.email.trim(), ip, anzahl)?;
+
                 let now = chrono::Utc::now().to_rfc3339();
+                let anfrage_id = format!("{}", uuid::Uuid::new_v4());
+                let neue_anfragen = a
+                    .blaetter
+                    .iter()
+                    .zip(bereiche.into_iter())
+                    .map(|(blatt, blatt_bis)| crate::db::ZugriffInfo {
+                        id: format!("{}", uuid::Uuid::new_v4()),
+                        name: a.name.trim().to_string(),
+                        email: a.email.trim().to_string(),
+                        typ: typ.to_string(),
+                        grund: Some(a.grund.trim().to_string()),
+                        angefragt: now.clone(),
+                        land: blatt.land.trim().to_string(),
+                        amtsgericht: blatt.amtsgericht.trim().to_string(),
+                        bezirk: blatt.grundbuchbezirk.trim().to_string(),
+                        blatt: blatt.blatt.trim().to_string(),
+                        blatt_bis,
+                        anfrage_id: Some(anfrage_id.clone()),
+                        ..Default::default()
+                    })
+                    .collect::<Vec<_>>();
+
+                // Administratoren sehen die Anfrage erst nach Bestätigung der E-Mail-Adresse.
+                // Die E-Mail wird zuerst gesendet, damit bei einem Fehler weder Anfragen
+                // noch Zählerstände ohne Bestätigungslink zurückbleiben.
+                let stunden = konfiguration.zugriff_bestaetigung_stunden;
+                let gueltig_bis = if stunden == 0 {
+                    None
+                } else {
+                    Some(chrono::Utc::now() + chrono::Duration::hours(stunden as i64))
+                };
+                let email = a.email.trim().to_string();
+                let email_anfrage_id = anfrage_id.clone();
+                let email_anfragen = neue_anfragen.clone();
+                let email_konfiguration = konfiguration.clone();
+                // SmtpTransport blockiert, daher nicht im async-Task selbst senden
+                tokio::task::spawn_blocking(move || {
+                    crate::email::send_zugriff_bestaetigung_email(
+                        &email_konfiguration,
+                        &email,
+                        &email_anfrage_id,
+                        gueltig_bis.as_ref(),
+                        &email_anfragen,
+                    )
+                })
+                .await
+                .map_err(|e| format!("{e}"))
+                .and_then(|r| r)
+                .map_err(|e| {
+                    println!("Fehler beim Senden der Bestätigungs-E-Mail an {}: {e}", a.email.trim());
+                    format!("E-Mail zur Bestätigung der Anfrage konnte nicht gesendet werden")
+                })?;
 
-                for blatt in a.blaetter.iter() {
-                    let zugriff_id = uuid::Uuid::new_v4();
-                    let zugriff_id = format!("{zugriff_id}");
+                crate::ratenbegrenzung::zugriff_anfrage_protokollieren(
+                    a.email.trim(),
+                    ip,
+                    anzahl,
+                    app_state,
+                )
+                .await?;
 
+                for neu in neue_anfragen.iter() {
                     crate::api::write_to_root_db(
                         DbChangeOp::CreateZugriff {
-                            id: zugriff_id,
-                            name: a.name.trim().to_string(),
-                            email: a.email.trim().to_string(),
+                            id: neu.id.clone(),
+                            name: neu.name.clone(),
+                            email: neu.email.clone(),
                             typ: typ,
                             grund: a.grund.trim().to_string(),
                             datum: now.clone(),
-                            land: blatt.land.trim().to_string(),
-                            amtsgericht: blatt.amtsgericht.trim().to_string(),
-                            bezirk: blatt.grundbuchbezirk.trim().to_string(),
-                            blatt: blatt.blatt.trim().to_string(),
+                            land: neu.land.clone(),
+                            amtsgericht: neu.amtsgericht.clone(),
+                            bezirk: neu.bezirk.clone(),
+                            blatt: neu.blatt.clone(),
+                            blatt_bis: neu.blatt_bis.clone(),
+                            anfrage_id: neu.anfrage_id.clone(),
+                            bestaetigt_am: None,
                         },
+                        a.email.trim(),
                         app_state,
                     )
                     .await?;
@@ -358,7 +715,256 @@ pub mod index {
                     ZugriffJsonAnfrageResponseOk {},
                 ))
             }
+            Verlaengern(v) => {
+                use super::commit::DbChangeOp;
+
+                if !crate::signatur::pruefen(
+                    &konfiguration,
+                    crate::signatur::ZWECK_ZUGRIFF_VERLAENGERN,
+                    &[v.id.trim()],
+                    &v.signatur,
+                ) {
+                    return Err(format!("Ungültiger Verlängerungslink"));
+                }
+
+                let zugriff = crate::db::get_zugriff(&konfiguration, v.id.trim())?
+                    .filter(|z| z.gewaehrt_von.is_some() && z.widerrufen_am.is_none())
+                    .ok_or(format!("Zugriff nicht gefunden oder nicht mehr gültig"))?;
+
+                // Mehrfaches Klicken auf den Link erzeugt nur eine Anfrage
+                if crate::db::offene_zugriff_anfrage_existiert(
+                    &konfiguration,
+                    &zugriff.email,
+                    &zugriff.land,
+                    &zugriff.amtsgericht,
+                    &zugriff.bezirk,
+                    &zugriff.blatt,
+                    zugriff.blatt_bis.as_deref(),
+                )? {
+                    return Ok(ZugriffJsonResponseOk::Verlaengern(
+                        ZugriffJsonAnfrageResponseOk {},
+                    ));
+                }
+
+                crate::ratenbegrenzung::zugriff_anfrage_pruefen(&konfiguration, &zugriff.email, ip, 1)?;
+                crate::ratenbegrenzung::zugriff_anfrage_protokollieren(
+                    &zugriff.email,
+                    ip,
+                    1,
+                    app_state,
+                )
+                .await?;
+
+                let grund = match v.grund.trim() {
+                    "" => format!("Verlängerung von Zugriff {}", zugriff.id),
+                    g => format!("Verlängerung von Zugriff {}: {g}", zugriff.id),
+                };
+
+                let anfrage_id = format!("{}", uuid::Uuid::new_v4());
+                let neu = crate::db::ZugriffInfo {
+                    id: format!("{}", uuid::Uuid::new_v4()),
+                    name: zugriff.name.clone(),
+                    email: zugriff.email.clone(),
+                    typ: zugriff.typ.clone(),
+                    grund: Some(grund.clone()),
+                    angefragt: chrono::Utc::now().to_rfc3339(),
+                    land: zugriff.land.clone(),
+                    amtsgericht: zugriff.amtsgericht.clone(),
+                    bezirk: zugriff.bezirk.clone(),
+                    blatt: zugriff.blatt.clone(),
+                    blatt_bis: zugriff.blatt_bis.clone(),
+                    anfrage_id: Some(anfrage_id.clone()),
+                    ..Default::default()
+                };
+                // Der Link kam per E-Mail an diese Adresse, eine weitere Bestätigung ist nicht nötig
+                let bestaetigt_am = Some(neu.angefragt.clone());
+
+                crate::api::write_to_root_db(
+                    DbChangeOp::CreateZugriff {
+                        id: neu.id.clone(),
+                        name: neu.name.clone(),
+                        email: neu.email.clone(),
+                        typ: ZugriffTyp::from_str(&neu.typ).unwrap_or(ZugriffTyp::Gast),
+                        grund,
+                        datum: neu.angefragt.clone(),
+                        land: neu.land.clone(),
+                        amtsgericht: neu.amtsgericht.clone(),
+                        bezirk: neu.bezirk.clone(),
+                        blatt: neu.blatt.clone(),
+                        blatt_bis: neu.blatt_bis.clone(),
+                        anfrage_id: neu.anfrage_id.clone(),
+                        bestaetigt_am,
+                    },
+                    &zugriff.email,
+                    app_state,
+                )
+                .await?;
+
+                let neue_anfragen = vec![neu];
+                let email = zugriff.email.clone();
+                let email_anfragen = neue_anfragen.clone();
+                let email_konfiguration = konfiguration.clone();
+                // SmtpTransport blockiert, daher nicht im async-Task selbst senden
+                tokio::spawn(async move {
+                    let gesendet = tokio::task::spawn_blocking(move || {
+                        crate::email::send_zugriff_anfrage_eingegangen_email(
+                            &email_konfiguration,
+                            &email,
+                            &anfrage_id,
+                            &email_anfragen,
+                        )
+                        .map_err(|e| format!("{email}: {e}"))
+                    })
+                    .await
+                    .map_err(|e| format!("{e}"))
+                    .and_then(|r| r);
+                    if let Err(e) = gesendet {
+                        println!("Fehler beim Senden der Eingangsbestätigung an {e}");
+                    }
+                });
+
+                admins_benachrichtigen(neue_anfragen, app_state);
+
+                Ok(ZugriffJsonResponseOk::Verlaengern(
+                    ZugriffJsonAnfrageResponseOk {},
+                ))
+            }
+            Status(st) => Ok(ZugriffJsonResponseOk::Status(anfrage_status(&konfiguration, &st.anfrage)?)),
+            Bestaetigen(b) => {
+                use super::commit::DbChangeOp;
+
+                let anfrage_id = b.anfrage.trim();
+                if !crate::signatur::pruefen(
+                    &konfiguration,
+                    crate::signatur::ZWECK_ZUGRIFF_BESTAETIGEN,
+                    &[anfrage_id, b.bis.trim()],
+                    &b.signatur,
+                ) {
+                    return Err(format!("Ungültiger Bestätigungslink"));
+                }
+                if let Ok(bis) = b.bis.trim().parse::<i64>() {
+                    if chrono::Utc::now().timestamp() > bis {
+                        return Err(format!(
+                            "Der Bestätigungslink ist abgelaufen, bitte stellen Sie die Anfrage erneut"
+                        ));
+                    }
+                }
+
+                let unbestaetigt = crate::db::get_zugriffe_fuer_anfrage(&konfiguration, anfrage_id)?
+                    .into_iter()
+                    .filter(|z| z.bestaetigt_am.is_none() && z.zurueckgezogen_am.is_none())
+                    .collect::<Vec<_>>();
+
+                // Erneutes Klicken auf den Link zeigt nur den Status an
+                if let Some(erster) = unbestaetigt.first() {
+                    crate::api::write_to_root_db(
+                        DbChangeOp::ZugriffeBestaetigen {
+                            anfrage_id: anfrage_id.to_string(),
+                            datum: chrono::Utc::now().to_rfc3339(),
+                        },
+                        &erster.email,
+                        app_state,
+                    )
+                    .await?;
+
+                    admins_benachrichtigen(unbestaetigt, app_state);
+                }
+
+                Ok(ZugriffJsonResponseOk::Bestaetigen(anfrage_status(&konfiguration, anfrage_id)?))
+            }
+            Zurueckziehen(z) => {
+                use super::commit::DbChangeOp;
+
+                let status = anfrage_status(&konfiguration, &z.anfrage)?;
+                let offen = status
+                    .blaetter
+                    .iter()
+                    .filter(|b| {
+                        (b.status == "offen" || b.status == "unbestaetigt") && z.ids.contains(&b.id)
+                    })
+                    .map(|b| b.id.clone())
+                    .collect::<Vec<_>>();
+
+                if offen.is_empty() {
+                    return Err(format!("Keine offenen Anfragen zum Zurückziehen ausgewählt"));
+                }
+
+                crate::api::write_to_root_db(
+                    DbChangeOp::ZugriffeZurueckziehen {
+                        anfrage_id: z.anfrage.trim().to_string(),
+                        ids: offen,
+                        datum: chrono::Utc::now().to_rfc3339(),
+                    },
+                    &status.email,
+                    app_state,
+                )
+                .await?;
+
+                Ok(ZugriffJsonResponseOk::Zurueckziehen(anfrage_status(&konfiguration, &z.anfrage)?))
+            }
+        }
+    }
+
+    fn anfrage_status(
+        konfiguration: &Konfiguration,
+        anfrage_id: &str,
+    ) -> Result<ZugriffJsonStatusResponseOk, String> {
+        let zugriffe = crate::db::get_zugriffe_fuer_anfrage(konfiguration, anfrage_id.trim())?;
+        let erster = zugriffe
+            .first()
+            .ok_or(format!("Anfrage nicht gefunden"))?;
+        let jetzt = chrono::Utc::now().to_rfc3339();
+
+        Ok(ZugriffJsonStatusResponseOk {
+            name: erster.name.clone(),
+            email: erster.email.clone(),
+            angefragt: erster.angefragt.clone(),
+            blaetter: zugriffe
+                .iter()
+                .map(|z| ZugriffJsonStatusBlatt::neu(konfiguration, z, &jetzt))
+                .collect(),
+        })
+    }
+
+    /// Benachrichtigt Administratoren sofort über neue Anfragen, falls
+    /// `email.zugriff.benachrichtigung` = `sofort` (sonst Zusammenfassung im Hintergrund).
+    /// Gesendet wird in einem eigenen Task, damit die Antwort nicht auf SMTP wartet.
+    fn admins_benachrichtigen(zugriffe: Vec<crate::db::ZugriffInfo>, app_state: &AppState) {
+        use super::commit::DbChangeOp;
+
+        let konfiguration = app_state.konfiguration();
+        if zugriffe.is_empty() || crate::email::get_zugriff_benachrichtigung(&konfiguration) != "sofort" {
+            return;
         }
+
+        let app_state = app_state.clone();
+        tokio::spawn(async move {
+            let ids = zugriffe.iter().map(|z| z.id.clone()).collect::<Vec<_>>();
+            let email = zugriffe[0].email.clone();
+
+            // SmtpTransport blockiert, daher nicht im async-Task selbst senden
+            let gesendet = tokio::task::spawn_blocking(move || {
+                crate::email::send_zugriff_anfragen_email(&konfiguration, &zugriffe, false)
+            })
+            .await
+            .map_err(|e| format!("{e}"))
+            .and_then(|r| r);
+
+            if let Err(e) = gesendet {
+                println!("Fehler beim Benachrichtigen über Zugriffsanfragen: {e}");
+                return;
+            }
+
+            let _ = crate::api::write_to_root_db(
+                DbChangeOp::ZugriffeAdminBenachrichtigt {
+                    ids,
+                    datum: chrono::Utc::now().to_rfc3339(),
+                },
+                &email,
+                &app_state,
+            )
+            .await;
+        });
     }
 
     // Seite mit API-Dokumentation
@@ -406,15 +1012,27 @@ pub mod login {
 
     // Login-Seite
     #[get("/login")]
-    async fn login_get(_: HttpRequest) -> impl Responder {
+    async fn login_get(app_state: web::Data<AppState>, _: HttpRequest) -> impl Responder {
         let css = crate::get_css();
         let css = format!("<style type='text/css'>{css}</style>");
+        let oidc = match app_state.konfiguration().oidc.as_ref() {
+            Some(k) => format!(
+                "<p><a class='oidc-login' href='/login/oidc'>{}</a></p>",
+                k.bezeichnung
+                    .replace('&', "&amp;")
+                    .replace('<', "&lt;")
+                    .replace('>', "&gt;")
+                    .replace('\'', "&#39;")
+            ),
+            None => String::new(),
+        };
         HttpResponse::Ok()
             .content_type("text/html; charset=utf-8")
             .body(
                 include_str!("../web/login.html")
                     .replace("<!-- CSS -->", &css)
-                    .replace("<!-- SVG -->", include_str!("../web/foam4.svg")),
+                    .replace("<!-- SVG -->", include_str!("../web/foam4.svg"))
+                    .replace("<!-- OIDC -->", &oidc),
             )
     }
 
@@ -423,6 +1041,8 @@ pub mod login {
         email: String,
         passwort: String,
         form: Option<String>,
+        /// TOTP- oder Wiederherstellungscode (zweiter Schritt)
+        code: Option<String>,
     }
 
     #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -430,14 +1050,74 @@ pub mod login {
     pub enum LoginResponse {
         #[serde(rename = "ok")]
         Ok(LoginResponseOk),
+        /// Passwort korrekt, es fehlt noch der Code für den zweiten Faktor
+        #[serde(rename = "zwei-faktor")]
+        ZweiFaktor(LoginResponseZweiFaktor),
         #[serde(rename = "error")]
         Error(LoginResponseError),
     }
 
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub struct LoginResponseZweiFaktor {
+        pub text: String,
+    }
+
     #[derive(Debug, Clone, Serialize, Deserialize)]
     pub struct LoginResponseOk {
         pub token: String,
         pub valid_until: DateTime<Utc>,
+        pub refresh_token: String,
+        pub refresh_valid_until: DateTime<Utc>,
+    }
+
+    /// Ohne `refresh_token` wird das Refresh-Token aus dem Cookie verwendet
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct RefreshForm {
+        #[serde(default)]
+        refresh_token: String,
+    }
+
+    /// Cookies der Web-Oberfläche: für Skripte nicht lesbar und nur über HTTPS
+    pub fn sitzung_cookies(
+        token: &str,
+        refresh_token: &str,
+    ) -> [actix_web::cookie::Cookie<'static>; 2] {
+        [("Authentication", token), ("Refresh", refresh_token)].map(|(name, wert)| {
+            let mut cookie = actix_web::cookie::Cookie::new(name, wert.to_string());
+            cookie.set_path("/");
+            cookie.set_http_only(true);
+            cookie.set_secure(true);
+            cookie.set_same_site(actix_web::cookie::SameSite::Lax);
+            cookie
+        })
+    }
+
+    pub fn sitzung_cookies_entfernen() -> [actix_web::cookie::Cookie<'static>; 2] {
+        sitzung_cookies("", "").map(|mut cookie| {
+            cookie.make_removal();
+            cookie
+        })
+    }
+
+    /// Setzt nach erfolgreicher Anmeldung die Cookies, nach einem Fehler
+    /// werden sie entfernt (falls `entfernen`)
+    fn login_antwort(response: &LoginResponse, entfernen: bool) -> HttpResponse {
+        let mut antwort = HttpResponse::Ok();
+        antwort.content_type("application/json; charset=utf-8");
+        match response {
+            LoginResponse::Ok(o) => {
+                for cookie in sitzung_cookies(&o.token, &o.refresh_token) {
+                    antwort.cookie(cookie);
+                }
+            }
+            _ if entfernen => {
+                for cookie in sitzung_cookies_entfernen() {
+                    antwort.cookie(cookie);
+                }
+            }
+            _ => {}
+        }
+        antwort.body(serde_json::to_string_pretty(response).unwrap_or_default())
     }
 
     #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -451,90 +1131,571 @@ pub mod login {
     async fn login_post(
         app_state: web::Data<AppState>,
         form: web::Form<LoginForm>,
-        _: HttpRequest,
+        req: HttpRequest,
     ) -> impl Responder {
-        let response = login_json(&form.email, &form.passwort, &*app_state).await;
-        HttpResponse::Ok()
-            .content_type("application/json; charset=utf-8")
-            .body(serde_json::to_string_pretty(&response).unwrap_or_default())
+        let response = login_json(
+            &form.email,
+            &form.passwort,
+            form.code.as_deref(),
+            &get_session_beschreibung(&req),
+            &crate::ratenbegrenzung::get_ip(&app_state.konfiguration(), &req),
+            &*app_state,
+        )
+        .await;
+        login_antwort(&response, false)
     }
 
-    pub async fn login_json(email: &str, passwort: &str, app_state: &AppState) -> LoginResponse {
-        use crate::api::commit::DbChangeOp;
+    /// Beschreibung einer Session für "Meine Sitzungen" (User-Agent)
+    pub fn get_session_beschreibung(req: &HttpRequest) -> String {
+        req.headers()
+            .get("User-Agent")
+            .and_then(|h| h.to_str().ok())
+            .unwrap_or_default()
+            .chars()
+            .take(1000)
+            .collect()
+    }
+
+    pub async fn login_json(
+        email: &str,
+        passwort: &str,
+        code: Option<&str>,
+        beschreibung: &str,
+        ip: &str,
+        app_state: &AppState,
+    ) -> LoginResponse {
+
+        let konfiguration = app_state.konfiguration();
+        // Gesperrtes Konto / zu viele Fehlversuche: Passwort wird gar nicht erst geprüft
+        if let Err(e) = crate::ratenbegrenzung::anmeldung_pruefen(&konfiguration, email, ip) {
+            return LoginResponse::Error(LoginResponseError { code: 429, text: e });
+        }
 
-        match crate::db::check_password(MountPoint::Local, &email, &passwort) {
-            // Benutzer + Token existiert
-            Ok((_info, token, valid_until)) => {
-                LoginResponse::Ok(LoginResponseOk { token, valid_until })
+        let info = match crate::db::check_password(&konfiguration, MountPoint::Local, &email, &passwort) {
+            Ok(info) => info,
+            // Benutzer existiert nicht / falsches Passwort
+            Err(Some(e)) => {
+                if let Err(e) =
+                    crate::ratenbegrenzung::fehlanmeldung_protokollieren(email, ip, app_state).await
+                {
+                    println!("Fehler beim Speichern der Fehlanmeldung: {e}");
+                }
+                return LoginResponse::Error(LoginResponseError {
+                    code: 0,
+                    text: e.clone(),
+                });
             }
-            // Benutzer existiert nicht
-            Err(Some(e)) => LoginResponse::Error(LoginResponseError {
-                code: 0,
-                text: e.clone(),
-            }),
-            // Benutzer existiert, aber noch kein Token
+            // Benutzer existiert, hat aber noch kein Passwort gesetzt
             Err(None) => {
-                let (token, gueltig_bis) = crate::db::generate_token();
+                return LoginResponse::Error(LoginResponseError {
+                    code: 0,
+                    text: format!("Für dieses Benutzerkonto wurde noch kein Passwort gesetzt"),
+                })
+            }
+        };
 
-                match crate::api::write_to_root_db(
-                    DbChangeOp::BenutzerSessionNeu {
-                        email: email.to_string(),
-                        token: token.clone(),
-                        gueltig_bis: gueltig_bis.clone(),
-                    },
-                    app_state,
-                )
-                .await
-                {
-                    Ok(_) => {}
-                    Err(e) => {
-                        return LoginResponse::Error(LoginResponseError {
-                            code: 500,
-                            text: e.clone(),
-                        });
-                    }
+        // Zweiter Schritt: TOTP- oder Wiederherstellungscode
+        if crate::zwei_faktor::get_zwei_faktor(&konfiguration, &info).map_or(false, |z| z.aktiviert) {
+            let code = match code.map(|c| c.trim()).filter(|c| !c.is_empty()) {
+                Some(s) => s,
+                None => {
+                    return LoginResponse::ZweiFaktor(LoginResponseZweiFaktor {
+                        text: format!("Bitte Code aus der Authenticator-App oder Wiederherstellungscode eingeben"),
+                    })
                 }
+            };
 
-                match crate::db::check_password(MountPoint::Local, &email, &passwort) {
-                    Ok((_info, token, valid_until)) => {
-                        LoginResponse::Ok(LoginResponseOk { token, valid_until })
+            match crate::zwei_faktor::zweiten_faktor_pruefen(&konfiguration, &info, code) {
+                Ok(faktor) => {
+                    if let Err(e) =
+                        crate::zwei_faktor::zweiten_faktor_verbrauchen(&info, faktor, app_state)
+                            .await
+                    {
+                        return LoginResponse::Error(LoginResponseError { code: 2, text: e });
                     }
-                    Err(e) => LoginResponse::Error(LoginResponseError {
-                        code: 0,
-                        text: e.unwrap_or_default().clone(),
-                    }),
+                }
+                Err(e) => {
+                    if let Err(e) =
+                        crate::ratenbegrenzung::fehlanmeldung_protokollieren(email, ip, app_state)
+                            .await
+                    {
+                        println!("Fehler beim Speichern der Fehlanmeldung: {e}");
+                    }
+                    return LoginResponse::Error(LoginResponseError { code: 2, text: e });
                 }
             }
         }
-    }
-}
-
-/// API für `/konto` Anfragen: Gibt HTML-Übersicht für Benutzer / Abo-Verwaltung
-pub mod konto {
-    use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
-    use serde_derive::{Deserialize, Serialize};
 
-    use crate::{
-        api::login::LoginResponse,
-        db::{GpgKeyPair, KontoData, KontoDataResult, get_abos_fuer_benutzer},
-        AppState, BenutzerLoeschenArgs, BenutzerNeuArgsJson, BezirkeLoeschenArgs, BezirkeNeuArgs, models::get_data_dir,
-    };
+        if let Err(e) = crate::ratenbegrenzung::anmeldung_erfolgreich(email, app_state).await {
+            println!("Fehler beim Zurücksetzen der Fehlanmeldungen: {e}");
+        }
 
-    #[derive(Deserialize)]
-    struct ZugriffId {
-        id: Option<String>,
+        session_anlegen(email, beschreibung, app_state).await
     }
 
-    // Konto-Seite
-    #[get("/konto")]
-    async fn konto_get(req: HttpRequest, zugriff: web::Query<ZugriffId>) -> impl Responder {
+    /// Legt nach erfolgreicher Anmeldung eine neue Session für den Benutzer an
+    async fn session_anlegen(email: &str, beschreibung: &str, app_state: &AppState) -> LoginResponse {
+        use crate::api::commit::DbChangeOp;
+        use crate::db::SessionNeu;
 
-        let user = super::get_benutzer_from_httpauth(&req).await;
+        let (session, token, refresh_token) = SessionNeu::generieren(&app_state.konfiguration());
 
-        if let Some(s) = zugriff.id.as_ref() {
-            let benutzer_existiert = benutzer_exists(zugriff.id.as_deref()).is_some();
-            if user.is_err() && !benutzer_existiert {
-                let html = include_str!("../web/konto-login.html").replace(
+        match crate::api::write_to_root_db(
+            DbChangeOp::BenutzerSessionNeu {
+                email: email.to_string(),
+                session: session.clone(),
+                beschreibung: beschreibung.to_string(),
+            },
+            email,
+            app_state,
+        )
+        .await
+        {
+            Ok(_) => LoginResponse::Ok(LoginResponseOk {
+                token,
+                valid_until: session.gueltig_bis,
+                refresh_token,
+                refresh_valid_until: session.refresh_gueltig_bis,
+            }),
+            Err(e) => LoginResponse::Error(LoginResponseError {
+                code: 500,
+                text: e.clone(),
+            }),
+        }
+    }
+
+    /// Tauscht ein Refresh-Token gegen ein neues Token + Refresh-Token
+    #[post("/login/refresh")]
+    async fn login_refresh(
+        app_state: web::Data<AppState>,
+        form: web::Form<RefreshForm>,
+        req: HttpRequest,
+    ) -> impl Responder {
+        let refresh_token = match form.refresh_token.as_str() {
+            "" => req.cookie("Refresh").map(|c| c.value().to_string()).unwrap_or_default(),
+            s => s.to_string(),
+        };
+        let response = refresh_json(&refresh_token, &*app_state).await;
+        login_antwort(&response, true)
+    }
+
+    async fn refresh_json(refresh_token: &str, app_state: &AppState) -> LoginResponse {
+        use crate::api::commit::DbChangeOp;
+        use crate::db::SessionNeu;
+
+        let konfiguration = app_state.konfiguration();
+        let benutzer = match crate::db::get_user_from_refresh_token(&konfiguration, refresh_token) {
+            Ok(o) => o,
+            Err(e) => {
+                return LoginResponse::Error(LoginResponseError { code: 0, text: e });
+            }
+        };
+
+        let (session, token, neues_refresh_token) = SessionNeu::generieren(&konfiguration);
+
+        match crate::api::write_to_root_db(
+            DbChangeOp::BenutzerSessionErneuern {
+                email: benutzer.email.clone(),
+                refresh_token_hash_alt: crate::db::hash_token(refresh_token),
+                session: session.clone(),
+            },
+            &benutzer.email,
+            app_state,
+        )
+        .await
+        {
+            Ok(_) => LoginResponse::Ok(LoginResponseOk {
+                token,
+                valid_until: session.gueltig_bis,
+                refresh_token: neues_refresh_token,
+                refresh_valid_until: session.refresh_gueltig_bis,
+            }),
+            Err(e) => LoginResponse::Error(LoginResponseError { code: 500, text: e }),
+        }
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    #[serde(tag = "status")]
+    enum LogoutResponse {
+        #[serde(rename = "ok")]
+        Ok(LogoutResponseOk),
+        #[serde(rename = "error")]
+        Error(LoginResponseError),
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct LogoutResponseOk {}
+
+    /// Beendet die Session des mitgesendeten Tokens
+    #[post("/logout")]
+    async fn logout(app_state: web::Data<AppState>, req: HttpRequest) -> impl Responder {
+        use crate::api::commit::DbChangeOp;
+
+        let [token_cookie, refresh_cookie] = sitzung_cookies_entfernen();
+
+        // Abmelden muss auch ohne die vorgeschriebene Zwei-Faktor-Authentifizierung gehen
+        let (token, benutzer) =
+            match super::get_benutzer_from_httpauth_konto(&app_state.konfiguration(), &req).await {
+                Ok(o) => o,
+                Err(mut antwort) => {
+                    let _ = antwort.add_cookie(&token_cookie);
+                    let _ = antwort.add_cookie(&refresh_cookie);
+                    return antwort;
+                }
+            };
+
+        let response = match crate::api::write_to_root_db(
+            DbChangeOp::BenutzerSessionAbmelden {
+                email: benutzer.email.clone(),
+                token_hash: crate::db::hash_token(&token),
+            },
+            &benutzer.email,
+            &*app_state,
+        )
+        .await
+        {
+            Ok(()) => LogoutResponse::Ok(LogoutResponseOk {}),
+            Err(e) => LogoutResponse::Error(LoginResponseError { code: 500, text: e }),
+        };
+
+        HttpResponse::Ok()
+            .cookie(token_cookie)
+            .cookie(refresh_cookie)
+            .content_type("application/json; charset=utf-8")
+            .body(serde_json::to_string_pretty(&response).unwrap_or_default())
+    }
+
+    /// Leitet zurück zur Login-Seite, die den Fehler anzeigt
+    fn oidc_fehler(e: &str) -> HttpResponse {
+        HttpResponse::Found()
+            .append_header(("Location", format!("/login?fehler={}", urlencoding::encode(e))))
+            .finish()
+    }
+
+    fn oidc_cookie(wert: String) -> actix_web::cookie::Cookie<'static> {
+        let mut cookie = actix_web::cookie::Cookie::new(crate::oidc::COOKIE_NAME, wert);
+        cookie.set_path("/login/oidc");
+        cookie.set_http_only(true);
+        cookie.set_same_site(actix_web::cookie::SameSite::Lax);
+        cookie
+    }
+
+    // Anmeldung über OpenID Connect: Weiterleitung zum Anbieter
+    #[get("/login/oidc")]
+    async fn oidc_login(app_state: web::Data<AppState>) -> impl Responder {
+        let k = match app_state.konfiguration().oidc.clone() {
+            Some(s) => s,
+            None => return oidc_fehler("Anmeldung über OpenID Connect ist nicht konfiguriert"),
+        };
+
+        let discovery = match crate::oidc::get_discovery(&k).await {
+            Ok(o) => o,
+            Err(e) => return oidc_fehler(&e),
+        };
+        let redirect_url = match crate::oidc::get_redirect_url(&app_state.konfiguration(), &k) {
+            Ok(o) => o,
+            Err(e) => return oidc_fehler(&e),
+        };
+
+        let anmeldung = crate::oidc::OidcAnmeldung::generieren();
+        let url = crate::oidc::get_autorisierung_url(&k, &discovery, &anmeldung, &redirect_url);
+
+        let mut cookie = oidc_cookie(anmeldung.to_cookie_wert());
+        cookie.set_secure(redirect_url.starts_with("https://"));
+        cookie.set_max_age(actix_web::cookie::time::Duration::minutes(
+            crate::oidc::ANMELDUNG_GUELTIGKEIT_MINUTEN,
+        ));
+
+        HttpResponse::Found()
+            .append_header(("Location", url))
+            .cookie(cookie)
+            .finish()
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct OidcCallback {
+        code: Option<String>,
+        state: Option<String>,
+        error: Option<String>,
+        error_description: Option<String>,
+    }
+
+    // Anmeldung über OpenID Connect: Rückkehr vom Anbieter
+    #[get("/login/oidc/callback")]
+    async fn oidc_callback(
+        app_state: web::Data<AppState>,
+        query: web::Query<OidcCallback>,
+        req: HttpRequest,
+    ) -> impl Responder {
+        let mut entfernen = oidc_cookie(String::new());
+        entfernen.make_removal();
+
+        match oidc_callback_inner(&*app_state, &query, &req).await {
+            Ok(o) => {
+                let [token, refresh] = sitzung_cookies(&o.token, &o.refresh_token);
+                HttpResponse::Found()
+                    .append_header(("Location", "/konto"))
+                    .cookie(token)
+                    .cookie(refresh)
+                    .cookie(entfernen)
+                    .finish()
+            }
+            Err(e) => {
+                let mut response = oidc_fehler(&e);
+                let _ = response.add_cookie(&entfernen);
+                response
+            }
+        }
+    }
+
+    async fn oidc_callback_inner(
+        app_state: &AppState,
+        query: &OidcCallback,
+        req: &HttpRequest,
+    ) -> Result<LoginResponseOk, String> {
+        let konfiguration = app_state.konfiguration();
+        let k = konfiguration
+            .oidc
+            .clone()
+            .ok_or(format!("Anmeldung über OpenID Connect ist nicht konfiguriert"))?;
+
+        if let Some(e) = query.error.as_ref() {
+            return Err(format!(
+                "Anmeldung beim Anbieter fehlgeschlagen: {e} {}",
+                query.error_description.as_deref().unwrap_or_default()
+            ));
+        }
+
+        let anmeldung = req
+            .cookie(crate::oidc::COOKIE_NAME)
+            .and_then(|c| crate::oidc::OidcAnmeldung::from_cookie_wert(c.value()))
+            .ok_or(format!("Anmeldung abgelaufen, bitte erneut versuchen"))?;
+
+        if query.state.as_deref() != Some(anmeldung.state.as_str()) {
+            return Err(format!("Ungültiger state-Parameter"));
+        }
+
+        let code = query
+            .code
+            .as_deref()
+            .ok_or(format!("Anbieter hat keinen Code übermittelt"))?;
+
+        let discovery = crate::oidc::get_discovery(&k).await?;
+        let redirect_url = crate::oidc::get_redirect_url(&konfiguration, &k)?;
+        let oidc_benutzer =
+            crate::oidc::code_einloesen(&k, &discovery, code, &anmeldung, &redirect_url).await?;
+        let email = crate::oidc::benutzer_anmelden(&k, &oidc_benutzer, app_state).await?;
+        crate::oidc::zwei_faktor_pruefen(&konfiguration, &k, &email)?;
+
+        match session_anlegen(&email, &get_session_beschreibung(req), app_state).await {
+            LoginResponse::Ok(o) => Ok(o),
+            LoginResponse::ZweiFaktor(z) => Err(z.text),
+            LoginResponse::Error(e) => Err(e.text),
+        }
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    #[serde(tag = "status")]
+    enum PasswortResponse {
+        #[serde(rename = "ok")]
+        Ok(PasswortResponseOk),
+        #[serde(rename = "error")]
+        Error(LoginResponseError),
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct PasswortResponseOk {}
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct PasswortVergessenForm {
+        email: String,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct PasswortZuruecksetzenForm {
+        token: String,
+        passwort: String,
+    }
+
+    // Seite "Passwort vergessen"
+    #[get("/passwort-vergessen")]
+    async fn passwort_vergessen_get(_: HttpRequest) -> impl Responder {
+        let css = crate::get_css();
+        let css = format!("<style type='text/css'>{css}</style>");
+        HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .body(
+                include_str!("../web/passwort-vergessen.html")
+                    .replace("<!-- CSS -->", &css)
+                    .replace("<!-- SVG -->", include_str!("../web/foam4.svg")),
+            )
+    }
+
+    /// Verschickt einen Link zum Zurücksetzen des Passworts. Antwortet immer mit
+    /// "ok", damit nicht abgefragt werden kann, welche E-Mail-Adressen existieren.
+    #[post("/passwort-vergessen")]
+    async fn passwort_vergessen_post(
+        req: HttpRequest,
+        app_state: web::Data<AppState>,
+        form: web::Form<PasswortVergessenForm>,
+    ) -> impl Responder {
+        let konfiguration = app_state.konfiguration();
+        let email = form.email.trim();
+        let ip = crate::ratenbegrenzung::get_ip(&konfiguration, &req);
+        match crate::ratenbegrenzung::passwort_vergessen_pruefen(&konfiguration, email, &ip) {
+            Ok(()) => {
+                let gezaehlt =
+                    crate::ratenbegrenzung::passwort_vergessen_protokollieren(email, &ip, &*app_state)
+                        .await;
+                if let Err(e) = gezaehlt {
+                    println!("Passwort vergessen ({email}): {e}");
+                }
+                // Im Hintergrund, damit die Antwortzeit nicht verrät, ob das Konto existiert
+                let email = email.to_string();
+                let app_state = app_state.get_ref().clone();
+                tokio::spawn(async move {
+                    if let Err(e) = passwort_vergessen_inner(&email, &app_state).await {
+                        println!("Passwort vergessen ({email}): {e}");
+                    }
+                });
+            }
+            Err(e) => println!("Passwort vergessen ({email}): {e}"),
+        }
+
+        HttpResponse::Ok()
+            .content_type("application/json; charset=utf-8")
+            .body(
+                serde_json::to_string_pretty(&PasswortResponse::Ok(PasswortResponseOk {}))
+                    .unwrap_or_default(),
+            )
+    }
+
+    async fn passwort_vergessen_inner(email: &str, app_state: &AppState) -> Result<(), String> {
+        use crate::api::commit::DbChangeOp;
+
+        let konfiguration = app_state.konfiguration();
+        let token = format!("{}", uuid::Uuid::new_v4());
+        let gueltig_bis = Utc::now()
+            .checked_add_signed(chrono::Duration::minutes(
+                konfiguration.passwort_reset_gueltigkeit_minuten,
+            ))
+            .ok_or(format!("Ungültige Gültigkeit für Passwort-Token"))?;
+
+        // Schlägt fehl, wenn kein Benutzerkonto für die E-Mail existiert
+        crate::api::write_to_root_db(
+            DbChangeOp::PasswortResetNeu {
+                email: email.to_string(),
+                token_hash: crate::db::hash_token(&token),
+                gueltig_bis: gueltig_bis.to_rfc3339(),
+            },
+            email,
+            app_state,
+        )
+        .await?;
+
+        // SmtpTransport blockiert, daher nicht im async-Task selbst senden
+        let email = email.to_string();
+        tokio::task::spawn_blocking(move || {
+            crate::email::send_passwort_reset_email(&konfiguration, &email, &token, &gueltig_bis)
+        })
+        .await
+        .map_err(|e| format!("{e}"))?
+    }
+
+    // Seite zum Setzen des neuen Passworts (Link aus der E-Mail)
+    #[get("/passwort-zuruecksetzen")]
+    async fn passwort_zuruecksetzen_get(_: HttpRequest) -> impl Responder {
+        let css = crate::get_css();
+        let css = format!("<style type='text/css'>{css}</style>");
+        HttpResponse::Ok()
+            .content_type("text/html; charset=utf-8")
+            .body(
+                include_str!("../web/passwort-zuruecksetzen.html")
+                    .replace("<!-- CSS -->", &css)
+                    .replace("<!-- SVG -->", include_str!("../web/foam4.svg")),
+            )
+    }
+
+    /// Setzt das Passwort mit dem Token aus der E-Mail neu und beendet alle Sessions
+    #[post("/passwort-zuruecksetzen")]
+    async fn passwort_zuruecksetzen_post(
+        app_state: web::Data<AppState>,
+        form: web::Form<PasswortZuruecksetzenForm>,
+    ) -> impl Responder {
+        let mut antwort = HttpResponse::Ok();
+        let response = match passwort_zuruecksetzen_inner(&form, &*app_state).await {
+            Ok(()) => {
+                // Alle Sessions wurden beendet
+                for cookie in sitzung_cookies_entfernen() {
+                    antwort.cookie(cookie);
+                }
+                PasswortResponse::Ok(PasswortResponseOk {})
+            }
+            Err(e) => PasswortResponse::Error(LoginResponseError { code: 0, text: e }),
+        };
+
+        antwort
+            .content_type("application/json; charset=utf-8")
+            .body(serde_json::to_string_pretty(&response).unwrap_or_default())
+    }
+
+    async fn passwort_zuruecksetzen_inner(
+        form: &PasswortZuruecksetzenForm,
+        app_state: &AppState,
+    ) -> Result<(), String> {
+        use crate::api::commit::DbChangeOp;
+
+        if form.passwort.trim().is_empty() {
+            return Err(format!("Passwort darf nicht leer sein"));
+        }
+
+        let benutzer = crate::db::get_user_from_passwort_reset(&app_state.konfiguration(), &form.token)?;
+
+        crate::api::write_to_root_db(
+            DbChangeOp::PasswortZuruecksetzen {
+                email: benutzer.email.clone(),
+                token_hash: crate::db::hash_token(&form.token),
+                passwort: form.passwort.clone(),
+            },
+            &benutzer.email,
+            app_state,
+        )
+        .await
+    }
+}
+
+/// API für `/konto` Anfragen: Gibt HTML-Übersicht für Benutzer / Abo-Verwaltung
+pub mod konto {
+    use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
+    use serde_derive::{Deserialize, Serialize};
+
+    use crate::{
+        api::login::{LoginResponse, LoginResponseOk},
+        config::Konfiguration,
+        db::{GpgKeyPair, KontoData, KontoDataResult, get_abos_fuer_benutzer},
+        AppState, BenutzerLoeschenArgs, BenutzerNeuArgsJson, BezirkeLoeschenArgs, BezirkeNeuArgs,
+    };
+
+    #[derive(Deserialize)]
+    struct ZugriffId {
+        id: Option<String>,
+    }
+
+    // Konto-Seite
+    #[get("/konto")]
+    async fn konto_get(
+        app_state: web::Data<AppState>,
+        req: HttpRequest,
+        zugriff: web::Query<ZugriffId>,
+    ) -> impl Responder {
+
+        let konfiguration = app_state.konfiguration();
+        let user = super::get_benutzer_from_httpauth_konto(&konfiguration, &req).await;
+
+        if let Some(s) = zugriff.id.as_ref() {
+            let benutzer_existiert = benutzer_exists(&konfiguration, zugriff.id.as_deref()).is_some();
+            if user.is_err() && !benutzer_existiert {
+                let html = include_str!("../web/konto-login.html").replace(
                     "<!-- CSS -->",
                     &format!("<style>{}</style>", crate::get_css()),
                 );
@@ -554,7 +1715,7 @@ pub mod konto {
             }
         };
 
-        let konto_data = match crate::db::get_konto_data(&benutzer) {
+        let konto_data = match crate::db::get_konto_data(&konfiguration, &benutzer) {
             Ok(KontoDataResult::Aktiviert(a)) => a,
             _ => KontoData::default(),
         };
@@ -581,9 +1742,9 @@ pub mod konto {
             .body(html)
     }
 
-    fn benutzer_exists(s: Option<&str>) -> Option<()> {
+    fn benutzer_exists(konfiguration: &Konfiguration, s: Option<&str>) -> Option<()> {
         let zugriff = s?;
-        if crate::db::zugriff_benutzer_exists(zugriff).ok()? {
+        if crate::db::zugriff_benutzer_exists(konfiguration, zugriff).ok()? {
             Some(())
         } else {
             None
@@ -595,6 +1756,9 @@ pub mod konto {
         auth: String,
         aktion: String,
         daten: Vec<String>,
+        /// Begründung für "zugriff-ablehnen"
+        #[serde(default)]
+        grund: Option<String>,
     }
 
     #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -618,6 +1782,7 @@ pub mod konto {
     #[derive(Debug, Clone, Serialize, Deserialize)]
     pub struct KontoNeuJsonResponseOk {
         token: String,
+        refresh_token: String,
     }
 
     #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -674,10 +1839,14 @@ pub mod konto {
         app_state: &AppState,
         data: &KontoGeneriereSchluessel,
     ) -> Result<GpgKeyPair, KontoGeneriereSchluesselPostResponseError> {
-        let benutzer = crate::db::get_user_from_token(&data.auth)
+        let benutzer = crate::db::get_user_from_token(&app_state.konfiguration(), &data.auth)
             .map_err(|e| KontoGeneriereSchluesselPostResponseError { code: 500, text: e })?;
 
-        if benutzer.rechte != "admin" && benutzer.rechte != "bearbeiter" {
+        use crate::rollen::Berechtigung;
+
+        if !benutzer.hat_berechtigung(Berechtigung::BenutzerVerwalten)
+            && !benutzer.hat_berechtigung(Berechtigung::GrundbuchBearbeiten)
+        {
             return Err(KontoGeneriereSchluesselPostResponseError {
                 code: 500,
                 text: format!(
@@ -720,18 +1889,63 @@ pub mod konto {
         Zip(Vec<u8>),
     }
 
+    /// Vom Antragsteller zurückgezogene oder (noch) nicht per E-Mail
+    /// bestätigte Anfragen können nicht bearbeitet werden
+    fn bearbeitbar(konfiguration: &Konfiguration, ids: &[String]) -> Vec<String> {
+        ids.iter()
+            .filter(|id| {
+                crate::db::get_zugriff(konfiguration, id)
+                    .ok()
+                    .flatten()
+                    .map_or(false, |z| {
+                        z.zurueckgezogen_am.is_none() && z.bestaetigt_am.is_some()
+                    })
+            })
+            .cloned()
+            .collect()
+    }
+
     async fn konto_post_inner(
         app_state: &AppState,
         data: &KontoJsonPost,
     ) -> Result<KontoDataPostType, KontoJsonPostResponseError> {
         use crate::api::commit::DbChangeOp;
         use crate::BezirkNeuArgs;
+        use crate::rollen::Berechtigung;
 
-        let benutzer = crate::db::get_user_from_token(&data.auth)
+        let konfiguration = app_state.konfiguration();
+        let benutzer = crate::db::get_user_from_token(&konfiguration, &data.auth)
             .map_err(|e| KontoJsonPostResponseError { code: 500, text: e })?;
 
-        match (benutzer.rechte.as_str(), data.aktion.as_str()) {
-            ("admin", "benutzer-neu") => {
+        // Einrichten und eigene Sitzungen beenden geht auch ohne die vorgeschriebene 2FA
+        if !data.aktion.starts_with("zwei-faktor-")
+            && data.aktion != "sitzungen-beenden"
+            && crate::zwei_faktor::einrichtung_erforderlich(&konfiguration, &benutzer)
+        {
+            return Err(KontoJsonPostResponseError {
+                code: 2,
+                text: format!(
+                    "Für Benutzer mit Rechten \"{}\" ist die Zwei-Faktor-Authentifizierung vorgeschrieben, bitte zuerst unter \"Zwei-Faktor\" einrichten",
+                    benutzer.rechte
+                ),
+            });
+        }
+
+        // Neue Wiederherstellungscodes werden nur einmal im Klartext angezeigt
+        let mut neue_wiederherstellungscodes = None;
+        // Ebenso das Secret eines neuen / erneuerten Webhook-Abos
+        let mut neues_webhook_secret = None;
+        let mut webhook_testzustellung = None;
+
+        let pruefen = |berechtigung: Berechtigung| {
+            benutzer
+                .berechtigung_pruefen(berechtigung)
+                .map_err(|e| KontoJsonPostResponseError { code: 500, text: e })
+        };
+
+        match data.aktion.as_str() {
+            "benutzer-neu" => {
+                pruefen(Berechtigung::BenutzerVerwalten)?;
                 let name = data.daten.get(0).ok_or(KontoJsonPostResponseError {
                     code: 500,
                     text: "Benutzer neu anlegen: kein Name in Daten vorhanden".to_string(),
@@ -744,7 +1958,7 @@ pub mod konto {
                     code: 500,
                     text: "Benutzer neu anlegen: kein Passwort in Daten vorhanden".to_string(),
                 })?;
-                let rechte = "gast";
+                let rechte = crate::rollen::STANDARD_ROLLE;
                 crate::api::write_to_root_db(
                     DbChangeOp::BenutzerNeu(BenutzerNeuArgsJson {
                         name: name.clone(),
@@ -753,56 +1967,81 @@ pub mod konto {
                         rechte: rechte.to_string(),
                         schluessel: None,
                     }),
+                    &benutzer.email,
                     &app_state,
                 )
                 .await
                 .map_err(|e| KontoJsonPostResponseError { code: 500, text: e })?;
             }
-            ("admin", "benutzer-loeschen") => {
+            "benutzer-loeschen" => {
+                pruefen(Berechtigung::BenutzerVerwalten)?;
                 let email = data.daten.get(0).ok_or(KontoJsonPostResponseError {
                     code: 500,
                     text: "Benutzer löschen: keine E-Mail in Daten vorhanden".to_string(),
                 })?;
+                benutzer
+                    .benutzer_bearbeiten_pruefen(&konfiguration, std::slice::from_ref(email))
+                    .map_err(|e| KontoJsonPostResponseError { code: 2, text: e })?;
                 crate::api::write_to_root_db(
                     DbChangeOp::BenutzerLoeschen(BenutzerLoeschenArgs {
                         email: email.clone(),
                     }),
+                    &benutzer.email,
                     &app_state,
                 )
                 .await
                 .map_err(|e| KontoJsonPostResponseError { code: 500, text: e })?;
             }
-            ("admin", "benutzer-bearbeite-kontotyp") => {
+            "benutzer-bearbeite-kontotyp" => {
+                pruefen(Berechtigung::BenutzerVerwalten)?;
                 let neuer_kontotyp = data.daten.get(0).ok_or(KontoJsonPostResponseError {
                     code: 500,
                     text: "Benutzer neu anlegen: kein Name in Daten vorhanden".to_string(),
                 })?;
 
-                let kontotypen = ["admin", "gast", "bearbeiter"];
-                if !kontotypen.iter().any(|k| *k == neuer_kontotyp) {
-                    return Err(KontoJsonPostResponseError {
+                let rolle = crate::rollen::get_rolle(&konfiguration, neuer_kontotyp)
+                    .map_err(|e| KontoJsonPostResponseError { code: 500, text: e })?
+                    .ok_or(KontoJsonPostResponseError {
                         code: 500,
                         text: "Benutzer-Kontotyp bearbeiten: Ungültiger Kontotyp".to_string(),
-                    });
-                }
+                    })?;
+                benutzer
+                    .rolle_vergeben_pruefen(&rolle)
+                    .map_err(|e| KontoJsonPostResponseError { code: 2, text: e })?;
 
-                let emails = data.daten.iter().skip(1).cloned().collect();
+                let emails = data.daten.iter().skip(1).cloned().collect::<Vec<_>>();
+                benutzer
+                    .benutzer_bearbeiten_pruefen(&konfiguration, &emails)
+                    .map_err(|e| KontoJsonPostResponseError { code: 2, text: e })?;
 
                 crate::api::write_to_root_db(
                     DbChangeOp::BenutzerAendernRechte {
                         neue_rechte: neuer_kontotyp.to_string(),
                         ids: emails,
                     },
+                    &benutzer.email,
                     &app_state,
                 )
                 .await
                 .map_err(|e| KontoJsonPostResponseError { code: 500, text: e })?;
             }
-            ("admin", "benutzer-bearbeite-pubkey") => {
+            "benutzer-bearbeite-pubkey" => {
+                // Fremde Schlüssel darf nur ein Administrator ersetzen, sonst könnte
+                // sich jeder mit "Benutzer verwalten" als Bearbeiter ausgeben
                 let email = data.daten.get(0).ok_or(KontoJsonPostResponseError {
                     code: 500,
                     text: "Benutzer PubKey ändern: keine E-Mail ID".to_string(),
-                })?;
+                })?.clone();
+                if email != benutzer.email {
+                    if benutzer.rechte != crate::rollen::ADMIN_ROLLE {
+                        return Err(KontoJsonPostResponseError {
+                            code: 2,
+                            text: "Benutzer PubKey ändern: nur Administratoren dürfen den Schlüssel anderer Benutzer ersetzen".to_string(),
+                        });
+                    }
+                } else if !benutzer.hat_berechtigung(Berechtigung::BenutzerVerwalten) {
+                    pruefen(Berechtigung::GrundbuchBearbeiten)?;
+                }
 
                 let pubkey = data.daten.get(1).ok_or(KontoJsonPostResponseError {
                     code: 500,
@@ -811,15 +2050,17 @@ pub mod konto {
 
                 crate::api::write_to_root_db(
                     DbChangeOp::BenutzerAendernPubkey {
-                        id: email.clone(),
+                        id: email,
                         neuer_pubkey: pubkey.clone(),
                     },
+                    &benutzer.email,
                     &app_state,
                 )
                 .await
                 .map_err(|e| KontoJsonPostResponseError { code: 500, text: e })?;
             }
-            ("admin", "bezirk-neu") => {
+            "bezirk-neu" => {
+                pruefen(Berechtigung::BezirkeVerwalten)?;
                 let mut bezirke = Vec::new();
                 // daten =  CSV-Datei als Zellen (Land, Amtsgericht, Bezirk)
                 for (i, triple) in data.daten.chunks(3).enumerate() {
@@ -848,39 +2089,54 @@ pub mod konto {
 
                 crate::api::write_to_root_db(
                     DbChangeOp::BezirkeNeu(BezirkeNeuArgs { bezirke: bezirke }),
+                    &benutzer.email,
                     &app_state,
                 )
                 .await
                 .map_err(|e| KontoJsonPostResponseError { code: 500, text: e })?;
             }
-            ("admin", "bezirk-loeschen") => {
+            "bezirk-loeschen" => {
+                pruefen(Berechtigung::BezirkeVerwalten)?;
                 crate::api::write_to_root_db(
                     DbChangeOp::BezirkeLoeschen(BezirkeLoeschenArgs {
                         ids: data.daten.clone(),
                     }),
+                    &benutzer.email,
                     &app_state,
                 )
                 .await
                 .map_err(|e| KontoJsonPostResponseError { code: 500, text: e })?;
             }
-            ("admin", "zugriff-genehmigen") => {
+            "zugriff-genehmigen" => {
+                pruefen(Berechtigung::ZugriffeVerwalten)?;
+                let ids = bearbeitbar(&konfiguration, &data.daten);
+                let jetzt = chrono::Utc::now();
+                let gueltig_bis = match konfiguration.zugriff_gueltigkeit_tage {
+                    0 => None,
+                    tage => jetzt
+                        .checked_add_signed(chrono::Duration::days(tage as i64))
+                        .map(|d| d.to_rfc3339()),
+                };
                 crate::api::write_to_root_db(
                     DbChangeOp::ZugriffGenehmigen {
-                        ids: data.daten.clone(),
+                        ids: ids.clone(),
                         email: benutzer.email.clone(),
-                        datum: chrono::Utc::now().to_rfc3339(),
+                        datum: jetzt.to_rfc3339(),
+                        gueltig_bis,
                     },
+                    &benutzer.email,
                     &app_state,
                 )
                 .await
                 .map_err(|e| KontoJsonPostResponseError { code: 500, text: e })?;
 
                 let benutzer_grouped_by_email =
-                    crate::db::get_benutzer_grouped_by_zugriff(data.daten.clone())
+                    crate::db::get_benutzer_grouped_by_zugriff(&konfiguration, ids)
                         .map_err(|e| KontoJsonPostResponseError { code: 500, text: e })?;
 
                 for (email, grundbuecher) in benutzer_grouped_by_email {
                     if let Err(e) = crate::email::send_zugriff_gewaehrt_email(
+                        &konfiguration,
                         &email,
                         &grundbuecher.zugriff_id,
                         &grundbuecher.grundbuecher,
@@ -889,58 +2145,323 @@ pub mod konto {
                     }
                 }
             }
-            ("admin", "zugriff-ablehnen") => {
+            "zugriff-ablehnen" => {
+                pruefen(Berechtigung::ZugriffeVerwalten)?;
+                if data.daten.is_empty() {
+                    return Err(KontoJsonPostResponseError {
+                        code: 2,
+                        text: format!("Keine Zugriffe ausgewählt"),
+                    });
+                }
+                let grund = data
+                    .grund
+                    .as_deref()
+                    .map(|g| g.trim().to_string())
+                    .filter(|g| !g.is_empty());
+                let ids = bearbeitbar(&konfiguration, &data.daten);
                 crate::api::write_to_root_db(
                     DbChangeOp::ZugriffAblehnen {
-                        ids: data.daten.clone(),
+                        ids: ids.clone(),
                         email: benutzer.email.clone(),
                         datum: chrono::Utc::now().to_rfc3339(),
+                        grund: grund.clone(),
                     },
+                    &benutzer.email,
                     &app_state,
                 )
                 .await
                 .map_err(|e| KontoJsonPostResponseError { code: 500, text: e })?;
 
-                // sende_email(&benutzer.email, zugriff_abgelehnt)
+                let benutzer_grouped_by_email =
+                    crate::db::get_benutzer_grouped_by_zugriff(&konfiguration, ids)
+                        .map_err(|e| KontoJsonPostResponseError { code: 500, text: e })?;
+
+                // Die Ablehnung ist bereits gespeichert, ein Fehler beim Senden
+                // wird daher nur protokolliert.
+                // SmtpTransport blockiert, daher nicht im async-Task selbst senden
+                let email_konfiguration = konfiguration.clone();
+                tokio::task::spawn_blocking(move || {
+                    for (email, grundbuecher) in benutzer_grouped_by_email {
+                        if let Err(e) = crate::email::send_zugriff_abgelehnt_email(
+                            &email_konfiguration,
+                            &email,
+                            &grundbuecher.grundbuecher,
+                            grund.as_deref(),
+                        ) {
+                            println!("Fehler beim Senden der Ablehnung an {email}: {e}");
+                        }
+                    }
+                });
             }
-            ("admin", "konfiguration-bearbeiten") => {
-                for pair in data.daten.chunks(2) {
-                    crate::api::write_to_root_db(
-                        DbChangeOp::BearbeiteEinstellung {
-                            id: pair[0].to_string(),
-                            neuer_wert: pair[1].to_string(),
-                        },
+            "zugriff-widerrufen" => {
+                pruefen(Berechtigung::ZugriffeVerwalten)?;
+                crate::api::write_to_root_db(
+                    DbChangeOp::ZugriffWiderrufen {
+                        ids: data.daten.clone(),
+                        email: benutzer.email.clone(),
+                        datum: chrono::Utc::now().to_rfc3339(),
+                    },
+                    &benutzer.email,
+                    &app_state,
+                )
+                .await
+                .map_err(|e| KontoJsonPostResponseError { code: 500, text: e })?;
+            }
+            "zugriff-gueltigkeit" => {
+                // daten: [gueltig_bis ("" = unbegrenzt), id, id, ...]
+                pruefen(Berechtigung::ZugriffeVerwalten)?;
+                let (datum, ids) = data.daten.split_first().ok_or(KontoJsonPostResponseError {
+                    code: 2,
+                    text: format!("Kein Ablaufdatum angegeben"),
+                })?;
+                let gueltig_bis = if datum.trim().is_empty() {
+                    None
+                } else {
+                    let d = crate::api_schluessel::gueltig_bis_parsen(datum.trim()).ok_or(
+                        KontoJsonPostResponseError {
+                            code: 2,
+                            text: format!("Ungültiges Ablaufdatum \"{datum}\""),
+                        },
+                    )?;
+                    Some(d.to_rfc3339())
+                };
+                crate::api::write_to_root_db(
+                    DbChangeOp::ZugriffGueltigkeit {
+                        ids: ids.to_vec(),
+                        gueltig_bis,
+                    },
+                    &benutzer.email,
+                    &app_state,
+                )
+                .await
+                .map_err(|e| KontoJsonPostResponseError { code: 500, text: e })?;
+            }
+            "konfiguration-bearbeiten" => {
+                pruefen(Berechtigung::KonfigurationBearbeiten)?;
+                for pair in data.daten.chunks(2) {
+                    crate::api::write_to_root_db(
+                        DbChangeOp::BearbeiteEinstellung {
+                            id: pair[0].to_string(),
+                            neuer_wert: pair[1].to_string(),
+                        },
+                        &benutzer.email,
                         &app_state,
                     )
                     .await
                     .map_err(|e| KontoJsonPostResponseError { code: 500, text: e })?;
                 }
             }
-            ("bearbeiter", "benutzer-bearbeite-pubkey") => {
-                let pubkey = data.daten.get(1).ok_or(KontoJsonPostResponseError {
+            "passwort-aendern" => {
+                let aktuelles_passwort = data.daten.get(0).ok_or(KontoJsonPostResponseError {
                     code: 500,
-                    text: "Benutzer PubKey ändern: kein PubKey".to_string(),
+                    text: "Passwort ändern: kein aktuelles Passwort angegeben".to_string(),
                 })?;
-
+                let neues_passwort = data.daten.get(1).ok_or(KontoJsonPostResponseError {
+                    code: 500,
+                    text: "Passwort ändern: kein neues Passwort angegeben".to_string(),
+                })?;
+                if neues_passwort.trim().is_empty() {
+                    return Err(KontoJsonPostResponseError {
+                        code: 2,
+                        text: "Passwort ändern: neues Passwort darf nicht leer sein".to_string(),
+                    });
+                }
+                crate::db::check_password(
+                    &konfiguration,
+                    crate::models::MountPoint::Local,
+                    &benutzer.email,
+                    aktuelles_passwort,
+                )
+                .map_err(|_| KontoJsonPostResponseError {
+                    code: 2,
+                    text: "Passwort ändern: aktuelles Passwort ist falsch".to_string(),
+                })?;
+                // Beendet auch alle Sitzungen, der Benutzer muss sich neu anmelden
                 crate::api::write_to_root_db(
-                    DbChangeOp::BenutzerAendernPubkey {
-                        id: benutzer.email.clone(),
-                        neuer_pubkey: pubkey.clone(),
+                    DbChangeOp::PasswortAendern {
+                        email: benutzer.email.clone(),
+                        passwort: neues_passwort.clone(),
+                    },
+                    &benutzer.email,
+                    &app_state,
+                )
+                .await
+                .map_err(|e| KontoJsonPostResponseError { code: 500, text: e })?;
+            }
+            "sitzungen-beenden" => {
+                crate::api::write_to_root_db(
+                    DbChangeOp::BenutzerSessionsBeenden {
+                        email: benutzer.email.clone(),
+                        ids: data.daten.clone(),
+                    },
+                    &benutzer.email,
+                    &app_state,
+                )
+                .await
+                .map_err(|e| KontoJsonPostResponseError { code: 500, text: e })?;
+            }
+            "zwei-faktor-einrichten" => {
+                if crate::zwei_faktor::get_zwei_faktor(&konfiguration, &benutzer).map_or(false, |z| z.aktiviert) {
+                    return Err(KontoJsonPostResponseError {
+                        code: 500,
+                        text: "Zwei-Faktor-Authentifizierung ist bereits aktiviert".to_string(),
+                    });
+                }
+                crate::api::write_to_root_db(
+                    DbChangeOp::ZweiFaktorEinrichten {
+                        email: benutzer.email.clone(),
+                        geheimnis: crate::zwei_faktor::geheimnis_generieren(),
+                    },
+                    &benutzer.email,
+                    &app_state,
+                )
+                .await
+                .map_err(|e| KontoJsonPostResponseError { code: 500, text: e })?;
+            }
+            "zwei-faktor-aktivieren" | "zwei-faktor-neue-codes" => {
+                let code = data.daten.get(0).ok_or(KontoJsonPostResponseError {
+                    code: 500,
+                    text: "Kein Code angegeben".to_string(),
+                })?;
+                let zf = crate::zwei_faktor::get_zwei_faktor(&konfiguration, &benutzer).ok_or(
+                    KontoJsonPostResponseError {
+                        code: 500,
+                        text: "Zwei-Faktor-Authentifizierung wurde noch nicht eingerichtet"
+                            .to_string(),
+                    },
+                )?;
+                // Bei neuen Codes auch mit Wiederherstellungscode möglich
+                let faktor = if zf.aktiviert {
+                    crate::zwei_faktor::zweiten_faktor_pruefen(&konfiguration, &benutzer, code).ok()
+                } else {
+                    crate::zwei_faktor::code_pruefen(
+                        &zf.geheimnis,
+                        code,
+                        chrono::Utc::now().timestamp(),
+                        zf.letzter_schritt,
+                    )
+                    .map(crate::zwei_faktor::ZweiterFaktor::Totp)
+                };
+                let faktor = faktor.ok_or(KontoJsonPostResponseError {
+                    code: 2,
+                    text: "Ungültiger Code".to_string(),
+                })?;
+                crate::zwei_faktor::zweiten_faktor_verbrauchen(&benutzer, faktor, &app_state)
+                    .await
+                    .map_err(|e| KontoJsonPostResponseError { code: 2, text: e })?;
+                let codes = crate::zwei_faktor::wiederherstellungscodes_generieren();
+                crate::api::write_to_root_db(
+                    DbChangeOp::ZweiFaktorAktivieren {
+                        email: benutzer.email.clone(),
+                        code_hashes: codes
+                            .iter()
+                            .map(|c| crate::zwei_faktor::wiederherstellungscode_hash(c))
+                            .collect(),
+                    },
+                    &benutzer.email,
+                    &app_state,
+                )
+                .await
+                .map_err(|e| KontoJsonPostResponseError { code: 500, text: e })?;
+                neue_wiederherstellungscodes = Some(codes);
+            }
+            "zwei-faktor-deaktivieren" => {
+                if crate::zwei_faktor::ist_pflicht(&konfiguration, &benutzer) {
+                    return Err(KontoJsonPostResponseError {
+                        code: 500,
+                        text: format!(
+                            "Für Benutzer mit Rechten \"{}\" ist die Zwei-Faktor-Authentifizierung vorgeschrieben",
+                            benutzer.rechte
+                        ),
+                    });
+                }
+                let aktiviert = crate::zwei_faktor::get_zwei_faktor(&konfiguration, &benutzer)
+                    .map_or(false, |z| z.aktiviert);
+                if aktiviert {
+                    let code = data.daten.get(0).map(|s| s.as_str()).unwrap_or_default();
+                    crate::zwei_faktor::zweiten_faktor_pruefen(&konfiguration, &benutzer, code)
+                        .map_err(|e| KontoJsonPostResponseError { code: 2, text: e })?;
+                }
+                crate::api::write_to_root_db(
+                    DbChangeOp::ZweiFaktorDeaktivieren {
+                        email: benutzer.email.clone(),
+                    },
+                    &benutzer.email,
+                    &app_state,
+                )
+                .await
+                .map_err(|e| KontoJsonPostResponseError { code: 500, text: e })?;
+            }
+            "benutzer-entsperren" => {
+                pruefen(Berechtigung::BenutzerVerwalten)?;
+                crate::api::write_to_root_db(
+                    DbChangeOp::KontenEntsperren {
+                        emails: data.daten.clone(),
+                    },
+                    &benutzer.email,
+                    &app_state,
+                )
+                .await
+                .map_err(|e| KontoJsonPostResponseError { code: 500, text: e })?;
+            }
+            "postausgang-erneut-senden" => {
+                pruefen(Berechtigung::AbosVerwalten)?;
+                crate::api::write_to_root_db(
+                    DbChangeOp::PostausgangErneutSenden {
+                        ids: data.daten.clone(),
+                        datum: chrono::Utc::now().to_rfc3339(),
+                    },
+                    &benutzer.email,
+                    &app_state,
+                )
+                .await
+                .map_err(|e| KontoJsonPostResponseError { code: 500, text: e })?;
+            }
+            "benutzer-zwei-faktor-zuruecksetzen" => {
+                pruefen(Berechtigung::BenutzerVerwalten)?;
+                benutzer
+                    .benutzer_bearbeiten_pruefen(&konfiguration, &data.daten)
+                    .map_err(|e| KontoJsonPostResponseError { code: 2, text: e })?;
+                for email in data.daten.iter() {
+                    crate::api::write_to_root_db(
+                        DbChangeOp::ZweiFaktorDeaktivieren {
+                            email: email.clone(),
+                        },
+                        &benutzer.email,
+                        &app_state,
+                    )
+                    .await
+                    .map_err(|e| KontoJsonPostResponseError { code: 500, text: e })?;
+                }
+            }
+            "api-schluessel-loeschen" => {
+                let email = if benutzer.hat_berechtigung(Berechtigung::BenutzerVerwalten) {
+                    None
+                } else {
+                    Some(benutzer.email.clone())
+                };
+                crate::api::write_to_root_db(
+                    DbChangeOp::ApiSchluesselLoeschen {
+                        email,
+                        ids: data.daten.clone(),
                     },
+                    &benutzer.email,
                     &app_state,
                 )
                 .await
                 .map_err(|e| KontoJsonPostResponseError { code: 500, text: e })?;
             }
-            (_, "blaetter-als-zip") => {
+            "blaetter-als-zip" => {
                 use std::path::Path;
+                use crate::einsicht::{EinsichtTyp, EinsichtZweck};
                 use crate::models::MountPoint;
 
-                let grundbuchblaetter = crate::db::get_verfuegbare_grundbuecher_fuer_benutzer(&benutzer)
+                let grundbuchblaetter = crate::db::get_verfuegbare_grundbuecher_fuer_benutzer(&konfiguration, &benutzer)
                 .map_err(|e| KontoJsonPostResponseError { code: 500, text: e })?;
                 println!("blaetter als zip!");
                 
                 let mut files = Vec::new();
+                let mut einsichten = Vec::new();
                 for l in data.daten.iter() {
                     let s = l.split("/").collect::<Vec<_>>();
                     let (la, ag, bez, blatt) = match s.chunks(4).next() {
@@ -950,8 +2471,9 @@ pub mod konto {
                     println!("testing {l:?}");
                     if grundbuchblaetter.iter().any(|(l, a, b, b2)| (l, a, b, b2) == (&la, &ag, &bez, &blatt)) {
                         println!("download blaetter als zip {:?}", s);
-                        match std::fs::read_to_string(Path::new(&get_data_dir(MountPoint::Local)).join(&la).join(&ag).join(&bez).join(format!("{bez}_{blatt}.gbx"))) {
+                        match std::fs::read_to_string(Path::new(&konfiguration.get_data_dir(MountPoint::Local)).join(&la).join(&ag).join(&bez).join(format!("{bez}_{blatt}.gbx"))) {
                             Ok(o) => {
+                                einsichten.push(EinsichtZweck::default().einsicht(EinsichtTyp::Zip, &la, &ag, &bez, &blatt));
                                 files.push((None, Path::new(&format!("/{la}/{ag}/{bez}/{blatt}.gbx")).to_path_buf(), o.as_bytes().to_vec()));
                                 if let Ok(f) = serde_json::from_str::<gbx::PdfFile>(&o) {
                                     let options = crate::pdf::PdfGrundbuchOptions {
@@ -971,11 +2493,15 @@ pub mod konto {
                         }
                     }
                 }
+                crate::api::einsichten_protokollieren(&benutzer, einsichten, &app_state);
+
                 let zip_data = crate::zip::write_files_to_zip(&files);
                 println!("zip data {}", zip_data.len());
                 return Ok(KontoDataPostType::Zip(zip_data))
             }
-            ("gast", "abo-neu") | ("bearbeiter", "abo-neu") => {
+            "abo-neu" => {
+                // Mit "Abonnements verwalten" auch für andere E-Mail-Adressen
+                let alle_abos = benutzer.hat_berechtigung(Berechtigung::AbosVerwalten);
 
                 let typ = match data.daten.get(0).map(|s| s.as_str()) {
                     Some("email") => "email",
@@ -984,12 +2510,18 @@ pub mod konto {
                 };
 
                 let text = match typ {
-                    "email" => benutzer.email.clone(),
-                    "webhook" => {
+                    "email" if alle_abos => {
                         data.daten.get(1)
-                        .and_then(|u| reqwest::Url::parse(u).ok())
                         .map(|u| u.to_string())
-                        .ok_or_else(|| KontoJsonPostResponseError { code: 500, text: "Keine Webhook-URL angegeben".to_string() })?
+                        .ok_or_else(|| KontoJsonPostResponseError { code: 500, text: "Keine E-Mail angegeben".to_string() })?
+                    },
+                    "email" => benutzer.email.clone(),
+                    "webhook" => {
+                        let url = data.daten.get(1)
+                        .ok_or_else(|| KontoJsonPostResponseError { code: 500, text: "Keine Webhook-URL angegeben".to_string() })?;
+                        crate::webhook::ziel_pruefen(&konfiguration, url).await
+                        .map_err(|e| KontoJsonPostResponseError { code: 2, text: e })?
+                        .to_string()
                     }
                     _ => return Err(KontoJsonPostResponseError { code: 500, text: "Ungültiger Typ for Abonnement".to_string() }),
                 };
@@ -1005,99 +2537,215 @@ pub mod konto {
 
                 let aktenzeichen = data.daten.get(5).map(|s| s.trim().to_string());
 
+                let detailstufe = data.daten.get(6)
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty());
+
+                // Filter, kommagetrennt (leer = alle)
+                let abteilungen = data.daten.get(7).map(|s| s.trim().to_string());
+                let aenderungsarten = data.daten.get(8).map(|s| s.trim().to_string());
+
+                // Gemarkung/Flur/Flurstück, nur mit Blatt "*"
+                let flurstueck = data.daten.get(9)
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty());
+
+                let webhook_secret = match typ {
+                    "webhook" => Some(crate::signatur::schluessel_generieren()),
+                    _ => None,
+                };
+
                 crate::api::write_to_root_db(
                     DbChangeOp::AboNeu(crate::AboNeuArgs { 
                         typ: typ.to_string(), 
                         blatt: format!("{ag}/{bezirk}/{blatt}"), 
                         text: text, 
                         aktenzeichen: aktenzeichen,
+                        besitzer: match typ {
+                            "webhook" => Some(benutzer.email.clone()),
+                            _ => None,
+                        },
+                        webhook_secret: webhook_secret.clone(),
+                        detailstufe: detailstufe,
+                        abteilungen: abteilungen,
+                        aenderungsarten: aenderungsarten,
+                        flurstueck: flurstueck,
+                        id: None,
                     }),
+                    &benutzer.email,
                     &app_state,
                 )
                 .await
                 .map_err(|e| KontoJsonPostResponseError { code: 500, text: e })?;
+
+                neues_webhook_secret = webhook_secret;
             },
-            ("admin", "abo-neu") => {
-                
-                let typ = match data.daten.get(0).map(|s| s.as_str()) {
-                    Some("email") => "email",
-                    Some("webhook") => "webhook",
-                    _ => return Err(KontoJsonPostResponseError { code: 500, text: "Ungültiger Typ for Abonnement".to_string() }),
-                };
+            "abo-benachrichtigung-aendern" => {
+                // daten = [sofort | taeglich | woechentlich], gilt für alle E-Mail-Abonnements
+                let benachrichtigung = data
+                    .daten
+                    .get(0)
+                    .and_then(|s| crate::models::AboBenachrichtigung::from_str(s))
+                    .ok_or(KontoJsonPostResponseError {
+                        code: 2,
+                        text: "Ungültige Benachrichtigung (erlaubt: sofort, taeglich, woechentlich)".to_string(),
+                    })?;
 
-                let text = match typ {
-                    "email" => {
-                        data.daten.get(1)
-                        .map(|u| u.to_string())
-                        .ok_or_else(|| KontoJsonPostResponseError { code: 500, text: "Keine E-Mail angegeben".to_string() })?
+                crate::api::write_to_root_db(
+                    DbChangeOp::BenutzerEinstellungSetzen {
+                        benutzer_id: benutzer.id,
+                        einstellung: crate::email::ABO_BENACHRICHTIGUNG_EINSTELLUNG.to_string(),
+                        wert: benachrichtigung.as_str().to_string(),
                     },
-                    "webhook" => {
-                        data.daten.get(1)
-                        .and_then(|u| reqwest::Url::parse(u).ok())
-                        .map(|u| u.to_string())
-                        .ok_or_else(|| KontoJsonPostResponseError { code: 500, text: "Keine Webhook-URL angegeben".to_string() })?
-                    }
-                    _ => return Err(KontoJsonPostResponseError { code: 500, text: "Ungültiger Typ for Abonnement".to_string() }),
-                };
-
-                let ag = data.daten.get(2).map(|s| s.trim().to_string())
-                .ok_or_else(|| KontoJsonPostResponseError { code: 500, text: "Kein Amtsgericht angegeben".to_string() })?;
-
-                let bezirk = data.daten.get(3).map(|s| s.trim().to_string())
-                .ok_or_else(|| KontoJsonPostResponseError { code: 500, text: "Kein Grundbuchbezirk angegeben".to_string() })?;
-
-                let blatt = data.daten.get(4).map(|s| s.trim().to_string())
-                .ok_or_else(|| KontoJsonPostResponseError { code: 500, text: "Keine Blattnr. angegeben (* = alle Blätter)".to_string() })?;
-
-                let aktenzeichen = data.daten.get(5).map(|s| s.trim().to_string());
-
-                let args = crate::AboNeuArgs { 
-                    typ: typ.to_string(), 
-                    blatt: format!("{ag}/{bezirk}/{blatt}"), 
-                    text: text, 
-                    aktenzeichen: aktenzeichen,
+                    &benutzer.email,
+                    &app_state,
+                )
+                .await
+                .map_err(|e| KontoJsonPostResponseError { code: 500, text: e })?;
+            },
+            "abo-detailstufe-aendern" => {
+                // daten = [detailstufe, abo_id, ...], ohne "Abonnements verwalten" nur die eigenen
+                let detailstufe = data.daten.get(0).ok_or(KontoJsonPostResponseError {
+                    code: 2,
+                    text: "Keine Detailstufe angegeben".to_string(),
+                })?;
+                let abos = if benutzer.hat_berechtigung(Berechtigung::AbosVerwalten) {
+                    None
+                } else {
+                    Some(crate::db::get_abos_fuer_benutzer(&konfiguration, &benutzer).unwrap_or_default()
+                    .into_iter().map(|s| s.id).collect::<std::collections::BTreeSet<_>>())
                 };
-
-                println!("abo neu {args:?}");
+                let ids = data.daten.iter().skip(1)
+                .filter(|id| abos.as_ref().map_or(true, |a| a.contains(*id)))
+                .cloned()
+                .collect::<Vec<_>>();
 
                 crate::api::write_to_root_db(
-                    DbChangeOp::AboNeu(args),
+                    DbChangeOp::AboDetailstufeAendern {
+                        ids,
+                        detailstufe: detailstufe.trim().to_string(),
+                    },
+                    &benutzer.email,
                     &app_state,
                 )
                 .await
-                .map_err(|e| KontoJsonPostResponseError { code: 500, text: e })?;
+                .map_err(|e| KontoJsonPostResponseError { code: 2, text: e })?;
             },
-            ("gast", "abo-loeschen") | ("bearbeiter", "abo-loeschen") => {
-                let abos = crate::db::get_abos_fuer_benutzer(&benutzer).unwrap_or_default()
-                .into_iter().map(|s| s.id).collect::<std::collections::BTreeSet<_>>();
+            "abo-webhook-secret-erneuern" | "abo-webhook-testen" => {
+                // Ohne "Abonnements verwalten" nur die eigenen Abonnements
+                let abo_id = data.daten.get(0).ok_or(KontoJsonPostResponseError {
+                    code: 2,
+                    text: "Kein Abonnement ausgewählt".to_string(),
+                })?;
+                let abo = crate::db::get_abo(&konfiguration, abo_id)
+                    .map_err(|e| KontoJsonPostResponseError { code: 500, text: e })?
+                    .filter(|a| {
+                        a.gehoert_zu(&benutzer)
+                            || benutzer.hat_berechtigung(Berechtigung::AbosVerwalten)
+                    })
+                    .ok_or(KontoJsonPostResponseError {
+                        code: 2,
+                        text: format!("Abonnement {abo_id} nicht gefunden"),
+                    })?;
 
-                for abo_id in data.daten.iter() {
-                    if !abos.contains(abo_id) {
-                        continue;
-                    }
+                if abo.typ != "webhook" {
+                    return Err(KontoJsonPostResponseError {
+                        code: 2,
+                        text: "Nur für Webhook-Abonnements möglich".to_string(),
+                    });
+                }
+
+                if data.aktion == "abo-webhook-testen" {
+                    let zustellungs_id = crate::email::send_test_webhook(&konfiguration, &abo.info)
+                        .await
+                        .map_err(|e| KontoJsonPostResponseError {
+                            code: 2,
+                            text: format!("Testzustellung an {} fehlgeschlagen: {e}", abo.info.text),
+                        })?;
+                    webhook_testzustellung = Some(zustellungs_id);
+                } else {
+                    let webhook_secret = crate::signatur::schluessel_generieren();
                     crate::api::write_to_root_db(
-                        DbChangeOp::AboLoeschen(crate::AboLoeschenArgs {
+                        DbChangeOp::AboWebhookSecretErneuern {
                             id: abo_id.clone(),
-                        }),
+                            webhook_secret: webhook_secret.clone(),
+                        },
+                        &benutzer.email,
                         &app_state,
                     )
                     .await
-                    .map_err(|e| KontoJsonPostResponseError { code: 500, text: e })?;
+                    .map_err(|e| KontoJsonPostResponseError { code: 2, text: e })?;
+                    neues_webhook_secret = Some(webhook_secret);
                 }
             },
-            ("admin", "abo-loeschen") => {
-                println!("admin abo loeschen {:#?}", data.daten);
+            "abo-loeschen" => {
+                // Ohne "Abonnements verwalten" nur die eigenen Abonnements
+                let abos = if benutzer.hat_berechtigung(Berechtigung::AbosVerwalten) {
+                    None
+                } else {
+                    Some(crate::db::get_abos_fuer_benutzer(&konfiguration, &benutzer).unwrap_or_default()
+                    .into_iter().map(|s| s.id).collect::<std::collections::BTreeSet<_>>())
+                };
+
                 for abo_id in data.daten.iter() {
+                    if abos.as_ref().map_or(false, |a| !a.contains(abo_id)) {
+                        continue;
+                    }
                     crate::api::write_to_root_db(
                         DbChangeOp::AboLoeschen(crate::AboLoeschenArgs {
                             id: abo_id.clone(),
                         }),
+                        &benutzer.email,
                         &app_state,
                     )
                     .await
                     .map_err(|e| KontoJsonPostResponseError { code: 500, text: e })?;
                 }
             },
+            "rolle-speichern" => {
+                pruefen(Berechtigung::RollenVerwalten)?;
+                // daten = [name, bezeichnung, berechtigung, ...]
+                let name = data.daten.get(0).ok_or(KontoJsonPostResponseError {
+                    code: 500,
+                    text: "Rolle speichern: kein Name angegeben".to_string(),
+                })?;
+                let bezeichnung = data.daten.get(1).cloned().unwrap_or_default();
+                let mut berechtigungen = std::collections::BTreeSet::new();
+                for b in data.daten.iter().skip(2).filter(|b| !b.trim().is_empty()) {
+                    berechtigungen.insert(Berechtigung::from_str(b).ok_or(
+                        KontoJsonPostResponseError {
+                            code: 2,
+                            text: format!("Rolle speichern: Unbekannte Berechtigung \"{b}\""),
+                        },
+                    )?);
+                }
+                let rolle = crate::rollen::Rolle {
+                    name: name.trim().to_string(),
+                    bezeichnung: bezeichnung.trim().to_string(),
+                    berechtigungen,
+                };
+                super::rollen::rolle_speichern_pruefen(&konfiguration, &benutzer, &rolle)
+                    .map_err(|e| KontoJsonPostResponseError { code: 2, text: e })?;
+                crate::api::write_to_root_db(
+                    DbChangeOp::RolleSpeichern(rolle),
+                    &benutzer.email,
+                    &app_state,
+                )
+                .await
+                .map_err(|e| KontoJsonPostResponseError { code: 2, text: e })?;
+            },
+            "rolle-loeschen" => {
+                pruefen(Berechtigung::RollenVerwalten)?;
+                crate::api::write_to_root_db(
+                    DbChangeOp::RollenLoeschen {
+                        namen: data.daten.clone(),
+                    },
+                    &benutzer.email,
+                    &app_state,
+                )
+                .await
+                .map_err(|e| KontoJsonPostResponseError { code: 2, text: e })?;
+            },
             _ => {
                 return Err(KontoJsonPostResponseError {
                     code: 500,
@@ -1107,28 +2755,57 @@ pub mod konto {
         }
 
         let konto_data =
-            crate::db::get_konto_data(&benutzer).unwrap_or(KontoDataResult::KeinPasswort);
+            crate::db::get_konto_data(&konfiguration, &benutzer).unwrap_or(KontoDataResult::KeinPasswort);
 
-        let konto_data = match konto_data {
+        let mut konto_data = match konto_data {
             KontoDataResult::Aktiviert(a) => a,
             KontoDataResult::KeinPasswort => KontoData::default(),
         };
 
+        if let Some(codes) = neue_wiederherstellungscodes {
+            if let Some(t) = konto_data.data.get_mut("zwei-faktor") {
+                t.insert_object("wiederherstellungscodes", codes);
+            }
+        }
+
+        if let Some(secret) = neues_webhook_secret {
+            if let Some(t) = konto_data.data.get_mut("abonnements") {
+                t.insert_object("webhook_secret", secret);
+            }
+        }
+
+        if let Some(zustellungs_id) = webhook_testzustellung {
+            if let Some(t) = konto_data.data.get_mut("abonnements") {
+                t.insert_object("webhook_testzustellung", zustellungs_id);
+            }
+        }
+
         Ok(KontoDataPostType::Json(konto_data))
     }
 
     #[post("/konto-neu")]
     async fn konto_post_neu(
-        _: HttpRequest,
+        req: HttpRequest,
         state: web::Data<AppState>,
         data: web::Json<KontoNeuJsonPost>,
     ) -> impl Responder {
-        let result = match konto_post_neu_inner(&*state, &*data).await {
-            Ok(s) => KontoNeuPostResponse::Ok(KontoNeuJsonResponseOk { token: s }),
+        let beschreibung = crate::api::login::get_session_beschreibung(&req);
+        let ip = crate::ratenbegrenzung::get_ip(&state.konfiguration(), &req);
+        let mut antwort = HttpResponse::Ok();
+        let result = match konto_post_neu_inner(&*state, &*data, &beschreibung, &ip).await {
+            Ok(o) => {
+                for cookie in crate::api::login::sitzung_cookies(&o.token, &o.refresh_token) {
+                    antwort.cookie(cookie);
+                }
+                KontoNeuPostResponse::Ok(KontoNeuJsonResponseOk {
+                    token: o.token,
+                    refresh_token: o.refresh_token,
+                })
+            }
             Err(e) => KontoNeuPostResponse::Error(e),
         };
 
-        HttpResponse::Ok()
+        antwort
             .content_type("application/json")
             .body(serde_json::to_string(&result).unwrap_or_default())
     }
@@ -1136,10 +2813,13 @@ pub mod konto {
     async fn konto_post_neu_inner(
         app_state: &AppState,
         data: &KontoNeuJsonPost,
-    ) -> Result<String, KontoJsonPostResponseError> {
+        beschreibung: &str,
+        ip: &str,
+    ) -> Result<LoginResponseOk, KontoJsonPostResponseError> {
         use crate::api::commit::DbChangeOp;
 
-        let benutzer_exists = crate::db::zugriff_benutzer_exists(&data.zugriff)
+        let konfiguration = app_state.konfiguration();
+        let benutzer_exists = crate::db::zugriff_benutzer_exists(&konfiguration, &data.zugriff)
             .map_err(|e| KontoJsonPostResponseError { code: 500, text: e })?;
 
         if benutzer_exists {
@@ -1149,7 +2829,7 @@ pub mod konto {
             });
         }
 
-        let zugriff = crate::db::get_benutzer_grouped_by_zugriff(vec![data.zugriff.clone()])
+        let zugriff = crate::db::get_benutzer_grouped_by_zugriff(&konfiguration, vec![data.zugriff.clone()])
             .map_err(|e| KontoJsonPostResponseError { code: 500, text: e })?;
 
         let (email, benutzer) = zugriff.iter().next().ok_or(KontoJsonPostResponseError {
@@ -1162,13 +2842,20 @@ pub mod konto {
                 email: email.clone(),
                 passwort: data.passwort.clone(),
             },
+            email,
             &app_state,
         )
         .await
         .map_err(|e| KontoJsonPostResponseError { code: 500, text: e })?;
 
-        match crate::api::login::login_json(email, &data.passwort, app_state).await {
-            LoginResponse::Ok(o) => Ok(o.token),
+        match crate::api::login::login_json(email, &data.passwort, None, beschreibung, ip, app_state)
+            .await
+        {
+            LoginResponse::Ok(o) => Ok(o),
+            LoginResponse::ZweiFaktor(z) => Err(KontoJsonPostResponseError {
+                code: 2,
+                text: z.text,
+            }),
             LoginResponse::Error(e) => Err(KontoJsonPostResponseError {
                 code: e.code,
                 text: e.text,
@@ -1190,17 +2877,21 @@ pub mod commit {
 
     use super::{
         pull::PullResponse,
-        upload::{commit_changes, sync_changes_to_disk, verify_signature, UploadChangeset},
+        upload::{
+            api_bereiche_pruefen, commit_changes, sync_changes_to_disk, verify_signature,
+            UploadChangeset,
+        },
     };
     use crate::api::index::ZugriffTyp;
-    use crate::models::{get_data_dir, get_db_path, MountPoint};
+    use crate::api_schluessel::{ApiSchluesselNeu, ApiScope};
+    use crate::config::Konfiguration;
+    use crate::db::SessionNeu;
+    use crate::models::MountPoint;
     use crate::{
         AboLoeschenArgs, AboNeuArgs, AppState, BenutzerLoeschenArgs, BenutzerNeuArgsJson,
         BezirkLoeschenArgs, BezirkNeuArgs, BezirkeLoeschenArgs, BezirkeNeuArgs,
     };
     use actix_web::{post, web, HttpRequest, HttpResponse, Responder};
-    use chrono::{DateTime, Utc};
-    use rusqlite::Connection;
     use serde_derive::{Deserialize, Serialize};
     use std::path::Path;
 
@@ -1245,6 +2936,7 @@ pub mod commit {
         req: &HttpRequest,
     ) -> Result<HttpResponse, HttpResponse> {
 
+        let konfiguration = app_state.konfiguration();
         println!("commit_internal");
 
         let response_err = |code: usize, text: String| {
@@ -1265,14 +2957,15 @@ pub mod commit {
         };
 
         let upload_changeset = &*upload_changeset;
-        let (token, benutzer) = super::get_benutzer_from_httpauth(&req).await?;
-        let zugriffe = crate::db::get_zugriffe(&benutzer).unwrap_or_default();
-        let hat_schreibzugriff = benutzer.rechte == "admin" || benutzer.rechte == "bearbeiter";
-        if !hat_schreibzugriff {
-            return Err(response_err(501, format!("Benutzer hat keine Schreibrechte")));
-        }
+        let (token, benutzer) = super::get_benutzer_from_httpauth_scope(&konfiguration, &req, ApiScope::Upload).await?;
+        let zugriffe = crate::db::get_zugriffe(&konfiguration, &benutzer).unwrap_or_default();
+        benutzer
+            .berechtigung_pruefen(crate::rollen::Berechtigung::GrundbuchBearbeiten)
+            .map_err(|e| response_err(501, e))?;
 
-        verify_signature(&benutzer.email, &upload_changeset).map_err(|e| {
+        api_bereiche_pruefen(&benutzer, upload_changeset).map_err(|e| response_err(403, e))?;
+
+        verify_signature(&konfiguration, &benutzer.email, &upload_changeset).map_err(|e| {
             response_err(
                 501,
                 format!("Fehler bei Überprüfung der digitalen Signatur:\r\n{e}"),
@@ -1280,8 +2973,8 @@ pub mod commit {
         })?;
 
         if app_state.k8s_aktiv() && app_state.sync_server() {
-            let remote_path = Path::new(&get_data_dir(MountPoint::Remote)).to_path_buf();
-            sync_changes_to_disk(&zugriffe, &upload_changeset, &remote_path)?;
+            let remote_path = Path::new(&konfiguration.get_data_dir(MountPoint::Remote)).to_path_buf();
+            sync_changes_to_disk(&konfiguration, &zugriffe, &upload_changeset, &remote_path)?;
             commit_changes(&app_state, &remote_path, &benutzer, &upload_changeset)
                 .await
                 .map_err(|e| {
@@ -1297,7 +2990,11 @@ pub mod commit {
                 }
                 let client = reqwest::Client::new();
                 let res = client
-                    .post(&format!("http://{}:8081/pull", peer.ip))
+                    .post(&format!(
+                        "http://{}:{}/pull",
+                        peer.ip,
+                        konfiguration.ports.intern
+                    ))
                     .body("")
                     .send()
                     .await;
@@ -1338,8 +3035,8 @@ pub mod commit {
             }
         } else {
             println!("local...");
-            let local_path = Path::new(&get_data_dir(MountPoint::Local)).to_path_buf();
-            sync_changes_to_disk(&zugriffe, &upload_changeset, &local_path)?;
+            let local_path = Path::new(&konfiguration.get_data_dir(MountPoint::Local)).to_path_buf();
+            sync_changes_to_disk(&konfiguration, &zugriffe, &upload_changeset, &local_path)?;
             println!("changes synced!");
             let _ = commit_changes(&app_state, &local_path, &benutzer, &upload_changeset).await;
             println!("committed!");
@@ -1352,14 +3049,14 @@ pub mod commit {
             return Ok(response_ok());
         }
 
-        let conn = Connection::open(&get_db_path(MountPoint::Local))
+        let conn = crate::db::verbindung_oeffnen(&konfiguration, MountPoint::Local)
             .map_err(|e| response_err(500, format!("{e}")))?;
 
         let mut stmt = conn.prepare(
-            "INSERT INTO grundbuecher (land, amtsgericht, bezirk, blatt) VALUES (?1, ?2, ?3, ?4)"
+            "INSERT OR IGNORE INTO grundbuecher (land, amtsgericht, bezirk, blatt) VALUES (?1, ?2, ?3, ?4)"
         ).map_err(|e| response_err(500, format!("{e}")))?;
 
-        let gemarkungsbezirke = crate::db::get_gemarkungen().unwrap_or_default();
+        let gemarkungsbezirke = crate::db::get_gemarkungen(&konfiguration).unwrap_or_default();
         println!("inserting grundbuchblätter {:#?}", upload_changeset.data.neu);
 
         for neu in upload_changeset.data.neu.iter() {
@@ -1423,6 +3120,16 @@ pub mod commit {
             passwort: String,
             email: String,
         },
+        PasswortResetNeu {
+            email: String,
+            token_hash: String,
+            gueltig_bis: String,
+        },
+        PasswortZuruecksetzen {
+            email: String,
+            token_hash: String,
+            passwort: String,
+        },
         BezirkNeu(BezirkNeuArgs),
         // Mehrere Bezirke gleichzeitig mit Datenbank abgleichen
         BezirkeNeu(BezirkeNeuArgs),
@@ -1431,37 +3138,371 @@ pub mod commit {
         BezirkeLoeschen(BezirkeLoeschenArgs),
         AboNeu(AboNeuArgs),
         AboLoeschen(AboLoeschenArgs),
-        CreateZugriff {
+        // Neues Secret für die Signatur von Webhook-Zustellungen
+        AboWebhookSecretErneuern {
             id: String,
-            name: String,
-            email: String,
-            typ: ZugriffTyp,
-            grund: String,
+            webhook_secret: String,
+        },
+        AboDetailstufeAendern {
+            ids: Vec<String>,
+            detailstufe: String,
+        },
+        // Aktenzeichen ("" = keins) und / oder Pausierung, None = unverändert
+        AboAendern {
+            id: String,
+            aktenzeichen: Option<String>,
+            pausiert: Option<bool>,
+        },
+        CreateZugriff {
+            id: String,
+            name: String,
+            email: String,
+            typ: ZugriffTyp,
+            grund: String,
             datum: String,
             land: String,
             amtsgericht: String,
             bezirk: String,
             blatt: String,
+            #[serde(default)]
+            blatt_bis: Option<String>,
+            #[serde(default)]
+            anfrage_id: Option<String>,
+            /// Zeitpunkt der Bestätigung der E-Mail-Adresse, `None` = unbestätigt
+            #[serde(default)]
+            bestaetigt_am: Option<String>,
+        },
+        // Antragsteller bestätigt seine E-Mail-Adresse über den signierten Link
+        ZugriffeBestaetigen {
+            anfrage_id: String,
+            datum: String,
+        },
+        // Antragsteller zieht unbearbeitete Anfragen über die Statusseite zurück
+        ZugriffeZurueckziehen {
+            anfrage_id: String,
+            ids: Vec<String>,
+            datum: String,
         },
         ZugriffGenehmigen {
             ids: Vec<String>,
             email: String,
             datum: String,
+            #[serde(default)]
+            gueltig_bis: Option<String>,
         },
         ZugriffAblehnen {
             ids: Vec<String>,
             email: String,
             datum: String,
+            #[serde(default)]
+            grund: Option<String>,
+        },
+        // Administratoren wurden über neue Zugriffsanfragen benachrichtigt
+        ZugriffeAdminBenachrichtigt {
+            ids: Vec<String>,
+            datum: String,
+        },
+        ZugriffWiderrufen {
+            ids: Vec<String>,
+            email: String,
+            datum: String,
+        },
+        ZugriffGueltigkeit {
+            ids: Vec<String>,
+            gueltig_bis: Option<String>,
         },
         BenutzerSessionNeu {
             email: String,
-            token: String,
-            gueltig_bis: DateTime<Utc>,
+            session: SessionNeu,
+            beschreibung: String,
+        },
+        BenutzerSessionErneuern {
+            email: String,
+            refresh_token_hash_alt: String,
+            session: SessionNeu,
+        },
+        BenutzerSessionsBeenden {
+            email: String,
+            ids: Vec<String>,
+        },
+        BenutzerSessionAbmelden {
+            email: String,
+            token_hash: String,
+        },
+        ApiSchluesselNeu {
+            email: String,
+            schluessel: ApiSchluesselNeu,
+        },
+        // email = None: Administrator löscht Schlüssel beliebiger Benutzer
+        ApiSchluesselLoeschen {
+            email: Option<String>,
+            ids: Vec<String>,
+        },
+        ApiSchluesselBenutzt {
+            id: String,
+            zeitpunkt: String,
+        },
+        ZweiFaktorEinrichten {
+            email: String,
+            geheimnis: String,
+        },
+        // Aktiviert 2FA und ersetzt die Wiederherstellungscodes
+        ZweiFaktorAktivieren {
+            email: String,
+            code_hashes: Vec<String>,
+        },
+        ZweiFaktorDeaktivieren {
+            email: String,
+        },
+        WiederherstellungscodeBenutzen {
+            email: String,
+            code_hash: String,
+        },
+        /// Zeitschritt des zuletzt akzeptierten TOTP-Codes
+        TotpSchrittBenutzen {
+            email: String,
+            schritt: i64,
+        },
+        // sperren = true: Konto wird gesperrt (gesperrt_bis = None: bis zur Entsperrung)
+        FehlanmeldungProtokollieren {
+            email: String,
+            ip: String,
+            zeitpunkt: String,
+            sperren: bool,
+            gesperrt_bis: Option<String>,
+        },
+        FehlanmeldungenZuruecksetzen {
+            email: String,
+        },
+        KontenEntsperren {
+            emails: Vec<String>,
+        },
+        RolleSpeichern(crate::rollen::Rolle),
+        RollenLoeschen {
+            namen: Vec<String>,
+        },
+        /// Benutzer nach Anmeldung über OpenID Connect verknüpfen bzw. anlegen
+        OidcBenutzerAnmelden {
+            email: String,
+            name: String,
+            rechte: String,
+            subjekt: String,
+        },
+        ZugriffAnfrageProtokollieren {
+            email: String,
+            ip: String,
+            zeitpunkt: String,
+            anzahl: usize,
+        },
+        PasswortVergessenProtokollieren {
+            email: String,
+            ip: String,
+            zeitpunkt: String,
         },
         BearbeiteEinstellung {
             id: String,
             neuer_wert: String,
         },
+        /// Einstellung eines einzelnen Benutzers (wird ggf. angelegt)
+        BenutzerEinstellungSetzen {
+            benutzer_id: i32,
+            einstellung: String,
+            wert: String,
+        },
+        /// Gesammelte Einsichten aus der lokalen Warteschlange eines Pods
+        EinsichtenProtokollieren {
+            eintraege: Vec<crate::einsicht::EinsichtenNeu>,
+        },
+        /// Benachrichtigungen an Abonnenten, werden im Hintergrund gesendet
+        PostausgangEinreihen {
+            eintraege: Vec<crate::postausgang::PostausgangNeu>,
+            datum: String,
+        },
+        PostausgangErneutSenden {
+            ids: Vec<String>,
+            datum: String,
+        },
+        /// Ergebnis eines Sendeversuchs aus dem Hintergrund-Task
+        PostausgangErgebnisSpeichern {
+            ids: Vec<String>,
+            status: String,
+            versuche: u32,
+            naechster_versuch: String,
+            letzter_fehler: Option<String>,
+            gesendet_am: Option<String>,
+        },
+    }
+
+    /// Body von `/db`: Änderung + auslösender Benutzer (für das Audit-Log)
+    ///
+    /// Der Akteur wird ungeprüft übernommen, `/db` ist deshalb nur auf dem
+    /// internen Port erreichbar und wird nur von den Pods selbst aufgerufen.
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    pub(crate) struct DbChangeAnfrage {
+        pub akteur: String,
+        pub op: DbChangeOp,
+    }
+
+    impl DbChangeOp {
+        /// Name der Operation im Audit-Log
+        pub(crate) fn get_operation(&self) -> &'static str {
+            match self {
+                DbChangeOp::BenutzerNeu(_) => "benutzer-neu",
+                DbChangeOp::BenutzerAendernRechte { .. } => "benutzer-aendern-rechte",
+                DbChangeOp::BenutzerAendernPubkey { .. } => "benutzer-aendern-pubkey",
+                DbChangeOp::BenutzerLoeschen(_) => "benutzer-loeschen",
+                DbChangeOp::PasswortAendern { .. } => "passwort-aendern",
+                DbChangeOp::PasswortResetNeu { .. } => "passwort-reset-neu",
+                DbChangeOp::PasswortZuruecksetzen { .. } => "passwort-zuruecksetzen",
+                DbChangeOp::BezirkNeu(_) => "bezirk-neu",
+                DbChangeOp::BezirkeNeu(_) => "bezirke-neu",
+                DbChangeOp::BezirkLoeschen(_) => "bezirk-loeschen",
+                DbChangeOp::BezirkeLoeschen(_) => "bezirke-loeschen",
+                DbChangeOp::AboNeu(_) => "abo-neu",
+                DbChangeOp::AboLoeschen(_) => "abo-loeschen",
+                DbChangeOp::AboWebhookSecretErneuern { .. } => "abo-webhook-secret-erneuern",
+                DbChangeOp::AboDetailstufeAendern { .. } => "abo-detailstufe-aendern",
+                DbChangeOp::AboAendern { .. } => "abo-aendern",
+                DbChangeOp::CreateZugriff { .. } => "zugriff-neu",
+                DbChangeOp::ZugriffGenehmigen { .. } => "zugriff-genehmigen",
+                DbChangeOp::ZugriffAblehnen { .. } => "zugriff-ablehnen",
+                DbChangeOp::ZugriffeAdminBenachrichtigt { .. } => "zugriffe-admin-benachrichtigt",
+                DbChangeOp::ZugriffeBestaetigen { .. } => "zugriffe-bestaetigen",
+                DbChangeOp::ZugriffeZurueckziehen { .. } => "zugriffe-zurueckziehen",
+                DbChangeOp::ZugriffWiderrufen { .. } => "zugriff-widerrufen",
+                DbChangeOp::ZugriffGueltigkeit { .. } => "zugriff-gueltigkeit",
+                DbChangeOp::BenutzerSessionNeu { .. } => "benutzer-session-neu",
+                DbChangeOp::BenutzerSessionErneuern { .. } => "benutzer-session-erneuern",
+                DbChangeOp::BenutzerSessionsBeenden { .. } => "benutzer-sessions-beenden",
+                DbChangeOp::BenutzerSessionAbmelden { .. } => "benutzer-session-abmelden",
+                DbChangeOp::ApiSchluesselNeu { .. } => "api-schluessel-neu",
+                DbChangeOp::ApiSchluesselLoeschen { .. } => "api-schluessel-loeschen",
+                DbChangeOp::ApiSchluesselBenutzt { .. } => "api-schluessel-benutzt",
+                DbChangeOp::ZweiFaktorEinrichten { .. } => "zwei-faktor-einrichten",
+                DbChangeOp::ZweiFaktorAktivieren { .. } => "zwei-faktor-aktivieren",
+                DbChangeOp::ZweiFaktorDeaktivieren { .. } => "zwei-faktor-deaktivieren",
+                DbChangeOp::WiederherstellungscodeBenutzen { .. } => {
+                    "wiederherstellungscode-benutzen"
+                }
+                DbChangeOp::TotpSchrittBenutzen { .. } => "totp-schritt-benutzen",
+                DbChangeOp::FehlanmeldungProtokollieren { sperren: true, .. } => "konto-sperren",
+                DbChangeOp::FehlanmeldungProtokollieren { .. } => "fehlanmeldung",
+                DbChangeOp::FehlanmeldungenZuruecksetzen { .. } => {
+                    "fehlanmeldungen-zuruecksetzen"
+                }
+                DbChangeOp::KontenEntsperren { .. } => "konten-entsperren",
+                DbChangeOp::RolleSpeichern(_) => "rolle-speichern",
+                DbChangeOp::RollenLoeschen { .. } => "rollen-loeschen",
+                DbChangeOp::OidcBenutzerAnmelden { .. } => "oidc-benutzer-anmelden",
+                DbChangeOp::ZugriffAnfrageProtokollieren { .. } => {
+                    "zugriff-anfrage-protokollieren"
+                }
+                DbChangeOp::PasswortVergessenProtokollieren { .. } => {
+                    "passwort-vergessen-protokollieren"
+                }
+                DbChangeOp::BearbeiteEinstellung { .. } => "einstellung-bearbeiten",
+                DbChangeOp::BenutzerEinstellungSetzen { .. } => "benutzer-einstellung-setzen",
+                DbChangeOp::EinsichtenProtokollieren { .. } => "einsichten-protokollieren",
+                DbChangeOp::PostausgangEinreihen { .. } => "postausgang-einreihen",
+                DbChangeOp::PostausgangErneutSenden { .. } => "postausgang-erneut-senden",
+                DbChangeOp::PostausgangErgebnisSpeichern { .. } => "postausgang-ergebnis-speichern",
+            }
+        }
+
+        /// Objekt, auf das sich die Operation bezieht (E-Mail, Blatt, ID, ...)
+        pub(crate) fn get_ziel(&self) -> String {
+            match self {
+                DbChangeOp::BenutzerNeu(un) => un.email.clone(),
+                DbChangeOp::BenutzerAendernRechte { ids, neue_rechte } => {
+                    format!("{} => {neue_rechte}", ids.join(", "))
+                }
+                DbChangeOp::BenutzerAendernPubkey { id, .. } => id.clone(),
+                DbChangeOp::BenutzerLoeschen(ul) => ul.email.clone(),
+                DbChangeOp::PasswortAendern { email, .. } => email.clone(),
+                DbChangeOp::PasswortResetNeu { email, .. } => email.clone(),
+                DbChangeOp::PasswortZuruecksetzen { email, .. } => email.clone(),
+                DbChangeOp::BezirkNeu(bn) => {
+                    format!("{}/{}/{}", bn.land, bn.amtsgericht, bn.bezirk)
+                }
+                DbChangeOp::BezirkeNeu(b) => format!("{} Bezirke", b.bezirke.len()),
+                DbChangeOp::BezirkLoeschen(bl) => {
+                    format!("{}/{}/{}", bl.land, bl.amtsgericht, bl.bezirk)
+                }
+                DbChangeOp::BezirkeLoeschen(b) => b.ids.join(", "),
+                DbChangeOp::AboNeu(an) => format!("{}: {}", an.text, an.blatt),
+                DbChangeOp::AboLoeschen(al) => al.id.clone(),
+                DbChangeOp::AboWebhookSecretErneuern { id, .. } => id.clone(),
+                DbChangeOp::AboDetailstufeAendern { ids, .. } => ids.join(", "),
+                DbChangeOp::AboAendern { id, .. } => id.clone(),
+                DbChangeOp::CreateZugriff {
+                    land,
+                    amtsgericht,
+                    bezirk,
+                    blatt,
+                    blatt_bis,
+                    ..
+                } => match blatt_bis {
+                    Some(bis) => format!("{land}/{amtsgericht}/{bezirk}/{blatt}-{bis}"),
+                    None => format!("{land}/{amtsgericht}/{bezirk}/{blatt}"),
+                },
+                DbChangeOp::ZugriffGenehmigen { ids, .. } => ids.join(", "),
+                DbChangeOp::ZugriffAblehnen { ids, .. } => ids.join(", "),
+                DbChangeOp::ZugriffeAdminBenachrichtigt { ids, .. } => ids.join(", "),
+                DbChangeOp::ZugriffeBestaetigen { anfrage_id, .. } => anfrage_id.clone(),
+                DbChangeOp::ZugriffeZurueckziehen { ids, .. } => ids.join(", "),
+                DbChangeOp::ZugriffWiderrufen { ids, .. } => ids.join(", "),
+                DbChangeOp::ZugriffGueltigkeit { ids, .. } => ids.join(", "),
+                DbChangeOp::BenutzerSessionNeu { email, .. } => email.clone(),
+                DbChangeOp::BenutzerSessionErneuern { email, .. } => email.clone(),
+                DbChangeOp::BenutzerSessionsBeenden { email, ids } => {
+                    format!("{email}: {}", ids.join(", "))
+                }
+                DbChangeOp::BenutzerSessionAbmelden { email, .. } => email.clone(),
+                DbChangeOp::ApiSchluesselNeu { email, schluessel } => {
+                    format!("{email}: {}", schluessel.name)
+                }
+                DbChangeOp::ApiSchluesselLoeschen { ids, .. } => ids.join(", "),
+                DbChangeOp::ApiSchluesselBenutzt { id, .. } => id.clone(),
+                DbChangeOp::ZweiFaktorEinrichten { email, .. } => email.clone(),
+                DbChangeOp::ZweiFaktorAktivieren { email, .. } => email.clone(),
+                DbChangeOp::ZweiFaktorDeaktivieren { email } => email.clone(),
+                DbChangeOp::WiederherstellungscodeBenutzen { email, .. } => email.clone(),
+                DbChangeOp::TotpSchrittBenutzen { email, .. } => email.clone(),
+                DbChangeOp::FehlanmeldungProtokollieren { email, ip, .. } => {
+                    format!("{email} ({ip})")
+                }
+                DbChangeOp::FehlanmeldungenZuruecksetzen { email } => email.clone(),
+                DbChangeOp::KontenEntsperren { emails } => emails.join(", "),
+                DbChangeOp::RolleSpeichern(rolle) => rolle.name.clone(),
+                DbChangeOp::RollenLoeschen { namen } => namen.join(", "),
+                DbChangeOp::OidcBenutzerAnmelden { email, .. } => email.clone(),
+                DbChangeOp::ZugriffAnfrageProtokollieren { email, ip, .. }
+                | DbChangeOp::PasswortVergessenProtokollieren { email, ip, .. } => {
+                    format!("{email} ({ip})")
+                }
+                DbChangeOp::BearbeiteEinstellung { id, .. } => id.clone(),
+                DbChangeOp::BenutzerEinstellungSetzen {
+                    benutzer_id,
+                    einstellung,
+                    ..
+                } => format!("{benutzer_id}: {einstellung}"),
+                DbChangeOp::EinsichtenProtokollieren { eintraege } => eintraege
+                    .iter()
+                    .flat_map(|n| n.einsichten.iter())
+                    .map(|e| format!("{}/{}/{}/{}", e.land, e.amtsgericht, e.bezirk, e.blatt))
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                DbChangeOp::PostausgangEinreihen { eintraege, .. } => eintraege
+                    .iter()
+                    .map(|e| e.aenderungs_id.clone())
+                    .collect::<std::collections::BTreeSet<_>>()
+                    .into_iter()
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                DbChangeOp::PostausgangErneutSenden { ids, .. } => ids.join(", "),
+                DbChangeOp::PostausgangErgebnisSpeichern { ids, .. } => ids.join(", "),
+            }
+        }
     }
 
     #[post("/get-db")]
@@ -1469,14 +3510,14 @@ pub mod commit {
         use lz4_flex::compress_prepend_size;
 
         if app_state.k8s_aktiv() && app_state.sync_server() {
-            let db_bytes = match std::fs::read(get_db_path(MountPoint::Remote)) {
+            let db_bytes = match std::fs::read(app_state.konfiguration().get_db_path(MountPoint::Remote)) {
                 Ok(o) => o,
                 Err(_) => return HttpResponse::NotFound().finish(),
             };
             let compressed = compress_prepend_size(&db_bytes);
             HttpResponse::Ok().body(compressed)
         } else {
-            let db_bytes = match std::fs::read(get_db_path(MountPoint::Local)) {
+            let db_bytes = match std::fs::read(app_state.konfiguration().get_db_path(MountPoint::Local)) {
                 Ok(o) => o,
                 Err(_) => return HttpResponse::NotFound().finish(),
             };
@@ -1487,11 +3528,11 @@ pub mod commit {
 
     #[post("/db")]
     async fn db(
-        upload_changeset: web::Json<DbChangeOp>,
+        upload_changeset: web::Json<DbChangeAnfrage>,
         app_state: web::Data<AppState>,
         _: HttpRequest,
     ) -> impl Responder {
-        match db_change_internal(&upload_changeset, &app_state) {
+        match db_change_internal(&upload_changeset.op, &upload_changeset.akteur, &app_state) {
             Ok(o) => o,
             Err(e) => e,
         }
@@ -1499,23 +3540,65 @@ pub mod commit {
 
     pub(crate) fn db_change_inner(
         change_op: &DbChangeOp,
+        akteur: &str,
         app_state: &AppState,
     ) -> Result<(), String> {
+        let konfiguration = app_state.konfiguration();
         let mount_point_write = if app_state.k8s_aktiv() && app_state.sync_server() {
             MountPoint::Remote
         } else {
             MountPoint::Local
         };
 
+        let mut conn = crate::db::verbindung_oeffnen(&konfiguration, mount_point_write)
+            .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+        let tx = conn
+            .transaction()
+            .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank: {e}"))?;
+
+        // Änderung und Audit-Eintrag in derselben Transaktion: schlägt
+        // eines von beiden fehl, wird nichts gespeichert
+        db_change_ausfuehren(&konfiguration, change_op, &tx)?;
+        crate::audit::protokollieren(&tx, akteur, change_op)?;
+
+        tx.commit()
+            .map_err(|e| format!("Fehler beim Speichern der Änderung: {e}"))
+    }
+
+    fn db_change_ausfuehren(
+        konfiguration: &Konfiguration,
+        change_op: &DbChangeOp,
+        conn: &rusqlite::Connection,
+    ) -> Result<(), String> {
         match change_op {
             DbChangeOp::BearbeiteEinstellung { id, neuer_wert } => {
-                crate::db::bearbeite_einstellung(mount_point_write, id, neuer_wert)
+                crate::db::bearbeite_einstellung(conn, id, neuer_wert)
             }
+            DbChangeOp::BenutzerEinstellungSetzen {
+                benutzer_id,
+                einstellung,
+                wert,
+            } => crate::db::benutzer_einstellung_setzen(
+                conn,
+                *benutzer_id,
+                einstellung,
+                wert,
+            ),
             DbChangeOp::PasswortAendern { passwort, email } => {
-                crate::db::passwort_aendern(mount_point_write, &email, &passwort)
+                crate::db::passwort_aendern(conn, &email, &passwort)
             }
+            DbChangeOp::PasswortResetNeu {
+                email,
+                token_hash,
+                gueltig_bis,
+            } => crate::db::passwort_reset_einfuegen(conn, email, token_hash, gueltig_bis),
+            DbChangeOp::PasswortZuruecksetzen {
+                token_hash,
+                passwort,
+                ..
+            } => crate::db::passwort_zuruecksetzen(conn, token_hash, passwort),
             DbChangeOp::BenutzerNeu(un) => crate::db::create_user(
-                mount_point_write,
+                conn,
                 &un.name,
                 &un.email,
                 &un.passwort,
@@ -1523,43 +3606,60 @@ pub mod commit {
                 un.schluessel.clone(),
             ),
             DbChangeOp::BenutzerAendernRechte { ids, neue_rechte } => {
-                crate::db::bearbeite_benutzer_rechte(mount_point_write, ids, neue_rechte)
+                crate::db::bearbeite_benutzer_rechte(conn, ids, neue_rechte)
             }
             DbChangeOp::BenutzerAendernPubkey { id, neuer_pubkey } => {
-                crate::db::bearbeite_benutzer_pubkey(mount_point_write, id, neuer_pubkey)
+                crate::db::bearbeite_benutzer_pubkey(conn, id, neuer_pubkey)
             }
             DbChangeOp::BezirkeLoeschen(b) => {
-                crate::db::bezirke_loeschen(mount_point_write, b.ids.as_ref())
+                crate::db::bezirke_loeschen(conn, b.ids.as_ref())
             }
             DbChangeOp::BenutzerLoeschen(ul) => {
-                crate::db::delete_user(mount_point_write, &ul.email)
+                crate::db::delete_user(conn, &ul.email)
             }
             DbChangeOp::BezirkNeu(bn) => crate::db::create_gemarkung(
-                mount_point_write,
+                konfiguration,
+                conn,
                 &bn.land,
                 &bn.amtsgericht,
                 &bn.bezirk,
             ),
             DbChangeOp::BezirkeNeu(b) => {
-                crate::db::bezirke_einfuegen(mount_point_write, &b.bezirke)
+                crate::db::bezirke_einfuegen(konfiguration, conn, &b.bezirke)
             }
             DbChangeOp::BezirkLoeschen(bl) => crate::db::delete_gemarkung(
-                mount_point_write,
+                conn,
                 &bl.land,
                 &bl.amtsgericht,
                 &bl.bezirk,
             ),
             DbChangeOp::AboNeu(an) => crate::db::create_abo(
-                mount_point_write,
+                conn,
                 &an.typ,
                 &an.blatt,
                 &an.text,
                 an.aktenzeichen.as_ref().map(|s| s.as_str()),
+                an.besitzer.as_deref(),
+                an.webhook_secret.as_deref(),
+                an.detailstufe.as_deref(),
+                an.abteilungen.as_deref(),
+                an.aenderungsarten.as_deref(),
+                an.flurstueck.as_deref(),
+                an.id.as_deref(),
             ),
             DbChangeOp::AboLoeschen(al) => crate::db::delete_abo(
-                mount_point_write,
+                conn,
                 &al.id,
             ),
+            DbChangeOp::AboWebhookSecretErneuern { id, webhook_secret } => {
+                crate::db::abo_webhook_secret_setzen(conn, id, webhook_secret)
+            }
+            DbChangeOp::AboDetailstufeAendern { ids, detailstufe } => {
+                crate::db::abo_detailstufe_setzen(conn, ids, detailstufe)
+            }
+            DbChangeOp::AboAendern { id, aktenzeichen, pausiert } => {
+                crate::db::abo_aendern(conn, id, aktenzeichen.as_deref(), *pausiert)
+            }
             DbChangeOp::CreateZugriff {
                 id,
                 name,
@@ -1571,8 +3671,11 @@ pub mod commit {
                 amtsgericht,
                 bezirk,
                 blatt,
+                blatt_bis,
+                anfrage_id,
+                bestaetigt_am,
             } => crate::db::create_zugriff(
-                mount_point_write,
+                conn,
                 id,
                 name,
                 email,
@@ -1583,25 +3686,184 @@ pub mod commit {
                 amtsgericht,
                 bezirk,
                 blatt,
+                blatt_bis.as_deref(),
+                anfrage_id.as_deref(),
+                bestaetigt_am.as_deref(),
+            ),
+            DbChangeOp::ZugriffeBestaetigen { anfrage_id, datum } => {
+                crate::db::zugriffe_bestaetigen(conn, anfrage_id, datum)
+            }
+            DbChangeOp::ZugriffeZurueckziehen {
+                anfrage_id,
+                ids,
+                datum,
+            } => crate::db::zugriffe_zurueckziehen(conn, anfrage_id, ids, datum),
+            DbChangeOp::ZugriffGenehmigen {
+                ids,
+                email,
+                datum,
+                gueltig_bis,
+            } => crate::db::zugriff_genehmigen(
+                conn,
+                ids,
+                email,
+                datum,
+                gueltig_bis.as_deref(),
             ),
-            DbChangeOp::ZugriffGenehmigen { ids, email, datum } => {
-                crate::db::zugriff_genehmigen(mount_point_write, ids, email, datum)
+            DbChangeOp::ZugriffAblehnen {
+                ids,
+                email,
+                datum,
+                grund,
+            } => crate::db::zugriff_ablehnen(conn, ids, email, datum, grund.as_deref()),
+            DbChangeOp::ZugriffeAdminBenachrichtigt { ids, datum } => {
+                crate::db::zugriffe_admin_benachrichtigt(conn, ids, datum)
+            }
+            DbChangeOp::ZugriffWiderrufen { ids, email, datum } => {
+                crate::db::zugriff_widerrufen(conn, ids, email, datum)
             }
-            DbChangeOp::ZugriffAblehnen { ids, email, datum } => {
-                crate::db::zugriff_ablehnen(mount_point_write, ids, email, datum)
+            DbChangeOp::ZugriffGueltigkeit { ids, gueltig_bis } => {
+                crate::db::zugriff_gueltigkeit_setzen(conn, ids, gueltig_bis.as_deref())
             }
             DbChangeOp::BenutzerSessionNeu {
                 email,
-                token,
-                gueltig_bis,
-            } => {
-                crate::db::insert_token_into_sessions(mount_point_write, email, token, gueltig_bis)
+                session,
+                beschreibung,
+            } => crate::db::insert_token_into_sessions(
+                conn,
+                email,
+                session,
+                beschreibung,
+            ),
+            DbChangeOp::BenutzerSessionErneuern {
+                refresh_token_hash_alt,
+                session,
+                ..
+            } => crate::db::session_erneuern(conn, refresh_token_hash_alt, session),
+            DbChangeOp::BenutzerSessionsBeenden { email, ids } => {
+                crate::db::sessions_beenden(conn, email, ids)
+            }
+            DbChangeOp::BenutzerSessionAbmelden { token_hash, .. } => {
+                crate::db::session_abmelden(conn, token_hash)
+            }
+            DbChangeOp::ApiSchluesselNeu { email, schluessel } => {
+                crate::api_schluessel::api_schluessel_einfuegen(conn, email, schluessel)
+            }
+            DbChangeOp::ApiSchluesselLoeschen { email, ids } => {
+                crate::api_schluessel::api_schluessel_loeschen(
+                    conn,
+                    email.as_deref(),
+                    ids,
+                )
+            }
+            DbChangeOp::ApiSchluesselBenutzt { id, zeitpunkt } => {
+                crate::api_schluessel::api_schluessel_benutzt(conn, id, zeitpunkt)
+            }
+            DbChangeOp::ZweiFaktorEinrichten { email, geheimnis } => {
+                crate::zwei_faktor::zwei_faktor_einrichten(conn, email, geheimnis)
             }
+            DbChangeOp::ZweiFaktorAktivieren { email, code_hashes } => {
+                crate::zwei_faktor::zwei_faktor_aktivieren(conn, email, code_hashes)
+            }
+            DbChangeOp::ZweiFaktorDeaktivieren { email } => {
+                crate::zwei_faktor::zwei_faktor_deaktivieren(conn, email)
+            }
+            DbChangeOp::WiederherstellungscodeBenutzen { email, code_hash } => {
+                crate::zwei_faktor::wiederherstellungscode_benutzen(
+                    conn,
+                    email,
+                    code_hash,
+                )
+            }
+            DbChangeOp::TotpSchrittBenutzen { email, schritt } => {
+                crate::zwei_faktor::totp_schritt_benutzen(conn, email, *schritt)
+            }
+            DbChangeOp::FehlanmeldungProtokollieren {
+                email,
+                ip,
+                zeitpunkt,
+                sperren,
+                gesperrt_bis,
+            } => crate::ratenbegrenzung::fehlanmeldung_einfuegen(
+                conn,
+                email,
+                ip,
+                zeitpunkt,
+                *sperren,
+                gesperrt_bis.as_deref(),
+            ),
+            DbChangeOp::FehlanmeldungenZuruecksetzen { email } => {
+                crate::ratenbegrenzung::fehlanmeldungen_loeschen(conn, email)
+            }
+            DbChangeOp::KontenEntsperren { emails } => {
+                crate::ratenbegrenzung::konten_entsperren(conn, emails)
+            }
+            DbChangeOp::RolleSpeichern(rolle) => {
+                crate::rollen::rolle_speichern(conn, rolle)
+            }
+            DbChangeOp::RollenLoeschen { namen } => {
+                crate::rollen::rollen_loeschen(conn, namen)
+            }
+            DbChangeOp::OidcBenutzerAnmelden {
+                email,
+                name,
+                rechte,
+                subjekt,
+            } => crate::oidc::oidc_benutzer_speichern(
+                conn,
+                email,
+                name,
+                rechte,
+                subjekt,
+            ),
+            DbChangeOp::ZugriffAnfrageProtokollieren {
+                email,
+                ip,
+                zeitpunkt,
+                anzahl,
+            } => crate::ratenbegrenzung::zugriff_anfrage_einfuegen(
+                conn,
+                email,
+                ip,
+                zeitpunkt,
+                *anzahl,
+            ),
+            DbChangeOp::PasswortVergessenProtokollieren {
+                email,
+                ip,
+                zeitpunkt,
+            } => crate::ratenbegrenzung::passwort_vergessen_einfuegen(conn, email, ip, zeitpunkt),
+            DbChangeOp::EinsichtenProtokollieren { eintraege } => {
+                crate::einsicht::einsichten_einfuegen(conn, eintraege)
+            }
+            DbChangeOp::PostausgangEinreihen { eintraege, datum } => {
+                crate::postausgang::einreihen(conn, eintraege, datum)
+            }
+            DbChangeOp::PostausgangErneutSenden { ids, datum } => {
+                crate::postausgang::erneut_senden(conn, ids, datum)
+            }
+            DbChangeOp::PostausgangErgebnisSpeichern {
+                ids,
+                status,
+                versuche,
+                naechster_versuch,
+                letzter_fehler,
+                gesendet_am,
+            } => crate::postausgang::ergebnis_setzen(
+                conn,
+                ids,
+                status,
+                *versuche,
+                naechster_versuch,
+                letzter_fehler.as_deref(),
+                gesendet_am.as_deref(),
+            ),
         }
     }
 
     fn db_change_internal(
         change_op: &DbChangeOp,
+        akteur: &str,
         app_state: &AppState,
     ) -> Result<HttpResponse, HttpResponse> {
         let response_err = |code: usize, text: String| {
@@ -1621,7 +3883,7 @@ pub mod commit {
             )
         };
 
-        match db_change_inner(change_op, app_state) {
+        match db_change_inner(change_op, akteur, app_state) {
             Ok(()) => Ok(response_ok()),
             Err(e) => Err(response_err(500, e)),
         }
@@ -1637,7 +3899,7 @@ pub mod commit {
 /// am PersistentVolume kommt.
 pub mod pull {
 
-    use crate::models::{get_data_dir, get_db_path, MountPoint};
+    use crate::models::MountPoint;
     use crate::AppState;
     use actix_web::{post, web, HttpRequest, HttpResponse, Responder};
     use serde_derive::{Deserialize, Serialize};
@@ -1690,7 +3952,7 @@ pub mod pull {
             return Ok(response_ok());
         }
 
-        let local_path = Path::new(&get_data_dir(MountPoint::Local)).to_path_buf();
+        let local_path = Path::new(&app_state.konfiguration().get_data_dir(MountPoint::Local)).to_path_buf();
         if !local_path.exists() {
             let _ = std::fs::create_dir(local_path.clone());
         }
@@ -1706,7 +3968,10 @@ pub mod pull {
             .await
             .map_err(|e| response_err(501, format!("Konnte Sync-Server nicht finden: {e}")))?;
 
-        let data_remote = format!("git://{sync_server_ip}:9418/");
+        let data_remote = format!(
+            "git://{sync_server_ip}:{}/",
+            app_state.konfiguration().ports.git
+        );
         repo.remote_add_fetch("origin", &data_remote)
             .map_err(|e| response_err(501, format!("git_clone({data_remote}): {e}")))?;
 
@@ -1749,11 +4014,11 @@ pub mod pull {
             return Ok(response_ok());
         }
 
-        let remote_db_bytes = crate::db::get_db_bytes()
+        let remote_db_bytes = crate::db::get_db_bytes(&app_state.konfiguration())
             .await
             .map_err(|e| response_err(500, format!("get_db_bytes: {e}")))?;
 
-        let local_path = Path::new(&get_db_path(MountPoint::Local)).to_path_buf();
+        let local_path = Path::new(&app_state.konfiguration().get_db_path(MountPoint::Local)).to_path_buf();
         if let Some(parent) = local_path.parent() {
             let _ = std::fs::create_dir(parent);
         }
@@ -1772,15 +4037,19 @@ pub mod pull {
 /// API für `/upload` Anfragen
 pub mod upload {
 
-    use super::commit::{CommitResponse, commit_internal};
+    use super::commit::{CommitResponse, DbChangeOp, commit_internal};
+    use crate::aenderungen::{AenderungsAutor, AenderungsDetails};
+    use crate::api_schluessel::ApiScope;
+    use crate::config::Konfiguration;
+    use crate::postausgang::{PostausgangNeu, PostausgangTyp};
     use crate::{
         db::GemarkungsBezirke,
-        models::{get_data_dir, BenutzerInfo, MountPoint, PdfFile},
+        models::{AboDetailstufe, AbonnementInfo, BenutzerInfo, MountPoint, PdfFile},
         AppState,
     };
     use actix_web::{post, web, HttpRequest, HttpResponse, Responder};
     use serde_derive::{Deserialize, Serialize};
-    use std::collections::BTreeSet;
+    use std::collections::{BTreeMap, BTreeSet};
     use std::path::{Path, PathBuf};
 
     pub type FileName = String;
@@ -1847,6 +4116,7 @@ pub mod upload {
         app_state: &AppState,
         req: &HttpRequest,
     ) -> Result<HttpResponse, HttpResponse> {
+        let konfiguration = app_state.konfiguration();
         let response_err = |code: usize, text: String| {
             HttpResponse::Ok().content_type("application/json").body(
                 serde_json::to_string_pretty(&UploadChangesetResponse::StatusError(
@@ -1869,13 +4139,14 @@ pub mod upload {
         };
 
         let upload_changeset = &*upload_changeset;
-        let (token, benutzer) = super::get_benutzer_from_httpauth(&req).await?;
-        let hat_schreibzugriff = benutzer.rechte == "admin" || benutzer.rechte == "bearbeiter";
-        if !hat_schreibzugriff {
-            return Err(response_err(501, format!("Benutzer hat keine Schreibrechte")));
-        }
+        let (token, benutzer) = super::get_benutzer_from_httpauth_scope(&konfiguration, &req, ApiScope::Upload).await?;
+        benutzer
+            .berechtigung_pruefen(crate::rollen::Berechtigung::GrundbuchBearbeiten)
+            .map_err(|e| response_err(501, e))?;
+
+        api_bereiche_pruefen(&benutzer, upload_changeset).map_err(|e| response_err(403, e))?;
 
-        verify_signature(&benutzer.email, &upload_changeset).map_err(|e| {
+        verify_signature(&konfiguration, &benutzer.email, &upload_changeset).map_err(|e| {
             response_err(
                 501,
                 format!("Fehler bei Überprüfung der digitalen Signatur:\r\n{e}"),
@@ -1888,7 +4159,10 @@ pub mod upload {
 
             let client = reqwest::Client::new();
             let res = client
-                .post(&format!("http://{k8s_sync_server_ip}:8081/commit"))
+                .post(&format!(
+                    "http://{k8s_sync_server_ip}:{}/commit",
+                    konfiguration.ports.intern
+                ))
                 .body(serde_json::to_string(&upload_changeset).unwrap_or_default())
                 .bearer_auth(token.clone())
                 .send()
@@ -1918,7 +4192,30 @@ pub mod upload {
         }
     }
 
-    pub fn verify_signature(email: &str, changeset: &UploadChangeset) -> Result<bool, String> {
+    /// Bei Upload mit API-Schlüssel: alle Blätter müssen in den freigegebenen Bezirken liegen
+    pub fn api_bereiche_pruefen(
+        benutzer: &BenutzerInfo,
+        changeset: &UploadChangeset,
+    ) -> Result<(), String> {
+        let titelblaetter = changeset
+            .data
+            .neu
+            .iter()
+            .chain(changeset.data.geaendert.iter().map(|g| &g.neu))
+            .map(|pdf| &pdf.analysiert.titelblatt);
+
+        for t in titelblaetter {
+            benutzer.api_bereich_pruefen(&t.amtsgericht, &t.grundbuch_von)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn verify_signature(
+        konfiguration: &Konfiguration,
+        email: &str,
+        changeset: &UploadChangeset,
+    ) -> Result<bool, String> {
         use sequoia_openpgp::policy::StandardPolicy as P;
 
         let json = serde_json::to_string_pretty(&changeset.data)
@@ -1933,7 +4230,7 @@ pub mod upload {
         let msg = format!("-----BEGIN PGP SIGNED MESSAGE-----\r\nHash: {hash}\r\n\r\n{json}\r\n-----BEGIN PGP SIGNATURE-----\r\n{signatur}\r\n-----END PGP SIGNATURE-----");
 
         let p = &P::new();
-        let cert = crate::db::get_key_for_fingerprint(&changeset.fingerprint, email)?;
+        let cert = crate::db::get_key_for_fingerprint(konfiguration, &changeset.fingerprint, email)?;
         let mut plaintext = Vec::new();
         let _ = crate::pgp::verify(p, &mut plaintext, msg.as_bytes(), &cert)
             .map_err(|e| format!("{e}"))?;
@@ -1969,11 +4266,12 @@ pub mod upload {
     }
 
     pub fn sync_changes_to_disk(
-        zugriffe: &[(String, String, String, String)],
+        konfiguration: &Konfiguration,
+        zugriffe: &[crate::db::ZugriffBereich],
         upload_changeset: &UploadChangeset,
         folder_path: &PathBuf,
     ) -> Result<(), HttpResponse> {
-        let gemarkungen = crate::db::get_gemarkungen().unwrap_or_default();
+        let gemarkungen = crate::db::get_gemarkungen(konfiguration).unwrap_or_default();
 
         println!(
             "got {} new {} changed files",
@@ -2098,7 +4396,8 @@ pub mod upload {
     ) -> Result<(), String> {
         use git2::Repository;
 
-        let gemarkungen = crate::db::get_gemarkungen().unwrap_or_default();
+        let konfiguration = app_state.konfiguration();
+        let gemarkungen = crate::db::get_gemarkungen(&konfiguration).unwrap_or_default();
 
         let repo = match Repository::open(&folder_path) {
             Ok(o) => o,
@@ -2139,17 +4438,57 @@ pub mod upload {
 
         let commit_id = format!("{}", commit_id);
 
-        let geaendert_blaetter = upload_changeset
+        // Geänderte und neu angelegte Blätter (alte Fassung, neue Fassung): auch ein
+        // neues Blatt kann ein abonniertes Flurstück enthalten (z.B. nach einer Teilung)
+        let blatt_fassungen = upload_changeset
             .data
             .geaendert
             .iter()
-            .map(|aenderung| {
-                let tb = &aenderung.neu.analysiert.titelblatt;
-                format!("{}/{}/{}", tb.amtsgericht, tb.grundbuch_von, tb.blatt)
+            .map(|aenderung| (Some(&aenderung.alt), &aenderung.neu))
+            .chain(upload_changeset.data.neu.iter().map(|neu| (None, neu)))
+            .map(|(alt, neu)| {
+                let tb = &neu.analysiert.titelblatt;
+                (format!("{}/{}/{}", tb.amtsgericht, tb.grundbuch_von, tb.blatt), alt, neu)
             })
+            .collect::<Vec<_>>();
+
+        let geaendert_blaetter = blatt_fassungen
+            .iter()
+            .map(|(blatt, _, _)| blatt.clone())
             .collect::<BTreeSet<_>>();
 
-        let (grundbuch_schema, grundbuch_index) = crate::index::get_grundbuch_index()
+        // Für Abos mit Detailstufe "details" und für die Filter der Abos
+        let details = blatt_fassungen
+            .iter()
+            .map(|(blatt, alt, neu)| {
+                let details = AenderungsDetails {
+                    titel: upload_changeset.titel.trim().to_string(),
+                    beschreibung: upload_changeset.beschreibung.clone(),
+                    autor: AenderungsAutor {
+                        name: benutzer.name.clone(),
+                        email: benutzer.email.clone(),
+                    },
+                    abteilungen: match alt {
+                        Some(alt) => crate::aenderungen::vergleichen(alt, neu),
+                        None => crate::aenderungen::vergleichen_neu(neu),
+                    },
+                };
+                (blatt.clone(), details)
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        // Flurstücke vor und nach der Änderung, damit Flurstück-Abos auch
+        // bei einem Wechsel des Flurstücks auf ein anderes Blatt auslösen
+        let flurstuecke = blatt_fassungen
+            .iter()
+            .map(|(blatt, alt, neu)| {
+                let mut f = alt.map(crate::aenderungen::get_flurstuecke).unwrap_or_default();
+                f.extend(crate::aenderungen::get_flurstuecke(neu));
+                (blatt.clone(), f)
+            })
+            .collect::<BTreeMap<_, _>>();
+
+        let (grundbuch_schema, grundbuch_index) = crate::index::get_grundbuch_index(&konfiguration)
             .map_err(|e| format!("Fehler in Index / Schema \"grundbuch\": {e}"))?;
 
         let mut index_writer = grundbuch_index
@@ -2211,24 +4550,66 @@ pub mod upload {
 
         println!("commit_changes");
 
+        // Benachrichtigungen werden nur eingereiht und im Hintergrund gesendet (siehe postausgang.rs)
+        let mut postausgang = Vec::new();
         for blatt in geaendert_blaetter {
-            let webhook_abos = crate::db::get_webhook_abos(&blatt).map_err(|e| format!("{e}"))?;
+            let details_fuer_abo = |abo_info: &AbonnementInfo| match abo_info.detailstufe {
+                AboDetailstufe::Details => details.get(&blatt).map(|d| abo_info.details_filtern(d)),
+                AboDetailstufe::Standard => None,
+            };
+
+            // (Abteilung, Art) aller Änderungen am Blatt, für die Filter der Abos
+            let aenderungsarten = details
+                .get(&blatt)
+                .map(|d| crate::aenderungen::get_aenderungsarten(&d.abteilungen))
+                .unwrap_or_default();
+
+            let flurstuecke_blatt = flurstuecke.get(&blatt).cloned().unwrap_or_default();
+
+            let webhook_abos = crate::db::get_webhook_abos(&konfiguration, &blatt, &flurstuecke_blatt).map_err(|e| format!("{e}"))?;
             println!("webhook_abos {:#?}", webhook_abos);
 
             for abo_info in webhook_abos {
-                let _ = crate::email::send_change_webhook(&abo_info, &commit_id).await;
+                if !abo_info.filter_passt(&aenderungsarten) {
+                    continue;
+                }
+                postausgang.push(PostausgangNeu::neu(
+                    PostausgangTyp::Webhook,
+                    &abo_info,
+                    &commit_id,
+                    details_fuer_abo(&abo_info).as_ref(),
+                ));
             }
 
-            let email_abos = crate::db::get_email_abos(&blatt).map_err(|e| format!("{e}"))?;
+            let email_abos = crate::db::get_email_abos(&konfiguration, &blatt, &flurstuecke_blatt).map_err(|e| format!("{e}"))?;
 
             println!("email_abos {:#?}", email_abos);
 
             for abo_info in email_abos {
-                let _ = crate::email::send_change_email(&abo_info, &commit_id);
-                println!("{:#?}", abo_info);
+                if !abo_info.filter_passt(&aenderungsarten) {
+                    continue;
+                }
+                postausgang.push(PostausgangNeu::neu(
+                    PostausgangTyp::Email,
+                    &abo_info,
+                    &commit_id,
+                    details_fuer_abo(&abo_info).as_ref(),
+                ));
             }
         }
 
+        if !postausgang.is_empty() {
+            crate::api::write_to_root_db(
+                DbChangeOp::PostausgangEinreihen {
+                    eintraege: postausgang,
+                    datum: chrono::Utc::now().to_rfc3339(),
+                },
+                &benutzer.email,
+                app_state,
+            )
+            .await?;
+        }
+
         println!("commit changes ok");
 
         Ok(())
@@ -2238,9 +4619,13 @@ pub mod upload {
 /// API für `/download` Anfragen
 pub mod download {
 
+    use crate::api_schluessel::ApiScope;
+    use crate::config::Konfiguration;
+    use crate::einsicht::{EinsichtTyp, EinsichtZweck};
     use crate::models::Grundbuch;
-    use crate::models::{get_data_dir, MountPoint, PdfFile};
+    use crate::models::{MountPoint, PdfFile};
     use crate::pdf::PdfGrundbuchOptions;
+    use crate::AppState;
     use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
     use serde_derive::{Deserialize, Serialize};
     use std::path::Path;
@@ -2261,8 +4646,11 @@ pub mod download {
     }
 
     #[get("/download/client/{platform}")]
-    async fn download_client(path: web::Path<String>) -> impl Responder {
-        let links = crate::db::get_globale_einstellungen(MountPoint::Local).unwrap_or_default();
+    async fn download_client(
+        path: web::Path<String>,
+        app_state: web::Data<AppState>,
+    ) -> impl Responder {
+        let links = crate::db::get_globale_einstellungen(&app_state.konfiguration(), MountPoint::Local).unwrap_or_default();
         let windows = links
             .iter()
             .filter(|(id, (k, v))| k == "download.release.url.windows")
@@ -2299,9 +4687,12 @@ pub mod download {
     #[get("/download/gbx/{amtsgericht}/{grundbuch_von}/{blatt}")]
     async fn download_gbx(
         path: web::Path<(String, String, usize)>,
+        zweck: web::Query<EinsichtZweck>,
+        app_state: web::Data<AppState>,
         req: HttpRequest,
     ) -> impl Responder {
-        let (_token, _benutzer) = match super::get_benutzer_from_httpauth(&req).await {
+        let konfiguration = app_state.konfiguration();
+        let (_token, benutzer) = match super::get_benutzer_from_httpauth_scope(&konfiguration, &req, ApiScope::ReadGbx).await {
             Ok(o) => o,
             Err(e) => {
                 return e;
@@ -2309,7 +4700,7 @@ pub mod download {
         };
         let (amtsgericht, grundbuch_von, blatt) = &*path;
         let mut amtsgericht = amtsgericht.clone();
-        let gemarkungen = crate::db::get_gemarkungen().unwrap_or_default();
+        let gemarkungen = crate::db::get_gemarkungen(&konfiguration).unwrap_or_default();
 
         let mut l = None;
         for (land, ag, bezirk) in gemarkungen.iter() {
@@ -2334,12 +4725,30 @@ pub mod download {
             }
         };
 
-        let folder_path = get_data_dir(MountPoint::Local);
+        let zugriff_pruefen = || {
+            benutzer.api_bereich_pruefen(&amtsgericht, grundbuch_von)?;
+            crate::db::Leserechte::laden(&konfiguration, &benutzer)?.pruefen(
+                &land,
+                &amtsgericht,
+                grundbuch_von,
+                &blatt.to_string(),
+            )
+        };
+        if let Err(e) = zugriff_pruefen() {
+            return HttpResponse::Ok()
+                .content_type("application/json")
+                .body(serde_json::to_string_pretty(&PdfFileOrEmpty::NichtVorhanden(PdfFileNichtVorhanden {
+                    code: 403,
+                    text: e,
+                })).unwrap_or_default());
+        }
+
+        let folder_path = konfiguration.get_data_dir(MountPoint::Local);
         let folder_path = Path::new(&folder_path);
 
         let file_path = folder_path
-            .join(land)
-            .join(amtsgericht)
+            .join(&land)
+            .join(&amtsgericht)
             .join(grundbuch_von)
             .join(&format!("{grundbuch_von}_{blatt}.gbx"));
 
@@ -2348,7 +4757,17 @@ pub mod download {
             .and_then(|s| serde_json::from_str(&s).ok());
 
         let response_json = match file {
-            Some(s) => PdfFileOrEmpty::Pdf(s),
+            Some(s) => {
+                let einsicht = zweck.einsicht(
+                    EinsichtTyp::Gbx,
+                    &land,
+                    &amtsgericht,
+                    grundbuch_von,
+                    &blatt.to_string(),
+                );
+                super::einsichten_protokollieren(&benutzer, vec![einsicht], &app_state);
+                PdfFileOrEmpty::Pdf(s)
+            }
             None => PdfFileOrEmpty::NichtVorhanden(PdfFileNichtVorhanden {
                 code: 404,
                 text: format!("Datei für {grundbuch_von}_{blatt}.gbx nicht gefunden"),
@@ -2363,9 +4782,12 @@ pub mod download {
     #[get("/download/pdf/{amtsgericht}/{grundbuch_von}/{blatt}")]
     async fn dowload_pdf(
         path: web::Path<(String, String, usize)>,
+        zweck: web::Query<EinsichtZweck>,
+        app_state: web::Data<AppState>,
         req: HttpRequest,
     ) -> impl Responder {
-        let (_token, benutzer) = match super::get_benutzer_from_httpauth(&req).await {
+        let konfiguration = app_state.konfiguration();
+        let (_token, benutzer) = match super::get_benutzer_from_httpauth_scope(&konfiguration, &req, ApiScope::ReadPdf).await {
             Ok(o) => o,
             Err(e) => {
                 return e;
@@ -2373,7 +4795,7 @@ pub mod download {
         };
         let (amtsgericht, grundbuch_von, blatt) = &*path;
         let mut amtsgericht = amtsgericht.clone();
-        let gemarkungen = crate::db::get_gemarkungen().unwrap_or_default();
+        let gemarkungen = crate::db::get_gemarkungen(&konfiguration).unwrap_or_default();
 
         let mut l = None;
         for (land, ag, bezirk) in gemarkungen.iter() {
@@ -2398,12 +4820,30 @@ pub mod download {
             }
         };
 
-        let folder_path = get_data_dir(MountPoint::Local);
+        let zugriff_pruefen = || {
+            benutzer.api_bereich_pruefen(&amtsgericht, grundbuch_von)?;
+            crate::db::Leserechte::laden(&konfiguration, &benutzer)?.pruefen(
+                &land,
+                &amtsgericht,
+                grundbuch_von,
+                &blatt.to_string(),
+            )
+        };
+        if let Err(e) = zugriff_pruefen() {
+            return HttpResponse::Ok()
+                .content_type("application/json")
+                .body(serde_json::to_string_pretty(&PdfFileOrEmpty::NichtVorhanden(PdfFileNichtVorhanden {
+                    code: 403,
+                    text: e,
+                })).unwrap_or_default());
+        }
+
+        let folder_path = konfiguration.get_data_dir(MountPoint::Local);
         let folder_path = Path::new(&folder_path);
 
         let file_path = folder_path
-            .join(land)
-            .join(amtsgericht)
+            .join(&land)
+            .join(&amtsgericht)
             .join(grundbuch_von)
             .join(&format!("{grundbuch_von}_{blatt}.gbx"));
 
@@ -2426,6 +4866,15 @@ pub mod download {
             }
         };
 
+        let einsicht = zweck.einsicht(
+            EinsichtTyp::Pdf,
+            &land,
+            &amtsgericht,
+            grundbuch_von,
+            &blatt.to_string(),
+        );
+        super::einsichten_protokollieren(&benutzer, vec![einsicht], &app_state);
+
         let options = PdfGrundbuchOptions {
             exportiere_bv: true,
             exportiere_abt1: true,
@@ -2443,15 +4892,20 @@ pub mod download {
     }
 
     #[get("/aenderung/pdf/{id}")]
-    async fn dowload_aenderung_pdf(path: web::Path<String>, req: HttpRequest) -> impl Responder {
-        let (_token, _) = match super::get_benutzer_from_httpauth(&req).await {
+    async fn dowload_aenderung_pdf(
+        path: web::Path<String>,
+        app_state: web::Data<AppState>,
+        req: HttpRequest,
+    ) -> impl Responder {
+        let konfiguration = app_state.konfiguration();
+        let (_token, _) = match super::get_benutzer_from_httpauth_scope(&konfiguration, &req, ApiScope::ReadPdf).await {
             Ok(o) => o,
             Err(e) => {
                 return e;
             }
         };
 
-        match generate_diff(&path) {
+        match generate_diff(&konfiguration, &path) {
             Ok(o) => {
                 HttpResponse::Ok()
                 .content_type("application/pdf")
@@ -2470,12 +4924,12 @@ pub mod download {
         }
     }
 
-    fn generate_diff(id: &str) -> Result<Vec<u8>, String> {
+    fn generate_diff(konfiguration: &Konfiguration, id: &str) -> Result<Vec<u8>, String> {
         use printpdf::{Mm, PdfDocument};
         use crate::pdf::PdfFonts;
         use git2::{Repository, Oid};
 
-        let local_path = Path::new(&get_data_dir(MountPoint::Local)).to_path_buf();
+        let local_path = Path::new(&konfiguration.get_data_dir(MountPoint::Local)).to_path_buf();
         if !local_path.exists() {
             let _ = std::fs::create_dir(local_path.clone());
         }
@@ -2547,7 +5001,10 @@ pub mod download {
 /// API für `/suche` Anfragen
 pub mod suche {
 
-    use crate::models::{get_data_dir, AbonnementInfo, MountPoint, Titelblatt};
+    use crate::api_schluessel::ApiScope;
+    use crate::einsicht::{EinsichtTyp, EinsichtZweck};
+    use crate::models::{AbonnementInfo, MountPoint, Titelblatt};
+    use crate::AppState;
     use crate::suche::{SuchErgebnisAenderung, SuchErgebnisGrundbuch};
     use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
     use regex::Regex;
@@ -2594,17 +5051,23 @@ pub mod suche {
     }
 
     #[get("/suche/{suchbegriff}")]
-    async fn suche(suchbegriff: web::Path<String>, req: HttpRequest) -> impl Responder {
-        let (_token, benutzer) = match super::get_benutzer_from_httpauth(&req).await {
+    async fn suche(
+        suchbegriff: web::Path<String>,
+        zweck: web::Query<EinsichtZweck>,
+        app_state: web::Data<AppState>,
+        req: HttpRequest,
+    ) -> impl Responder {
+        let konfiguration = app_state.konfiguration();
+        let (_token, benutzer) = match super::get_benutzer_from_httpauth_scope(&konfiguration, &req, ApiScope::Search).await {
             Ok(o) => o,
             Err(e) => {
                 return e;
             }
         };
-        let folder_path = get_data_dir(MountPoint::Local);
+        let folder_path = konfiguration.get_data_dir(MountPoint::Local);
         let suchbegriff = &*suchbegriff;
 
-        let ergebnisse = match crate::suche::suche_in_index(&suchbegriff) {
+        let ergebnisse = match crate::suche::suche_in_index(&konfiguration, &suchbegriff) {
             Ok(o) => o,
             Err(e) => {
                 let json = serde_json::to_string_pretty(&GrundbuchSucheResponse::StatusErr(
@@ -2621,12 +5084,37 @@ pub mod suche {
             }
         };
 
-        let abos = crate::db::get_abos_fuer_benutzer(&benutzer).unwrap_or_default();
+        let leserechte = match crate::db::Leserechte::laden(&konfiguration, &benutzer) {
+            Ok(o) => o,
+            Err(e) => {
+                let json = serde_json::to_string_pretty(&GrundbuchSucheResponse::StatusErr(
+                    GrundbuchSucheError { code: 500, text: e },
+                ))
+                .unwrap_or_default();
+
+                return HttpResponse::Ok()
+                    .content_type("application/json")
+                    .body(json);
+            }
+        };
+
+        let abos = crate::db::get_abos_fuer_benutzer(&konfiguration, &benutzer).unwrap_or_default();
 
         let grundbuecher = ergebnisse
             .grundbuecher
             .into_iter()
             .filter_map(|ergebnis| {
+                if !benutzer.api_bereich_erlaubt(&ergebnis.amtsgericht, &ergebnis.grundbuch_von)
+                    || !leserechte.erlaubt(
+                        &ergebnis.land,
+                        &ergebnis.amtsgericht,
+                        &ergebnis.grundbuch_von,
+                        &ergebnis.blatt,
+                    )
+                {
+                    return None;
+                }
+
                 let titelblatt = Titelblatt {
                     amtsgericht: ergebnis.amtsgericht.clone(),
                     grundbuch_von: ergebnis.grundbuch_von.clone(),
@@ -2651,6 +5139,24 @@ pub mod suche {
             })
             .collect::<Vec<_>>();
 
+        // Jedes gefundene Blatt nur einmal protokollieren
+        let einsichten = grundbuecher
+            .iter()
+            .map(|g| {
+                (
+                    g.ergebnis.land.clone(),
+                    g.ergebnis.amtsgericht.clone(),
+                    g.ergebnis.grundbuch_von.clone(),
+                    g.ergebnis.blatt.clone(),
+                )
+            })
+            .collect::<std::collections::BTreeSet<_>>()
+            .into_iter()
+            .map(|(l, a, b, bl)| zweck.einsicht(EinsichtTyp::Suchtreffer, &l, &a, &b, &bl))
+            .collect::<Vec<_>>();
+
+        super::einsichten_protokollieren(&benutzer, einsichten, &app_state);
+
         let json =
             serde_json::to_string_pretty(&GrundbuchSucheResponse::StatusOk(GrundbuchSucheOk {
                 grundbuecher: grundbuecher,
@@ -2668,8 +5174,15 @@ pub mod suche {
 pub mod abo {
 
     use super::commit::DbChangeOp;
-    use crate::{models::MountPoint, AboLoeschenArgs, AboNeuArgs, AppState};
-    use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
+    use crate::aenderungen::AenderungsArt;
+    use crate::api_schluessel::ApiScope;
+    use crate::config::Konfiguration;
+    use crate::db::AboEintrag;
+    use crate::models::AboDetailstufe;
+    use crate::postausgang::{AboZustellung, PostausgangTyp};
+    use crate::rollen::Berechtigung;
+    use crate::{AboLoeschenArgs, AboNeuArgs, AppState};
+    use actix_web::{delete, get, patch, post, web, HttpRequest, HttpResponse, Responder};
     use serde_derive::{Deserialize, Serialize};
 
     #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -2681,8 +5194,15 @@ pub mod abo {
         Err(AboNeuAnfrageErr),
     }
 
-    #[derive(Debug, Clone, Serialize, Deserialize)]
-    struct AboNeuAnfrageOk {}
+    #[derive(Debug, Default, Clone, Serialize, Deserialize)]
+    struct AboNeuAnfrageOk {
+        /// Nur bei neuen Webhook-Abos und nach Erneuerung, wird nur einmal angezeigt
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        webhook_secret: Option<String>,
+        /// Nur bei Testzustellungen
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        zustellungs_id: Option<String>,
+    }
 
     #[derive(Debug, Clone, Serialize, Deserialize)]
     struct AboNeuAnfrageErr {
@@ -2693,15 +5213,32 @@ pub mod abo {
     #[derive(Debug, Clone, Serialize, Deserialize)]
     struct AboNeuForm {
         tag: Option<String>,
+        /// URL des Webhooks (nur bei Typ "webhook")
+        #[serde(default)]
+        url: Option<String>,
+        /// "standard" oder "details"
+        #[serde(default)]
+        detailstufe: Option<String>,
+        /// Nur diese Abteilungen, kommagetrennt ("abt2,abt3")
+        #[serde(default)]
+        abteilungen: Option<String>,
+        /// Nur diese Arten von Änderungen, kommagetrennt ("neu,loeschung,veraenderung")
+        #[serde(default)]
+        aenderungsarten: Option<String>,
+        /// Flurstück "Gemarkung/Flur/Flurstück" (nur mit Blatt "*")
+        #[serde(default)]
+        flurstueck: Option<String>,
     }
 
+    /// `grundbuchbezirk` und `blatt` dürfen "*" sein (ganzes Amtsgericht / ganzer Bezirk)
     #[get("/abo-neu/{email_oder_webhook}/{amtsgericht}/{grundbuchbezirk}/{blatt}")]
     async fn abo_neu(
         app_state: web::Data<AppState>,
-        path: web::Path<(String, String, String, usize)>,
+        path: web::Path<(String, String, String, String)>,
         form: web::Json<AboNeuForm>,
         req: HttpRequest,
     ) -> impl Responder {
+        let konfiguration = app_state.konfiguration();
         let response_err = |code: usize, text: String| {
             let json = serde_json::to_string_pretty(&AboNeuAnfrage::Err(AboNeuAnfrageErr {
                 code: code,
@@ -2714,39 +5251,56 @@ pub mod abo {
                 .body(json)
         };
 
-        let (token, benutzer) = match super::get_benutzer_from_httpauth(&req).await {
-            Ok(o) => o,
-            Err(e) => {
-                return e;
-            }
-        };
+        let (token, benutzer) =
+            match super::get_benutzer_from_httpauth_scope(&konfiguration, &req, ApiScope::AboManage).await {
+                Ok(o) => o,
+                Err(e) => {
+                    return e;
+                }
+            };
         let (email_oder_webhook, amtsgericht, grundbuchbezirk, blatt) = &*path;
 
-        let abo_return = if app_state.k8s_aktiv() {
-            super::write_to_root_db(
-                DbChangeOp::AboNeu(AboNeuArgs {
-                    typ: email_oder_webhook.clone(),
-                    blatt: format!("{amtsgericht}/{grundbuchbezirk}/{blatt}"),
-                    text: benutzer.email.clone(),
-                    aktenzeichen: form.tag.clone(),
-                }),
-                &*app_state,
-            )
-            .await
-        } else {
-            crate::db::create_abo(
-                MountPoint::Local,
-                &email_oder_webhook,
-                &format!("{amtsgericht}/{grundbuchbezirk}/{blatt}"),
-                &benutzer.email,
-                form.tag.as_ref().map(|s| s.as_str()),
-            )
+        if let Err(e) = benutzer.api_bereich_pruefen(amtsgericht, grundbuchbezirk) {
+            return response_err(403, e);
+        }
+
+        let (text, webhook_secret) = match email_oder_webhook.as_str() {
+            "webhook" => match form.url.as_ref() {
+                Some(u) => match crate::webhook::ziel_pruefen(&konfiguration, u).await {
+                    Ok(u) => (u.to_string(), Some(crate::signatur::schluessel_generieren())),
+                    Err(e) => return response_err(400, e),
+                },
+                None => return response_err(400, format!("Keine Webhook-URL angegeben")),
+            },
+            _ => (benutzer.email.clone(), None),
         };
 
+        let abo_return = super::write_to_root_db(
+            DbChangeOp::AboNeu(AboNeuArgs {
+                typ: email_oder_webhook.clone(),
+                blatt: format!("{amtsgericht}/{grundbuchbezirk}/{blatt}"),
+                text: text,
+                aktenzeichen: form.tag.clone(),
+                besitzer: Some(benutzer.email.clone()),
+                webhook_secret: webhook_secret.clone(),
+                detailstufe: form.detailstufe.clone(),
+                abteilungen: form.abteilungen.clone(),
+                aenderungsarten: form.aenderungsarten.clone(),
+                flurstueck: form.flurstueck.clone(),
+                id: None,
+            }),
+            &benutzer.email,
+            &*app_state,
+        )
+        .await;
+
         match abo_return {
             Ok(s) => HttpResponse::Ok().content_type("application/json").body(
-                serde_json::to_string_pretty(&AboNeuAnfrage::Ok(AboNeuAnfrageOk {}))
-                    .unwrap_or_default(),
+                serde_json::to_string_pretty(&AboNeuAnfrage::Ok(AboNeuAnfrageOk {
+                    webhook_secret,
+                    ..Default::default()
+                }))
+                .unwrap_or_default(),
             ),
             Err(e) => response_err(500, format!("Fehler beim Erstellen des Abonnements: {e}")),
         }
@@ -2758,6 +5312,86 @@ pub mod abo {
         path: web::Path<String>,
         req: HttpRequest,
     ) -> impl Responder {
+        let konfiguration = app_state.konfiguration();
+        let response_err = |code: usize, text: String| {
+            let json = serde_json::to_string_pretty(&AboNeuAnfrage::Err(AboNeuAnfrageErr {
+                code: code,
+                text: text,
+            }))
+            .unwrap_or_default();
+
+            HttpResponse::Ok()
+                .content_type("application/json")
+                .body(json)
+        };
+
+        let (_token, benutzer) =
+            match super::get_benutzer_from_httpauth_scope(&konfiguration, &req, ApiScope::AboManage).await {
+                Ok(o) => o,
+                Err(e) => {
+                    return e;
+                }
+            };
+        let id = &*path;
+
+        if let Err((code, e)) = get_eigenes_abo(&konfiguration, id, &benutzer) {
+            return response_err(code, e);
+        }
+
+        let abo_return = super::write_to_root_db(
+            DbChangeOp::AboLoeschen(AboLoeschenArgs {
+                id: id.to_string(),
+            }),
+            &benutzer.email,
+            &*app_state,
+        )
+        .await;
+        match abo_return {
+            Ok(()) => HttpResponse::Ok().content_type("application/json").body(
+                serde_json::to_string_pretty(&AboNeuAnfrage::Ok(AboNeuAnfrageOk::default()))
+                    .unwrap_or_default(),
+            ),
+            Err(e) => response_err(500, format!("Fehler beim Löschen des Abonnements: {e}")),
+        }
+    }
+
+    /// Abo des Benutzers (oder beliebiges mit "Abonnements verwalten"),
+    /// bei API-Schlüsseln nur innerhalb der freigegebenen Bereiche
+    fn get_eigenes_abo(
+        konfiguration: &Konfiguration,
+        id: &str,
+        benutzer: &crate::models::BenutzerInfo,
+    ) -> Result<AboEintrag, (usize, String)> {
+        let abo = crate::db::get_abo(konfiguration, id)
+            .map_err(|e| (500, e))?
+            .filter(|a| a.gehoert_zu(benutzer) || benutzer.hat_berechtigung(Berechtigung::AbosVerwalten))
+            .ok_or((404, format!("Abonnement {id} nicht gefunden")))?;
+        benutzer
+            .api_bereich_pruefen(&abo.info.amtsgericht, &abo.info.grundbuchbezirk)
+            .map_err(|e| (403, e))?;
+        Ok(abo)
+    }
+
+    /// Webhook-Abo des Benutzers (oder beliebiges mit "Abonnements verwalten")
+    fn get_webhook_abo(
+        konfiguration: &Konfiguration,
+        id: &str,
+        benutzer: &crate::models::BenutzerInfo,
+    ) -> Result<AboEintrag, (usize, String)> {
+        let abo = get_eigenes_abo(konfiguration, id, benutzer)?;
+        if abo.typ != "webhook" {
+            return Err((400, format!("Abonnement {id} ist kein Webhook-Abonnement")));
+        }
+        Ok(abo)
+    }
+
+    #[post("/abo-webhook-secret-erneuern/{id}")]
+    async fn abo_webhook_secret_erneuern(
+        app_state: web::Data<AppState>,
+        path: web::Path<String>,
+        req: HttpRequest,
+    ) -> impl Responder {
+        let konfiguration = app_state.konfiguration();
         let response_err = |code: usize, text: String| {
             let json = serde_json::to_string_pretty(&AboNeuAnfrage::Err(AboNeuAnfrageErr {
                 code: code,
@@ -2770,34 +5404,902 @@ pub mod abo {
                 .body(json)
         };
 
-        let (_token, benutzer) = match super::get_benutzer_from_httpauth(&req).await {
+        let (_token, benutzer) =
+            match super::get_benutzer_from_httpauth_scope(&konfiguration, &req, ApiScope::AboManage).await {
+                Ok(o) => o,
+                Err(e) => {
+                    return e;
+                }
+            };
+        let id = &*path;
+
+        if let Err((code, e)) = get_webhook_abo(&konfiguration, id, &benutzer) {
+            return response_err(code, e);
+        }
+
+        let webhook_secret = crate::signatur::schluessel_generieren();
+        let abo_return = super::write_to_root_db(
+            DbChangeOp::AboWebhookSecretErneuern {
+                id: id.to_string(),
+                webhook_secret: webhook_secret.clone(),
+            },
+            &benutzer.email,
+            &*app_state,
+        )
+        .await;
+        match abo_return {
+            Ok(()) => HttpResponse::Ok().content_type("application/json").body(
+                serde_json::to_string_pretty(&AboNeuAnfrage::Ok(AboNeuAnfrageOk {
+                    webhook_secret: Some(webhook_secret),
+                    ..Default::default()
+                }))
+                .unwrap_or_default(),
+            ),
+            Err(e) => response_err(500, format!("Fehler beim Erneuern des Webhook-Secrets: {e}")),
+        }
+    }
+
+    #[post("/abo-webhook-testen/{id}")]
+    async fn abo_webhook_testen(
+        path: web::Path<String>,
+        app_state: web::Data<AppState>,
+        req: HttpRequest,
+    ) -> impl Responder {
+        let konfiguration = app_state.konfiguration();
+        let response_err = |code: usize, text: String| {
+            let json = serde_json::to_string_pretty(&AboNeuAnfrage::Err(AboNeuAnfrageErr {
+                code: code,
+                text: text,
+            }))
+            .unwrap_or_default();
+
+            HttpResponse::Ok()
+                .content_type("application/json")
+                .body(json)
+        };
+
+        let (_token, benutzer) =
+            match super::get_benutzer_from_httpauth_scope(&konfiguration, &req, ApiScope::AboManage).await {
+                Ok(o) => o,
+                Err(e) => {
+                    return e;
+                }
+            };
+
+        let abo = match get_webhook_abo(&konfiguration, &*path, &benutzer) {
             Ok(o) => o,
-            Err(e) => {
-                return e;
+            Err((code, e)) => return response_err(code, e),
+        };
+
+        match crate::email::send_test_webhook(&konfiguration, &abo.info).await {
+            Ok(zustellungs_id) => HttpResponse::Ok().content_type("application/json").body(
+                serde_json::to_string_pretty(&AboNeuAnfrage::Ok(AboNeuAnfrageOk {
+                    zustellungs_id: Some(zustellungs_id),
+                    ..Default::default()
+                }))
+                .unwrap_or_default(),
+            ),
+            Err(e) => response_err(502, format!("Testzustellung an {} fehlgeschlagen: {e}", abo.info.text)),
+        }
+    }
+
+    /// Abonnement in `GET /abos`, `POST /abos` und `PATCH /abos/{id}` (ohne Webhook-Secret)
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct AboDaten {
+        id: String,
+        typ: String,
+        amtsgericht: String,
+        grundbuchbezirk: String,
+        /// Blatt-Nr. oder "*"
+        blatt: String,
+        /// E-Mail-Adresse bzw. URL des Webhooks
+        ziel: String,
+        aktenzeichen: Option<String>,
+        detailstufe: AboDetailstufe,
+        abteilungen: Vec<String>,
+        aenderungsarten: Vec<AenderungsArt>,
+        flurstueck: Option<String>,
+        pausiert: bool,
+    }
+
+    impl AboDaten {
+        fn neu(abo: &AboEintrag) -> Self {
+            Self {
+                id: abo.info.id.clone(),
+                typ: abo.typ.clone(),
+                amtsgericht: abo.info.amtsgericht.clone(),
+                grundbuchbezirk: abo.info.grundbuchbezirk.clone(),
+                blatt: abo.info.blatt.to_string(),
+                ziel: abo.info.text.clone(),
+                aktenzeichen: abo.info.aktenzeichen.clone(),
+                detailstufe: abo.info.detailstufe,
+                abteilungen: abo.info.abteilungen.clone(),
+                aenderungsarten: abo.info.aenderungsarten.clone(),
+                flurstueck: abo.info.flurstueck.clone(),
+                pausiert: abo.info.pausiert,
             }
+        }
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    #[serde(tag = "status")]
+    enum AboListeResponse {
+        #[serde(rename = "ok")]
+        Ok(AboListeResponseOk),
+        #[serde(rename = "error")]
+        Err(AboNeuAnfrageErr),
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct AboListeResponseOk {
+        abos: Vec<AboDaten>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    #[serde(tag = "status")]
+    enum AboResponse {
+        #[serde(rename = "ok")]
+        Ok(AboResponseOk),
+        #[serde(rename = "error")]
+        Err(AboNeuAnfrageErr),
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct AboResponseOk {
+        abo: AboDaten,
+        /// Nur bei neuen Webhook-Abos, wird nur einmal angezeigt
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        webhook_secret: Option<String>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    #[serde(tag = "status")]
+    enum AboZustellungenResponse {
+        #[serde(rename = "ok")]
+        Ok(AboZustellungenResponseOk),
+        #[serde(rename = "error")]
+        Err(AboNeuAnfrageErr),
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct AboZustellungenResponseOk {
+        zustellungen: Vec<AboZustellung>,
+    }
+
+    /// Body von `POST /abos`
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct AboAnlegenAnfrage {
+        typ: PostausgangTyp,
+        amtsgericht: String,
+        /// Bezirk oder "*" (ganzes Amtsgericht)
+        grundbuchbezirk: String,
+        /// Blatt-Nr. oder "*" (alle Blätter)
+        blatt: String,
+        /// URL des Webhooks (nur bei Typ "webhook")
+        #[serde(default)]
+        url: Option<String>,
+        #[serde(default)]
+        aktenzeichen: Option<String>,
+        #[serde(default)]
+        detailstufe: AboDetailstufe,
+        #[serde(default)]
+        abteilungen: Vec<String>,
+        #[serde(default)]
+        aenderungsarten: Vec<AenderungsArt>,
+        /// Flurstück "Gemarkung/Flur/Flurstück" (nur mit Blatt "*")
+        #[serde(default)]
+        flurstueck: Option<String>,
+    }
+
+    /// Body von `PATCH /abos/{id}`, fehlende Felder bleiben unverändert
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct AboAendernAnfrage {
+        /// "" = kein Aktenzeichen
+        #[serde(default)]
+        aktenzeichen: Option<String>,
+        #[serde(default)]
+        pausiert: Option<bool>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct AboZustellungenQuery {
+        #[serde(default)]
+        limit: Option<usize>,
+    }
+
+    /// Standardanzahl bzw. Maximum für `GET /abos/{id}/zustellungen`
+    const ZUSTELLUNGEN_LIMIT: usize = 20;
+    const ZUSTELLUNGEN_LIMIT_MAX: usize = 200;
+
+    fn json_response<T: serde::Serialize>(response: &T) -> HttpResponse {
+        HttpResponse::Ok()
+            .content_type("application/json")
+            .body(serde_json::to_string_pretty(response).unwrap_or_default())
+    }
+
+    fn abo_response(
+        konfiguration: &Konfiguration,
+        id: &str,
+        webhook_secret: Option<String>,
+    ) -> HttpResponse {
+        match crate::db::get_abo(konfiguration, id) {
+            Ok(Some(abo)) => json_response(&AboResponse::Ok(AboResponseOk {
+                abo: AboDaten::neu(&abo),
+                webhook_secret,
+            })),
+            Ok(None) => json_response(&AboResponse::Err(AboNeuAnfrageErr {
+                code: 404,
+                text: format!("Abonnement {id} nicht gefunden"),
+            })),
+            Err(e) => json_response(&AboResponse::Err(AboNeuAnfrageErr { code: 500, text: e })),
+        }
+    }
+
+    fn abo_response_err(code: usize, text: String) -> HttpResponse {
+        json_response(&AboResponse::Err(AboNeuAnfrageErr { code, text }))
+    }
+
+    /// Eigene Abonnements (Besitzer oder eigene E-Mail-Adresse)
+    #[get("/abos")]
+    async fn abos_liste(app_state: web::Data<AppState>, req: HttpRequest) -> impl Responder {
+        let konfiguration = app_state.konfiguration();
+        let (_token, benutzer) =
+            match super::get_benutzer_from_httpauth_scope(&konfiguration, &req, ApiScope::AboManage).await {
+                Ok(o) => o,
+                Err(e) => {
+                    return e;
+                }
+            };
+
+        let response = match crate::db::get_abo_eintraege_fuer_benutzer(&konfiguration, &benutzer) {
+            Ok(abos) => AboListeResponse::Ok(AboListeResponseOk {
+                abos: abos
+                    .iter()
+                    .filter(|a| benutzer.api_bereich_erlaubt(&a.info.amtsgericht, &a.info.grundbuchbezirk))
+                    .map(AboDaten::neu)
+                    .collect(),
+            }),
+            Err(e) => AboListeResponse::Err(AboNeuAnfrageErr { code: 500, text: e }),
+        };
+
+        json_response(&response)
+    }
+
+    #[post("/abos")]
+    async fn abos_neu(
+        app_state: web::Data<AppState>,
+        anfrage: web::Json<AboAnlegenAnfrage>,
+        req: HttpRequest,
+    ) -> impl Responder {
+        let konfiguration = app_state.konfiguration();
+        let (_token, benutzer) =
+            match super::get_benutzer_from_httpauth_scope(&konfiguration, &req, ApiScope::AboManage).await {
+                Ok(o) => o,
+                Err(e) => {
+                    return e;
+                }
+            };
+        let anfrage = anfrage.into_inner();
+
+        if let Err(e) = benutzer.api_bereich_pruefen(&anfrage.amtsgericht, &anfrage.grundbuchbezirk) {
+            return abo_response_err(403, e);
+        }
+
+        let (text, webhook_secret) = match anfrage.typ {
+            PostausgangTyp::Webhook => match anfrage.url.as_ref() {
+                Some(u) => match crate::webhook::ziel_pruefen(&konfiguration, u).await {
+                    Ok(u) => (u.to_string(), Some(crate::signatur::schluessel_generieren())),
+                    Err(e) => return abo_response_err(400, e),
+                },
+                None => return abo_response_err(400, format!("Keine Webhook-URL angegeben")),
+            },
+            PostausgangTyp::Email => (benutzer.email.clone(), None),
+        };
+
+        let id = crate::db::generate_uuid();
+        let abo = AboNeuArgs {
+            typ: anfrage.typ.as_str().to_string(),
+            blatt: format!(
+                "{}/{}/{}",
+                anfrage.amtsgericht.trim(),
+                anfrage.grundbuchbezirk.trim(),
+                anfrage.blatt.trim()
+            ),
+            text: text,
+            aktenzeichen: anfrage.aktenzeichen.filter(|s| !s.trim().is_empty()),
+            besitzer: Some(benutzer.email.clone()),
+            webhook_secret: webhook_secret.clone(),
+            detailstufe: Some(anfrage.detailstufe.as_str().to_string()),
+            abteilungen: Some(anfrage.abteilungen.join(",")),
+            aenderungsarten: Some(
+                anfrage
+                    .aenderungsarten
+                    .iter()
+                    .map(|a| a.as_str())
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ),
+            flurstueck: anfrage.flurstueck,
+            id: Some(id.clone()),
         };
+
+        // Ungültige Angaben vorher prüfen (400), damit Fehler beim Schreiben als 500 gemeldet werden
+        if let Err(e) = crate::db::abo_pruefen(
+            &abo.typ,
+            &abo.blatt,
+            abo.detailstufe.as_deref(),
+            abo.abteilungen.as_deref(),
+            abo.aenderungsarten.as_deref(),
+            abo.flurstueck.as_deref(),
+        ) {
+            return abo_response_err(400, e);
+        }
+
+        let abo_return = super::write_to_root_db(DbChangeOp::AboNeu(abo), &benutzer.email, &*app_state).await;
+
+        match abo_return {
+            Ok(()) => abo_response(&konfiguration, &id, webhook_secret),
+            Err(e) => abo_response_err(500, format!("Fehler beim Erstellen des Abonnements: {e}")),
+        }
+    }
+
+    #[patch("/abos/{id}")]
+    async fn abos_aendern(
+        app_state: web::Data<AppState>,
+        path: web::Path<String>,
+        anfrage: web::Json<AboAendernAnfrage>,
+        req: HttpRequest,
+    ) -> impl Responder {
+        let konfiguration = app_state.konfiguration();
+        let (_token, benutzer) =
+            match super::get_benutzer_from_httpauth_scope(&konfiguration, &req, ApiScope::AboManage).await {
+                Ok(o) => o,
+                Err(e) => {
+                    return e;
+                }
+            };
         let id = &*path;
 
-        let abo_return = if app_state.k8s_aktiv() {
-            super::write_to_root_db(
-                DbChangeOp::AboLoeschen(AboLoeschenArgs {
+        if let Err((code, e)) = get_eigenes_abo(&konfiguration, id, &benutzer) {
+            return abo_response_err(code, e);
+        }
+
+        let anfrage = anfrage.into_inner();
+        if anfrage.aktenzeichen.is_some() || anfrage.pausiert.is_some() {
+            let abo_return = super::write_to_root_db(
+                DbChangeOp::AboAendern {
                     id: id.to_string(),
-                }),
+                    aktenzeichen: anfrage.aktenzeichen,
+                    pausiert: anfrage.pausiert,
+                },
+                &benutzer.email,
                 &*app_state,
             )
-            .await
-        } else {
-            crate::db::delete_abo(
-                MountPoint::Local,
-                &id,
-            )
+            .await;
+
+            if let Err(e) = abo_return {
+                return abo_response_err(500, format!("Fehler beim Ändern des Abonnements: {e}"));
+            }
+        }
+
+        abo_response(&konfiguration, id, None)
+    }
+
+    #[delete("/abos/{id}")]
+    async fn abos_loeschen(
+        app_state: web::Data<AppState>,
+        path: web::Path<String>,
+        req: HttpRequest,
+    ) -> impl Responder {
+        let konfiguration = app_state.konfiguration();
+        let (_token, benutzer) =
+            match super::get_benutzer_from_httpauth_scope(&konfiguration, &req, ApiScope::AboManage).await {
+                Ok(o) => o,
+                Err(e) => {
+                    return e;
+                }
+            };
+        let id = &*path;
+
+        let response = match get_eigenes_abo(&konfiguration, id, &benutzer) {
+            Ok(_) => {
+                let abo_return = super::write_to_root_db(
+                    DbChangeOp::AboLoeschen(AboLoeschenArgs { id: id.to_string() }),
+                    &benutzer.email,
+                    &*app_state,
+                )
+                .await;
+                match abo_return {
+                    Ok(()) => AboNeuAnfrage::Ok(AboNeuAnfrageOk::default()),
+                    Err(e) => AboNeuAnfrage::Err(AboNeuAnfrageErr {
+                        code: 500,
+                        text: format!("Fehler beim Löschen des Abonnements: {e}"),
+                    }),
+                }
+            }
+            Err((code, text)) => AboNeuAnfrage::Err(AboNeuAnfrageErr { code, text }),
         };
-        match abo_return {
+
+        json_response(&response)
+    }
+
+    /// Letzte Zustellungen (E-Mails / Webhooks) an ein Abonnement, neueste zuerst
+    #[get("/abos/{id}/zustellungen")]
+    async fn abos_zustellungen(
+        path: web::Path<String>,
+        query: web::Query<AboZustellungenQuery>,
+        app_state: web::Data<AppState>, req: HttpRequest,
+    ) -> impl Responder {
+        let konfiguration = app_state.konfiguration();
+        let (_token, benutzer) =
+            match super::get_benutzer_from_httpauth_scope(&konfiguration, &req, ApiScope::AboManage).await {
+                Ok(o) => o,
+                Err(e) => {
+                    return e;
+                }
+            };
+        let id = &*path;
+
+        let limit = query
+            .limit
+            .unwrap_or(ZUSTELLUNGEN_LIMIT)
+            .min(ZUSTELLUNGEN_LIMIT_MAX);
+
+        let response = match get_eigenes_abo(&konfiguration, id, &benutzer)
+            .and_then(|_| crate::postausgang::get_zustellungen_fuer_abo(&konfiguration, id, limit).map_err(|e| (500, e)))
+        {
+            Ok(zustellungen) => {
+                AboZustellungenResponse::Ok(AboZustellungenResponseOk { zustellungen })
+            }
+            Err((code, text)) => AboZustellungenResponse::Err(AboNeuAnfrageErr { code, text }),
+        };
+
+        json_response(&response)
+    }
+}
+
+/// API für `/audit-log` (Berechtigung "protokolle-lesen")
+pub mod audit {
+
+    use crate::audit::{AuditEintrag, AuditFilter};
+    use crate::AppState;
+    use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
+    use serde_derive::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    #[serde(tag = "status")]
+    enum AuditLogResponse {
+        #[serde(rename = "ok")]
+        Ok(AuditLogResponseOk),
+        #[serde(rename = "error")]
+        Err(AuditLogResponseErr),
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct AuditLogResponseOk {
+        eintraege: Vec<AuditEintrag>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct AuditLogResponseErr {
+        code: usize,
+        text: String,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct AuditLogQuery {
+        akteur: Option<String>,
+        operation: Option<String>,
+        ziel: Option<String>,
+        von: Option<String>,
+        bis: Option<String>,
+        limit: Option<usize>,
+        /// `json` (Standard) oder `csv`
+        format: Option<String>,
+    }
+
+    #[get("/audit-log")]
+    async fn audit_log(
+        query: web::Query<AuditLogQuery>,
+        app_state: web::Data<AppState>,
+        req: HttpRequest,
+    ) -> impl Responder {
+        let konfiguration = app_state.konfiguration();
+        let response_err = |code: usize, text: String| {
+            let json = serde_json::to_string_pretty(&AuditLogResponse::Err(AuditLogResponseErr {
+                code: code,
+                text: text,
+            }))
+            .unwrap_or_default();
+
+            HttpResponse::Ok()
+                .content_type("application/json")
+                .body(json)
+        };
+
+        let (_token, benutzer) = match super::get_benutzer_from_httpauth(&konfiguration, &req).await {
+            Ok(o) => o,
+            Err(e) => {
+                return e;
+            }
+        };
+
+        if let Err(e) = benutzer.berechtigung_pruefen(crate::rollen::Berechtigung::ProtokolleLesen) {
+            return response_err(0, e);
+        }
+
+        let filter = AuditFilter {
+            akteur: query.akteur.clone().filter(|s| !s.trim().is_empty()),
+            operation: query.operation.clone().filter(|s| !s.trim().is_empty()),
+            ziel: query.ziel.clone().filter(|s| !s.trim().is_empty()),
+            von: query.von.clone().filter(|s| !s.trim().is_empty()),
+            bis: query.bis.clone().filter(|s| !s.trim().is_empty()),
+            limit: query.limit,
+        };
+
+        let eintraege = match crate::audit::get_audit_log(&konfiguration, &filter) {
+            Ok(o) => o,
+            Err(e) => return response_err(500, e),
+        };
+
+        match query.format.as_deref() {
+            Some("csv") => HttpResponse::Ok()
+                .content_type("text/csv; charset=utf-8")
+                .insert_header((
+                    "Content-Disposition",
+                    "attachment; filename=\"audit-log.csv\"",
+                ))
+                .body(crate::audit::als_csv(&eintraege)),
+            _ => HttpResponse::Ok().content_type("application/json").body(
+                serde_json::to_string_pretty(&AuditLogResponse::Ok(AuditLogResponseOk {
+                    eintraege,
+                }))
+                .unwrap_or_default(),
+            ),
+        }
+    }
+}
+
+/// API für `/rollen` (Berechtigung "rollen-verwalten")
+pub mod rollen {
+
+    use crate::config::Konfiguration;
+    use crate::rollen::{Berechtigung, Rolle};
+    use crate::AppState;
+    use actix_web::{delete, get, post, web, HttpRequest, HttpResponse, Responder};
+    use serde_derive::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    #[serde(tag = "status")]
+    enum RollenResponse {
+        #[serde(rename = "ok")]
+        Ok(RollenResponseOk),
+        #[serde(rename = "error")]
+        Err(RollenResponseErr),
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct RollenResponseOk {
+        rollen: Vec<Rolle>,
+        /// Alle Berechtigungen mit Beschreibung
+        berechtigungen: BTreeMap<String, String>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct RollenResponseErr {
+        code: usize,
+        text: String,
+    }
+
+    fn rollen_response(
+        konfiguration: &Konfiguration,
+        ergebnis: Result<(), (usize, String)>,
+    ) -> HttpResponse {
+        let response = match ergebnis.and_then(|_| crate::rollen::get_rollen(konfiguration).map_err(|e| (500, e))) {
+            Ok(rollen) => RollenResponse::Ok(RollenResponseOk {
+                rollen,
+                berechtigungen: Berechtigung::ALLE
+                    .iter()
+                    .map(|b| (b.as_str().to_string(), b.beschreibung().to_string()))
+                    .collect(),
+            }),
+            Err((code, text)) => RollenResponse::Err(RollenResponseErr { code, text }),
+        };
+
+        HttpResponse::Ok()
+            .content_type("application/json")
+            .body(serde_json::to_string_pretty(&response).unwrap_or_default())
+    }
+
+    /// Neue und bisherige Berechtigungen der Rolle müssen in den eigenen enthalten sein
+    pub(crate) fn rolle_speichern_pruefen(
+        konfiguration: &Konfiguration,
+        benutzer: &crate::models::BenutzerInfo,
+        rolle: &Rolle,
+    ) -> Result<(), String> {
+        benutzer.rolle_vergeben_pruefen(rolle)?;
+        if let Some(bisher) = crate::rollen::get_rolle(konfiguration, rolle.name.trim())? {
+            benutzer.rolle_vergeben_pruefen(&bisher)?;
+        }
+        Ok(())
+    }
+
+    async fn rollen_verwalter_pruefen(
+        konfiguration: &Konfiguration,
+        req: &HttpRequest,
+    ) -> Result<crate::models::BenutzerInfo, HttpResponse> {
+        let (_token, benutzer) = super::get_benutzer_from_httpauth(konfiguration, req).await?;
+        benutzer
+            .berechtigung_pruefen(Berechtigung::RollenVerwalten)
+            .map_err(|e| rollen_response(konfiguration, Err((0, e))))?;
+        Ok(benutzer)
+    }
+
+    #[get("/rollen")]
+    async fn rollen_get(app_state: web::Data<AppState>, req: HttpRequest) -> impl Responder {
+        let konfiguration = app_state.konfiguration();
+        match rollen_verwalter_pruefen(&konfiguration, &req).await {
+            Ok(_) => rollen_response(&konfiguration, Ok(())),
+            Err(e) => e,
+        }
+    }
+
+    /// Legt eine Rolle an oder ersetzt sie (Body: `Rolle`)
+    #[post("/rollen")]
+    async fn rollen_post(
+        app_state: web::Data<AppState>,
+        rolle: web::Json<Rolle>,
+        req: HttpRequest,
+    ) -> impl Responder {
+        let konfiguration = app_state.konfiguration();
+        let benutzer = match rollen_verwalter_pruefen(&konfiguration, &req).await {
+            Ok(o) => o,
+            Err(e) => return e,
+        };
+
+        let rolle = rolle.into_inner();
+        if let Err(e) = rolle_speichern_pruefen(&konfiguration, &benutzer, &rolle) {
+            return rollen_response(&konfiguration, Err((2, e)));
+        }
+
+        let ergebnis = crate::api::write_to_root_db(
+            super::commit::DbChangeOp::RolleSpeichern(rolle),
+            &benutzer.email,
+            &*app_state,
+        )
+        .await;
+
+        rollen_response(&konfiguration, ergebnis.map_err(|e| (2, e)))
+    }
+
+    #[delete("/rollen/{name}")]
+    async fn rollen_delete(
+        app_state: web::Data<AppState>,
+        name: web::Path<String>,
+        req: HttpRequest,
+    ) -> impl Responder {
+        let konfiguration = app_state.konfiguration();
+        let benutzer = match rollen_verwalter_pruefen(&konfiguration, &req).await {
+            Ok(o) => o,
+            Err(e) => return e,
+        };
+
+        let ergebnis = crate::api::write_to_root_db(
+            super::commit::DbChangeOp::RollenLoeschen {
+                namen: vec![name.into_inner()],
+            },
+            &benutzer.email,
+            &*app_state,
+        )
+        .await;
+
+        rollen_response(&konfiguration, ergebnis.map_err(|e| (2, e)))
+    }
+}
+
+/// API für `/einsichtsprotokoll`
+pub mod einsicht {
+
+    use crate::einsicht::{EinsichtEintrag, EinsichtFilter};
+    use crate::AppState;
+    use actix_web::{get, web, HttpRequest, HttpResponse, Responder};
+    use serde_derive::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    #[serde(tag = "status")]
+    enum EinsichtsprotokollResponse {
+        #[serde(rename = "ok")]
+        Ok(EinsichtsprotokollResponseOk),
+        #[serde(rename = "error")]
+        Err(EinsichtsprotokollResponseErr),
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct EinsichtsprotokollResponseOk {
+        eintraege: Vec<EinsichtEintrag>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct EinsichtsprotokollResponseErr {
+        code: usize,
+        text: String,
+    }
+
+    #[get("/einsichtsprotokoll")]
+    async fn einsichtsprotokoll(
+        query: web::Query<EinsichtFilter>,
+        app_state: web::Data<AppState>, req: HttpRequest,
+    ) -> impl Responder {
+        let konfiguration = app_state.konfiguration();
+        let (_token, benutzer) = match super::get_benutzer_from_httpauth(&konfiguration, &req).await {
+            Ok(o) => o,
+            Err(e) => {
+                return e;
+            }
+        };
+
+        let response = match crate::einsicht::get_einsichten(&konfiguration, &benutzer, &query) {
+            Ok(eintraege) => {
+                EinsichtsprotokollResponse::Ok(EinsichtsprotokollResponseOk { eintraege })
+            }
+            Err(e) => EinsichtsprotokollResponse::Err(EinsichtsprotokollResponseErr {
+                code: 500,
+                text: e,
+            }),
+        };
+
+        HttpResponse::Ok()
+            .content_type("application/json")
+            .body(serde_json::to_string_pretty(&response).unwrap_or_default())
+    }
+}
+
+/// API für `/api-schluessel`: API-Schlüssel für Maschinen-Clients anlegen
+pub mod api_schluessel {
+
+    use super::commit::DbChangeOp;
+    use crate::api_schluessel::{ApiSchluesselAnfrage, ApiSchluesselInfo};
+    use crate::AppState;
+    use actix_web::{get, post, web, HttpRequest, HttpResponse, Responder};
+    use serde_derive::{Deserialize, Serialize};
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    #[serde(tag = "status")]
+    enum ApiSchluesselResponse {
+        #[serde(rename = "ok")]
+        Ok(ApiSchluesselResponseOk),
+        #[serde(rename = "error")]
+        Err(ApiSchluesselResponseErr),
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct ApiSchluesselResponseOk {
+        id: String,
+        /// Klartext-Schlüssel, wird nur einmal angezeigt
+        schluessel: String,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    #[serde(tag = "status")]
+    enum ApiSchluesselListeResponse {
+        #[serde(rename = "ok")]
+        Ok(ApiSchluesselListeResponseOk),
+        #[serde(rename = "error")]
+        Err(ApiSchluesselResponseErr),
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct ApiSchluesselListeResponseOk {
+        schluessel: Vec<ApiSchluesselInfo>,
+    }
+
+    #[derive(Debug, Clone, Serialize, Deserialize)]
+    struct ApiSchluesselResponseErr {
+        code: usize,
+        text: String,
+    }
+
+    #[get("/api-schluessel")]
+    async fn api_schluessel_liste(
+        app_state: web::Data<AppState>,
+        req: HttpRequest,
+    ) -> impl Responder {
+        let konfiguration = app_state.konfiguration();
+        let (_token, benutzer) = match super::get_benutzer_from_httpauth(&konfiguration, &req).await {
+            Ok(o) => o,
+            Err(e) => {
+                return e;
+            }
+        };
+
+        let response = match crate::api_schluessel::get_api_schluessel(&konfiguration, &benutzer) {
+            Ok(schluessel) => {
+                ApiSchluesselListeResponse::Ok(ApiSchluesselListeResponseOk { schluessel })
+            }
+            Err(e) => ApiSchluesselListeResponse::Err(ApiSchluesselResponseErr {
+                code: 500,
+                text: e,
+            }),
+        };
+
+        HttpResponse::Ok()
+            .content_type("application/json")
+            .body(serde_json::to_string_pretty(&response).unwrap_or_default())
+    }
+
+    #[post("/api-schluessel")]
+    async fn api_schluessel_neu(
+        app_state: web::Data<AppState>,
+        anfrage: web::Json<ApiSchluesselAnfrage>,
+        req: HttpRequest,
+    ) -> impl Responder {
+        let response_err = |code: usize, text: String| {
+            let json =
+                serde_json::to_string_pretty(&ApiSchluesselResponse::Err(ApiSchluesselResponseErr {
+                    code: code,
+                    text: text,
+                }))
+                .unwrap_or_default();
+
+            HttpResponse::Ok()
+                .content_type("application/json")
+                .body(json)
+        };
+
+        let (_token, benutzer) = match super::get_benutzer_from_httpauth(&app_state.konfiguration(), &req).await {
+            Ok(o) => o,
+            Err(e) => {
+                return e;
+            }
+        };
+
+        let email = match anfrage.benutzer.as_deref().map(|s| s.trim()) {
+            None | Some("") => benutzer.email.clone(),
+            Some(s) if s == benutzer.email => benutzer.email.clone(),
+            Some(s) if benutzer.hat_berechtigung(crate::rollen::Berechtigung::BenutzerVerwalten) => {
+                s.to_string()
+            }
+            Some(_) => {
+                return response_err(
+                    0,
+                    format!("Nur Benutzerverwalter dürfen API-Schlüssel für andere Benutzer anlegen"),
+                );
+            }
+        };
+
+        // Ein fremder Schlüssel handelt mit den Rechten des anderen Benutzers
+        if email != benutzer.email {
+            if let Err(e) = benutzer.benutzer_bearbeiten_pruefen(&app_state.konfiguration(), std::slice::from_ref(&email)) {
+                return response_err(0, e);
+            }
+        }
+
+        let (neu, schluessel) = match anfrage.generieren() {
+            Ok(o) => o,
+            Err(e) => return response_err(1, e),
+        };
+
+        let id = neu.id.clone();
+
+        let result = super::write_to_root_db(
+            DbChangeOp::ApiSchluesselNeu {
+                email,
+                schluessel: neu,
+            },
+            &benutzer.email,
+            &*app_state,
+        )
+        .await;
+
+        match result {
             Ok(()) => HttpResponse::Ok().content_type("application/json").body(
-                serde_json::to_string_pretty(&AboNeuAnfrage::Ok(AboNeuAnfrageOk {}))
-                    .unwrap_or_default(),
+                serde_json::to_string_pretty(&ApiSchluesselResponse::Ok(
+                    ApiSchluesselResponseOk { id, schluessel },
+                ))
+                .unwrap_or_default(),
             ),
-            Err(e) => response_err(500, format!("Fehler beim Löschen des Abonnements: {e}")),
+            Err(e) => response_err(500, format!("Fehler beim Anlegen des API-Schlüssels: {e}")),
         }
     }
 }
@@ -0,0 +1,400 @@
+//! API-Schlüssel für Maschinen-Clients
+//!
+//! Ein API-Schlüssel gehört zu einem Benutzer und wird wie ein Session-Token
+//! im `Authorization: Bearer ...`-Header gesendet. Anders als ein Session-Token
+//! läuft er nicht nach 30 Minuten ab, sondern optional zu einem festen Datum.
+//! Jeder Schlüssel erlaubt nur die angegebenen Scopes und kann zusätzlich auf
+//! einzelne Amtsgerichte bzw. Bezirke eingeschränkt werden. Gespeichert wird nur
+//! der SHA-256-Hash des Schlüssels, der Klartext wird einmalig beim Anlegen
+//! zurückgegeben.
+
+use crate::api::commit::DbChangeOp;
+use crate::config::Konfiguration;
+use crate::models::{BenutzerInfo, MountPoint};
+use crate::AppState;
+use chrono::{DateTime, NaiveDate, Utc};
+use rusqlite::Connection;
+use serde_derive::{Deserialize, Serialize};
+
+/// Präfix, an dem API-Schlüssel von Session-Tokens unterschieden werden
+pub const API_SCHLUESSEL_PRAEFIX: &str = "dgb_";
+
+/// `zuletzt_benutzt` wird höchstens in diesem Abstand aktualisiert
+const ZULETZT_BENUTZT_INTERVALL_MINUTEN: i64 = 5;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum ApiScope {
+    #[serde(rename = "read-gbx")]
+    ReadGbx,
+    #[serde(rename = "read-pdf")]
+    ReadPdf,
+    #[serde(rename = "search")]
+    Search,
+    #[serde(rename = "upload")]
+    Upload,
+    #[serde(rename = "abo-manage")]
+    AboManage,
+}
+
+impl ApiScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ApiScope::ReadGbx => "read-gbx",
+            ApiScope::ReadPdf => "read-pdf",
+            ApiScope::Search => "search",
+            ApiScope::Upload => "upload",
+            ApiScope::AboManage => "abo-manage",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.trim() {
+            "read-gbx" => Some(ApiScope::ReadGbx),
+            "read-pdf" => Some(ApiScope::ReadPdf),
+            "search" => Some(ApiScope::Search),
+            "upload" => Some(ApiScope::Upload),
+            "abo-manage" => Some(ApiScope::AboManage),
+            _ => None,
+        }
+    }
+}
+
+/// Einschränkung auf ein Amtsgericht (und optional einen Bezirk)
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct ApiBereich {
+    pub amtsgericht: String,
+    pub bezirk: Option<String>,
+}
+
+/// API-Schlüssel ohne den Schlüssel selbst
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApiSchluesselInfo {
+    pub id: String,
+    pub benutzer: String,
+    pub name: String,
+    pub scopes: Vec<ApiScope>,
+    /// Leer = keine Einschränkung
+    pub bereiche: Vec<ApiBereich>,
+    pub erstellt_am: String,
+    pub gueltig_bis: Option<String>,
+    pub zuletzt_benutzt: Option<String>,
+}
+
+impl ApiSchluesselInfo {
+    pub fn bereich_erlaubt(&self, amtsgericht: &str, bezirk: &str) -> bool {
+        self.bereiche.is_empty()
+            || self.bereiche.iter().any(|b| {
+                b.amtsgericht == amtsgericht && b.bezirk.as_deref().map_or(true, |bz| bz == bezirk)
+            })
+    }
+}
+
+/// Neuer API-Schlüssel, wie er über `DbChangeOp::ApiSchluesselNeu` gespeichert wird
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApiSchluesselNeu {
+    pub id: String,
+    pub name: String,
+    pub schluessel_hash: String,
+    pub scopes: Vec<ApiScope>,
+    pub bereiche: Vec<ApiBereich>,
+    pub gueltig_bis: Option<String>,
+}
+
+/// Body von `POST /api-schluessel`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ApiSchluesselAnfrage {
+    pub name: String,
+    pub scopes: Vec<ApiScope>,
+    #[serde(default)]
+    pub bereiche: Vec<ApiBereich>,
+    /// Datum (`2023-12-31`) oder Zeitpunkt (RFC 3339), leer = unbegrenzt
+    #[serde(default)]
+    pub gueltig_bis: Option<String>,
+    /// E-Mail des Benutzers, für den der Schlüssel angelegt wird (nur Administratoren)
+    #[serde(default)]
+    pub benutzer: Option<String>,
+}
+
+impl ApiSchluesselAnfrage {
+    /// Prüft die Anfrage und generiert einen neuen Schlüssel,
+    /// gibt den Datenbank-Eintrag und den Klartext-Schlüssel zurück
+    pub fn generieren(&self) -> Result<(ApiSchluesselNeu, String), String> {
+        let name = self.name.trim();
+        if name.is_empty() {
+            return Err(format!("Kein Name für den API-Schlüssel angegeben"));
+        }
+
+        if self.scopes.is_empty() {
+            return Err(format!("Keine Scopes für den API-Schlüssel angegeben"));
+        }
+
+        let gueltig_bis = match self.gueltig_bis.as_deref().map(|s| s.trim()) {
+            None | Some("") => None,
+            Some(s) => {
+                let g = gueltig_bis_parsen(s)
+                    .ok_or(format!("Ungültiges Ablaufdatum \"{s}\""))?;
+                if g < Utc::now() {
+                    return Err(format!("Ablaufdatum \"{s}\" liegt in der Vergangenheit"));
+                }
+                Some(g.to_rfc3339())
+            }
+        };
+
+        let mut scopes = self.scopes.clone();
+        scopes.sort();
+        scopes.dedup();
+
+        let bereiche = self
+            .bereiche
+            .iter()
+            .map(|b| ApiBereich {
+                amtsgericht: b.amtsgericht.trim().to_string(),
+                bezirk: b
+                    .bezirk
+                    .as_ref()
+                    .map(|s| s.trim().to_string())
+                    .filter(|s| !s.is_empty()),
+            })
+            .filter(|b| !b.amtsgericht.is_empty())
+            .collect();
+
+        let schluessel = format!(
+            "{API_SCHLUESSEL_PRAEFIX}{}",
+            uuid::Uuid::new_v4().simple()
+        );
+
+        let neu = ApiSchluesselNeu {
+            id: crate::db::generate_uuid(),
+            name: name.to_string(),
+            schluessel_hash: crate::db::hash_token(&schluessel),
+            scopes,
+            bereiche,
+            gueltig_bis,
+        };
+
+        Ok((neu, schluessel))
+    }
+}
+
+pub(crate) fn gueltig_bis_parsen(s: &str) -> Option<DateTime<Utc>> {
+    if let Ok(d) = DateTime::parse_from_rfc3339(s) {
+        return Some(d.with_timezone(&Utc));
+    }
+    // Nur Datum: bis Ende des Tages gültig
+    let d = NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()?;
+    Some(DateTime::<Utc>::from_utc(d.and_hms(23, 59, 59), Utc))
+}
+
+impl BenutzerInfo {
+    /// Bei Anmeldung mit API-Schlüssel: prüft, ob der Schlüssel den Scope hat.
+    /// Bei Anmeldung mit Session ist alles erlaubt.
+    pub fn api_scope_pruefen(&self, scope: ApiScope) -> Result<(), String> {
+        match self.api_schluessel.as_ref() {
+            Some(k) if !k.scopes.contains(&scope) => Err(format!(
+                "API-Schlüssel \"{}\" hat keine Berechtigung \"{}\"",
+                k.name,
+                scope.as_str()
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Bei Anmeldung mit API-Schlüssel: prüft die Einschränkung auf Amtsgerichte / Bezirke
+    pub fn api_bereich_erlaubt(&self, amtsgericht: &str, bezirk: &str) -> bool {
+        self.api_schluessel
+            .as_ref()
+            .map_or(true, |k| k.bereich_erlaubt(amtsgericht, bezirk))
+    }
+
+    pub fn api_bereich_pruefen(&self, amtsgericht: &str, bezirk: &str) -> Result<(), String> {
+        if self.api_bereich_erlaubt(amtsgericht, bezirk) {
+            Ok(())
+        } else {
+            Err(format!(
+                "API-Schlüssel ist nicht für {amtsgericht}/{bezirk} freigegeben"
+            ))
+        }
+    }
+}
+
+pub fn api_schluessel_einfuegen(
+    conn: &Connection,
+    email: &str,
+    schluessel: &ApiSchluesselNeu,
+) -> Result<(), String> {
+    let benutzer_id = conn
+        .query_row(
+            "SELECT id FROM benutzer WHERE email = ?1",
+            rusqlite::params![email],
+            |row| row.get::<usize, i32>(0),
+        )
+        .map_err(|_| format!("Kein Benutzerkonto für angegebene E-Mail-Adresse vorhanden"))?;
+
+    conn.execute(
+        "INSERT INTO api_schluessel (id, benutzer, name, schluessel_hash, scopes, bereiche, erstellt_am, gueltig_bis)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        rusqlite::params![
+            schluessel.id,
+            benutzer_id,
+            schluessel.name,
+            schluessel.schluessel_hash,
+            schluessel
+                .scopes
+                .iter()
+                .map(|s| s.as_str())
+                .collect::<Vec<_>>()
+                .join(","),
+            serde_json::to_string(&schluessel.bereiche).unwrap_or_default(),
+            Utc::now().to_rfc3339(),
+            schluessel.gueltig_bis,
+        ],
+    )
+    .map_err(|e| format!("Fehler beim Anlegen des API-Schlüssels: {e}"))?;
+
+    Ok(())
+}
+
+/// Löscht API-Schlüssel anhand ihrer ID. Ist `email` gesetzt, werden
+/// nur Schlüssel dieses Benutzers gelöscht.
+pub fn api_schluessel_loeschen(
+    conn: &Connection,
+    email: Option<&str>,
+    ids: &[String],
+) -> Result<(), String> {
+    for id in ids {
+        conn.execute(
+            "DELETE FROM api_schluessel WHERE id = ?1
+            AND (?2 IS NULL OR benutzer = (SELECT id FROM benutzer WHERE email = ?2))",
+            rusqlite::params![id, email],
+        )
+        .map_err(|e| format!("Fehler beim Löschen des API-Schlüssels: {e}"))?;
+    }
+
+    Ok(())
+}
+
+pub fn api_schluessel_benutzt(
+    conn: &Connection,
+    id: &str,
+    zeitpunkt: &str,
+) -> Result<(), String> {
+    conn.execute(
+        "UPDATE api_schluessel SET zuletzt_benutzt = ?1 WHERE id = ?2",
+        rusqlite::params![zeitpunkt, id],
+    )
+    .map_err(|e| format!("Fehler beim Aktualisieren des API-Schlüssels: {e}"))?;
+
+    Ok(())
+}
+
+const API_SCHLUESSEL_SPALTEN: &str = "a.id, b.email, a.name, a.scopes, a.bereiche, a.erstellt_am, a.gueltig_bis, a.zuletzt_benutzt";
+
+fn api_schluessel_aus_zeile(row: &rusqlite::Row) -> rusqlite::Result<ApiSchluesselInfo> {
+    let scopes = row.get::<usize, String>(3)?;
+    let bereiche = row.get::<usize, String>(4)?;
+    Ok(ApiSchluesselInfo {
+        id: row.get(0)?,
+        benutzer: row.get(1)?,
+        name: row.get(2)?,
+        scopes: scopes.split(',').filter_map(ApiScope::from_str).collect(),
+        bereiche: serde_json::from_str(&bereiche).unwrap_or_default(),
+        erstellt_am: row.get(5)?,
+        gueltig_bis: row.get(6)?,
+        zuletzt_benutzt: row.get(7)?,
+    })
+}
+
+/// Sucht den Benutzer zu einem (noch gültigen) API-Schlüssel
+pub fn get_user_from_api_schluessel(
+    konfiguration: &Konfiguration,
+    schluessel: &str,
+) -> Result<BenutzerInfo, String> {
+    let conn = crate::db::verbindung_oeffnen(konfiguration, MountPoint::Local)
+        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+
+    let (info, benutzer_id) = conn
+        .query_row(
+            &format!(
+                "SELECT {API_SCHLUESSEL_SPALTEN}, a.benutzer
+                FROM api_schluessel a JOIN benutzer b ON a.benutzer = b.id
+                WHERE a.schluessel_hash = ?1"
+            ),
+            rusqlite::params![crate::db::hash_token(schluessel)],
+            |row| Ok((api_schluessel_aus_zeile(row)?, row.get::<usize, i32>(8)?)),
+        )
+        .map_err(|_| format!("Ungültiger API-Schlüssel"))?;
+
+    if let Some(g) = info.gueltig_bis.as_deref() {
+        let g = DateTime::parse_from_rfc3339(g).map_err(|_| format!("Ungültiger API-Schlüssel"))?;
+        if Utc::now() > g {
+            return Err(format!("API-Schlüssel abgelaufen"));
+        }
+    }
+
+    let mut benutzer = crate::db::get_benutzer_by_id(&conn, benutzer_id)?;
+    benutzer.api_schluessel = Some(info);
+    Ok(benutzer)
+}
+
+/// Benutzerverwalter sehen alle API-Schlüssel, andere Benutzer nur ihre eigenen
+pub fn get_api_schluessel(
+    konfiguration: &Konfiguration,
+    benutzer: &BenutzerInfo,
+) -> Result<Vec<ApiSchluesselInfo>, String> {
+    let conn = crate::db::verbindung_oeffnen(konfiguration, MountPoint::Local)
+        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {API_SCHLUESSEL_SPALTEN}
+            FROM api_schluessel a JOIN benutzer b ON a.benutzer = b.id
+            WHERE ?1 OR a.benutzer = ?2
+            ORDER BY b.email, a.name"
+        ))
+        .map_err(|e| format!("Fehler beim Auslesen der API-Schlüssel: {e}"))?;
+
+    let schluessel = stmt
+        .query_map(
+            rusqlite::params![
+                benutzer.hat_berechtigung(crate::rollen::Berechtigung::BenutzerVerwalten),
+                benutzer.id
+            ],
+            api_schluessel_aus_zeile,
+        )
+        .map_err(|e| format!("Fehler beim Auslesen der API-Schlüssel: {e}"))?
+        .filter_map(|r| r.ok())
+        .collect();
+
+    Ok(schluessel)
+}
+
+/// Aktualisiert `zuletzt_benutzt`, falls die letzte Aktualisierung länger
+/// als `ZULETZT_BENUTZT_INTERVALL_MINUTEN` zurückliegt
+pub async fn zuletzt_benutzt_aktualisieren(benutzer: &BenutzerInfo, app_state: &AppState) {
+    let info = match benutzer.api_schluessel.as_ref() {
+        Some(s) => s,
+        None => return,
+    };
+
+    let jetzt = Utc::now();
+    let aktuell = info
+        .zuletzt_benutzt
+        .as_deref()
+        .and_then(|z| DateTime::parse_from_rfc3339(z).ok())
+        .map_or(false, |z| {
+            jetzt.signed_duration_since(z) < chrono::Duration::minutes(ZULETZT_BENUTZT_INTERVALL_MINUTEN)
+        });
+
+    if aktuell {
+        return;
+    }
+
+    let change = DbChangeOp::ApiSchluesselBenutzt {
+        id: info.id.clone(),
+        zeitpunkt: jetzt.to_rfc3339(),
+    };
+
+    if let Err(e) = crate::api::write_to_root_db(change, &benutzer.email, app_state).await {
+        log::error!("API-Schlüssel {}: zuletzt_benutzt nicht aktualisiert: {e}", info.id);
+    }
+}
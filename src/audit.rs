@@ -0,0 +1,203 @@
+//! Audit-Log für alle Änderungen an der Benutzerdatenbank
+//!
+//! Jede `DbChangeOp` wird in `commit::db_change_inner` in derselben Transaktion wie
+//! die Änderung selbst in die Tabelle `audit_log` geschrieben (wer, wann, welche
+//! Operation, welches Ziel).
+//! Die Tabelle ist append-only: `UPDATE` und `DELETE` werden per Trigger abgelehnt.
+//! Passwörter, Tokens und andere Geheimnisse werden vor dem Speichern entfernt.
+
+use crate::api::commit::DbChangeOp;
+use crate::config::Konfiguration;
+use crate::models::MountPoint;
+use chrono::Utc;
+use rusqlite::Connection;
+use serde_derive::{Deserialize, Serialize};
+
+/// Ersatzwert für entfernte Felder
+const GESCHWAERZT: &str = "***";
+
+/// Felder, deren Name einen dieser Teile enthält, werden nicht gespeichert
+const GEHEIME_FELDER: &[&str] = &["passwort", "password", "token", "secret", "geheimnis", "private", "hash"];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AuditEintrag {
+    pub id: i64,
+    pub zeitpunkt: String,
+    pub akteur: String,
+    pub operation: String,
+    pub ziel: String,
+    pub daten: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct AuditFilter {
+    /// E-Mail des auslösenden Benutzers (exakt)
+    pub akteur: Option<String>,
+    /// Name der Operation, z.B. `benutzer-neu` (exakt)
+    pub operation: Option<String>,
+    /// Teilstring im Ziel der Operation
+    pub ziel: Option<String>,
+    /// Einträge ab diesem Zeitpunkt (RFC 3339)
+    pub von: Option<String>,
+    /// Einträge bis zu diesem Zeitpunkt (RFC 3339)
+    pub bis: Option<String>,
+    /// Maximale Anzahl der Einträge (neueste zuerst)
+    pub limit: Option<usize>,
+}
+
+/// Schreibt eine ausgeführte Änderung in das Audit-Log
+pub fn protokollieren(
+    conn: &Connection,
+    akteur: &str,
+    change_op: &DbChangeOp,
+) -> Result<(), String> {
+    // Einsichten stehen bereits im Einsichtsprotokoll, die Nutzung
+    // eines API-Schlüssels in der Tabelle api_schluessel (ebenso der letzte
+    // TOTP-Zeitschritt in der Tabelle zwei_faktor), Zähler der
+    // Ratenbegrenzung in eigenen Tabellen (nur Kontosperren werden protokolliert),
+    // Benachrichtigungen an Abonnenten in der Tabelle postausgang
+    match change_op {
+        DbChangeOp::EinsichtenProtokollieren { .. }
+        | DbChangeOp::ApiSchluesselBenutzt { .. }
+        | DbChangeOp::TotpSchrittBenutzen { .. }
+        | DbChangeOp::FehlanmeldungProtokollieren { sperren: false, .. }
+        | DbChangeOp::FehlanmeldungenZuruecksetzen { .. }
+        | DbChangeOp::ZugriffAnfrageProtokollieren { .. }
+        | DbChangeOp::PasswortVergessenProtokollieren { .. }
+        | DbChangeOp::ZugriffeAdminBenachrichtigt { .. }
+        | DbChangeOp::PostausgangEinreihen { .. }
+        | DbChangeOp::PostausgangErgebnisSpeichern { .. } => return Ok(()),
+        _ => {}
+    }
+
+    let mut daten = serde_json::to_value(change_op).unwrap_or_default();
+    schwaerzen(&mut daten);
+
+    // Der Wert einer Einstellung ist nur anhand ihres Namens als geheim erkennbar
+    if let DbChangeOp::BearbeiteEinstellung { id, .. } = change_op {
+        let einstellung = conn
+            .query_row(
+                "SELECT einstellung FROM einstellungen WHERE id = ?1",
+                rusqlite::params![id],
+                |row| row.get::<usize, String>(0),
+            )
+            .unwrap_or_default();
+        if ist_geheim(&einstellung) {
+            if let Some(wert) = daten
+                .get_mut("BearbeiteEinstellung")
+                .and_then(|v| v.get_mut("neuer_wert"))
+            {
+                *wert = serde_json::Value::String(GESCHWAERZT.to_string());
+            }
+        }
+    }
+
+    conn.execute(
+        "INSERT INTO audit_log (zeitpunkt, akteur, operation, ziel, daten) VALUES (?1, ?2, ?3, ?4, ?5)",
+        rusqlite::params![
+            Utc::now().to_rfc3339(),
+            akteur,
+            change_op.get_operation(),
+            change_op.get_ziel(),
+            serde_json::to_string(&daten).unwrap_or_default(),
+        ],
+    )
+    .map_err(|e| format!("Fehler beim Schreiben des Audit-Logs: {e}"))?;
+
+    Ok(())
+}
+
+fn ist_geheim(feld: &str) -> bool {
+    let feld = feld.to_lowercase();
+    GEHEIME_FELDER.iter().any(|g| feld.contains(g))
+}
+
+/// Ersetzt rekursiv alle Werte von geheimen Feldern
+fn schwaerzen(wert: &mut serde_json::Value) {
+    match wert {
+        serde_json::Value::Object(map) => {
+            for (k, v) in map.iter_mut() {
+                if ist_geheim(k) {
+                    *v = serde_json::Value::String(GESCHWAERZT.to_string());
+                } else {
+                    schwaerzen(v);
+                }
+            }
+        }
+        serde_json::Value::Array(a) => {
+            for v in a.iter_mut() {
+                schwaerzen(v);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Liest das Audit-Log (neueste Einträge zuerst)
+pub fn get_audit_log(
+    konfiguration: &Konfiguration,
+    filter: &AuditFilter,
+) -> Result<Vec<AuditEintrag>, String> {
+    let conn = crate::db::verbindung_oeffnen(konfiguration, MountPoint::Local)
+        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, zeitpunkt, akteur, operation, ziel, daten FROM audit_log
+            WHERE (?1 IS NULL OR akteur = ?1)
+            AND (?2 IS NULL OR operation = ?2)
+            AND (?3 IS NULL OR instr(ziel, ?3) > 0)
+            AND (?4 IS NULL OR zeitpunkt >= ?4)
+            AND (?5 IS NULL OR zeitpunkt <= ?5)
+            ORDER BY id DESC
+            LIMIT ?6",
+        )
+        .map_err(|e| format!("Fehler beim Auslesen des Audit-Logs: {e}"))?;
+
+    let limit = filter.limit.map(|l| l as i64).unwrap_or(-1);
+
+    let eintraege = stmt
+        .query_map(
+            rusqlite::params![
+                filter.akteur,
+                filter.operation,
+                filter.ziel,
+                filter.von,
+                filter.bis,
+                limit
+            ],
+            |row| {
+                Ok(AuditEintrag {
+                    id: row.get(0)?,
+                    zeitpunkt: row.get(1)?,
+                    akteur: row.get(2)?,
+                    operation: row.get(3)?,
+                    ziel: row.get(4)?,
+                    daten: row.get(5)?,
+                })
+            },
+        )
+        .map_err(|e| format!("Fehler beim Auslesen des Audit-Logs: {e}"))?
+        .filter_map(|r| r.ok())
+        .collect();
+
+    Ok(eintraege)
+}
+
+/// Exportiert Audit-Log-Einträge als CSV (Trennzeichen `;`)
+pub fn als_csv(eintraege: &[AuditEintrag]) -> String {
+    let feld = |s: &str| format!("\"{}\"", s.replace('"', "\"\""));
+    let mut csv = "id;zeitpunkt;akteur;operation;ziel;daten\r\n".to_string();
+    for e in eintraege {
+        csv.push_str(&format!(
+            "{};{};{};{};{};{}\r\n",
+            e.id,
+            feld(&e.zeitpunkt),
+            feld(&e.akteur),
+            feld(&e.operation),
+            feld(&e.ziel),
+            feld(&e.daten)
+        ));
+    }
+    csv
+}
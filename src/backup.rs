@@ -0,0 +1,446 @@
+//! Sicherung und Wiederherstellung von Benutzer-Datenbank, Git-Repository und Suchindex
+//!
+//! Ein Backup ist ein `.tar.zst`-Archiv (optional PGP-verschlüsselt) mit folgendem Inhalt:
+//!
+//! - `manifest.json`: Version, Zeitpunkt, Schema-Version, Git-HEAD und SHA256-Prüfsummen
+//! - `benutzer.sqlite.db`: konsistente Kopie der Datenbank (SQLite Online-Backup)
+//! - `daten.bundle`: `git bundle` des Daten-Repositorys (fehlt, wenn noch kein Commit existiert)
+//! - `index/...`: Dateien des Suchindex
+//!
+//! Bei der Wiederherstellung werden zuerst alle Prüfsummen kontrolliert, erst danach
+//! werden die vorhandenen Dateien ersetzt (die alten Dateien bleiben als `*.alt` erhalten).
+//! Archive mit Dateien, die nicht im Manifest stehen, werden abgelehnt.
+
+use crate::config::Konfiguration;
+use crate::models::MountPoint;
+use crate::{BackupArgs, RestoreArgs};
+use chrono::Utc;
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+
+const MANIFEST: &str = "manifest.json";
+const DB_DATEI: &str = "benutzer.sqlite.db";
+const GIT_BUNDLE: &str = "daten.bundle";
+const INDEX_DIR: &str = "index";
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xB5, 0x2F, 0xFD];
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BackupManifest {
+    /// Version des dgb-server, der das Backup erstellt hat
+    pub server_version: String,
+    /// Zeitpunkt der Sicherung (RFC 3339)
+    pub erstellt_am: String,
+    /// Schema-Version der gesicherten Datenbank
+    pub schema_version: u32,
+    /// Letzter Commit im Daten-Repository
+    pub git_head: Option<String>,
+    /// Pfad im Archiv => SHA256-Prüfsumme (hex)
+    pub pruefsummen: BTreeMap<String, String>,
+}
+
+fn get_mount_point(konfiguration: &Konfiguration) -> MountPoint {
+    if konfiguration.sync_modus {
+        MountPoint::Remote
+    } else {
+        MountPoint::Local
+    }
+}
+
+fn get_temp_dir(neben: &Path, name: &str) -> PathBuf {
+    neben.join(format!(".dgb-{name}-{}", crate::db::generate_uuid()))
+}
+
+fn sha256_datei(pfad: &Path) -> Result<String, String> {
+    let mut file = std::fs::File::open(pfad)
+        .map_err(|e| format!("Konnte {} nicht öffnen: {e}", pfad.display()))?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0; 64 * 1024];
+    loop {
+        let n = file
+            .read(&mut buf)
+            .map_err(|e| format!("Konnte {} nicht lesen: {e}", pfad.display()))?;
+        if n == 0 {
+            break;
+        }
+        hasher.update(&buf[..n]);
+    }
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Sammelt alle Dateien in `dir` rekursiv als (Pfad im Archiv, Pfad auf Festplatte)
+fn sammle_dateien(
+    dir: &Path,
+    prefix: &str,
+    out: &mut Vec<(String, PathBuf)>,
+) -> Result<(), String> {
+    let entries = std::fs::read_dir(dir)
+        .map_err(|e| format!("Konnte {} nicht lesen: {e}", dir.display()))?;
+    for entry in entries {
+        let entry = entry.map_err(|e| format!("{e}"))?;
+        let pfad = entry.path();
+        let name = format!("{prefix}/{}", entry.file_name().to_string_lossy());
+        if pfad.is_dir() {
+            sammle_dateien(&pfad, &name, out)?;
+        } else {
+            out.push((name, pfad));
+        }
+    }
+    Ok(())
+}
+
+pub fn backup_erstellen(konfiguration: &Konfiguration, args: &BackupArgs) -> Result<(), String> {
+    let mount_point = get_mount_point(konfiguration);
+
+    let out_dir = args
+        .out
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .map(|p| p.to_path_buf())
+        .unwrap_or(PathBuf::from("."));
+    let temp_dir = get_temp_dir(&out_dir, "backup");
+    std::fs::create_dir_all(&temp_dir)
+        .map_err(|e| format!("Konnte {} nicht erstellen: {e}", temp_dir.display()))?;
+
+    let result = backup_erstellen_inner(args, konfiguration, mount_point, &temp_dir);
+    let _ = std::fs::remove_dir_all(&temp_dir);
+    result
+}
+
+fn backup_erstellen_inner(
+    args: &BackupArgs,
+    konfiguration: &Konfiguration,
+    mount_point: MountPoint,
+    temp_dir: &Path,
+) -> Result<(), String> {
+    // 1. Datenbank über die Online-Backup-API kopieren
+    let db_snapshot = temp_dir.join(DB_DATEI);
+    let conn = rusqlite::Connection::open(konfiguration.get_db_path(mount_point))
+        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+    conn.backup(rusqlite::DatabaseName::Main, &db_snapshot, None)
+        .map_err(|e| format!("Fehler beim Sichern der Datenbank: {e}"))?;
+    let schema_version = crate::migration::get_schema_version(&conn)
+        .map_err(|e| format!("Fehler beim Lesen der Schema-Version: {e}"))?;
+    println!("Datenbank gesichert (Schema-Version {schema_version})");
+
+    // 2. Git-Repository als Bundle sichern
+    let data_dir = konfiguration.get_data_dir(mount_point);
+    let git_head = git2::Repository::open(&data_dir)
+        .ok()
+        .and_then(|repo| repo.head().ok()?.target())
+        .map(|oid| oid.to_string());
+
+    let mut dateien = vec![(DB_DATEI.to_string(), db_snapshot)];
+
+    if let Some(head) = git_head.as_ref() {
+        let bundle = temp_dir.join(GIT_BUNDLE);
+        let output = std::process::Command::new("git")
+            .arg("-C")
+            .arg(&data_dir)
+            .arg("bundle")
+            .arg("create")
+            .arg(&bundle)
+            .arg("--all")
+            .output()
+            .map_err(|e| format!("Konnte git nicht starten: {e}"))?;
+        if !output.status.success() {
+            return Err(format!(
+                "git bundle fehlgeschlagen: {}",
+                String::from_utf8_lossy(&output.stderr)
+            ));
+        }
+        println!("Git-Repository gesichert (HEAD {head})");
+        dateien.push((GIT_BUNDLE.to_string(), bundle));
+    } else {
+        println!("Git-Repository enthält noch keine Commits, wird übersprungen");
+    }
+
+    // 3. Suchindex
+    let index_dir = konfiguration.get_index_dir();
+    if Path::new(&index_dir).exists() {
+        sammle_dateien(Path::new(&index_dir), INDEX_DIR, &mut dateien)?;
+    }
+
+    // 4. Manifest mit Prüfsummen
+    let mut pruefsummen = BTreeMap::new();
+    for (name, pfad) in dateien.iter() {
+        pruefsummen.insert(name.clone(), sha256_datei(pfad)?);
+    }
+
+    let manifest = BackupManifest {
+        server_version: env!("CARGO_PKG_VERSION").to_string(),
+        erstellt_am: Utc::now().to_rfc3339(),
+        schema_version,
+        git_head,
+        pruefsummen,
+    };
+    let manifest_pfad = temp_dir.join(MANIFEST);
+    std::fs::write(
+        &manifest_pfad,
+        serde_json::to_string_pretty(&manifest).unwrap_or_default(),
+    )
+    .map_err(|e| format!("Konnte Manifest nicht schreiben: {e}"))?;
+
+    // 5. Archiv schreiben (optional verschlüsselt)
+    let mut out_file = std::fs::File::create(&args.out)
+        .map_err(|e| format!("Konnte {} nicht erstellen: {e}", args.out.display()))?;
+
+    match args.empfaenger.as_ref() {
+        Some(empfaenger) => {
+            let cert_bytes = std::fs::read(empfaenger)
+                .map_err(|e| format!("Konnte {} nicht lesen: {e}", empfaenger.display()))?;
+            let cert = crate::pgp::parse_cert(&cert_bytes)?;
+            let policy = sequoia_openpgp::policy::StandardPolicy::new();
+            let writer = crate::pgp::encrypt(&policy, &mut out_file, &cert)
+                .map_err(|e| format!("Fehler beim Verschlüsseln: {e}"))?;
+            let writer = schreibe_archiv(writer, &manifest_pfad, &dateien)?;
+            writer
+                .finalize()
+                .map_err(|e| format!("Fehler beim Verschlüsseln: {e}"))?;
+            println!("Backup verschlüsselt für {}", cert.fingerprint());
+        }
+        None => {
+            let mut writer = schreibe_archiv(out_file, &manifest_pfad, &dateien)?;
+            writer
+                .flush()
+                .map_err(|e| format!("Fehler beim Schreiben des Archivs: {e}"))?;
+        }
+    }
+
+    println!("Backup erstellt: {}", args.out.display());
+    Ok(())
+}
+
+fn schreibe_archiv<W: Write>(
+    writer: W,
+    manifest: &Path,
+    dateien: &[(String, PathBuf)],
+) -> Result<W, String> {
+    let encoder = zstd::Encoder::new(writer, 3)
+        .map_err(|e| format!("Fehler beim Komprimieren: {e}"))?;
+    let mut tar = tar::Builder::new(encoder);
+
+    tar.append_path_with_name(manifest, MANIFEST)
+        .map_err(|e| format!("Fehler beim Schreiben des Archivs: {e}"))?;
+    for (name, pfad) in dateien.iter() {
+        tar.append_path_with_name(pfad, name)
+            .map_err(|e| format!("Fehler beim Schreiben des Archivs ({name}): {e}"))?;
+    }
+
+    let encoder = tar
+        .into_inner()
+        .map_err(|e| format!("Fehler beim Schreiben des Archivs: {e}"))?;
+    encoder
+        .finish()
+        .map_err(|e| format!("Fehler beim Komprimieren: {e}"))
+}
+
+pub fn backup_wiederherstellen(
+    konfiguration: &Konfiguration,
+    args: &RestoreArgs,
+) -> Result<(), String> {
+    let mount_point = get_mount_point(konfiguration);
+
+    let base_path = konfiguration.get_base_path(mount_point);
+    let _ = std::fs::create_dir_all(&base_path);
+    let temp_dir = get_temp_dir(Path::new(&base_path), "restore");
+    std::fs::create_dir_all(&temp_dir)
+        .map_err(|e| format!("Konnte {} nicht erstellen: {e}", temp_dir.display()))?;
+
+    let result = backup_wiederherstellen_inner(args, konfiguration, mount_point, &temp_dir);
+    let _ = std::fs::remove_dir_all(&temp_dir);
+    result
+}
+
+fn backup_wiederherstellen_inner(
+    args: &RestoreArgs,
+    konfiguration: &Konfiguration,
+    mount_point: MountPoint,
+    temp_dir: &Path,
+) -> Result<(), String> {
+    // 1. Archiv entpacken (ggf. entschlüsseln), ohne es komplett in den Speicher zu laden
+    let file = std::fs::File::open(&args.datei)
+        .map_err(|e| format!("Konnte {} nicht öffnen: {e}", args.datei.display()))?;
+    let mut reader = BufReader::new(file);
+    let unverschluesselt = reader
+        .fill_buf()
+        .map_err(|e| format!("Konnte {} nicht lesen: {e}", args.datei.display()))?
+        .starts_with(&ZSTD_MAGIC);
+
+    let manifest = if unverschluesselt {
+        entpacke_archiv(reader, temp_dir)?
+    } else {
+        let schluessel = args.schluessel.as_ref().ok_or(format!(
+            "Archiv ist verschlüsselt, bitte privaten Schlüssel mit --schluessel angeben"
+        ))?;
+        let cert_bytes = std::fs::read(schluessel)
+            .map_err(|e| format!("Konnte {} nicht lesen: {e}", schluessel.display()))?;
+        let cert = crate::pgp::parse_cert(&cert_bytes)?;
+        let policy = sequoia_openpgp::policy::StandardPolicy::new();
+        let reader = crate::pgp::decrypt(&policy, reader, &cert)
+            .map_err(|e| format!("Fehler beim Entschlüsseln: {e}"))?;
+        entpacke_archiv(reader, temp_dir)?
+    };
+
+    // 2. Prüfsummen kontrollieren, bevor irgendetwas ersetzt wird
+    for (name, pruefsumme) in manifest.pruefsummen.iter() {
+        let pfad = temp_dir.join(name);
+        let ist = sha256_datei(&pfad)?;
+        if ist != *pruefsumme {
+            return Err(format!(
+                "Prüfsumme von {name} stimmt nicht überein (erwartet {pruefsumme}, ist {ist})"
+            ));
+        }
+    }
+
+    if !manifest.pruefsummen.contains_key(DB_DATEI) {
+        return Err(format!("Archiv enthält keine Datenbank"));
+    }
+    if manifest.git_head.is_some() && !manifest.pruefsummen.contains_key(GIT_BUNDLE) {
+        return Err(format!("Archiv enthält kein Git-Bundle"));
+    }
+
+    println!(
+        "Backup vom {} (dgb-server {}, Schema-Version {}, HEAD {}): Prüfsummen ok",
+        manifest.erstellt_am,
+        manifest.server_version,
+        manifest.schema_version,
+        manifest.git_head.as_deref().unwrap_or("<leer>")
+    );
+
+    if args.nur_pruefen {
+        return Ok(());
+    }
+
+    // 3. Ersetzen: Datenbank, Git-Repository, Index
+    let db_path = konfiguration.get_db_path(mount_point);
+    let data_dir = konfiguration.get_data_dir(mount_point);
+    let index_dir = konfiguration.get_index_dir();
+
+    ersetze(&temp_dir.join(DB_DATEI), Path::new(&db_path))?;
+
+    let neues_repo = temp_dir.join("daten");
+    match manifest.git_head.as_ref() {
+        Some(head) => {
+            let output = std::process::Command::new("git")
+                .arg("clone")
+                .arg(temp_dir.join(GIT_BUNDLE))
+                .arg(&neues_repo)
+                .output()
+                .map_err(|e| format!("Konnte git nicht starten: {e}"))?;
+            if !output.status.success() {
+                return Err(format!(
+                    "git clone aus Bundle fehlgeschlagen: {}",
+                    String::from_utf8_lossy(&output.stderr)
+                ));
+            }
+            let repo = git2::Repository::open(&neues_repo).map_err(|e| format!("{e}"))?;
+            let _ = repo.remote_delete("origin");
+            let ist_head = repo
+                .head()
+                .ok()
+                .and_then(|h| h.target())
+                .map(|oid| oid.to_string());
+            if ist_head.as_ref() != Some(head) {
+                return Err(format!(
+                    "HEAD des wiederhergestellten Repositorys ({}) stimmt nicht mit Manifest überein ({head})",
+                    ist_head.unwrap_or_default()
+                ));
+            }
+        }
+        None => {
+            git2::Repository::init(&neues_repo).map_err(|e| format!("{e}"))?;
+        }
+    }
+    ersetze(&neues_repo, Path::new(&data_dir))?;
+
+    let neuer_index = temp_dir.join(INDEX_DIR);
+    if neuer_index.exists() {
+        ersetze(&neuer_index, Path::new(&index_dir))?;
+    }
+
+    println!("Backup wiederhergestellt. Bitte alle Server-Instanzen neu starten.");
+    Ok(())
+}
+
+/// Entpackt das Archiv nach `ziel` und gibt das Manifest zurück
+///
+/// Das Manifest muss der erste Eintrag im Archiv sein, alle weiteren Einträge
+/// müssen reguläre Dateien sein, die im Manifest aufgeführt sind.
+fn entpacke_archiv<R: Read>(reader: R, ziel: &Path) -> Result<BackupManifest, String> {
+    let decoder =
+        zstd::Decoder::new(reader).map_err(|e| format!("Fehler beim Dekomprimieren: {e}"))?;
+    let mut archiv = tar::Archive::new(decoder);
+    let eintraege = archiv
+        .entries()
+        .map_err(|e| format!("Fehler beim Entpacken des Archivs: {e}"))?;
+
+    let mut manifest: Option<BackupManifest> = None;
+    for eintrag in eintraege {
+        let mut eintrag = eintrag.map_err(|e| format!("Fehler beim Entpacken des Archivs: {e}"))?;
+        let name = eintrag
+            .path()
+            .map_err(|e| format!("Fehler beim Entpacken des Archivs: {e}"))?
+            .to_string_lossy()
+            .to_string();
+
+        if manifest.is_none() {
+            if name != MANIFEST {
+                return Err(format!("Archiv beginnt nicht mit {MANIFEST}"));
+            }
+            let mut json = String::new();
+            eintrag
+                .read_to_string(&mut json)
+                .map_err(|e| format!("Konnte {MANIFEST} nicht lesen: {e}"))?;
+            manifest = Some(
+                serde_json::from_str::<BackupManifest>(&json)
+                    .map_err(|e| format!("Ungültiges {MANIFEST}: {e}"))?,
+            );
+            continue;
+        }
+
+        let im_manifest = manifest
+            .as_ref()
+            .map(|m| m.pruefsummen.contains_key(&name))
+            .unwrap_or(false);
+        if !im_manifest || !eintrag.header().entry_type().is_file() {
+            return Err(format!(
+                "Archiv enthält {name}, das nicht im {MANIFEST} aufgeführt ist"
+            ));
+        }
+
+        let entpackt = eintrag
+            .unpack_in(ziel)
+            .map_err(|e| format!("Fehler beim Entpacken von {name}: {e}"))?;
+        if !entpackt {
+            return Err(format!("Ungültiger Pfad im Archiv: {name}"));
+        }
+    }
+
+    manifest.ok_or(format!("Archiv enthält kein {MANIFEST}"))
+}
+
+/// Verschiebt `neu` nach `ziel`, ein vorhandenes `ziel` wird zu `ziel.alt`
+fn ersetze(neu: &Path, ziel: &Path) -> Result<(), String> {
+    if let Some(parent) = ziel.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+
+    if ziel.exists() {
+        let mut alt = ziel.as_os_str().to_owned();
+        alt.push(".alt");
+        let alt = PathBuf::from(alt);
+        if alt.is_dir() {
+            let _ = std::fs::remove_dir_all(&alt);
+        } else if alt.exists() {
+            let _ = std::fs::remove_file(&alt);
+        }
+        std::fs::rename(ziel, &alt)
+            .map_err(|e| format!("Konnte {} nicht sichern: {e}", ziel.display()))?;
+    }
+
+    std::fs::rename(neu, ziel)
+        .map_err(|e| format!("Konnte {} nicht ersetzen: {e}", ziel.display()))
+}
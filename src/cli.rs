@@ -1,11 +1,12 @@
 use crate::api::commit::DbChangeOp;
+use crate::config::Konfiguration;
 use crate::db::GpgPublicKeyPair;
 use crate::{
     AboLoeschenArgs, AboNeuArgs, BenutzerLoeschenArgs, BenutzerNeuArgsCli, BezirkLoeschenArgs,
-    BezirkNeuArgs, SchluesselNeuArgs,
+    BezirkNeuArgs, DbMigrateArgs, SchluesselNeuArgs,
 };
 
-pub async fn pull_db_cli() -> Result<(), String> {
+pub async fn pull_db_cli(konfiguration: &Konfiguration) -> Result<(), String> {
     use crate::api::pull::PullResponse;
 
     let k8s = crate::k8s::is_running_in_k8s().await;
@@ -27,7 +28,11 @@ pub async fn pull_db_cli() -> Result<(), String> {
         }
 
         let res = client
-            .post(&format!("http://{}:8081/pull-db", peer.ip))
+            .post(&format!(
+                "http://{}:{}/pull-db",
+                peer.ip,
+                konfiguration.ports.intern
+            ))
             .send()
             .await;
 
@@ -80,8 +85,8 @@ pub async fn pull_db_cli() -> Result<(), String> {
     Ok(())
 }
 
-pub async fn pull() -> Result<(), String> {
-    use crate::{get_data_dir, MountPoint};
+pub async fn pull(konfiguration: &Konfiguration) -> Result<(), String> {
+    use crate::MountPoint;
     use git2::Repository;
     use std::path::Path;
 
@@ -91,7 +96,7 @@ pub async fn pull() -> Result<(), String> {
         return Ok(());
     }
 
-    let local_path = Path::new(&get_data_dir(MountPoint::Local)).to_path_buf();
+    let local_path = Path::new(&konfiguration.get_data_dir(MountPoint::Local)).to_path_buf();
     if !local_path.exists() {
         let _ = std::fs::create_dir(local_path.clone());
     }
@@ -105,7 +110,10 @@ pub async fn pull() -> Result<(), String> {
         .await
         .map_err(|e| format!("Konnte Sync-Server nicht finden: {e}"))?;
 
-    let data_remote = format!("git://{sync_server_ip}:9418/");
+    let data_remote = format!(
+        "git://{sync_server_ip}:{}/",
+        konfiguration.ports.git
+    );
     println!("git clone {data_remote}");
     repo.remote_add_fetch("origin", &data_remote)
         .map_err(|e| format!("git_clone({data_remote}): {e}"))?;
@@ -135,17 +143,63 @@ pub async fn pull() -> Result<(), String> {
     Ok(())
 }
 
-pub async fn create_bezirk_cli(args: &BezirkNeuArgs) -> Result<(), anyhow::Error> {
-    let app_state = crate::load_app_state().await;
-    crate::api::write_to_root_db(DbChangeOp::BezirkNeu(args.clone()), &app_state)
+pub fn db_migrate(
+    konfiguration: &Konfiguration,
+    args: &DbMigrateArgs,
+) -> Result<(), anyhow::Error> {
+    use crate::models::MountPoint;
+
+    let mount_point = if konfiguration.sync_modus {
+        MountPoint::Remote
+    } else {
+        MountPoint::Local
+    };
+
+    let db_path = konfiguration.get_db_path(mount_point);
+    if !std::path::Path::new(&db_path).exists() {
+        return Err(anyhow::anyhow!("Datenbank {db_path} existiert nicht"));
+    }
+
+    let mut conn = rusqlite::Connection::open(&db_path)?;
+    let version = crate::migration::get_schema_version(&conn)?;
+    println!("Datenbank: {db_path}");
+    println!("Schema-Version: {version}");
+
+    let migrationen = crate::migration::migrieren(&mut conn, args.dry_run)?;
+    if migrationen.is_empty() {
+        println!("Keine ausstehenden Migrationen.");
+        return Ok(());
+    }
+
+    for m in migrationen.iter() {
+        if args.dry_run {
+            println!("\r\nausstehend: {:03} {}", m.version, m.beschreibung);
+            println!("{}", m.sql);
+        } else {
+            println!("ausgeführt: {:03} {}", m.version, m.beschreibung);
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn create_bezirk_cli(
+    konfiguration: &Konfiguration,
+    args: &BezirkNeuArgs,
+) -> Result<(), anyhow::Error> {
+    let app_state = crate::load_app_state(konfiguration).await;
+    crate::api::write_to_root_db(DbChangeOp::BezirkNeu(args.clone()), "cli", &app_state)
         .await
         .map_err(|e| anyhow::anyhow!("{e}"))?;
     Ok(())
 }
 
-pub async fn delete_bezirk_cli(args: &BezirkLoeschenArgs) -> Result<(), anyhow::Error> {
-    let app_state = crate::load_app_state().await;
-    crate::api::write_to_root_db(DbChangeOp::BezirkLoeschen(args.clone()), &app_state)
+pub async fn delete_bezirk_cli(
+    konfiguration: &Konfiguration,
+    args: &BezirkLoeschenArgs,
+) -> Result<(), anyhow::Error> {
+    let app_state = crate::load_app_state(konfiguration).await;
+    crate::api::write_to_root_db(DbChangeOp::BezirkLoeschen(args.clone()), "cli", &app_state)
         .await
         .map_err(|e| anyhow::anyhow!("{e}"))?;
     Ok(())
@@ -183,34 +237,53 @@ pub fn schluessel_neu(args: &SchluesselNeuArgs) -> Result<(), anyhow::Error> {
     Ok(())
 }
 
-pub async fn create_user_cli(args: &BenutzerNeuArgsCli) -> Result<(), anyhow::Error> {
+pub async fn create_user_cli(
+    konfiguration: &Konfiguration,
+    args: &BenutzerNeuArgsCli,
+) -> Result<(), anyhow::Error> {
     let benutzer_args_json = args.into_json()?;
-    let app_state = crate::load_app_state().await;
-    crate::api::write_to_root_db(DbChangeOp::BenutzerNeu(benutzer_args_json), &app_state)
+    let app_state = crate::load_app_state(konfiguration).await;
+    crate::api::write_to_root_db(DbChangeOp::BenutzerNeu(benutzer_args_json), "cli", &app_state)
         .await
         .map_err(|e| anyhow::anyhow!("{e}"))?;
     Ok(())
 }
 
-pub async fn delete_user_cli(args: &BenutzerLoeschenArgs) -> Result<(), anyhow::Error> {
-    let app_state = crate::load_app_state().await;
-    crate::api::write_to_root_db(DbChangeOp::BenutzerLoeschen(args.clone()), &app_state)
+pub async fn delete_user_cli(
+    konfiguration: &Konfiguration,
+    args: &BenutzerLoeschenArgs,
+) -> Result<(), anyhow::Error> {
+    let app_state = crate::load_app_state(konfiguration).await;
+    crate::api::write_to_root_db(DbChangeOp::BenutzerLoeschen(args.clone()), "cli", &app_state)
         .await
         .map_err(|e| anyhow::anyhow!("{e}"))?;
     Ok(())
 }
 
-pub async fn create_abo_cli(args: &AboNeuArgs) -> Result<(), anyhow::Error> {
-    let app_state = crate::load_app_state().await;
-    crate::api::write_to_root_db(DbChangeOp::AboNeu(args.clone()), &app_state)
+pub async fn create_abo_cli(
+    konfiguration: &Konfiguration,
+    args: &AboNeuArgs,
+) -> Result<(), anyhow::Error> {
+    let app_state = crate::load_app_state(konfiguration).await;
+    let mut args = args.clone();
+    if args.typ == "webhook" {
+        args.webhook_secret = Some(crate::signatur::schluessel_generieren());
+    }
+    crate::api::write_to_root_db(DbChangeOp::AboNeu(args.clone()), "cli", &app_state)
         .await
         .map_err(|e| anyhow::anyhow!("{e}"))?;
+    if let Some(secret) = args.webhook_secret.as_ref() {
+        println!("Webhook-Secret (wird nur einmal angezeigt): {secret}");
+    }
     Ok(())
 }
 
-pub async fn delete_abo_cli(args: &AboLoeschenArgs) -> Result<(), anyhow::Error> {
-    let app_state = crate::load_app_state().await;
-    crate::api::write_to_root_db(DbChangeOp::AboLoeschen(args.clone()), &app_state)
+pub async fn delete_abo_cli(
+    konfiguration: &Konfiguration,
+    args: &AboLoeschenArgs,
+) -> Result<(), anyhow::Error> {
+    let app_state = crate::load_app_state(konfiguration).await;
+    crate::api::write_to_root_db(DbChangeOp::AboLoeschen(args.clone()), "cli", &app_state)
         .await
         .map_err(|e| anyhow::anyhow!("{e}"))?;
     Ok(())
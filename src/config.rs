@@ -0,0 +1,603 @@
+//! Server-Konfiguration
+//!
+//! Die Konfiguration wird beim Start einmalig geladen und in `AppStateData`
+//! abgelegt. Reihenfolge (spätere Quellen überschreiben frühere):
+//!
+//! 1. Standardwerte (siehe `Default`-Implementierungen)
+//! 2. TOML-Datei (`--konfiguration` / `DGB_KONFIGURATION`, Standard: `dgb-server.toml`
+//!    neben der ausführbaren Datei, falls vorhanden)
+//! 3. Umgebungsvariablen (`SYNC_MODE`, `REMOTE_MOUNT_POINT`, `ACME_CONFIG`, `DGB_*`);
+//!    das veraltete `REMOTE_MOUNT` gilt noch als Alias für `REMOTE_MOUNT_POINT`
+//! 4. Kommandozeilenparameter
+//!
+//! Beispiel für eine `dgb-server.toml`:
+//!
+//! ```toml
+//! sync_modus = false
+//! token_gueltigkeit_minuten = 30
+//! refresh_token_gueltigkeit_tage = 14
+//! passwort_reset_gueltigkeit_minuten = 60
+//! einsichtsprotokoll_aufbewahrung_tage = 730
+//! zugriff_gueltigkeit_tage = 365
+//! zugriff_erinnerung_tage = 14
+//! zugriff_bestaetigung_stunden = 48
+//!
+//! [pfade]
+//! lokal = "/var/lib/dgb-server"
+//! remote = "/mnt/data/files"
+//! db = "benutzer.sqlite.db"
+//! daten = "data"
+//! index = "/var/cache/dgb-server/index"
+//!
+//! [ports]
+//! http = 8080
+//! intern = 8081
+//! https = 443
+//! http_ohne_tls = 8082
+//! git = 9418
+//!
+//! [ratenbegrenzung]
+//! zeitraum_minuten = 60
+//! verzoegerung_ab_fehlversuchen = 3
+//! verzoegerung_sekunden = 2
+//! max_verzoegerung_sekunden = 300
+//! sperre_ab_fehlversuchen = 10
+//! sperrdauer_minuten = 60
+//! zugriff_anfragen_pro_email_tag = 20
+//! zugriff_anfragen_pro_ip_tag = 50
+//! passwort_vergessen_pro_email_stunde = 3
+//! passwort_vergessen_pro_ip_stunde = 20
+//! vertrauenswuerdige_proxies = ["10.0.0.0/8"]
+//!
+//! [postausgang]
+//! intervall_sekunden = 30
+//! max_versuche = 8
+//! wartezeit_sekunden = 60
+//! max_wartezeit_minuten = 360
+//! aufbewahrung_tage = 30
+//!
+//! [webhook]
+//! nur_https = true
+//! private_adressen_erlauben = false
+//! max_weiterleitungen = 3
+//! timeout_sekunden = 30
+//! verbindung_timeout_sekunden = 10
+//!
+//! [oidc]
+//! aussteller = "https://login.example.com/realms/grundbuch"
+//! client_id = "dgb-server"
+//! client_secret = "..."   # besser per DGB_OIDC_CLIENT_SECRET
+//! gruppen_claim = "groups"
+//! standard_rechte = "gast"
+//!
+//! [oidc.rechte]
+//! "grundbuch-admin" = "admin"
+//! "grundbuch-bearbeiter" = "bearbeiter"
+//!
+//! [acme]
+//! domains = ["grundbuch-test.eu"]
+//! email = ["test@grundbuch-test.eu"]
+//! cache = "acme-cache"
+//! prod = false
+//! ```
+
+use crate::{models::MountPoint, AcmeArgs};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Name der Konfigurationsdatei, die neben der ausführbaren Datei gesucht wird
+pub const KONFIGURATION_DATEINAME: &str = "dgb-server.toml";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Konfiguration {
+    /// Verzeichnisse für Datenbank, Git-Repository und Suchindex
+    pub pfade: PfadKonfiguration,
+    /// Ports der einzelnen Server
+    pub ports: PortKonfiguration,
+    /// Ob dieser Server als Sync-Server läuft (Schreibzugriff auf `pfade.remote`)
+    pub sync_modus: bool,
+    /// Einstellungen für Let's Encrypt, `None` = kein TLS
+    pub acme: Option<AcmeArgs>,
+    /// Gültigkeit eines Login-Tokens in Minuten
+    pub token_gueltigkeit_minuten: i64,
+    /// Gültigkeit eines Refresh-Tokens in Tagen (verlängert sich bei jedem Refresh)
+    pub refresh_token_gueltigkeit_tage: i64,
+    /// Gültigkeit eines Links zum Zurücksetzen des Passworts in Minuten
+    pub passwort_reset_gueltigkeit_minuten: i64,
+    /// Aufbewahrungsdauer des Einsichtsprotokolls in Tagen, `0` = unbegrenzt
+    pub einsichtsprotokoll_aufbewahrung_tage: u32,
+    /// Standard-Gültigkeit eines genehmigten Zugriffs in Tagen, `0` = unbegrenzt
+    pub zugriff_gueltigkeit_tage: u32,
+    /// Wie viele Tage vor Ablauf eines Zugriffs eine Erinnerung gesendet wird, `0` = keine
+    pub zugriff_erinnerung_tage: u32,
+    /// Wie lange eine Zugriffsanfrage per E-Mail-Link bestätigt werden kann, `0` = unbegrenzt
+    pub zugriff_bestaetigung_stunden: u32,
+    /// Schutz von `/login` und `/zugriff` gegen zu viele Anfragen
+    pub ratenbegrenzung: RatenbegrenzungKonfiguration,
+    /// Wiederholungen beim Versand von E-Mails und Webhooks an Abonnenten
+    pub postausgang: PostausgangKonfiguration,
+    /// Einschränkungen für Webhook-URLs (Schutz vor Anfragen ins interne Netz)
+    pub webhook: WebhookKonfiguration,
+    /// Anmeldung über OpenID Connect, `None` = deaktiviert
+    pub oidc: Option<OidcKonfiguration>,
+}
+
+impl Default for Konfiguration {
+    fn default() -> Self {
+        Self {
+            pfade: PfadKonfiguration::default(),
+            ports: PortKonfiguration::default(),
+            sync_modus: false,
+            acme: None,
+            token_gueltigkeit_minuten: 30,
+            refresh_token_gueltigkeit_tage: 14,
+            passwort_reset_gueltigkeit_minuten: 60,
+            einsichtsprotokoll_aufbewahrung_tage: 730,
+            zugriff_gueltigkeit_tage: 365,
+            zugriff_erinnerung_tage: 14,
+            zugriff_bestaetigung_stunden: 48,
+            ratenbegrenzung: RatenbegrenzungKonfiguration::default(),
+            postausgang: PostausgangKonfiguration::default(),
+            webhook: WebhookKonfiguration::default(),
+            oidc: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PfadKonfiguration {
+    /// Basisverzeichnis für lokale Daten (Standard: `[exe-dir]/local`)
+    pub lokal: String,
+    /// Basisverzeichnis des geteilten k8s-Volumes
+    pub remote: String,
+    /// Pfad der Benutzerdatenbank, relativ zum Basisverzeichnis oder absolut
+    pub db: String,
+    /// Pfad des Git-Repositorys, relativ zum Basisverzeichnis oder absolut
+    pub daten: String,
+    /// Pfad des Suchindex, relativ zum lokalen Basisverzeichnis oder absolut
+    pub index: String,
+}
+
+impl Default for PfadKonfiguration {
+    fn default() -> Self {
+        Self {
+            lokal: get_standard_lokal_pfad(),
+            remote: "/mnt/data/files".to_string(),
+            db: "benutzer.sqlite.db".to_string(),
+            daten: "data".to_string(),
+            index: "index".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PortKonfiguration {
+    /// Öffentlicher HTTP-Port
+    pub http: u16,
+    /// Cluster-interner Port (`/pull`, `/pull-db` bzw. Sync-Server `/commit`, `/db`)
+    pub intern: u16,
+    /// HTTPS-Port, falls ACME konfiguriert ist
+    pub https: u16,
+    /// Ersatz-Port für den dritten Server, falls ACME nicht konfiguriert ist
+    pub http_ohne_tls: u16,
+    /// Port des `git daemon` auf dem Sync-Server
+    pub git: u16,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RatenbegrenzungKonfiguration {
+    /// Zeitraum in Minuten, in dem Fehlversuche gezählt werden
+    pub zeitraum_minuten: i64,
+    /// Ab so vielen Fehlversuchen (pro Konto oder IP) wird die Anmeldung verzögert
+    pub verzoegerung_ab_fehlversuchen: u32,
+    /// Wartezeit nach dem ersten verzögerten Fehlversuch, verdoppelt sich danach
+    pub verzoegerung_sekunden: i64,
+    /// Obergrenze für die Wartezeit
+    pub max_verzoegerung_sekunden: i64,
+    /// Ab so vielen Fehlversuchen wird das Konto gesperrt, `0` = keine Sperre
+    pub sperre_ab_fehlversuchen: u32,
+    /// Dauer der Kontosperre in Minuten, `0` = bis zur Entsperrung durch einen Administrator
+    pub sperrdauer_minuten: i64,
+    /// Maximale Anzahl angefragter Blätter pro E-Mail und Tag auf `/zugriff`
+    /// (Bereiche und Platzhalter zählen einfach)
+    pub zugriff_anfragen_pro_email_tag: u32,
+    /// Maximale Anzahl angefragter Blätter pro IP und Tag auf `/zugriff`
+    pub zugriff_anfragen_pro_ip_tag: u32,
+    /// Maximale Anzahl Anfragen pro E-Mail und Stunde auf `/passwort-vergessen`
+    pub passwort_vergessen_pro_email_stunde: u32,
+    /// Maximale Anzahl Anfragen pro IP und Stunde auf `/passwort-vergessen`
+    pub passwort_vergessen_pro_ip_stunde: u32,
+    /// IP-Adressen bzw. Netze (CIDR) der Load Balancer / Proxies, deren
+    /// `Forwarded`- bzw. `X-Forwarded-For`-Header übernommen werden
+    pub vertrauenswuerdige_proxies: Vec<String>,
+}
+
+impl Default for RatenbegrenzungKonfiguration {
+    fn default() -> Self {
+        Self {
+            zeitraum_minuten: 60,
+            verzoegerung_ab_fehlversuchen: 3,
+            verzoegerung_sekunden: 2,
+            max_verzoegerung_sekunden: 300,
+            sperre_ab_fehlversuchen: 10,
+            sperrdauer_minuten: 60,
+            zugriff_anfragen_pro_email_tag: 20,
+            zugriff_anfragen_pro_ip_tag: 50,
+            passwort_vergessen_pro_email_stunde: 3,
+            passwort_vergessen_pro_ip_stunde: 20,
+            vertrauenswuerdige_proxies: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct PostausgangKonfiguration {
+    /// Wie oft der Postausgang auf fällige Einträge geprüft wird
+    pub intervall_sekunden: u64,
+    /// Nach so vielen Fehlversuchen wird ein Eintrag nicht mehr automatisch gesendet
+    pub max_versuche: u32,
+    /// Wartezeit nach dem ersten Fehlversuch, verdoppelt sich danach
+    pub wartezeit_sekunden: i64,
+    /// Obergrenze für die Wartezeit
+    pub max_wartezeit_minuten: i64,
+    /// Aufbewahrung gesendeter Einträge in Tagen, `0` = unbegrenzt
+    pub aufbewahrung_tage: u32,
+}
+
+impl Default for PostausgangKonfiguration {
+    fn default() -> Self {
+        Self {
+            intervall_sekunden: 30,
+            max_versuche: 8,
+            wartezeit_sekunden: 60,
+            max_wartezeit_minuten: 360,
+            aufbewahrung_tage: 30,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WebhookKonfiguration {
+    /// Nur `https://`-URLs zulassen (`false` nur zum Testen)
+    pub nur_https: bool,
+    /// Private, Loopback- und Link-Local-Adressen zulassen (nur zum Testen)
+    pub private_adressen_erlauben: bool,
+    /// Maximale Anzahl an Weiterleitungen (307 / 308), `0` = keine
+    pub max_weiterleitungen: usize,
+    /// Zeit für die gesamte Anfrage inkl. Antwort
+    pub timeout_sekunden: u64,
+    /// Zeit für den Verbindungsaufbau
+    pub verbindung_timeout_sekunden: u64,
+}
+
+impl Default for WebhookKonfiguration {
+    fn default() -> Self {
+        Self {
+            nur_https: true,
+            private_adressen_erlauben: false,
+            max_weiterleitungen: 3,
+            timeout_sekunden: 30,
+            verbindung_timeout_sekunden: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct OidcKonfiguration {
+    /// Issuer-URL, `{aussteller}/.well-known/openid-configuration` muss erreichbar sein
+    pub aussteller: String,
+    pub client_id: String,
+    pub client_secret: String,
+    /// Redirect-URL, Standard: `{server.config.url}/login/oidc/callback`
+    pub redirect_url: Option<String>,
+    pub scopes: Vec<String>,
+    /// Claim mit den Gruppen des Benutzers, verschachtelt mit `.` (z.B. `realm_access.roles`)
+    pub gruppen_claim: String,
+    /// Gruppe => Rechte (`admin`, `bearbeiter`, `gast`), bei mehreren gilt das höchste
+    pub rechte: BTreeMap<String, String>,
+    /// Rechte für neue Benutzer ohne passende Gruppe, `None` = Anmeldung abgelehnt
+    pub standard_rechte: Option<String>,
+    /// Beschriftung des Buttons auf der Login-Seite
+    pub bezeichnung: String,
+    /// Der Anbieter verlangt selbst einen zweiten Faktor: Konten, die nur über den
+    /// Anbieter angemeldet werden, brauchen dann keine eigene 2FA, auch wenn sie
+    /// vorgeschrieben ist. Sonst wird die Anmeldung über den Anbieter für Konten mit
+    /// aktivierter oder vorgeschriebener 2FA abgelehnt.
+    pub zwei_faktor_beim_anbieter: bool,
+}
+
+impl Default for OidcKonfiguration {
+    fn default() -> Self {
+        Self {
+            aussteller: String::new(),
+            client_id: String::new(),
+            client_secret: String::new(),
+            redirect_url: None,
+            scopes: vec![
+                "openid".to_string(),
+                "email".to_string(),
+                "profile".to_string(),
+            ],
+            gruppen_claim: "groups".to_string(),
+            rechte: BTreeMap::new(),
+            standard_rechte: None,
+            bezeichnung: "Mit Dienstkonto anmelden".to_string(),
+            zwei_faktor_beim_anbieter: false,
+        }
+    }
+}
+
+impl Default for PortKonfiguration {
+    fn default() -> Self {
+        Self {
+            http: 8080,
+            intern: 8081,
+            https: 443,
+            http_ohne_tls: 8082,
+            git: 9418,
+        }
+    }
+}
+
+/// Überschreibungen der Konfiguration per Kommandozeile (oder Umgebungsvariable)
+#[derive(clap::Args, Debug, Clone, Default, PartialEq)]
+pub struct KonfigurationArgs {
+    /// Pfad zur Konfigurationsdatei (TOML)
+    #[clap(long = "konfiguration", env = "DGB_KONFIGURATION", global = true)]
+    pub konfiguration: Option<PathBuf>,
+    /// Basisverzeichnis für lokale Daten
+    #[clap(long, env = "DGB_LOKAL_DIR", global = true)]
+    pub lokal_dir: Option<String>,
+    /// Basisverzeichnis des geteilten k8s-Volumes (veralteter Alias: `REMOTE_MOUNT`)
+    #[clap(long, env = "REMOTE_MOUNT_POINT", global = true)]
+    pub remote_dir: Option<String>,
+    /// Pfad der Benutzerdatenbank
+    #[clap(long, env = "DGB_DB_PFAD", global = true)]
+    pub db_pfad: Option<String>,
+    /// Pfad des Git-Repositorys
+    #[clap(long, env = "DGB_DATEN_DIR", global = true)]
+    pub daten_dir: Option<String>,
+    /// Pfad des Suchindex
+    #[clap(long, env = "DGB_INDEX_DIR", global = true)]
+    pub index_dir: Option<String>,
+    /// Öffentlicher HTTP-Port
+    #[clap(long, env = "DGB_PORT_HTTP", global = true)]
+    pub port_http: Option<u16>,
+    /// Cluster-interner Port
+    #[clap(long, env = "DGB_PORT_INTERN", global = true)]
+    pub port_intern: Option<u16>,
+    /// HTTPS-Port
+    #[clap(long, env = "DGB_PORT_HTTPS", global = true)]
+    pub port_https: Option<u16>,
+    /// Ersatz-Port, falls kein TLS konfiguriert ist
+    #[clap(long, env = "DGB_PORT_HTTP_OHNE_TLS", global = true)]
+    pub port_http_ohne_tls: Option<u16>,
+    /// Port des git daemon
+    #[clap(long, env = "DGB_PORT_GIT", global = true)]
+    pub port_git: Option<u16>,
+    /// Server als Sync-Server starten (entspricht `SYNC_MODE=1`)
+    #[clap(long, global = true)]
+    pub sync_modus: bool,
+    /// Gültigkeit eines Login-Tokens in Minuten
+    #[clap(long, env = "DGB_TOKEN_GUELTIGKEIT_MINUTEN", global = true)]
+    pub token_gueltigkeit_minuten: Option<i64>,
+    /// Gültigkeit eines Refresh-Tokens in Tagen
+    #[clap(long, env = "DGB_REFRESH_TOKEN_GUELTIGKEIT_TAGE", global = true)]
+    pub refresh_token_gueltigkeit_tage: Option<i64>,
+    /// Gültigkeit eines Links zum Zurücksetzen des Passworts in Minuten
+    #[clap(long, env = "DGB_PASSWORT_RESET_GUELTIGKEIT_MINUTEN", global = true)]
+    pub passwort_reset_gueltigkeit_minuten: Option<i64>,
+    /// Aufbewahrungsdauer des Einsichtsprotokolls in Tagen (0 = unbegrenzt)
+    #[clap(long, env = "DGB_EINSICHTSPROTOKOLL_AUFBEWAHRUNG_TAGE", global = true)]
+    pub einsichtsprotokoll_aufbewahrung_tage: Option<u32>,
+    /// Standard-Gültigkeit genehmigter Zugriffe in Tagen (0 = unbegrenzt)
+    #[clap(long, env = "DGB_ZUGRIFF_GUELTIGKEIT_TAGE", global = true)]
+    pub zugriff_gueltigkeit_tage: Option<u32>,
+    /// Erinnerung an ablaufende Zugriffe x Tage vorher (0 = keine)
+    #[clap(long, env = "DGB_ZUGRIFF_ERINNERUNG_TAGE", global = true)]
+    pub zugriff_erinnerung_tage: Option<u32>,
+    /// Frist zur Bestätigung von Zugriffsanfragen per E-Mail in Stunden (0 = unbegrenzt)
+    #[clap(long, env = "DGB_ZUGRIFF_BESTAETIGUNG_STUNDEN", global = true)]
+    pub zugriff_bestaetigung_stunden: Option<u32>,
+    /// Client-Secret für OpenID Connect (überschreibt `oidc.client_secret`)
+    #[clap(long, env = "DGB_OIDC_CLIENT_SECRET", hide_env_values = true, global = true)]
+    pub oidc_client_secret: Option<String>,
+}
+
+impl Konfiguration {
+    /// Lädt die Konfiguration aus Datei, Umgebungsvariablen und Kommandozeile
+    pub fn laden(args: &KonfigurationArgs) -> Result<Self, String> {
+        let standard_pfad = Path::new(&get_standard_lokal_pfad())
+            .parent()
+            .map(|p| p.join(KONFIGURATION_DATEINAME));
+
+        let mut konfiguration = match args.konfiguration.as_ref() {
+            Some(pfad) => Self::aus_datei(pfad)?,
+            None => match standard_pfad {
+                Some(p) if p.exists() => Self::aus_datei(&p)?,
+                _ => Self::default(),
+            },
+        };
+
+        // Umgebungsvariablen, die schon vor der Konfigurationsdatei existierten
+        if let Ok(s) = std::env::var("SYNC_MODE") {
+            konfiguration.sync_modus = s.trim() == "1" || s.trim() == "true";
+        }
+        if let Some(acme) = std::env::var("ACME_CONFIG")
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+        {
+            konfiguration.acme = Some(acme);
+        }
+        // Alter Name, REMOTE_MOUNT_POINT / --remote-dir haben Vorrang
+        if let Ok(s) = std::env::var("REMOTE_MOUNT") {
+            println!("Warnung: REMOTE_MOUNT ist veraltet, bitte REMOTE_MOUNT_POINT verwenden");
+            konfiguration.pfade.remote = s;
+        }
+
+        let p = &mut konfiguration.pfade;
+        if let Some(s) = args.lokal_dir.as_ref() {
+            p.lokal = s.clone();
+        }
+        if let Some(s) = args.remote_dir.as_ref() {
+            p.remote = s.clone();
+        }
+        if let Some(s) = args.db_pfad.as_ref() {
+            p.db = s.clone();
+        }
+        if let Some(s) = args.daten_dir.as_ref() {
+            p.daten = s.clone();
+        }
+        if let Some(s) = args.index_dir.as_ref() {
+            p.index = s.clone();
+        }
+
+        let p = &mut konfiguration.ports;
+        p.http = args.port_http.unwrap_or(p.http);
+        p.intern = args.port_intern.unwrap_or(p.intern);
+        p.https = args.port_https.unwrap_or(p.https);
+        p.http_ohne_tls = args.port_http_ohne_tls.unwrap_or(p.http_ohne_tls);
+        p.git = args.port_git.unwrap_or(p.git);
+
+        if args.sync_modus {
+            konfiguration.sync_modus = true;
+        }
+        if let Some(t) = args.token_gueltigkeit_minuten {
+            konfiguration.token_gueltigkeit_minuten = t;
+        }
+        if let Some(t) = args.refresh_token_gueltigkeit_tage {
+            konfiguration.refresh_token_gueltigkeit_tage = t;
+        }
+        if let Some(t) = args.passwort_reset_gueltigkeit_minuten {
+            konfiguration.passwort_reset_gueltigkeit_minuten = t;
+        }
+        if let Some(t) = args.einsichtsprotokoll_aufbewahrung_tage {
+            konfiguration.einsichtsprotokoll_aufbewahrung_tage = t;
+        }
+        if let Some(t) = args.zugriff_gueltigkeit_tage {
+            konfiguration.zugriff_gueltigkeit_tage = t;
+        }
+        if let Some(t) = args.zugriff_erinnerung_tage {
+            konfiguration.zugriff_erinnerung_tage = t;
+        }
+        if let Some(t) = args.zugriff_bestaetigung_stunden {
+            konfiguration.zugriff_bestaetigung_stunden = t;
+        }
+        if let (Some(oidc), Some(s)) = (
+            konfiguration.oidc.as_mut(),
+            args.oidc_client_secret.as_ref(),
+        ) {
+            oidc.client_secret = s.clone();
+        }
+
+        if konfiguration.token_gueltigkeit_minuten <= 0 {
+            return Err(format!(
+                "Ungültige token_gueltigkeit_minuten: {}",
+                konfiguration.token_gueltigkeit_minuten
+            ));
+        }
+
+        if konfiguration.refresh_token_gueltigkeit_tage <= 0 {
+            return Err(format!(
+                "Ungültige refresh_token_gueltigkeit_tage: {}",
+                konfiguration.refresh_token_gueltigkeit_tage
+            ));
+        }
+
+        if konfiguration.passwort_reset_gueltigkeit_minuten <= 0 {
+            return Err(format!(
+                "Ungültige passwort_reset_gueltigkeit_minuten: {}",
+                konfiguration.passwort_reset_gueltigkeit_minuten
+            ));
+        }
+
+        let r = &konfiguration.ratenbegrenzung;
+        if r.zeitraum_minuten <= 0
+            || r.verzoegerung_sekunden < 0
+            || r.max_verzoegerung_sekunden < 0
+            || r.sperrdauer_minuten < 0
+        {
+            return Err(format!("Ungültige Konfiguration für ratenbegrenzung: {r:?}"));
+        }
+        for netz in r.vertrauenswuerdige_proxies.iter() {
+            if !crate::ratenbegrenzung::netz_pruefen(netz) {
+                return Err(format!(
+                    "ratenbegrenzung: Ungültige Adresse in vertrauenswuerdige_proxies: {netz:?}"
+                ));
+            }
+        }
+
+        let w = &konfiguration.webhook;
+        if w.timeout_sekunden == 0 || w.verbindung_timeout_sekunden == 0 {
+            return Err(format!("Ungültige Konfiguration für webhook: {w:?}"));
+        }
+
+        if let Some(oidc) = konfiguration.oidc.as_ref() {
+            if oidc.aussteller.trim().is_empty() || oidc.client_id.trim().is_empty() {
+                return Err(format!("oidc: aussteller und client_id müssen angegeben werden"));
+            }
+            // Ob die Rolle existiert, wird erst bei der Anmeldung geprüft
+            for rechte in oidc.rechte.values().chain(oidc.standard_rechte.iter()) {
+                if rechte.trim().is_empty() {
+                    return Err(format!("oidc: Leere Rolle in oidc.rechte / standard_rechte"));
+                }
+            }
+        }
+
+        Ok(konfiguration)
+    }
+
+    fn aus_datei(pfad: &Path) -> Result<Self, String> {
+        let s = std::fs::read_to_string(pfad)
+            .map_err(|e| format!("Konnte Konfiguration {} nicht lesen: {e}", pfad.display()))?;
+        toml::from_str(&s)
+            .map_err(|e| format!("Ungültige Konfiguration in {}: {e}", pfad.display()))
+    }
+
+    pub fn get_base_path(&self, mount_point: MountPoint) -> String {
+        match mount_point {
+            MountPoint::Local => self.pfade.lokal.clone(),
+            MountPoint::Remote => self.pfade.remote.clone(),
+        }
+    }
+
+    pub fn get_db_path(&self, mount_point: MountPoint) -> String {
+        Path::new(&self.get_base_path(mount_point))
+            .join(&self.pfade.db)
+            .to_str()
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    pub fn get_data_dir(&self, mount_point: MountPoint) -> String {
+        Path::new(&self.get_base_path(mount_point))
+            .join(&self.pfade.daten)
+            .to_str()
+            .unwrap_or_default()
+            .to_string()
+    }
+
+    pub fn get_index_dir(&self) -> String {
+        Path::new(&self.get_base_path(MountPoint::Local))
+            .join(&self.pfade.index)
+            .to_str()
+            .unwrap_or_default()
+            .to_string()
+    }
+}
+
+fn get_standard_lokal_pfad() -> String {
+    std::env::current_exe()
+        .ok()
+        .and_then(|p| Some(p.parent()?.join("local")))
+        .and_then(|p| p.to_str().map(|s| s.to_string()))
+        .unwrap_or_default()
+}
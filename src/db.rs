@@ -5,25 +5,29 @@ use crate::{
         index::ZugriffTyp,
         pull::{PullResponse, PullResponseError},
     },
+    config::Konfiguration,
     email::SmtpConfig,
-    models::{get_data_dir, get_db_path, AbonnementInfoBlattNr, AbonnementInfo, BenutzerInfo, PdfFile},
+    models::{AboBenachrichtigung, AboDetailstufe, AbonnementInfoBlattNr, AbonnementInfo, BenutzerInfo, PdfFile},
     BezirkNeuArgs, MountPoint,
 };
 use chrono::{DateTime, Utc};
 use git2::Repository;
 use lz4_flex::decompress_size_prepended;
-use rusqlite::{Connection, OpenFlags};
+use rusqlite::{Connection, OpenFlags, OptionalExtension};
 use serde_derive::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 
 pub type GemarkungsBezirke = Vec<(String, String, String)>;
 const PASSWORD_LEN: usize = 128;
 
-pub async fn get_db_bytes() -> Result<Vec<u8>, String> {
+pub async fn get_db_bytes(konfiguration: &Konfiguration) -> Result<Vec<u8>, String> {
     let ip = crate::k8s::get_sync_server_ip().await?;
     let client = reqwest::Client::new();
     let res = client
-        .post(&format!("http://{ip}:8081/get-db"))
+        .post(&format!(
+            "http://{ip}:{}/get-db",
+            konfiguration.ports.intern
+        ))
         .send()
         .await
         .map_err(|e| format!("Konnte Sync-Server nicht erreichen: {e}"))?;
@@ -45,7 +49,7 @@ pub async fn get_db_bytes() -> Result<Vec<u8>, String> {
     Ok(bytes)
 }
 
-pub async fn pull_db() -> Result<(), PullResponseError> {
+pub async fn pull_db(konfiguration: &Konfiguration) -> Result<(), PullResponseError> {
     let k8s = crate::k8s::is_running_in_k8s().await;
     if !k8s {
         return Ok(());
@@ -66,7 +70,11 @@ pub async fn pull_db() -> Result<(), PullResponseError> {
         }
         let client = reqwest::Client::new();
         let res = client
-            .post(&format!("http://{}:8081/pull-db", peer.ip))
+            .post(&format!(
+                "http://{}:{}/pull-db",
+                peer.ip,
+                konfiguration.ports.intern
+            ))
             .send()
             .await;
 
@@ -101,7 +109,10 @@ pub async fn pull_db() -> Result<(), PullResponseError> {
     Ok(())
 }
 
-pub fn create_database(mount_point: MountPoint) -> Result<(), rusqlite::Error> {
+pub fn create_database(
+    konfiguration: &Konfiguration,
+    mount_point: MountPoint,
+) -> Result<(), rusqlite::Error> {
     let mut open_flags = OpenFlags::empty();
 
     open_flags.set(OpenFlags::SQLITE_OPEN_NOFOLLOW, true);
@@ -109,115 +120,46 @@ pub fn create_database(mount_point: MountPoint) -> Result<(), rusqlite::Error> {
     open_flags.set(OpenFlags::SQLITE_OPEN_CREATE, true);
     open_flags.set(OpenFlags::SQLITE_OPEN_READ_WRITE, true);
 
-    if let Some(parent) = std::path::Path::new(&get_db_path(mount_point)).parent() {
+    if let Some(parent) = std::path::Path::new(&konfiguration.get_db_path(mount_point)).parent() {
         let _ = std::fs::create_dir_all(parent);
     }
 
-    let conn = Connection::open_with_flags(get_db_path(mount_point), open_flags)?;
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS zugriffe (
-                id              STRING PRIMARY KEY UNIQUE NOT NULL,
-                name            VARCHAR(255) NOT NULL,
-                email           VARCHAR(255) NOT NULL,
-                typ             VARCHAR(50) NOT NULL,
-                grund           STRING,
-                land            STRING NOT NULL,
-                amtsgericht     STRING NOT NULL,
-                bezirk          STRING NOT NULL,
-                blatt           STRING NOT NULL,
-                angefragt       STRING NOT NULL,
-                gewaehrt_von    STRING,
-                abgelehnt_von   STRING,
-                am              STRING
-        )",
-        [],
-    )?;
-
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS benutzer (
-                id              INTEGER PRIMARY KEY AUTOINCREMENT,
-                email           VARCHAR(255) UNIQUE NOT NULL,
-                name            VARCHAR(255) NOT NULL,
-                rechte          VARCHAR(255) NOT NULL,
-                password_hashed BLOB
-        )",
-        [],
-    )?;
-
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS sessions (
-                benutzer        INTEGER,
-                token           VARCHAR(1024) UNIQUE NOT NULL,
-                gueltig_bis     VARCHAR(255) NOT NULL
-        )",
-        [],
-    )?;
-
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS bezirke (
-            id              VARCHAR(255) NOT NULL,
-            land            VARCHAR(255) NOT NULL,
-            amtsgericht     VARCHAR(255) NOT NULL,
-            bezirk          VARCHAR(255) NOT NULL
-        )",
-        [],
-    )?;
-
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS abonnements (
-            id              VARCHAR(255) NOT NULL,
-            typ              VARCHAR(50) NOT NULL,
-            text             VARCHAR(1023) NOT NULL,
-            amtsgericht      VARCHAR(255) NOT NULL,
-            bezirk           VARCHAR(255) NOT NULL,
-            blatt            VARCHAR(255) NOT NULL,
-            aktenzeichen     VARCHAR(1023)
-        )",
-        [],
-    )?;
-
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS publickeys (
-                email           VARCHAR(255) NOT NULL,
-                pubkey          TEXT NOT NULL,
-                fingerprint     VARCHAR(2048) NOT NULL
-        )",
-        [],
-    )?;
-
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS einstellungen (
-            id              VARCHAR(255) NOT NULL,
-            benutzer        INTEGER NOT NULL,
-            einstellung     VARCHAR(1023) NOT NULL,
-            wert            STRING NOT NULL
-        )",
-        [],
-    )?;
+    // Migrationen laufen ohne Fremdschlüssel-Prüfung, da beim Neuaufbau
+    // einer Tabelle (DROP + RENAME) sonst abhängige Zeilen gelöscht würden
+    let mut conn = Connection::open_with_flags(konfiguration.get_db_path(mount_point), open_flags)?;
+    let migriert = crate::migration::migrieren(&mut conn, false)?;
+    for m in migriert.iter() {
+        println!("Datenbank migriert: {:03} {}", m.version, m.beschreibung);
+    }
 
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS grundbuecher (
-            land            VARCHAR(255) NOT NULL,
-            amtsgericht     VARCHAR(1024) NOT NULL,
-            bezirk          VARCHAR(1024) NOT NULL,
-            blatt           VARCHAR(20) NOT NULL
-        )",
-        [],
-    )?;
+    let verletzungen = crate::migration::fremdschluessel_pruefen(&conn)?;
+    if verletzungen > 0 {
+        println!("Warnung: {verletzungen} Zeilen verweisen auf nicht existierende Einträge");
+    }
 
-    seed_benutzer_einstellungen(mount_point)?;
+    seed_benutzer_einstellungen(konfiguration, mount_point)?;
 
     Ok(())
 }
 
-pub fn bearbeite_einstellung(
+/// Öffnet die Benutzer-Datenbank mit aktivierten Fremdschlüsseln
+///
+/// SQLite beachtet `REFERENCES ... ON DELETE CASCADE` nur, wenn
+/// `PRAGMA foreign_keys` auf der jeweiligen Verbindung gesetzt ist.
+pub fn verbindung_oeffnen(
+    konfiguration: &Konfiguration,
     mount_point: MountPoint,
+) -> Result<Connection, rusqlite::Error> {
+    let conn = Connection::open(konfiguration.get_db_path(mount_point))?;
+    conn.execute_batch("PRAGMA foreign_keys = ON;")?;
+    Ok(conn)
+}
+
+pub fn bearbeite_einstellung(
+    conn: &Connection,
     einstellung_id: &str,
     neuer_wert: &str,
 ) -> Result<(), String> {
-    let mut conn = Connection::open(get_db_path(mount_point))
-        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
-
     conn.execute(
         "UPDATE einstellungen SET wert = ?2 WHERE id = ?1",
         rusqlite::params![einstellung_id, neuer_wert],
@@ -227,8 +169,11 @@ pub fn bearbeite_einstellung(
     Ok(())
 }
 
-pub fn seed_benutzer_einstellungen(mount_point: MountPoint) -> Result<(), rusqlite::Error> {
-    let mut conn = Connection::open(get_db_path(mount_point))?;
+pub fn seed_benutzer_einstellungen(
+    konfiguration: &Konfiguration,
+    mount_point: MountPoint,
+) -> Result<(), rusqlite::Error> {
+    let mut conn = verbindung_oeffnen(konfiguration, mount_point)?;
 
     let benutzer = {
         let mut stmt = conn.prepare("SELECT id, rechte FROM benutzer")?;
@@ -279,6 +224,25 @@ pub fn seed_benutzer_einstellungen(mount_point: MountPoint) -> Result<(), rusqli
             ""
         ])?;
 
+        prepared.execute(rusqlite::params![
+            generate_uuid(),
+            -1,
+            crate::zwei_faktor::PFLICHT_EINSTELLUNG,
+            ""
+        ])?;
+        prepared.execute(rusqlite::params![
+            generate_uuid(),
+            -1,
+            crate::email::ZUGRIFF_BENACHRICHTIGUNG_EINSTELLUNG,
+            "sofort"
+        ])?;
+        prepared.execute(rusqlite::params![
+            generate_uuid(),
+            -1,
+            crate::signatur::SCHLUESSEL_EINSTELLUNG,
+            crate::signatur::schluessel_generieren()
+        ])?;
+
         prepared.execute(rusqlite::params![
             generate_uuid(),
             -1,
@@ -304,25 +268,70 @@ pub fn seed_benutzer_einstellungen(mount_point: MountPoint) -> Result<(), rusqli
     Ok(())
 }
 
+/// Setzt eine Einstellung des Benutzers, fehlt sie noch, wird sie angelegt
+pub fn benutzer_einstellung_setzen(
+    conn: &Connection,
+    benutzer_id: i32,
+    einstellung: &str,
+    wert: &str,
+) -> Result<(), String> {
+    let geaendert = conn
+        .execute(
+            "UPDATE einstellungen SET wert = ?3 WHERE benutzer = ?1 AND einstellung = ?2",
+            rusqlite::params![benutzer_id, einstellung, wert],
+        )
+        .map_err(|e| format!("Fehler beim Bearbeiten der Einstellung {einstellung}: {e}"))?;
+
+    if geaendert == 0 {
+        conn.execute(
+            "INSERT INTO einstellungen(id, benutzer, einstellung, wert) VALUES (?1, ?2, ?3, ?4)",
+            rusqlite::params![generate_uuid(), benutzer_id, einstellung, wert],
+        )
+        .map_err(|e| format!("Fehler beim Bearbeiten der Einstellung {einstellung}: {e}"))?;
+    }
+
+    Ok(())
+}
+
+/// Einstellung `email.abo.benachrichtigung` des Benutzers mit der E-Mail-Adresse
+/// `email` (Standard: `sofort`, auch für Empfänger ohne Konto)
+pub fn get_abo_benachrichtigung(conn: &Connection, email: &str) -> AboBenachrichtigung {
+    conn.query_row(
+        "SELECT e.wert FROM einstellungen e
+        INNER JOIN benutzer b ON e.benutzer = b.id
+        WHERE b.email = ?1 AND e.einstellung = ?2",
+        rusqlite::params![email, crate::email::ABO_BENACHRICHTIGUNG_EINSTELLUNG],
+        |row| row.get::<usize, String>(0),
+    )
+    .ok()
+    .and_then(|w| AboBenachrichtigung::from_str(&w))
+    .unwrap_or_default()
+}
+
 pub fn get_globale_einstellungen(
+    konfiguration: &Konfiguration,
     mount_point: MountPoint,
 ) -> Result<BTreeMap<String, (String, String)>, String> {
     get_einstellungen_fuer_benutzer(
+        konfiguration,
         mount_point,
         &BenutzerInfo {
             id: -1,
             rechte: "gast".to_string(),
             name: "".to_string(),
             email: "".to_string(),
+            api_schluessel: None,
+            berechtigungen: Default::default(),
         },
     )
 }
 
 pub fn get_einstellungen_fuer_benutzer(
+    konfiguration: &Konfiguration,
     mount_point: MountPoint,
     info: &BenutzerInfo,
 ) -> Result<BTreeMap<String, (String, String)>, String> {
-    let mut conn = Connection::open(get_db_path(mount_point))
+    let mut conn = verbindung_oeffnen(konfiguration, mount_point)
         .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
 
     let mut stmt = conn
@@ -346,21 +355,18 @@ pub fn get_einstellungen_fuer_benutzer(
         .collect())
 }
 
-pub fn bezirke_einfuegen(mount_point: MountPoint, bezirke: &[BezirkNeuArgs]) -> Result<(), String> {
-    let mut conn = Connection::open(get_db_path(mount_point))
-        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
-
-    let tx = conn
-        .transaction()
-        .map_err(|e| format!("Fehler beim Erstellen der Transaktion: {e}"))?;
-
-    tx.execute("DELETE FROM bezirke", rusqlite::params![])
+pub fn bezirke_einfuegen(
+    konfiguration: &Konfiguration,
+    conn: &Connection,
+    bezirke: &[BezirkNeuArgs],
+) -> Result<(), String> {
+    conn.execute("DELETE FROM bezirke", rusqlite::params![])
         .map_err(|e| format!("Fehler beim Einfügen von Bezirken in Datenbank: {e}"))?;
 
     // https://stackoverflow.com/questions/1609637
     for (i, row) in bezirke.iter().enumerate() {
         let land = row.land.replace("\"", "").replace("\'", "");
-        let id = crate::db::generate_token().0;
+        let id = crate::db::generate_token(konfiguration).0;
         let land = match Bundesland::from_code(&land) {
             Some(s) => s,
             None => match Bundesland::from_string(&land) {
@@ -374,43 +380,31 @@ pub fn bezirke_einfuegen(mount_point: MountPoint, bezirke: &[BezirkNeuArgs]) ->
         let amtsgericht = row.amtsgericht.replace("\"", "").replace("\'", "");
         let bezirk = row.bezirk.replace("\"", "").replace("\'", "");
 
-        tx.execute(
+        conn.execute(
             "INSERT INTO bezirke (id, land, amtsgericht, bezirk) VALUES (?1, ?2, ?3, ?4)",
             rusqlite::params![id, land.into_code(), amtsgericht, bezirk],
         )
         .map_err(|e| format!("Fehler beim Einfügen von Zeile {i} in Datenbank: {e}"))?;
     }
 
-    tx.commit()
-        .map_err(|e| format!("Fehler beim Einfügen von Bezirken in Datenbank: {e}"))?;
-
     Ok(())
 }
 
-pub fn bezirke_loeschen(mount_point: MountPoint, ids: &[String]) -> Result<(), String> {
-    let mut conn = Connection::open(get_db_path(mount_point))
-        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
-
-    let tx = conn
-        .transaction()
-        .map_err(|e| format!("Fehler beim Löschen von Bezirken in Datenbank: {e}"))?;
-
+pub fn bezirke_loeschen(conn: &Connection, ids: &[String]) -> Result<(), String> {
     for id in ids.iter() {
-        tx.execute(
+        conn.execute(
             "DELETE FROM bezirke WHERE id = ?1",
             rusqlite::params![id.clone()],
         )
         .map_err(|e| format!("Fehler beim Löschen von Bezirken in Datenbank: {e}"))?;
     }
 
-    tx.commit()
-        .map_err(|e| format!("Fehler beim Löschen von Bezirken in Datenbank: {e}"))?;
-
     Ok(())
 }
 
 pub fn create_gemarkung(
-    mount_point: MountPoint,
+    konfiguration: &Konfiguration,
+    conn: &Connection,
     land: &str,
     amtsgericht: &str,
     bezirk: &str,
@@ -426,10 +420,7 @@ pub fn create_gemarkung(
         },
     };
 
-    let conn = Connection::open(get_db_path(mount_point))
-        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
-
-    let id = crate::db::generate_token().0;
+    let id = crate::db::generate_token(konfiguration).0;
 
     conn.execute(
         "INSERT INTO bezirke (id, land, amtsgericht, bezirk) VALUES (?1, ?2, ?3)",
@@ -445,8 +436,8 @@ pub fn create_gemarkung(
     Ok(())
 }
 
-pub fn get_gemarkungen() -> Result<GemarkungsBezirke, String> {
-    let conn = Connection::open(get_db_path(MountPoint::Local))
+pub fn get_gemarkungen(konfiguration: &Konfiguration) -> Result<GemarkungsBezirke, String> {
+    let conn = verbindung_oeffnen(konfiguration, MountPoint::Local)
         .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
 
     let mut stmt = conn
@@ -584,11 +575,14 @@ impl Bundesland {
     }
 }
 
-pub fn get_amtsgerichte_for_bundesland(bundesland: &str) -> Result<Vec<String>, String> {
+pub fn get_amtsgerichte_for_bundesland(
+    konfiguration: &Konfiguration,
+    bundesland: &str,
+) -> Result<Vec<String>, String> {
     let bundesland_clean = match bundesland {
         "ALLE_BUNDESLAENDER" => {
             return {
-                Ok(get_gemarkungen()?
+                Ok(get_gemarkungen(konfiguration)?
                     .into_iter()
                     .map(|(_, ag, _)| ag.clone())
                     .collect())
@@ -597,7 +591,7 @@ pub fn get_amtsgerichte_for_bundesland(bundesland: &str) -> Result<Vec<String>,
         other => Bundesland::from_code(other).ok_or(format!("Ungültige Bundesland-ID"))?,
     };
 
-    let conn = Connection::open(get_db_path(MountPoint::Local))
+    let conn = verbindung_oeffnen(konfiguration, MountPoint::Local)
         .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
 
     let mut stmt = conn
@@ -621,11 +615,14 @@ pub fn get_amtsgerichte_for_bundesland(bundesland: &str) -> Result<Vec<String>,
     Ok(amtsgerichte)
 }
 
-pub fn get_bezirke_for_amtsgericht(amtsgericht: &str) -> Result<Vec<String>, String> {
+pub fn get_bezirke_for_amtsgericht(
+    konfiguration: &Konfiguration,
+    amtsgericht: &str,
+) -> Result<Vec<String>, String> {
     let amtsgericht_clean = match amtsgericht {
         "ALLE_AMTSGERICHTE" => {
             return {
-                Ok(get_gemarkungen()?
+                Ok(get_gemarkungen(konfiguration)?
                     .into_iter()
                     .map(|(_, _, bezirk)| bezirk.clone())
                     .collect())
@@ -634,7 +631,7 @@ pub fn get_bezirke_for_amtsgericht(amtsgericht: &str) -> Result<Vec<String>, Str
         other => other,
     };
 
-    let conn = Connection::open(get_db_path(MountPoint::Local))
+    let conn = verbindung_oeffnen(konfiguration, MountPoint::Local)
         .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
 
     let mut stmt = conn
@@ -659,6 +656,7 @@ pub fn get_bezirke_for_amtsgericht(amtsgericht: &str) -> Result<Vec<String>, Str
 }
 
 pub fn get_blaetter_for_bezirk(
+    konfiguration: &Konfiguration,
     land: &str,
     amtsgericht: &str,
     bezirk: &str,
@@ -673,7 +671,7 @@ pub fn get_blaetter_for_bezirk(
             }
         },
     };
-    let folder = Path::new(&get_data_dir(MountPoint::Local))
+    let folder = Path::new(&konfiguration.get_data_dir(MountPoint::Local))
         .join(land.into_str())
         .join(amtsgericht)
         .join(bezirk);
@@ -699,7 +697,7 @@ pub fn get_blaetter_for_bezirk(
 }
 
 pub fn delete_gemarkung(
-    mount_point: MountPoint,
+    conn: &Connection,
     land: &str,
     amtsgericht: &str,
     bezirk: &str,
@@ -714,9 +712,6 @@ pub fn delete_gemarkung(
         },
     };
 
-    let conn = Connection::open(get_db_path(mount_point))
-        .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
-
     conn.execute(
         "DELETE FROM bezirke WHERE land = ?1 AND amtsgericht = ?2 AND bezirk = ?3",
         rusqlite::params![land.into_code(), amtsgericht, bezirk],
@@ -732,7 +727,7 @@ pub fn delete_gemarkung(
 }
 
 pub fn passwort_aendern(
-    mount_point: MountPoint,
+    conn: &Connection,
     email: &str,
     passwort: &str,
 ) -> Result<(), String> {
@@ -742,20 +737,170 @@ pub fn passwort_aendern(
 
     let password_hashed = hash_password(passwort);
 
-    let conn = Connection::open(get_db_path(mount_point))
-        .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
-
     conn.execute(
         "UPDATE benutzer SET password_hashed = ?1 WHERE email = ?2",
         rusqlite::params![password_hashed, email],
     )
     .map_err(|e| format!("Fehler beim Einfügen von Benutzer in Datenbank: {e}"))?;
 
+    // Nach einer Passwortänderung sind alle bisherigen Sitzungen ungültig
+    conn.execute(
+        "DELETE FROM sessions WHERE benutzer IN (SELECT id FROM benutzer WHERE email = ?1)",
+        rusqlite::params![email],
+    )
+    .map_err(|e| format!("Fehler beim Beenden der Sessions: {e}"))?;
+
     Ok(())
 }
 
-pub fn create_user(
+/// Legt ein neues Token zum Zurücksetzen des Passworts an (nur der Hash wird
+/// gespeichert). Ältere, noch nicht benutzte Tokens des Benutzers werden ungültig.
+pub fn passwort_reset_einfuegen(
+    conn: &Connection,
+    email: &str,
+    token_hash: &str,
+    gueltig_bis: &str,
+) -> Result<(), String> {
+    let id = conn
+        .query_row(
+            "SELECT id FROM benutzer WHERE email = ?1",
+            rusqlite::params![email],
+            |row| row.get::<usize, i32>(0),
+        )
+        .map_err(|_| format!("Kein Benutzerkonto für angegebene E-Mail-Adresse vorhanden"))?;
+
+    conn.execute(
+        "DELETE FROM passwort_reset WHERE benutzer = ?1 AND benutzt_am IS NULL",
+        rusqlite::params![id],
+    )
+    .map_err(|e| format!("Fehler beim Anlegen des Passwort-Tokens: {e}"))?;
+
+    conn.execute(
+        "INSERT INTO passwort_reset (benutzer, token_hash, erstellt_am, gueltig_bis)
+        VALUES (?1, ?2, ?3, ?4)",
+        rusqlite::params![id, token_hash, Utc::now().to_rfc3339(), gueltig_bis],
+    )
+    .map_err(|e| format!("Fehler beim Anlegen des Passwort-Tokens: {e}"))?;
+
+    Ok(())
+}
+
+/// Sucht den Benutzer zu einem noch gültigen, unbenutzten Token zum
+/// Zurücksetzen des Passworts
+pub fn get_user_from_passwort_reset(
+    konfiguration: &Konfiguration,
+    token: &str,
+) -> Result<BenutzerInfo, String> {
+    let conn = verbindung_oeffnen(konfiguration, MountPoint::Local)
+        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+
+    let (id, gueltig_bis, benutzt_am) = conn
+        .query_row(
+            "SELECT benutzer, gueltig_bis, benutzt_am FROM passwort_reset WHERE token_hash = ?1",
+            rusqlite::params![hash_token(token)],
+            |row| {
+                Ok((
+                    row.get::<usize, i32>(0)?,
+                    row.get::<usize, String>(1)?,
+                    row.get::<usize, Option<String>>(2)?,
+                ))
+            },
+        )
+        .map_err(|_| format!("Ungültiger Link zum Zurücksetzen des Passworts"))?;
+
+    if benutzt_am.is_some() {
+        return Err(format!("Link zum Zurücksetzen des Passworts wurde bereits benutzt"));
+    }
+
+    let gueltig_bis = DateTime::parse_from_rfc3339(&gueltig_bis)
+        .map_err(|_| format!("Ungültiger Link zum Zurücksetzen des Passworts"))?;
+
+    if Utc::now() > gueltig_bis {
+        return Err(format!("Link zum Zurücksetzen des Passworts ist abgelaufen"));
+    }
+
+    get_benutzer_by_id(&conn, id)
+}
+
+/// Setzt das Passwort über ein Token zurück: Das Token wird entwertet,
+/// alle Sessions des Benutzers werden beendet
+pub fn passwort_zuruecksetzen(
+    conn: &Connection,
+    token_hash: &str,
+    passwort: &str,
+) -> Result<(), String> {
+    if passwort.len() > 50 {
+        return Err(format!("Passwort zu lang"));
+    }
+
+    let password_hashed = hash_password(passwort);
+
+    let jetzt = Utc::now().to_rfc3339();
+
+    // Token nur einmal benutzbar, auch bei gleichzeitigen Anfragen
+    let geaendert = conn
+        .execute(
+            "UPDATE passwort_reset SET benutzt_am = ?1
+            WHERE token_hash = ?2 AND benutzt_am IS NULL AND gueltig_bis > ?1",
+            rusqlite::params![jetzt, token_hash],
+        )
+        .map_err(|e| format!("Fehler beim Zurücksetzen des Passworts: {e}"))?;
+
+    if geaendert == 0 {
+        return Err(format!("Ungültiger oder abgelaufener Link zum Zurücksetzen des Passworts"));
+    }
+
+    let id = conn
+        .query_row(
+            "SELECT benutzer FROM passwort_reset WHERE token_hash = ?1",
+            rusqlite::params![token_hash],
+            |row| row.get::<usize, i32>(0),
+        )
+        .map_err(|e| format!("Fehler beim Zurücksetzen des Passworts: {e}"))?;
+
+    conn.execute(
+        "UPDATE benutzer SET password_hashed = ?1 WHERE id = ?2",
+        rusqlite::params![password_hashed, id],
+    )
+    .map_err(|e| format!("Fehler beim Zurücksetzen des Passworts: {e}"))?;
+
+    conn.execute(
+        "DELETE FROM sessions WHERE benutzer = ?1",
+        rusqlite::params![id],
+    )
+    .map_err(|e| format!("Fehler beim Beenden der Sessions: {e}"))?;
+
+    // Wer das Passwort per E-Mail zurücksetzt, wird auch entsperrt
+    for tabelle in ["kontosperren", "fehlanmeldungen"] {
+        conn.execute(
+            &format!(
+                "DELETE FROM {tabelle} WHERE email = (SELECT LOWER(email) FROM benutzer WHERE id = ?1)"
+            ),
+            rusqlite::params![id],
+        )
+        .map_err(|e| format!("Fehler beim Zurücksetzen des Passworts: {e}"))?;
+    }
+
+    Ok(())
+}
+
+/// Löscht abgelaufene und bereits benutzte Tokens zum Zurücksetzen des Passworts
+pub fn passwort_reset_bereinigen(
+    konfiguration: &Konfiguration,
     mount_point: MountPoint,
+) -> Result<usize, String> {
+    let conn = verbindung_oeffnen(konfiguration, mount_point)
+        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+
+    conn.execute(
+        "DELETE FROM passwort_reset WHERE benutzt_am IS NOT NULL OR gueltig_bis < ?1",
+        rusqlite::params![Utc::now().to_rfc3339()],
+    )
+    .map_err(|e| format!("Fehler beim Bereinigen der Passwort-Tokens: {e}"))
+}
+
+pub fn create_user(
+    conn: &Connection,
     name: &str,
     email: &str,
     passwort: &str,
@@ -768,9 +913,6 @@ pub fn create_user(
 
     let password_hashed = hash_password(passwort);
 
-    let conn = Connection::open(get_db_path(mount_point))
-        .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
-
     conn.execute(
         "INSERT INTO benutzer (email, name, rechte, password_hashed) VALUES (?1, ?2, ?3, ?4)",
         rusqlite::params![email, name, rechte, password_hashed],
@@ -793,7 +935,7 @@ pub fn create_user(
 }
 
 pub fn bearbeite_benutzer_pubkey(
-    mount_point: MountPoint,
+    conn: &Connection,
     id: &str,
     neuer_pubkey: &str,
 ) -> Result<(), String> {
@@ -806,9 +948,6 @@ pub fn bearbeite_benutzer_pubkey(
 
     let fingerprint = cert.fingerprint().to_string();
 
-    let mut conn = Connection::open(get_db_path(mount_point))
-        .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
-
     let ok_update = conn
         .execute(
             "UPDATE publickeys SET pubkey = ?2, fingerprint = ?3 WHERE email = ?1",
@@ -828,19 +967,12 @@ pub fn bearbeite_benutzer_pubkey(
 }
 
 pub fn bearbeite_benutzer_rechte(
-    mount_point: MountPoint,
+    conn: &Connection,
     ids: &[String],
     neue_rechte: &str,
 ) -> Result<(), String> {
-    let mut conn = Connection::open(get_db_path(mount_point))
-        .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
-
-    let mut tx = conn
-        .transaction()
-        .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
-
     {
-        let mut stmt = tx
+        let mut stmt = conn
             .prepare("UPDATE benutzer SET rechte = ?2 WHERE email = ?1")
             .map_err(|e| format!("Fehler beim Bearbeiten der Benutzerrechte"))?;
 
@@ -852,9 +984,6 @@ pub fn bearbeite_benutzer_rechte(
         }
     }
 
-    tx.commit()
-        .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
-
     Ok(())
 }
 
@@ -935,6 +1064,7 @@ pub fn create_gpg_key(name: &str, email: &str) -> Result<GpgKeyPair, String> {
 }
 
 pub fn get_key_for_fingerprint(
+    konfiguration: &Konfiguration,
     fingerprint: &str,
     email: &str,
 ) -> Result<sequoia_openpgp::Cert, String> {
@@ -942,7 +1072,7 @@ pub fn get_key_for_fingerprint(
     use sequoia_openpgp::parse::Parse;
     use sequoia_openpgp::Cert;
 
-    let conn = Connection::open(get_db_path(MountPoint::Local))
+    let conn = verbindung_oeffnen(konfiguration, MountPoint::Local)
         .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
 
     let mut stmt = conn
@@ -977,7 +1107,10 @@ pub enum KontoDataResult {
 
 #[derive(Debug, Default, Clone, Serialize, Deserialize)]
 pub struct KontoData {
-    pub kontotyp: String,
+    /// Rolle des Benutzers
+    pub rechte: String,
+    /// Wirksame Berechtigungen der Rolle, `konto.js` zeigt danach die Bereiche an
+    pub berechtigungen: Vec<String>,
     pub ausgewaehlt: Option<String>,
     pub data: BTreeMap<String, KontoTabelle>,
 }
@@ -998,8 +1131,11 @@ impl KontoTabelle {
     }
 }
 
-pub fn get_server_address(mount_point: MountPoint) -> Result<String, String> {
-    let conn = Connection::open(get_db_path(mount_point))
+pub fn get_server_address(
+    konfiguration: &Konfiguration,
+    mount_point: MountPoint,
+) -> Result<String, String> {
+    let conn = verbindung_oeffnen(konfiguration, mount_point)
         .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
 
     conn.query_row(
@@ -1010,8 +1146,8 @@ pub fn get_server_address(mount_point: MountPoint) -> Result<String, String> {
     .map_err(|_| format!("Fehler beim Auslesen der Einstellungen"))
 }
 
-pub fn get_email_config() -> Result<SmtpConfig, String> {
-    let global = get_globale_einstellungen(MountPoint::Local)?;
+pub fn get_email_config(konfiguration: &Konfiguration) -> Result<SmtpConfig, String> {
+    let global = get_globale_einstellungen(konfiguration, MountPoint::Local)?;
     let global = global
         .into_iter()
         .map(|(_, (k, v))| (k, v))
@@ -1038,9 +1174,10 @@ pub fn get_email_config() -> Result<SmtpConfig, String> {
 }
 
 pub fn get_active_publickey_for_benutzer(
+    konfiguration: &Konfiguration,
     benutzer: &BenutzerInfo,
 ) -> Result<Option<String>, String> {
-    let conn = Connection::open(get_db_path(MountPoint::Local))
+    let conn = verbindung_oeffnen(konfiguration, MountPoint::Local)
         .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
 
     conn.query_row(
@@ -1051,553 +1188,788 @@ pub fn get_active_publickey_for_benutzer(
     .map_err(|_| format!("Fehler beim Auslesen der Einstellungen"))
 }
 
-pub fn get_konto_data(benutzer_info: &BenutzerInfo) -> Result<KontoDataResult, String> {
-    let mut data = KontoData::default();
-    data.kontotyp = benutzer_info.rechte.clone();
+pub fn get_konto_data(
+    konfiguration: &Konfiguration,
+    benutzer_info: &BenutzerInfo,
+) -> Result<KontoDataResult, String> {
+    use crate::rollen::Berechtigung;
 
-    let conn = Connection::open(get_db_path(MountPoint::Local))
+    let mut data = KontoData::default();
+    data.rechte = benutzer_info.rechte.clone();
+    data.berechtigungen = Berechtigung::ALLE
+        .iter()
+        .filter(|b| benutzer_info.hat_berechtigung(**b))
+        .map(|b| b.as_str().to_string())
+        .collect();
+
+    let conn = verbindung_oeffnen(konfiguration, MountPoint::Local)
         .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
 
-    let pw_rows: Option<Vec<u8>> = conn
+    // Über OpenID Connect angemeldete Benutzer brauchen kein Passwort
+    let (pw_rows, oidc_subjekt): (Option<Vec<u8>>, Option<String>) = conn
         .query_row(
-            "SELECT password_hashed FROM benutzer WHERE id = ?1",
+            "SELECT password_hashed, oidc_subjekt FROM benutzer WHERE id = ?1",
             rusqlite::params![benutzer_info.id],
-            |row| row.get(0),
+            |row| Ok((row.get(0)?, row.get(1)?)),
         )
         .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
 
-    if pw_rows.is_none() {
+    if pw_rows.is_none() && oidc_subjekt.is_none() {
         return Ok(KontoDataResult::KeinPasswort);
     }
 
-    match benutzer_info.rechte.as_str() {
-        "admin" => {
-            // Zugriffe
-            let mut stmt = conn
-                .prepare(
-                    "
-                SELECT 
-                    id,
-                    name,
-                    email,
-                    typ,
-                    grund,
-                    land,
-                    amtsgericht,
-                    bezirk,
-                    blatt,
-                    angefragt,
-                    gewaehrt_von,
-                    abgelehnt_von,
-                    am
-                FROM zugriffe 
-            ",
-                )
-                .map_err(|e| format!("Fehler beim Auslesen der Benutzerdaten 1"))?;
-
-            let zugriffe = stmt
-                .query_map(rusqlite::params![], |row| {
-                    Ok((
-                        row.get::<usize, String>(0)?,
-                        row.get::<usize, String>(1)?,
-                        row.get::<usize, String>(2)?,
-                        row.get::<usize, String>(3)?,
-                        row.get::<usize, Option<String>>(4)?,
-                        row.get::<usize, String>(5)?,
-                        row.get::<usize, String>(6)?,
-                        row.get::<usize, String>(7)?,
-                        row.get::<usize, String>(8)
-                            .or_else(|_| row.get::<usize, i32>(8).map(|s| format!("{s}")))?,
-                        row.get::<usize, String>(9)?,
-                        row.get::<usize, Option<String>>(10)?,
-                        row.get::<usize, Option<String>>(11)?,
-                        row.get::<usize, Option<String>>(12)?,
-                    ))
-                })
-                .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?
-                .collect::<Vec<_>>();
-
-            data.data.insert(
-                "zugriffe".to_string(),
-                KontoTabelle {
-                    spalten: vec![
-                        "id".to_string(),
-                        "name".to_string(),
-                        "email".to_string(),
-                        "typ".to_string(),
-                        "grund".to_string(),
-                        "land".to_string(),
-                        "amtsgericht".to_string(),
-                        "bezirk".to_string(),
-                        "blatt".to_string(),
-                        "angefragt".to_string(),
-                        "gewaehrt_von".to_string(),
-                        "abgelehnt_von".to_string(),
-                        "am".to_string(),
-                    ],
-                    daten: zugriffe
-                        .into_iter()
-                        .filter_map(|row| {
-                            let row = row.ok()?;
-                            Some((
-                                row.0.clone(),
-                                vec![
-                                    row.0.clone(),
-                                    row.1.clone(),
-                                    row.2.clone(),
-                                    row.3.clone(),
-                                    row.4.clone().unwrap_or_default(),
-                                    row.5.clone(),
-                                    row.6.clone(),
-                                    row.7.clone(),
-                                    row.8.clone(),
-                                    row.9.clone(),
-                                    row.10.clone().unwrap_or_default(),
-                                    row.11.clone().unwrap_or_default(),
-                                    row.12.clone().unwrap_or_default(),
-                                ],
-                            ))
-                        })
-                        .collect(),
-                        .. Default::default()
-                },
-            );
-
-            // Benutzer
-            //
-            // https://www.sqlitetutorial.net/sqlite-full-outer-join/
-            let mut stmt = conn
-                .prepare(
-                    "
-                SELECT  benutzer.name,
-                        benutzer.email,
-                        benutzer.rechte,
-                        publickeys.pubkey,
-                        publickeys.fingerprint
-                FROM benutzer
-                LEFT JOIN publickeys ON publickeys.email = benutzer.email
-                    UNION ALL
-                    SELECT benutzer.name,
-                        benutzer.email,
-                        benutzer.rechte,
-                        publickeys.pubkey,
-                        publickeys.fingerprint
-                    FROM publickeys
-                    LEFT JOIN benutzer ON benutzer.email = publickeys.email
-                    WHERE publickeys.email IS NULL
-            ",
-                )
-                .map_err(|e| format!("Fehler beim Auslesen der Benutzerdaten 2: {e}"))?;
-
-            let benutzer = stmt
-                .query_map(rusqlite::params![], |row| {
-                    Ok((
-                        row.get::<usize, String>(0)?,
-                        row.get::<usize, String>(1)?,
-                        row.get::<usize, String>(2)?,
-                        row.get::<usize, Option<String>>(3)?,
-                        row.get::<usize, Option<String>>(4)?,
-                    ))
-                })
-                .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?
-                .collect::<Vec<_>>();
+    let darf = |b: Berechtigung| benutzer_info.hat_berechtigung(b);
 
-            data.data.insert(
-                "benutzer".to_string(),
-                KontoTabelle {
-                    spalten: vec![
-                        "name".to_string(),
-                        "email".to_string(),
-                        "rechte".to_string(),
-                        "publickeys.fingerprint".to_string(),
-                        "publickeys.pubkey".to_string(),
-                    ],
-                    daten: benutzer
-                        .into_iter()
-                        .filter_map(|row| {
-                            let row = row.ok()?;
-                            Some((
-                                row.1.clone(),
-                                vec![
-                                    row.0.clone(),
-                                    row.1.clone(),
-                                    row.2.clone(),
-                                    row.3.clone().unwrap_or_default(),
-                                    row.4.clone().unwrap_or_default(),
-                                ],
-                            ))
-                        })
-                        .collect(),
-                    .. Default::default()
-                },
-            );
-
-            let mut stmt = conn
-                .prepare("SELECT id, land, amtsgericht, bezirk FROM bezirke")
-                .map_err(|e| format!("Fehler beim Auslesen der Benutzerdaten 3"))?;
-
-            let bezirke = stmt
-                .query_map(rusqlite::params![], |row| {
-                    Ok((
-                        row.get::<usize, String>(0)?,
-                        row.get::<usize, String>(1)?,
-                        row.get::<usize, String>(2)?,
-                        row.get::<usize, String>(3)?,
-                    ))
-                })
-                .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?
-                .collect::<Vec<_>>();
-
-            data.data.insert(
-                "bezirke".to_string(),
-                KontoTabelle {
-                    spalten: vec![
-                        "id".to_string(),
-                        "land".to_string(),
-                        "amtsgericht".to_string(),
-                        "bezirk".to_string(),
-                    ],
-                    daten: bezirke
-                        .into_iter()
-                        .enumerate()
-                        .filter_map(|(i, row)| {
-                            let row = row.ok()?;
-                            let bundesland = Bundesland::from_code(&row.1)?.into_str().to_string();
-                            Some((
-                                format!("{i}"),
-                                vec![row.0.clone(), bundesland, row.2.clone(), row.3.clone()],
-                            ))
-                        })
-                        .collect(),
-                    .. Default::default()
-                },
-            );
-
-            let aenderungen = crate::db::get_aenderungen(AenderungFilter::GetLast(500));
-            data.data.insert(
-                "aenderungen".to_string(),
-                KontoTabelle {
-                    spalten: vec![
-                        "id".to_string(),
-                        "name".to_string(),
-                        "email".to_string(),
-                        "zeit-sec".to_string(),
-                        "zeit-offset".to_string(),
-                        "zeit-tz".to_string(),
-                        "titel".to_string(),
-                        "beschreibung".to_string(),
-                    ],
-                    daten: aenderungen
-                        .into_iter()
-                        .enumerate()
-                        .map(|(i, a)| (i.to_string(), a))
-                        .collect(),
-                    .. Default::default()
-                },
-            );
-
-            // Grundbücher
-            let verfuegbare_grundbuecher =
-                crate::db::get_verfuegbare_grundbuecher_fuer_benutzer(&benutzer_info)
-                    .unwrap_or_default();
-
-            println!("verfügbare grundbücher = {:?}", verfuegbare_grundbuecher);
-
-            data.data.insert(
-                "blaetter".to_string(),
-                KontoTabelle {
-                    spalten: vec![
-                        "land".to_string(),
-                        "amtsgericht".to_string(),
-                        "bezirk".to_string(),
-                        "blatt".to_string(),
-                    ],
-                    daten: verfuegbare_grundbuecher
-                        .into_iter()
-                        .map(|(l, a, g, b)| (format!("{l}/{a}/{g}/{b}"), vec![l, a, g, b]))
-                        .collect(),
-                    .. Default::default()
-                },
-            );
-
-            // Abonnements
-            let abos =
-                crate::db::get_email_abonnements_fuer_benutzer(&benutzer_info).unwrap_or_default();
-
-            println!("{:#?}", abos);
-
-            data.data.insert(
-                "abonnements".to_string(),
-                KontoTabelle {
-                    spalten: vec![
-                        "id".to_string(),
-                        "typ".to_string(),
-                        "text".to_string(),
-                        "amtsgericht".to_string(),
-                        "bezirk".to_string(),
-                        "blatt".to_string(),
-                        "aktenzeichen".to_string(),
-                    ],
-                    daten: abos
-                        .into_iter()
-                        .map(|(id, typ, text, l, a, g, b)| (id.clone(), vec![id, typ, text, l, a, g, b]))
-                        .collect(),
-                    .. Default::default()
-                },
-            );
-
-            // Einstellungen
-            data.data.insert(
-                "meine-kontodaten".to_string(),
-                KontoTabelle {
-                    spalten: vec![
-                        "id".to_string(),
-                        "global".to_string(),
-                        "einstellung".to_string(),
-                        "wert".to_string(),
-                    ],
-                    daten: {
-                        let mut d = BTreeMap::new();
-                        for (id, (k, v)) in
-                            get_einstellungen_fuer_benutzer(MountPoint::Local, &benutzer_info)?
-                                .into_iter()
-                        {
-                            d.insert(
-                                id.to_string(),
-                                vec!["false".to_string(), k.to_string(), v.to_string()],
-                            );
-                        }
-                        for (id, (k, v)) in
-                            get_globale_einstellungen(MountPoint::Local)?.into_iter()
-                        {
-                            d.insert(
-                                id.to_string(),
-                                vec!["true".to_string(), k.to_string(), v.to_string()],
-                            );
-                        }
-                        d
-                    },
-                    .. Default::default()
-                },
-            );
-        }
-        "bearbeiter" => {
-            // Extra Kontodaten
-            let publickey = match crate::db::get_active_publickey_for_benutzer(&benutzer_info) {
-                Ok(o) => o,
-                Err(_) => None,
-            };
+    if darf(Berechtigung::ZugriffeVerwalten) {
+        // Zugriffe
+        let mut stmt = conn
+            .prepare(
+                "
+            SELECT 
+                id,
+                name,
+                email,
+                typ,
+                grund,
+                land,
+                amtsgericht,
+                bezirk,
+                blatt,
+                angefragt,
+                gewaehrt_von,
+                abgelehnt_von,
+                am,
+                gueltig_bis,
+                widerrufen_von,
+                widerrufen_am,
+                blatt_bis,
+                ablehnungsgrund,
+                zurueckgezogen_am
+            FROM zugriffe
+            WHERE bestaetigt_am IS NOT NULL
+        ",
+            )
+            .map_err(|e| format!("Fehler beim Auslesen der Benutzerdaten 1"))?;
 
-            data.data.insert(
-                "kontodaten-extra".to_string(),
-                KontoTabelle {
-                    spalten: vec!["wert".to_string()],
-                    daten: {
-                        let mut b = BTreeMap::new();
-                        b.insert(
-                            "konto.publickey".to_string(),
-                            vec![publickey.unwrap_or_default()],
-                        );
-                        b.insert("konto.email".to_string(), vec![benutzer_info.email.clone()]);
-                        b.insert("konto.name".to_string(), vec![benutzer_info.name.clone()]);
-                        b
-                    },
-                    .. Default::default()
-                },
-            );
-
-            // Änderungen des Bearbeiters
-            let aenderungen = crate::db::get_aenderungen(AenderungFilter::FilterEmail(
-                benutzer_info.email.clone(),
-            ));
-            data.data.insert(
-                "aenderungen".to_string(),
-                KontoTabelle {
-                    spalten: vec![
-                        "id".to_string(),
-                        "name".to_string(),
-                        "email".to_string(),
-                        "zeit-sec".to_string(),
-                        "zeit-offset".to_string(),
-                        "zeit-tz".to_string(),
-                        "zusammenfassung".to_string(),
-                    ],
-                    daten: aenderungen
-                        .into_iter()
-                        .filter(|a| a[2] == benutzer_info.email)
-                        .enumerate()
-                        .map(|(i, a)| (i.to_string(), a))
-                        .collect(),
-                    .. Default::default()
-                },
-            );
-
-            // Grundbücher
-            let verfuegbare_grundbuecher =
-                crate::db::get_verfuegbare_grundbuecher_fuer_benutzer(&benutzer_info)
-                    .unwrap_or_default();
-
-            println!("verfügbare grundbücher bearbeiter = {:?}", verfuegbare_grundbuecher);
-
-            data.data.insert(
-                "blaetter".to_string(),
-                KontoTabelle {
-                    spalten: vec![
-                        "land".to_string(),
-                        "amtsgericht".to_string(),
-                        "bezirk".to_string(),
-                        "blatt".to_string(),
-                    ],
-                    daten: verfuegbare_grundbuecher
-                        .into_iter()
-                        .map(|(l, a, g, b)| (format!("{l}/{a}/{g}/{b}"), vec![l, a, g, b]))
-                        .collect(),
-                    .. Default::default()
-                },
-            );
-
-            // Abonnements
-            let abos =
-                crate::db::get_email_abonnements_fuer_benutzer(&benutzer_info).unwrap_or_default();
-
-            println!("{:#?}", abos);
-
-            data.data.insert(
-                "abonnements".to_string(),
-                KontoTabelle {
-                    spalten: vec![
-                        "id".to_string(),
-                        "typ".to_string(),
-                        "text".to_string(),
-                        "amtsgericht".to_string(),
-                        "bezirk".to_string(),
-                        "blatt".to_string(),
-                        "aktenzeichen".to_string(),
-                    ],
-                    daten: abos
-                        .into_iter()
-                        .map(|(id, typ, text, l, a, g, b)| (id.to_string(), vec![id, typ, text, l, a, g, b]))
-                        .collect(),
-                    .. Default::default()
-                },
-            );
-
-            // Einstellungen
-            data.data.insert(
-                "meine-kontodaten".to_string(),
-                KontoTabelle {
-                    spalten: vec![
-                        "id".to_string(),
-                        "global".to_string(),
-                        "einstellung".to_string(),
-                        "wert".to_string(),
-                    ],
-                    daten: {
-                        let mut d = BTreeMap::new();
-                        for (id, (k, v)) in
-                            get_einstellungen_fuer_benutzer(MountPoint::Local, &benutzer_info)?
-                                .into_iter()
-                        {
-                            d.insert(
-                                id.to_string(),
-                                vec!["false".to_string(), k.to_string(), v.to_string()],
-                            );
-                        }
-                        d
-                    },
-                    .. Default::default()
-                },
-            );
-        }
-        "gast" => {
-            // Grundbücher
-            let verfuegbare_grundbuecher =
-                crate::db::get_verfuegbare_grundbuecher_fuer_benutzer(&benutzer_info)
-                    .unwrap_or_default();
-
-            data.data.insert(
-                "blaetter".to_string(),
-                KontoTabelle {
-                    spalten: vec![
-                        "land".to_string(),
-                        "amtsgericht".to_string(),
-                        "bezirk".to_string(),
-                        "blatt".to_string(),
-                    ],
-                    daten: verfuegbare_grundbuecher
-                        .into_iter()
-                        .map(|(l, a, g, b)| (format!("{l}/{a}/{g}/{b}"), vec![l, a, g, b]))
-                        .collect(),
-                    .. Default::default()
-                },
-            );
-
-            // Abonnements
-            let abos =
-                crate::db::get_email_abonnements_fuer_benutzer(&benutzer_info).unwrap_or_default();
-
-            println!("{:#?}", abos);
-
-            data.data.insert(
-                "abonnements".to_string(),
-                KontoTabelle {
-                    spalten: vec![
-                        "typ".to_string(),
-                        "text".to_string(),
-                        "amtsgericht".to_string(),
-                        "bezirk".to_string(),
-                        "blatt".to_string(),
-                        "aktenzeichen".to_string(),
-                    ],
-                    daten: abos
-                        .into_iter()
-                        .map(|(id, typ, text, l, a, g, b)| (id.clone(), vec![id, typ, text, l, a, g, b]))
-                        .collect(),
-                    .. Default::default()
-                },
-            );
-
-            // Einstellungen
-            data.data.insert(
-                "meine-kontodaten".to_string(),
-                KontoTabelle {
-                    spalten: vec![
-                        "id".to_string(),
-                        "global".to_string(),
-                        "einstellung".to_string(),
-                        "wert".to_string(),
-                    ],
-                    daten: {
-                        let mut d = BTreeMap::new();
-                        for (id, (k, v)) in
-                            get_einstellungen_fuer_benutzer(MountPoint::Local, &benutzer_info)?
-                                .into_iter()
-                        {
-                            d.insert(
-                                id.to_string(),
-                                vec!["false".to_string(), k.to_string(), v.to_string()],
-                            );
-                        }
-                        d
+        let zugriffe = stmt
+            .query_map(rusqlite::params![], |row| {
+                Ok((
+                    row.get::<usize, String>(0)?,
+                    row.get::<usize, String>(1)?,
+                    row.get::<usize, String>(2)?,
+                    row.get::<usize, String>(3)?,
+                    row.get::<usize, Option<String>>(4)?,
+                    row.get::<usize, String>(5)?,
+                    row.get::<usize, String>(6)?,
+                    row.get::<usize, String>(7)?,
+                    row.get::<usize, String>(8)
+                        .or_else(|_| row.get::<usize, i32>(8).map(|s| format!("{s}")))?,
+                    row.get::<usize, String>(9)?,
+                    row.get::<usize, Option<String>>(10)?,
+                    row.get::<usize, Option<String>>(11)?,
+                    row.get::<usize, Option<String>>(12)?,
+                    row.get::<usize, Option<String>>(13)?,
+                    row.get::<usize, Option<String>>(14)?,
+                    row.get::<usize, Option<String>>(15)?,
+                    row.get::<usize, Option<String>>(16)?,
+                    row.get::<usize, Option<String>>(17)?,
+                    row.get::<usize, Option<String>>(18)?,
+                ))
+            })
+            .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?
+            .collect::<Vec<_>>();
+
+        // Anzahl der vorhandenen Blätter, die ein Zugriff umfasst (für Bereiche und ALLE_*),
+        // nur für offene Anfragen, über die noch entschieden werden muss
+        let zugriffe = zugriffe.into_iter().filter_map(|r| r.ok()).collect::<Vec<_>>();
+        let offen = zugriffe
+            .iter()
+            .filter(|row| row.10.is_none() && row.11.is_none() && row.18.is_none())
+            .map(|row| {
+                (
+                    row.0.clone(),
+                    ZugriffBereich {
+                        land: row.5.clone(),
+                        amtsgericht: row.6.clone(),
+                        bezirk: row.7.clone(),
+                        blatt: row.8.clone(),
+                        blatt_bis: row.16.clone(),
                     },
+                )
+            })
+            .collect::<Vec<_>>();
+        let bereiche = offen.iter().map(|(_, z)| z.clone()).collect::<Vec<_>>();
+        let blaetter_anzahl = offen
+            .iter()
+            .map(|(id, _)| id.clone())
+            .zip(anzahl_blaetter(&conn, &bereiche).unwrap_or_default())
+            .collect::<BTreeMap<_, _>>();
+
+        data.data.insert(
+            "zugriffe".to_string(),
+            KontoTabelle {
+                spalten: vec![
+                    "id".to_string(),
+                    "name".to_string(),
+                    "email".to_string(),
+                    "typ".to_string(),
+                    "grund".to_string(),
+                    "land".to_string(),
+                    "amtsgericht".to_string(),
+                    "bezirk".to_string(),
+                    "blatt".to_string(),
+                    "angefragt".to_string(),
+                    "gewaehrt_von".to_string(),
+                    "abgelehnt_von".to_string(),
+                    "am".to_string(),
+                    "gueltig_bis".to_string(),
+                    "widerrufen_von".to_string(),
+                    "widerrufen_am".to_string(),
+                    "blatt_bis".to_string(),
+                    "blaetter_anzahl".to_string(),
+                    "ablehnungsgrund".to_string(),
+                    "zurueckgezogen_am".to_string(),
+                ],
+                daten: zugriffe
+                    .into_iter()
+                    .map(|row| {
+                        let blaetter_anzahl = blaetter_anzahl
+                            .get(&row.0)
+                            .map(|n| n.to_string())
+                            .unwrap_or_default();
+                        (
+                            row.0.clone(),
+                            vec![
+                                row.0.clone(),
+                                row.1.clone(),
+                                row.2.clone(),
+                                row.3.clone(),
+                                row.4.clone().unwrap_or_default(),
+                                row.5.clone(),
+                                row.6.clone(),
+                                row.7.clone(),
+                                row.8.clone(),
+                                row.9.clone(),
+                                row.10.clone().unwrap_or_default(),
+                                row.11.clone().unwrap_or_default(),
+                                row.12.clone().unwrap_or_default(),
+                                row.13.clone().unwrap_or_default(),
+                                row.14.clone().unwrap_or_default(),
+                                row.15.clone().unwrap_or_default(),
+                                row.16.clone().unwrap_or_default(),
+                                blaetter_anzahl,
+                                row.17.clone().unwrap_or_default(),
+                                row.18.clone().unwrap_or_default(),
+                            ],
+                        )
+                    })
+                    .collect(),
                     .. Default::default()
-                },
-            );
-        }
-        _ => {}
+            },
+        );
     }
 
-    Ok(KontoDataResult::Aktiviert(data))
-}
+    if darf(Berechtigung::BenutzerVerwalten) {
+        // Benutzer
+        //
+        // https://www.sqlitetutorial.net/sqlite-full-outer-join/
+        let mut stmt = conn
+            .prepare(
+                "
+            SELECT  benutzer.name,
+                    benutzer.email,
+                    benutzer.rechte,
+                    publickeys.pubkey,
+                    publickeys.fingerprint
+            FROM benutzer
+            LEFT JOIN publickeys ON publickeys.email = benutzer.email
+                UNION ALL
+                SELECT benutzer.name,
+                    benutzer.email,
+                    benutzer.rechte,
+                    publickeys.pubkey,
+                    publickeys.fingerprint
+                FROM publickeys
+                LEFT JOIN benutzer ON benutzer.email = publickeys.email
+                WHERE publickeys.email IS NULL
+        ",
+            )
+            .map_err(|e| format!("Fehler beim Auslesen der Benutzerdaten 2: {e}"))?;
 
-#[derive(Debug, Clone, PartialEq)]
-pub enum AenderungFilter {
-    GetLast(usize),
-    FilterEmail(String),
+        let benutzer = stmt
+            .query_map(rusqlite::params![], |row| {
+                Ok((
+                    row.get::<usize, String>(0)?,
+                    row.get::<usize, String>(1)?,
+                    row.get::<usize, String>(2)?,
+                    row.get::<usize, Option<String>>(3)?,
+                    row.get::<usize, Option<String>>(4)?,
+                ))
+            })
+            .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?
+            .collect::<Vec<_>>();
+
+        let kontosperren =
+            crate::ratenbegrenzung::get_kontosperren(konfiguration, MountPoint::Local).unwrap_or_default();
+
+        data.data.insert(
+            "benutzer".to_string(),
+            KontoTabelle {
+                spalten: vec![
+                    "name".to_string(),
+                    "email".to_string(),
+                    "rechte".to_string(),
+                    "publickeys.fingerprint".to_string(),
+                    "publickeys.pubkey".to_string(),
+                    "kontosperren.gesperrt_bis".to_string(),
+                ],
+                daten: benutzer
+                    .into_iter()
+                    .filter_map(|row| {
+                        let row = row.ok()?;
+                        Some((
+                            row.1.clone(),
+                            vec![
+                                row.0.clone(),
+                                row.1.clone(),
+                                row.2.clone(),
+                                row.3.clone().unwrap_or_default(),
+                                row.4.clone().unwrap_or_default(),
+                                kontosperren
+                                    .get(&row.1.to_lowercase())
+                                    .map(|bis| {
+                                        if bis.is_empty() {
+                                            "unbegrenzt".to_string()
+                                        } else {
+                                            bis.clone()
+                                        }
+                                    })
+                                    .unwrap_or_default(),
+                            ],
+                        ))
+                    })
+                    .collect(),
+                .. Default::default()
+            },
+        );
+    }
+
+    if darf(Berechtigung::BezirkeVerwalten) {
+        // Bezirke
+        let mut stmt = conn
+            .prepare("SELECT id, land, amtsgericht, bezirk FROM bezirke")
+            .map_err(|e| format!("Fehler beim Auslesen der Benutzerdaten 3"))?;
+
+        let bezirke = stmt
+            .query_map(rusqlite::params![], |row| {
+                Ok((
+                    row.get::<usize, String>(0)?,
+                    row.get::<usize, String>(1)?,
+                    row.get::<usize, String>(2)?,
+                    row.get::<usize, String>(3)?,
+                ))
+            })
+            .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?
+            .collect::<Vec<_>>();
+
+        data.data.insert(
+            "bezirke".to_string(),
+            KontoTabelle {
+                spalten: vec![
+                    "id".to_string(),
+                    "land".to_string(),
+                    "amtsgericht".to_string(),
+                    "bezirk".to_string(),
+                ],
+                daten: bezirke
+                    .into_iter()
+                    .enumerate()
+                    .filter_map(|(i, row)| {
+                        let row = row.ok()?;
+                        let bundesland = Bundesland::from_code(&row.1)?.into_str().to_string();
+                        Some((
+                            format!("{i}"),
+                            vec![row.0.clone(), bundesland, row.2.clone(), row.3.clone()],
+                        ))
+                    })
+                    .collect(),
+                .. Default::default()
+            },
+        );
+    }
+
+    if darf(Berechtigung::GrundbuecherAlleLesen) {
+        // Alle Änderungen
+        let aenderungen = crate::db::get_aenderungen(konfiguration, AenderungFilter::GetLast(500));
+        data.data.insert(
+            "aenderungen".to_string(),
+            KontoTabelle {
+                spalten: vec![
+                    "id".to_string(),
+                    "name".to_string(),
+                    "email".to_string(),
+                    "zeit-sec".to_string(),
+                    "zeit-offset".to_string(),
+                    "zeit-tz".to_string(),
+                    "titel".to_string(),
+                    "beschreibung".to_string(),
+                ],
+                daten: aenderungen
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, a)| (i.to_string(), a))
+                    .collect(),
+                .. Default::default()
+            },
+        );
+    } else if darf(Berechtigung::GrundbuchBearbeiten) {
+        // Änderungen des Bearbeiters
+        let aenderungen = crate::db::get_aenderungen(konfiguration, AenderungFilter::FilterEmail(
+            benutzer_info.email.clone(),
+        ));
+        data.data.insert(
+            "aenderungen".to_string(),
+            KontoTabelle {
+                spalten: vec![
+                    "id".to_string(),
+                    "name".to_string(),
+                    "email".to_string(),
+                    "zeit-sec".to_string(),
+                    "zeit-offset".to_string(),
+                    "zeit-tz".to_string(),
+                    "titel".to_string(),
+                    "beschreibung".to_string(),
+                ],
+                daten: aenderungen
+                    .into_iter()
+                    .filter(|a| a[2] == benutzer_info.email)
+                    .enumerate()
+                    .map(|(i, a)| (i.to_string(), a))
+                    .collect(),
+                .. Default::default()
+            },
+        );
+    }
+
+    if darf(Berechtigung::ProtokolleLesen) {
+        // Audit-Log (nur die neuesten Einträge, vollständig über /audit-log)
+        let audit_log = crate::audit::get_audit_log(konfiguration, &crate::audit::AuditFilter {
+            limit: Some(500),
+            ..Default::default()
+        })
+        .unwrap_or_default();
+
+        data.data.insert(
+            "audit-log".to_string(),
+            KontoTabelle {
+                spalten: vec![
+                    "zeitpunkt".to_string(),
+                    "akteur".to_string(),
+                    "operation".to_string(),
+                    "ziel".to_string(),
+                    "daten".to_string(),
+                ],
+                daten: audit_log
+                    .into_iter()
+                    .enumerate()
+                    .map(|(i, e)| {
+                        (
+                            format!("{i:05}"),
+                            vec![e.zeitpunkt, e.akteur, e.operation, e.ziel, e.daten],
+                        )
+                    })
+                    .collect(),
+                .. Default::default()
+            },
+        );
+    }
+
+    if darf(Berechtigung::AbosVerwalten) {
+        // Postausgang (E-Mails und Webhooks an Abonnenten)
+        let postausgang = crate::postausgang::get_eintraege(konfiguration, 500).unwrap_or_default();
+
+        data.data.insert(
+            "postausgang".to_string(),
+            KontoTabelle {
+                spalten: vec![
+                    "id".to_string(),
+                    "typ".to_string(),
+                    "ziel".to_string(),
+                    "status".to_string(),
+                    "versuche".to_string(),
+                    "naechster_versuch".to_string(),
+                    "letzter_fehler".to_string(),
+                    "erstellt_am".to_string(),
+                    "gesendet_am".to_string(),
+                    "aenderungs_id".to_string(),
+                ],
+                daten: postausgang
+                    .into_iter()
+                    .map(|e| {
+                        (
+                            e.id.clone(),
+                            vec![
+                                e.id,
+                                e.typ,
+                                e.ziel,
+                                e.status,
+                                e.versuche.to_string(),
+                                e.naechster_versuch,
+                                e.letzter_fehler.unwrap_or_default(),
+                                e.erstellt_am,
+                                e.gesendet_am.unwrap_or_default(),
+                                e.aenderungs_id.unwrap_or_default(),
+                            ],
+                        )
+                    })
+                    .collect(),
+                .. Default::default()
+            },
+        );
+    }
+
+    if darf(Berechtigung::GrundbuchBearbeiten) {
+        // Eigener Schlüssel zum Signieren der Änderungen
+        let publickey = match crate::db::get_active_publickey_for_benutzer(konfiguration, &benutzer_info) {
+            Ok(o) => o,
+            Err(_) => None,
+        };
+
+        data.data.insert(
+            "kontodaten-extra".to_string(),
+            KontoTabelle {
+                spalten: vec!["wert".to_string()],
+                daten: {
+                    let mut b = BTreeMap::new();
+                    b.insert(
+                        "konto.publickey".to_string(),
+                        vec![publickey.unwrap_or_default()],
+                    );
+                    b.insert("konto.email".to_string(), vec![benutzer_info.email.clone()]);
+                    b.insert("konto.name".to_string(), vec![benutzer_info.name.clone()]);
+                    b
+                },
+                .. Default::default()
+            },
+        );
+    }
+
+    // Grundbücher
+    let verfuegbare_grundbuecher =
+        crate::db::get_verfuegbare_grundbuecher_fuer_benutzer(konfiguration, &benutzer_info)
+            .unwrap_or_default();
+
+    println!("verfügbare grundbücher = {:?}", verfuegbare_grundbuecher);
+
+    data.data.insert(
+        "blaetter".to_string(),
+        KontoTabelle {
+            spalten: vec![
+                "land".to_string(),
+                "amtsgericht".to_string(),
+                "bezirk".to_string(),
+                "blatt".to_string(),
+            ],
+            daten: verfuegbare_grundbuecher
+                .into_iter()
+                .map(|(l, a, g, b)| (format!("{l}/{a}/{g}/{b}"), vec![l, a, g, b]))
+                .collect(),
+            .. Default::default()
+        },
+    );
+
+    // Abonnements
+    let abos =
+        crate::db::get_email_abonnements_fuer_benutzer(konfiguration, &benutzer_info).unwrap_or_default();
+
+    println!("{:#?}", abos);
+
+    data.data.insert(
+        "abonnements".to_string(),
+        KontoTabelle {
+            spalten: vec![
+                "id".to_string(),
+                "typ".to_string(),
+                "text".to_string(),
+                "amtsgericht".to_string(),
+                "bezirk".to_string(),
+                "blatt".to_string(),
+                "aktenzeichen".to_string(),
+                "detailstufe".to_string(),
+                "filter".to_string(),
+            ],
+            daten: abos
+                .into_iter()
+                .map(|(id, typ, text, l, a, g, b, d, f)| (id.clone(), vec![id, typ, text, l, a, g, b, d, f]))
+                .collect(),
+            .. Default::default()
+        },
+    );
+
+    // Einstellungen
+    data.data.insert(
+        "meine-kontodaten".to_string(),
+        KontoTabelle {
+            spalten: vec![
+                "id".to_string(),
+                "global".to_string(),
+                "einstellung".to_string(),
+                "wert".to_string(),
+            ],
+            daten: {
+                let mut d = BTreeMap::new();
+                for (id, (k, v)) in
+                    get_einstellungen_fuer_benutzer(konfiguration, MountPoint::Local, &benutzer_info)?
+                        .into_iter()
+                {
+                    d.insert(
+                        id.to_string(),
+                        vec!["false".to_string(), k.to_string(), v.to_string()],
+                    );
+                }
+                if darf(Berechtigung::KonfigurationBearbeiten) {
+                    for (id, (k, v)) in
+                        get_globale_einstellungen(konfiguration, MountPoint::Local)?.into_iter()
+                    {
+                        d.insert(
+                            id.to_string(),
+                            vec!["true".to_string(), k.to_string(), v.to_string()],
+                        );
+                    }
+                }
+                d
+            },
+            .. Default::default()
+        },
+    );
+
+    if darf(Berechtigung::RollenVerwalten) || darf(Berechtigung::BenutzerVerwalten) {
+        // Rollen (auch für die Auswahl unter "Benutzer"), extra: alle Berechtigungen mit Beschreibung
+        let rollen = crate::rollen::get_rollen(konfiguration)?;
+        let mut tabelle = KontoTabelle {
+            spalten: vec![
+                "name".to_string(),
+                "bezeichnung".to_string(),
+                "berechtigungen".to_string(),
+            ],
+            daten: rollen
+                .into_iter()
+                .map(|r| {
+                    let berechtigungen = r
+                        .berechtigungen
+                        .iter()
+                        .map(|b| b.as_str())
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    (r.name.clone(), vec![r.name, r.bezeichnung, berechtigungen])
+                })
+                .collect(),
+            .. Default::default()
+        };
+        tabelle.extra.insert(
+            "berechtigungen".to_string(),
+            Berechtigung::ALLE
+                .iter()
+                .map(|b| (b.as_str().to_string(), serde_json::Value::from(b.beschreibung())))
+                .collect::<serde_json::Map<_, _>>()
+                .into(),
+        );
+        data.data.insert("rollen".to_string(), tabelle);
+    }
+
+    // Sofort oder als Zusammenfassung, für die Auswahl unter "Abonnements"
+    if let Some(abos) = data.data.get_mut("abonnements") {
+        abos.insert_object(
+            "benachrichtigung",
+            get_abo_benachrichtigung(&conn, &benutzer_info.email).as_str(),
+        );
+    }
+
+    // Meine Sitzungen
+    let sessions = get_sessions_fuer_benutzer(konfiguration, benutzer_info).unwrap_or_default();
+    data.data.insert(
+        "sitzungen".to_string(),
+        KontoTabelle {
+            spalten: vec![
+                "id".to_string(),
+                "erstellt_am".to_string(),
+                "gueltig_bis".to_string(),
+                "refresh_gueltig_bis".to_string(),
+                "beschreibung".to_string(),
+            ],
+            daten: sessions
+                .into_iter()
+                .enumerate()
+                .map(|(i, s)| {
+                    (
+                        format!("{i:05}"),
+                        vec![
+                            s.id.to_string(),
+                            s.erstellt_am,
+                            s.gueltig_bis,
+                            s.refresh_gueltig_bis,
+                            s.beschreibung,
+                        ],
+                    )
+                })
+                .collect(),
+            .. Default::default()
+        },
+    );
+
+    // Zwei-Faktor-Authentifizierung
+    let zwei_faktor = crate::zwei_faktor::get_zwei_faktor(konfiguration, benutzer_info);
+    let pflicht = crate::zwei_faktor::ist_pflicht(konfiguration, benutzer_info);
+    let status = match zwei_faktor.as_ref() {
+        Some(z) if z.aktiviert => "aktiviert",
+        Some(_) => "ausstehend",
+        None => "deaktiviert",
+    };
+    let mut zeilen = vec![
+        vec!["Status".to_string(), status.to_string()],
+        vec![
+            "Vorgeschrieben".to_string(),
+            if pflicht { "ja" } else { "nein" }.to_string(),
+        ],
+    ];
+    match zwei_faktor.as_ref() {
+        Some(z) if z.aktiviert => zeilen.push(vec![
+            "Verbleibende Wiederherstellungscodes".to_string(),
+            z.wiederherstellungscodes_uebrig.to_string(),
+        ]),
+        Some(z) => {
+            zeilen.push(vec!["Geheimnis".to_string(), z.geheimnis.clone()]);
+            zeilen.push(vec![
+                "otpauth-URI".to_string(),
+                crate::zwei_faktor::otpauth_uri(&benutzer_info.email, &z.geheimnis),
+            ]);
+        }
+        None => {}
+    }
+    let mut tabelle = KontoTabelle {
+        spalten: vec!["einstellung".to_string(), "wert".to_string()],
+        daten: zeilen
+            .into_iter()
+            .enumerate()
+            .map(|(i, z)| (format!("{i:05}"), z))
+            .collect(),
+        .. Default::default()
+    };
+    tabelle.insert_object("status", status);
+    tabelle.insert_object("einrichtung_erforderlich", pflicht && status != "aktiviert");
+    data.data.insert("zwei-faktor".to_string(), tabelle);
+
+    // API-Schlüssel (Administratoren: alle)
+    let schluessel = crate::api_schluessel::get_api_schluessel(konfiguration, benutzer_info).unwrap_or_default();
+    data.data.insert(
+        "api-schluessel".to_string(),
+        KontoTabelle {
+            spalten: vec![
+                "id".to_string(),
+                "benutzer".to_string(),
+                "name".to_string(),
+                "scopes".to_string(),
+                "bereiche".to_string(),
+                "gueltig_bis".to_string(),
+                "zuletzt_benutzt".to_string(),
+            ],
+            daten: schluessel
+                .into_iter()
+                .enumerate()
+                .map(|(i, s)| {
+                    let bereiche = s
+                        .bereiche
+                        .iter()
+                        .map(|b| match b.bezirk.as_ref() {
+                            Some(bezirk) => format!("{}/{bezirk}", b.amtsgericht),
+                            None => b.amtsgericht.clone(),
+                        })
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    (
+                        format!("{i:05}"),
+                        vec![
+                            s.id,
+                            s.benutzer,
+                            s.name,
+                            s.scopes
+                                .iter()
+                                .map(|s| s.as_str())
+                                .collect::<Vec<_>>()
+                                .join(", "),
+                            bereiche,
+                            s.gueltig_bis.unwrap_or_default(),
+                            s.zuletzt_benutzt.unwrap_or_default(),
+                        ],
+                    )
+                })
+                .collect(),
+            .. Default::default()
+        },
+    );
+
+    // Einsichtsprotokoll (Administratoren: alle, sonst nur Blätter mit Zugriff)
+    let einsichten = crate::einsicht::get_einsichten(
+        konfiguration,
+        benutzer_info,
+        &crate::einsicht::EinsichtFilter {
+            limit: Some(500),
+            ..Default::default()
+        },
+    )
+    .unwrap_or_default();
+
+    data.data.insert(
+        "einsichtsprotokoll".to_string(),
+        KontoTabelle {
+            spalten: vec![
+                "zeitpunkt".to_string(),
+                "benutzer".to_string(),
+                "blatt".to_string(),
+                "typ".to_string(),
+                "aktenzeichen".to_string(),
+                "zweck".to_string(),
+            ],
+            daten: einsichten
+                .into_iter()
+                .enumerate()
+                .map(|(i, e)| {
+                    let blatt = format!(
+                        "{}/{}/{}/{}",
+                        e.einsicht.land,
+                        e.einsicht.amtsgericht,
+                        e.einsicht.bezirk,
+                        e.einsicht.blatt
+                    );
+                    (
+                        format!("{i:05}"),
+                        vec![
+                            e.zeitpunkt,
+                            e.benutzer,
+                            blatt,
+                            e.einsicht.typ.as_str().to_string(),
+                            e.einsicht.aktenzeichen.unwrap_or_default(),
+                            e.einsicht.zweck.unwrap_or_default(),
+                        ],
+                    )
+                })
+                .collect(),
+            .. Default::default()
+        },
+    );
+
+
+    Ok(KontoDataResult::Aktiviert(data))
 }
 
-pub fn get_aenderungen(filter: AenderungFilter) -> Vec<Vec<String>> {
-    let repo = match Repository::open(get_data_dir(MountPoint::Local)) {
+#[derive(Debug, Clone, PartialEq)]
+pub enum AenderungFilter {
+    GetLast(usize),
+    FilterEmail(String),
+}
+
+pub fn get_aenderungen(konfiguration: &Konfiguration, filter: AenderungFilter) -> Vec<Vec<String>> {
+    let repo = match Repository::open(konfiguration.get_data_dir(MountPoint::Local)) {
         Ok(s) => s,
         Err(e) => return Vec::new(),
     };
@@ -1663,16 +2035,25 @@ pub fn get_aenderungen(filter: AenderungFilter) -> Vec<Vec<String>> {
     commits
 }
 
-pub fn get_user_from_token(token: &str) -> Result<BenutzerInfo, String> {
-    let conn = Connection::open(get_db_path(MountPoint::Local))
+/// SHA-256 eines Session- oder Refresh-Tokens (als Hex), nur der Hash wird gespeichert
+pub fn hash_token(token: &str) -> String {
+    use sha2::{Digest, Sha256};
+    format!("{:x}", Sha256::digest(token.as_bytes()))
+}
+
+pub fn get_user_from_token(
+    konfiguration: &Konfiguration,
+    token: &str,
+) -> Result<BenutzerInfo, String> {
+    let conn = verbindung_oeffnen(konfiguration, MountPoint::Local)
         .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
 
     let mut stmt = conn
-        .prepare("SELECT benutzer, gueltig_bis FROM sessions WHERE token = ?1")
+        .prepare("SELECT benutzer, gueltig_bis FROM sessions WHERE token_hash = ?1")
         .map_err(|e| format!("Fehler beim Auslesen der Benutzerdaten 6"))?;
 
     let tokens = stmt
-        .query_map(rusqlite::params![token], |row| {
+        .query_map(rusqlite::params![hash_token(token)], |row| {
             Ok((row.get::<usize, i32>(0)?, row.get::<usize, String>(1)?))
         })
         .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?
@@ -1697,6 +2078,26 @@ pub fn get_user_from_token(token: &str) -> Result<BenutzerInfo, String> {
         }
     };
 
+    get_benutzer_by_id(&conn, *id)
+}
+
+pub(crate) fn get_benutzer_by_email(conn: &Connection, email: &str) -> Result<Option<BenutzerInfo>, String> {
+    let id = conn
+        .query_row(
+            "SELECT id FROM benutzer WHERE email = ?1",
+            rusqlite::params![email],
+            |row| row.get::<usize, i32>(0),
+        )
+        .optional()
+        .map_err(|e| format!("Fehler beim Auslesen der Benutzerdaten: {e}"))?;
+
+    match id {
+        Some(id) => get_benutzer_by_id(conn, id).map(Some),
+        None => Ok(None),
+    }
+}
+
+pub(crate) fn get_benutzer_by_id(conn: &Connection, id: i32) -> Result<BenutzerInfo, String> {
     let mut stmt = conn
         .prepare("SELECT name, email, rechte FROM benutzer WHERE id = ?1")
         .map_err(|e| format!("Fehler beim Auslesen der Benutzerdaten 6"))?;
@@ -1718,64 +2119,49 @@ pub fn get_user_from_token(token: &str) -> Result<BenutzerInfo, String> {
     };
 
     Ok(BenutzerInfo {
-        id: *id,
+        id: id,
         name: name.to_string(),
         email: email.to_string(),
         rechte: rechte.to_string(),
+        api_schluessel: None,
+        berechtigungen: crate::rollen::get_berechtigungen(conn, rechte),
     })
 }
 
 pub fn zugriff_ablehnen(
-    mount_point: MountPoint,
+    conn: &Connection,
     ids: &[String],
     email: &str,
     datum: &str,
+    grund: Option<&str>,
 ) -> Result<(), String> {
-    let mut conn = Connection::open(get_db_path(mount_point))
-        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
-
-    let tx = conn
-        .transaction()
-        .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank: {e}"))?;
-
     for id in ids.iter() {
-        tx.execute(
-            "UPDATE zugriffe SET gewaehrt_von = NULL, abgelehnt_von = ?1, am = ?2 WHERE id  = ?3",
-            rusqlite::params![email.clone(), datum.clone(), id.clone()],
+        conn.execute(
+            "UPDATE zugriffe SET gewaehrt_von = NULL, abgelehnt_von = ?1, am = ?2, ablehnungsgrund = ?3 WHERE id  = ?4",
+            rusqlite::params![email.clone(), datum.clone(), grund, id.clone()],
         )
         .map_err(|e| format!("Fehler beim Genehmigen vom Zugriffen: {e}"))?;
     }
 
-    tx.commit()
-        .map_err(|e| format!("Fehler beim Genehmigen vom Zugriffen: {e}"))?;
-
     Ok(())
 }
 
 pub fn zugriff_genehmigen(
-    mount_point: MountPoint,
+    conn: &Connection,
     ids: &[String],
     email: &str,
     datum: &str,
+    gueltig_bis: Option<&str>,
 ) -> Result<(), String> {
-    let mut conn = Connection::open(get_db_path(mount_point))
-        .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank 0: {e}"))?;
-
-    let tx = conn
-        .transaction()
-        .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank 1: {e}"))?;
-
     for id in ids.iter() {
-        tx.execute(
-            "UPDATE zugriffe SET abgelehnt_von = NULL, gewaehrt_von = ?1, am = ?2 WHERE id = ?3",
-            rusqlite::params![email.clone(), datum.clone(), id.clone()],
+        conn.execute(
+            "UPDATE zugriffe SET abgelehnt_von = NULL, ablehnungsgrund = NULL, gewaehrt_von = ?1, am = ?2, gueltig_bis = ?3,
+                widerrufen_von = NULL, widerrufen_am = NULL, erinnerung_gesendet = NULL WHERE id = ?4",
+            rusqlite::params![email.clone(), datum.clone(), gueltig_bis, id.clone()],
         )
         .map_err(|e| format!("Fehler beim Genehmigen vom Zugriffen: {e}"))?;
     }
 
-    tx.commit()
-        .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank 4: {e}"))?;
-
     // Benutzer erstellen mit passwort = NULL (wird beim ersten Login gesetzt)
     let mut benutzer_neu = Vec::new();
     for id in ids.iter() {
@@ -1800,7 +2186,23 @@ pub fn zugriff_genehmigen(
         println!("query_benutzer_count = {:?}", query_benutzer_count);
 
         if query_benutzer_count == 0 {
-            benutzer_neu.push((name, email, typ));
+            // typ ist als JSON-String gespeichert, z.B. "\"bearbeiter\""
+            let rolle = crate::api::index::ZugriffTyp::from_str(&typ)
+                .map(|t| t.get_rolle())
+                .unwrap_or(crate::rollen::STANDARD_ROLLE);
+            let rolle_existiert: i32 = conn
+                .query_row(
+                    "SELECT COUNT(*) FROM rollen WHERE name = ?1",
+                    rusqlite::params![rolle],
+                    |row| row.get(0),
+                )
+                .map_err(|e| format!("{e}"))?;
+            let rolle = if rolle_existiert > 0 {
+                rolle
+            } else {
+                crate::rollen::STANDARD_ROLLE
+            };
+            benutzer_neu.push((name, email, rolle));
         }
     }
 
@@ -1808,28 +2210,344 @@ pub fn zugriff_genehmigen(
         return Ok(());
     }
 
-    let tx = conn
-        .transaction()
-        .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank 5: {e}"))?;
-
-    if let Ok(mut stmt) = tx.prepare(
+    if let Ok(mut stmt) = conn.prepare(
         "INSERT INTO benutzer (name, email, rechte, password_hashed) VALUES (?1, ?2, ?3, NULL)",
     ) {
-        for (name, email, typ) in benutzer_neu {
-            stmt.execute(rusqlite::params![
-                name,
-                email,
-                match typ.as_str() {
-                    "bearbeiter" => "bearbeiter",
-                    _ => "gast",
-                },
-            ])
+        for (name, email, rolle) in benutzer_neu {
+            stmt.execute(rusqlite::params![name, email, rolle])
             .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank 6: {e}"))?;
         }
     }
 
-    tx.commit()
-        .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank 7: {e}"))?;
+    Ok(())
+}
+
+/// Widerruft bereits genehmigte Zugriffe, der Eintrag bleibt zur Nachverfolgung erhalten
+pub fn zugriff_widerrufen(
+    conn: &Connection,
+    ids: &[String],
+    email: &str,
+    datum: &str,
+) -> Result<(), String> {
+    for id in ids.iter() {
+        conn.execute(
+            "UPDATE zugriffe SET widerrufen_von = ?1, widerrufen_am = ?2 WHERE id = ?3 AND gewaehrt_von IS NOT NULL",
+            rusqlite::params![email, datum, id],
+        )
+        .map_err(|e| format!("Fehler beim Widerrufen von Zugriffen: {e}"))?;
+    }
+
+    Ok(())
+}
+
+/// Setzt das Ablaufdatum von Zugriffen (`None` = unbegrenzt gültig)
+pub fn zugriff_gueltigkeit_setzen(
+    conn: &Connection,
+    ids: &[String],
+    gueltig_bis: Option<&str>,
+) -> Result<(), String> {
+    for id in ids.iter() {
+        // Erinnerung zurücksetzen, damit vor dem neuen Ablaufdatum erneut erinnert wird
+        conn.execute(
+            "UPDATE zugriffe SET gueltig_bis = ?1, erinnerung_gesendet = NULL WHERE id = ?2",
+            rusqlite::params![gueltig_bis, id],
+        )
+        .map_err(|e| format!("Fehler beim Ändern der Gültigkeit: {e}"))?;
+    }
+
+    Ok(())
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ZugriffInfo {
+    pub id: String,
+    pub name: String,
+    pub email: String,
+    pub typ: String,
+    pub grund: Option<String>,
+    pub angefragt: String,
+    pub land: String,
+    pub amtsgericht: String,
+    pub bezirk: String,
+    pub blatt: String,
+    pub blatt_bis: Option<String>,
+    pub gewaehrt_von: Option<String>,
+    pub gueltig_bis: Option<String>,
+    pub widerrufen_am: Option<String>,
+    pub abgelehnt_von: Option<String>,
+    pub am: Option<String>,
+    pub ablehnungsgrund: Option<String>,
+    /// Gemeinsame ID aller Blätter einer Anfrage (Link zur Statusseite)
+    pub anfrage_id: Option<String>,
+    pub zurueckgezogen_am: Option<String>,
+    /// Bestätigung der E-Mail-Adresse durch den Antragsteller (`None` = unbestätigt)
+    pub bestaetigt_am: Option<String>,
+}
+
+const ZUGRIFF_INFO_SPALTEN: &str = "id, name, email, typ, land, amtsgericht, bezirk, blatt, blatt_bis, gewaehrt_von, gueltig_bis, widerrufen_am, grund, angefragt, abgelehnt_von, am, ablehnungsgrund, anfrage_id, zurueckgezogen_am, bestaetigt_am";
+
+fn zugriff_info_aus_zeile(r: &rusqlite::Row) -> rusqlite::Result<ZugriffInfo> {
+    Ok(ZugriffInfo {
+        id: r.get(0)?,
+        name: r.get(1)?,
+        email: r.get(2)?,
+        typ: r.get(3)?,
+        land: r.get(4)?,
+        amtsgericht: r.get(5)?,
+        bezirk: r.get(6)?,
+        blatt: r
+            .get::<usize, String>(7)
+            .or_else(|_| r.get::<usize, i32>(7).map(|s| format!("{s}")))?,
+        blatt_bis: r.get(8)?,
+        gewaehrt_von: r.get(9)?,
+        gueltig_bis: r.get(10)?,
+        widerrufen_am: r.get(11)?,
+        grund: r.get(12)?,
+        angefragt: r.get(13)?,
+        abgelehnt_von: r.get(14)?,
+        am: r.get(15)?,
+        ablehnungsgrund: r.get(16)?,
+        anfrage_id: r.get(17)?,
+        zurueckgezogen_am: r.get(18)?,
+        bestaetigt_am: r.get(19)?,
+    })
+}
+
+impl ZugriffInfo {
+    pub fn get_bereich(&self) -> ZugriffBereich {
+        ZugriffBereich {
+            land: self.land.clone(),
+            amtsgericht: self.amtsgericht.clone(),
+            bezirk: self.bezirk.clone(),
+            blatt: self.blatt.clone(),
+            blatt_bis: self.blatt_bis.clone(),
+        }
+    }
+}
+
+pub fn get_zugriff(konfiguration: &Konfiguration, id: &str) -> Result<Option<ZugriffInfo>, String> {
+    let conn = verbindung_oeffnen(konfiguration, MountPoint::Local)
+        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+
+    conn.query_row(
+        &format!("SELECT {ZUGRIFF_INFO_SPALTEN} FROM zugriffe WHERE id = ?1"),
+        rusqlite::params![id],
+        zugriff_info_aus_zeile,
+    )
+    .optional()
+    .map_err(|e| format!("Fehler beim Auslesen des Zugriffs: {e}"))
+}
+
+/// Alle Blätter einer Anfrage (für die Statusseite des Antragstellers)
+pub fn get_zugriffe_fuer_anfrage(
+    konfiguration: &Konfiguration,
+    anfrage_id: &str,
+) -> Result<Vec<ZugriffInfo>, String> {
+    let conn = verbindung_oeffnen(konfiguration, MountPoint::Local)
+        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {ZUGRIFF_INFO_SPALTEN} FROM zugriffe WHERE anfrage_id = ?1 ORDER BY land, amtsgericht, bezirk, blatt"
+        ))
+        .map_err(|e| format!("{e}"))?;
+
+    let zugriffe = stmt
+        .query_map(rusqlite::params![anfrage_id], zugriff_info_aus_zeile)
+        .map_err(|e| format!("{e}"))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("{e}"))?;
+
+    Ok(zugriffe)
+}
+
+/// Zieht unbearbeitete Anfragen zurück, nur innerhalb der angegebenen Anfrage
+pub fn zugriffe_zurueckziehen(
+    conn: &Connection,
+    anfrage_id: &str,
+    ids: &[String],
+    datum: &str,
+) -> Result<(), String> {
+    for id in ids.iter() {
+        conn.execute(
+            "UPDATE zugriffe SET zurueckgezogen_am = ?1
+            WHERE id = ?2 AND anfrage_id = ?3
+            AND gewaehrt_von IS NULL AND abgelehnt_von IS NULL AND zurueckgezogen_am IS NULL",
+            rusqlite::params![datum, id, anfrage_id],
+        )
+        .map_err(|e| format!("Fehler beim Zurückziehen der Anfrage: {e}"))?;
+    }
+
+    Ok(())
+}
+
+/// Bestätigt alle noch nicht zurückgezogenen Blätter einer Anfrage (Double-Opt-In)
+pub fn zugriffe_bestaetigen(
+    conn: &Connection,
+    anfrage_id: &str,
+    datum: &str,
+) -> Result<(), String> {
+    conn.execute(
+        "UPDATE zugriffe SET bestaetigt_am = ?1
+        WHERE anfrage_id = ?2 AND bestaetigt_am IS NULL AND zurueckgezogen_am IS NULL",
+        rusqlite::params![datum, anfrage_id],
+    )
+    .map_err(|e| format!("Fehler beim Bestätigen der Anfrage: {e}"))?;
+
+    Ok(())
+}
+
+/// Löscht Anfragen, die nicht innerhalb von `stunden` bestätigt wurden (`0` = unbegrenzt)
+pub fn unbestaetigte_zugriffe_bereinigen(
+    konfiguration: &Konfiguration,
+    mount_point: MountPoint,
+    stunden: u32,
+) -> Result<usize, String> {
+    if stunden == 0 {
+        return Ok(0);
+    }
+
+    let conn = verbindung_oeffnen(konfiguration, mount_point)
+        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+
+    let grenze = Utc::now() - chrono::Duration::hours(stunden as i64);
+    conn.execute(
+        "DELETE FROM zugriffe
+        WHERE bestaetigt_am IS NULL AND angefragt < ?1
+        AND gewaehrt_von IS NULL AND abgelehnt_von IS NULL",
+        rusqlite::params![grenze.to_rfc3339()],
+    )
+    .map_err(|e| format!("Fehler beim Löschen unbestätigter Anfragen: {e}"))
+}
+
+/// Ob für die E-Mail bereits eine unbearbeitete Anfrage für das Blatt existiert
+pub fn offene_zugriff_anfrage_existiert(
+    konfiguration: &Konfiguration,
+    email: &str,
+    land: &str,
+    amtsgericht: &str,
+    bezirk: &str,
+    blatt: &str,
+    blatt_bis: Option<&str>,
+) -> Result<bool, String> {
+    let conn = verbindung_oeffnen(konfiguration, MountPoint::Local)
+        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+
+    let anzahl: i32 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM zugriffe
+            WHERE email = ?1 AND land = ?2 AND amtsgericht = ?3 AND bezirk = ?4 AND blatt = ?5
+            AND blatt_bis IS ?6 AND gewaehrt_von IS NULL AND abgelehnt_von IS NULL AND zurueckgezogen_am IS NULL",
+            rusqlite::params![email, land, amtsgericht, bezirk, blatt, blatt_bis],
+            |r| r.get(0),
+        )
+        .map_err(|e| format!("{e}"))?;
+
+    Ok(anzahl > 0)
+}
+
+/// Gültige Zugriffe, die vor `bis` ablaufen und für die noch keine Erinnerung gesendet wurde
+pub fn get_ablaufende_zugriffe(
+    konfiguration: &Konfiguration,
+    mount_point: MountPoint,
+    jetzt: &str,
+    bis: &str,
+) -> Result<Vec<ZugriffInfo>, String> {
+    let conn = verbindung_oeffnen(konfiguration, mount_point)
+        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {ZUGRIFF_INFO_SPALTEN} FROM zugriffe
+            WHERE gewaehrt_von IS NOT NULL AND widerrufen_am IS NULL AND erinnerung_gesendet IS NULL
+            AND gueltig_bis IS NOT NULL AND gueltig_bis > ?1 AND gueltig_bis <= ?2
+            ORDER BY email"
+        ))
+        .map_err(|e| format!("{e}"))?;
+
+    let zugriffe = stmt
+        .query_map(rusqlite::params![jetzt, bis], zugriff_info_aus_zeile)
+        .map_err(|e| format!("{e}"))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("{e}"))?;
+
+    Ok(zugriffe)
+}
+
+/// Unbearbeitete Zugriffsanfragen, über die Administratoren noch nicht benachrichtigt wurden
+pub fn get_unbenachrichtigte_zugriff_anfragen(
+    konfiguration: &Konfiguration,
+    mount_point: MountPoint,
+) -> Result<Vec<ZugriffInfo>, String> {
+    let conn = verbindung_oeffnen(konfiguration, mount_point)
+        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {ZUGRIFF_INFO_SPALTEN} FROM zugriffe
+            WHERE gewaehrt_von IS NULL AND abgelehnt_von IS NULL AND zurueckgezogen_am IS NULL
+            AND bestaetigt_am IS NOT NULL AND admin_benachrichtigt IS NULL
+            ORDER BY angefragt"
+        ))
+        .map_err(|e| format!("{e}"))?;
+
+    let zugriffe = stmt
+        .query_map(rusqlite::params![], zugriff_info_aus_zeile)
+        .map_err(|e| format!("{e}"))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("{e}"))?;
+
+    Ok(zugriffe)
+}
+
+/// Zeitpunkt der letzten Benachrichtigung von Administratoren über neue Zugriffsanfragen
+pub fn get_letzte_admin_benachrichtigung(
+    konfiguration: &Konfiguration,
+    mount_point: MountPoint,
+) -> Result<Option<String>, String> {
+    let conn = verbindung_oeffnen(konfiguration, mount_point)
+        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+
+    conn.query_row(
+        "SELECT MAX(admin_benachrichtigt) FROM zugriffe",
+        rusqlite::params![],
+        |r| r.get(0),
+    )
+    .map_err(|e| format!("{e}"))
+}
+
+pub fn zugriffe_admin_benachrichtigt(
+    conn: &Connection,
+    ids: &[String],
+    datum: &str,
+) -> Result<(), String> {
+    for id in ids.iter() {
+        conn.execute(
+            "UPDATE zugriffe SET admin_benachrichtigt = ?1 WHERE id = ?2",
+            rusqlite::params![datum, id],
+        )
+        .map_err(|e| format!("{e}"))?;
+    }
+
+    Ok(())
+}
+
+pub fn zugriff_erinnerung_gesendet(
+    konfiguration: &Konfiguration,
+    mount_point: MountPoint,
+    ids: &[String],
+    datum: &str,
+) -> Result<(), String> {
+    let conn = verbindung_oeffnen(konfiguration, mount_point)
+        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+
+    for id in ids.iter() {
+        conn.execute(
+            "UPDATE zugriffe SET erinnerung_gesendet = ?1 WHERE id = ?2",
+            rusqlite::params![datum, id],
+        )
+        .map_err(|e| format!("{e}"))?;
+    }
 
     Ok(())
 }
@@ -1844,15 +2562,16 @@ pub struct BenutzerGrundbuecher {
 }
 
 pub fn get_email_abonnements_fuer_benutzer(
+    konfiguration: &Konfiguration,
     benutzer: &BenutzerInfo,
-) -> Result<Vec<(String, String, String, String, String, String, String)>, String> {
-    let conn = Connection::open(get_db_path(MountPoint::Local))
+) -> Result<Vec<(String, String, String, String, String, String, String, String, String)>, String> {
+    let conn = verbindung_oeffnen(konfiguration, MountPoint::Local)
         .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
-    let is_admin = benutzer.rechte == "admin";
+    let is_admin = benutzer.hat_berechtigung(crate::rollen::Berechtigung::AbosVerwalten);
     let mut stmt = conn.prepare(if is_admin {
-        "SELECT id, typ, text, amtsgericht, bezirk, blatt, aktenzeichen FROM abonnements"
+        "SELECT id, typ, text, amtsgericht, bezirk, blatt, aktenzeichen, detailstufe, abteilungen, aenderungsarten, flurstueck, pausiert FROM abonnements"
     } else {
-        "SELECT id, typ, text, amtsgericht, bezirk, blatt, aktenzeichen FROM abonnements WHERE typ = 'email' AND text = ?1"
+        "SELECT id, typ, text, amtsgericht, bezirk, blatt, aktenzeichen, detailstufe, abteilungen, aenderungsarten, flurstueck, pausiert FROM abonnements WHERE (typ = 'email' AND text = ?1) OR besitzer = ?1"
     }).map_err(|e| format!("{e}"))?;
 
     let pa = rusqlite::params![benutzer.email];
@@ -1866,8 +2585,20 @@ pub fn get_email_abonnements_fuer_benutzer(
                 r.get::<usize, String>(2)?,
                 r.get::<usize, String>(3)?,
                 r.get::<usize, String>(4)?,
-                r.get::<usize, String>(5)?,
+                // Blatt "*" mit Flurstück: "* (Flurstück Schenkenberg/1/26)"
+                match r.get::<usize, Option<String>>(10)? {
+                    Some(f) => format!("{} (Flurstück {f})", r.get::<usize, String>(5)?),
+                    None => r.get::<usize, String>(5)?,
+                } + if r.get::<usize, i32>(11)? != 0 { " (pausiert)" } else { "" },
                 r.get::<usize, Option<String>>(6)?.unwrap_or_default(),
+                r.get::<usize, String>(7)?,
+                // "abt2, abt3 / neu" (Abteilungen / Arten der Änderungen)
+                [r.get::<usize, String>(8)?, r.get::<usize, String>(9)?]
+                    .into_iter()
+                    .filter(|s| !s.is_empty())
+                    .collect::<Vec<_>>()
+                    .join(" / ")
+                    .replace(',', ", "),
             ))
         })
         .map_err(|e| format!("{e}"))?
@@ -1878,23 +2609,112 @@ pub fn get_email_abonnements_fuer_benutzer(
     Ok(abos)
 }
 
-pub fn get_zugriffe(benutzer: &BenutzerInfo) -> Result<Vec<(String, String, String, String)>, String> {
+/// Bereich eines Zugriffs: jede Ebene kann auch `ALLE_BUNDESLAENDER`,
+/// `ALLE_AMTSGERICHTE`, `ALLE_GRUNDBUCHBEZIRKE` bzw. `ALLE_BLAETTER` sein.
+/// Ist `blatt_bis` gesetzt, umfasst der Zugriff alle Blattnummern von
+/// `blatt` bis `blatt_bis` (einschließlich).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ZugriffBereich {
+    pub land: String,
+    pub amtsgericht: String,
+    pub bezirk: String,
+    pub blatt: String,
+    pub blatt_bis: Option<String>,
+}
+
+impl ZugriffBereich {
+    pub fn umfasst(&self, land: &str, amtsgericht: &str, bezirk: &str, blatt: &str) -> bool {
+        (self.land == land || self.land == "ALLE_BUNDESLAENDER")
+            && (self.amtsgericht == amtsgericht || self.amtsgericht == "ALLE_AMTSGERICHTE")
+            && (self.bezirk == bezirk || self.bezirk == "ALLE_GRUNDBUCHBEZIRKE")
+            && match self.blatt_bis.as_deref() {
+                Some(bis) => match (blatt_nummer(&self.blatt), blatt_nummer(bis), blatt_nummer(blatt)) {
+                    (Some(von), Some(bis), Some(n)) => von <= n && n <= bis,
+                    _ => false,
+                },
+                None => self.blatt == blatt || self.blatt == "ALLE_BLAETTER",
+            }
+    }
+
+    /// Lesbare Beschreibung des Blatts bzw. Bereichs, z.B. "100 - 250"
+    pub fn blatt_text(&self) -> String {
+        match self.blatt_bis.as_deref() {
+            Some(bis) => format!("{} - {bis}", self.blatt),
+            None => self.blatt.clone(),
+        }
+    }
+}
+
+/// Anzahl der vorhandenen Blätter, die jeder der Bereiche umfasst. Die Liste aller
+/// Blätter wird nur geladen, wenn ein Bereich oder Platzhalter (`ALLE_*`) dabei ist,
+/// einzelne Blätter zählen immer als 1 (auch wenn sie noch nicht vorhanden sind).
+pub fn anzahl_blaetter(conn: &Connection, bereiche: &[ZugriffBereich]) -> Result<Vec<usize>, String> {
+    let ist_einzelblatt = |z: &ZugriffBereich| {
+        z.blatt_bis.is_none()
+            && ![&z.land, &z.amtsgericht, &z.bezirk, &z.blatt]
+                .iter()
+                .any(|s| s.starts_with("ALLE_"))
+    };
+
+    let grundbuchblaetter = if bereiche.iter().all(ist_einzelblatt) {
+        Vec::new()
+    } else {
+        get_alle_grundbuchblaetter(conn)?
+    };
+
+    Ok(bereiche
+        .iter()
+        .map(|z| {
+            if ist_einzelblatt(z) {
+                return 1;
+            }
+            grundbuchblaetter
+                .iter()
+                .filter(|(l, ag, bz, b)| z.umfasst(l, ag, bz, b))
+                .count()
+        })
+        .collect())
+}
+
+/// Nummer eines Blatts ohne Buchstaben-Zusatz ("123a" => 123)
+pub fn blatt_nummer(blatt: &str) -> Option<u64> {
+    let ziffern = blatt
+        .trim()
+        .chars()
+        .take_while(|c| c.is_ascii_digit())
+        .collect::<String>();
+    ziffern.parse().ok()
+}
+
+pub fn get_zugriffe(
+    konfiguration: &Konfiguration,
+    benutzer: &BenutzerInfo,
+) -> Result<Vec<ZugriffBereich>, String> {
 
-    let conn = Connection::open(get_db_path(MountPoint::Local))
+    let conn = verbindung_oeffnen(konfiguration, MountPoint::Local)
         .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
 
     let mut stmt = conn
-        .prepare("SELECT land, amtsgericht, bezirk, blatt FROM zugriffe WHERE email = ?1 AND gewaehrt_von IS NOT NULL")
+        .prepare("
+            SELECT land, amtsgericht, bezirk, blatt, blatt_bis FROM zugriffe
+            WHERE email = ?1 AND gewaehrt_von IS NOT NULL AND widerrufen_am IS NULL
+            AND (gueltig_bis IS NULL OR gueltig_bis > ?2)
+        ")
         .map_err(|e| format!("{e}"))?;
 
+    // Abgelaufene und widerrufene Zugriffe zählen nicht
+    let jetzt = Utc::now().to_rfc3339();
     let zugriffe = stmt
-        .query_map(rusqlite::params![benutzer.email], |r| {
-            Ok((
-                r.get::<usize, String>(0)?,
-                r.get::<usize, String>(1)?,
-                r.get::<usize, String>(2)?,
-                r.get::<usize, String>(3)?,
-            ))
+        .query_map(rusqlite::params![benutzer.email, jetzt], |r| {
+            Ok(ZugriffBereich {
+                land: r.get(0)?,
+                amtsgericht: r.get(1)?,
+                bezirk: r.get(2)?,
+                blatt: r
+                    .get::<usize, String>(3)
+                    .or_else(|_| r.get::<usize, i32>(3).map(|s| format!("{s}")))?,
+                blatt_bis: r.get(4)?,
+            })
         })
         .map_err(|e| format!("{e}"))?
         .into_iter()
@@ -1904,29 +2724,55 @@ pub fn get_zugriffe(benutzer: &BenutzerInfo) -> Result<Vec<(String, String, Stri
     Ok(zugriffe)
 }
 
-pub fn benutzer_hat_zugriff_auf_blatt(zugriffe: &[(String, String, String, String)], land: &str, amtsgericht: &str, bezirk: &str, blatt: &str) -> bool {
-    let zugriffe = zugriffe.iter().filter(|(l, ag, bz, b)| l.as_str() == land || l.as_str() == "ALLE_BUNDESLAENDER").collect::<Vec<_>>();
-    let zugriffe = zugriffe.iter().filter(|(l, ag, bz, b)| ag.as_str() == amtsgericht || ag.as_str() == "ALLE_AMTSGERICHTE").collect::<Vec<_>>();
-    let zugriffe = zugriffe.iter().filter(|(l, ag, bz, b)| bz.as_str() == bezirk || bz.as_str() == "ALLE_GRUNDBUCHBEZIRKE").collect::<Vec<_>>();
-    let zugriffe = zugriffe.iter().filter(|(l, ag, bz, b)| b.as_str() == blatt || b.as_str() == "ALLE_BLAETTER").collect::<Vec<_>>();
-    if !zugriffe.is_empty() { true } else { false }
+pub fn benutzer_hat_zugriff_auf_blatt(zugriffe: &[ZugriffBereich], land: &str, amtsgericht: &str, bezirk: &str, blatt: &str) -> bool {
+    zugriffe.iter().any(|z| z.umfasst(land, amtsgericht, bezirk, blatt))
 }
 
-/// (Bundesland, Amtsgericht, Bezirk, Blatt)
-pub fn get_verfuegbare_grundbuecher_fuer_benutzer(
-    benutzer: &BenutzerInfo,
-) -> Result<Vec<(String, String, String, String)>, String> {
-    use std::collections::BTreeSet;
+/// Welche Blätter ein Benutzer lesen darf: alle (`grundbuecher-alle-lesen`)
+/// oder nur die seiner gewährten, nicht abgelaufenen und nicht widerrufenen Zugriffe
+#[derive(Debug, Clone, PartialEq)]
+pub struct Leserechte {
+    alle: bool,
+    zugriffe: Vec<ZugriffBereich>,
+}
 
-    let conn = Connection::open(get_db_path(MountPoint::Local))
-        .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+impl Leserechte {
+    pub fn laden(konfiguration: &Konfiguration, benutzer: &BenutzerInfo) -> Result<Self, String> {
+        if benutzer.hat_berechtigung(crate::rollen::Berechtigung::GrundbuecherAlleLesen) {
+            return Ok(Self {
+                alle: true,
+                zugriffe: Vec::new(),
+            });
+        }
 
-    let is_admin = benutzer.rechte == "admin";
-    let mut alle_grundbuecher = conn
+        Ok(Self {
+            alle: false,
+            zugriffe: get_zugriffe(konfiguration, benutzer)?,
+        })
+    }
+
+    pub fn erlaubt(&self, land: &str, amtsgericht: &str, bezirk: &str, blatt: &str) -> bool {
+        self.alle || benutzer_hat_zugriff_auf_blatt(&self.zugriffe, land, amtsgericht, bezirk, blatt)
+    }
+
+    pub fn pruefen(&self, land: &str, amtsgericht: &str, bezirk: &str, blatt: &str) -> Result<(), String> {
+        if self.erlaubt(land, amtsgericht, bezirk, blatt) {
+            Ok(())
+        } else {
+            Err(format!(
+                "Kein gültiger Zugriff auf {amtsgericht}/{bezirk}/{blatt} (nicht gewährt, abgelaufen oder widerrufen)"
+            ))
+        }
+    }
+}
+
+/// (Bundesland, Amtsgericht, Bezirk, Blatt) aller vorhandenen Grundbuchblätter
+fn get_alle_grundbuchblaetter(conn: &Connection) -> Result<Vec<(String, String, String, String)>, String> {
+    let mut stmt = conn
         .prepare("SELECT land, amtsgericht, bezirk, blatt FROM grundbuecher")
         .map_err(|e| format!("{e}"))?;
 
-    let grundbuchblaetter = alle_grundbuecher
+    let blaetter = stmt
         .query_map(rusqlite::params![], |r| {
             Ok((
                 r.get::<usize, String>(0)?,
@@ -1936,58 +2782,48 @@ pub fn get_verfuegbare_grundbuecher_fuer_benutzer(
             ))
         })
         .map_err(|e| format!("{e}"))?
-        .into_iter()
         .collect::<Result<Vec<_>, _>>()
         .map_err(|e| format!("{e}"))?;
 
-    println!("alle grundbuecher: {:?}", grundbuchblaetter);
-
-    if grundbuchblaetter.is_empty() {
-        return Ok(Vec::new());
-    }
-
-    let grundbuchblaetter = grundbuchblaetter.into_iter().collect::<BTreeSet<_>>();
-    if is_admin {
-        return Ok(grundbuchblaetter.into_iter().collect::<Vec<_>>());
-    }
-
-    let zugriffe = get_zugriffe(benutzer)?;
-
-    println!("zugriffe = {:?}", zugriffe);
-
-    if zugriffe.is_empty() {
-        return Ok(Vec::new());
-    }
+    Ok(blaetter)
+}
 
-    let mut result = BTreeSet::new();
+/// (Bundesland, Amtsgericht, Bezirk, Blatt)
+pub fn get_verfuegbare_grundbuecher_fuer_benutzer(
+    konfiguration: &Konfiguration,
+    benutzer: &BenutzerInfo,
+) -> Result<Vec<(String, String, String, String)>, String> {
+    let conn = verbindung_oeffnen(konfiguration, MountPoint::Local)
+        .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
 
-    for (land, amtsgericht, bezirk, blatt) in grundbuchblaetter {
-        if benutzer_hat_zugriff_auf_blatt(&zugriffe, &land, &amtsgericht, &bezirk, &blatt) {
-            result.insert((land, amtsgericht, bezirk, blatt));
-        }
-    }
+    let leserechte = Leserechte::laden(konfiguration, benutzer)?;
+    let grundbuchblaetter = get_alle_grundbuchblaetter(&conn)?;
 
-    println!("result = {:?}", result);
+    let result = grundbuchblaetter
+        .into_iter()
+        .filter(|(land, amtsgericht, bezirk, blatt)| leserechte.erlaubt(land, amtsgericht, bezirk, blatt))
+        .collect::<BTreeSet<_>>();
 
     Ok(result.into_iter().collect())
 }
 
 pub fn get_benutzer_grouped_by_zugriff(
+    konfiguration: &Konfiguration,
     ids: Vec<String>,
 ) -> Result<BTreeMap<String, BenutzerGrundbuecher>, String> {
-    let conn = Connection::open(get_db_path(MountPoint::Local))
+    let conn = verbindung_oeffnen(konfiguration, MountPoint::Local)
         .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
 
     let mut map = BTreeMap::new();
 
     let mut stmt = conn
         .prepare(
-            "SELECT name, typ, email, land, amtsgericht, bezirk, blatt FROM zugriffe WHERE id = ?1",
+            "SELECT name, typ, email, land, amtsgericht, bezirk, blatt, blatt_bis FROM zugriffe WHERE id = ?1",
         )
         .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
 
     for id in ids.iter() {
-        let (name, rechte, email, land, amtsgericht, bezirk, blatt) = stmt
+        let (name, rechte, email, land, amtsgericht, bezirk, blatt, blatt_bis) = stmt
             .query_row(rusqlite::params![id], |r| {
                 Ok((
                     r.get::<usize, String>(0)?,
@@ -1997,6 +2833,7 @@ pub fn get_benutzer_grouped_by_zugriff(
                     r.get::<usize, String>(4)?,
                     r.get::<usize, String>(5)?,
                     r.get::<usize, String>(6)?,
+                    r.get::<usize, Option<String>>(7)?,
                 ))
             })
             .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
@@ -2008,6 +2845,11 @@ pub fn get_benutzer_grouped_by_zugriff(
         grundbuecher.rechte = rechte.clone().replace("\"", "");
         grundbuecher.name = name.clone();
         grundbuecher.zugriff_id = id.clone();
+        // Bereiche werden als "100 - 250" angezeigt
+        let blatt = match blatt_bis {
+            Some(bis) => format!("{blatt} - {bis}"),
+            None => blatt,
+        };
         grundbuecher
             .grundbuecher
             .push((land, amtsgericht, bezirk, blatt));
@@ -2018,8 +2860,11 @@ pub fn get_benutzer_grouped_by_zugriff(
     Ok(map)
 }
 
-pub fn zugriff_benutzer_exists(zugriff_id: &str) -> Result<bool, String> {
-    let conn = Connection::open(get_db_path(MountPoint::Local))
+pub fn zugriff_benutzer_exists(
+    konfiguration: &Konfiguration,
+    zugriff_id: &str,
+) -> Result<bool, String> {
+    let conn = verbindung_oeffnen(konfiguration, MountPoint::Local)
         .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
 
     let result =  conn.query_row(
@@ -2035,7 +2880,7 @@ pub fn zugriff_benutzer_exists(zugriff_id: &str) -> Result<bool, String> {
 }
 
 pub fn create_zugriff(
-    mount_point: MountPoint,
+    conn: &Connection,
     id: &str,
     name: &str,
     email: &str,
@@ -2046,20 +2891,22 @@ pub fn create_zugriff(
     amtsgericht: &str,
     bezirk: &str,
     blatt: &str,
+    blatt_bis: Option<&str>,
+    anfrage_id: Option<&str>,
+    bestaetigt_am: Option<&str>,
 ) -> Result<(), String> {
-    let conn = Connection::open(get_db_path(mount_point))
-        .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
-
     conn.execute(
         "
         INSERT INTO zugriffe (
             id, name, email,
             typ, grund, land,
-            amtsgericht, bezirk, blatt, angefragt
+            amtsgericht, bezirk, blatt, angefragt,
+            blatt_bis, anfrage_id, bestaetigt_am
         ) VALUES (
             ?1, ?2, ?3, 
             ?4, ?5, ?6,
-            ?7, ?8, ?9, ?10
+            ?7, ?8, ?9, ?10,
+            ?11, ?12, ?13
         )",
         rusqlite::params![
             id,
@@ -2071,7 +2918,10 @@ pub fn create_zugriff(
             amtsgericht,
             bezirk,
             format!("{blatt}"),
-            datum
+            datum,
+            blatt_bis,
+            anfrage_id,
+            bestaetigt_am
         ],
     )
     .map_err(|e| format!("Fehler beim Einfügen von Zugriff: {e}"))?;
@@ -2084,12 +2934,13 @@ pub fn generate_uuid() -> String {
     format!("{token}")
 }
 
-pub fn generate_token() -> (String, DateTime<Utc>) {
+pub fn generate_token(konfiguration: &Konfiguration) -> (String, DateTime<Utc>) {
     use uuid::Uuid;
 
+    let gueltigkeit = konfiguration.token_gueltigkeit_minuten;
     let gueltig_bis = Utc::now();
     let gueltig_bis = gueltig_bis
-        .checked_add_signed(chrono::Duration::minutes(30))
+        .checked_add_signed(chrono::Duration::minutes(gueltigkeit))
         .unwrap_or(gueltig_bis);
     let token = Uuid::new_v4();
     let token = format!("{token}");
@@ -2097,12 +2948,14 @@ pub fn generate_token() -> (String, DateTime<Utc>) {
     (token, gueltig_bis)
 }
 
+/// Prüft E-Mail und Passwort. `Err(None)` = Benutzer hat noch kein Passwort gesetzt
 pub fn check_password(
+    konfiguration: &Konfiguration,
     mount_point: MountPoint,
     email: &str,
     passwort: &str,
-) -> Result<(BenutzerInfo, String, DateTime<Utc>), Option<String>> {
-    let conn = Connection::open(get_db_path(mount_point))
+) -> Result<BenutzerInfo, Option<String>> {
+    let conn = verbindung_oeffnen(konfiguration, mount_point)
         .map_err(|e| Some(format!("Fehler bei Verbindung zur Benutzerdatenbank")))?;
 
     let mut stmt = conn
@@ -2140,91 +2993,224 @@ pub fn check_password(
         return Err(Some(format!("Ungültiges Passwort")));
     }
 
-    let info = BenutzerInfo {
-        id: *id,
-        name: name.clone(),
-        email: email.clone(),
-        rechte: rechte.clone(),
-    };
+    Ok(BenutzerInfo {
+        id: *id,
+        name: name.clone(),
+        email: email.clone(),
+        rechte: rechte.clone(),
+        api_schluessel: None,
+        berechtigungen: crate::rollen::get_berechtigungen(&conn, rechte),
+    })
+}
+
+/// Session, wie sie in "Meine Sitzungen" angezeigt wird
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionInfo {
+    pub id: i64,
+    pub erstellt_am: String,
+    pub gueltig_bis: String,
+    pub refresh_gueltig_bis: String,
+    pub beschreibung: String,
+}
+
+/// Neue Session (Token und Refresh-Token nur als Hash)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionNeu {
+    pub token_hash: String,
+    pub refresh_token_hash: String,
+    pub gueltig_bis: DateTime<Utc>,
+    pub refresh_gueltig_bis: DateTime<Utc>,
+}
+
+impl SessionNeu {
+    /// Generiert ein neues Token-Paar, gibt die Session und die
+    /// Klartext-Tokens (token, refresh_token) zurück
+    pub fn generieren(konfiguration: &Konfiguration) -> (Self, String, String) {
+        let (token, gueltig_bis) = generate_token(konfiguration);
+        let refresh_token = format!("{}", uuid::Uuid::new_v4());
+        let refresh_gueltig_bis = Utc::now()
+            .checked_add_signed(chrono::Duration::days(
+                konfiguration.refresh_token_gueltigkeit_tage,
+            ))
+            .unwrap_or(gueltig_bis);
+
+        let session = SessionNeu {
+            token_hash: hash_token(&token),
+            refresh_token_hash: hash_token(&refresh_token),
+            gueltig_bis,
+            refresh_gueltig_bis,
+        };
+
+        (session, token, refresh_token)
+    }
+}
+
+pub fn insert_token_into_sessions(
+    conn: &Connection,
+    email: &str,
+    session: &SessionNeu,
+    beschreibung: &str,
+) -> Result<(), String> {
+    let id = conn
+        .query_row(
+            "SELECT id FROM benutzer WHERE email = ?1",
+            rusqlite::params![email],
+            |row| row.get::<usize, i32>(0),
+        )
+        .map_err(|_| format!("Kein Benutzerkonto für angegebene E-Mail-Adresse vorhanden"))?;
 
-    let mut stmt = conn
-        .prepare("SELECT token, gueltig_bis FROM sessions WHERE benutzer = ?1")
-        .map_err(|e| Some(format!("Fehler beim Auslesen der Benutzerdaten 8")))?;
+    conn.execute(
+        "INSERT INTO sessions (benutzer, token_hash, refresh_token_hash, erstellt_am, gueltig_bis, refresh_gueltig_bis, beschreibung)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        rusqlite::params![
+            id,
+            session.token_hash,
+            session.refresh_token_hash,
+            Utc::now().to_rfc3339(),
+            session.gueltig_bis.to_rfc3339(),
+            session.refresh_gueltig_bis.to_rfc3339(),
+            beschreibung,
+        ],
+    )
+    .map_err(|e| format!("Fehler beim Einfügen von Token in Sessions: {e}"))?;
 
-    let tokens = stmt
-        .query_map(rusqlite::params![info.id], |row| {
-            Ok((row.get::<usize, String>(0)?, row.get::<usize, String>(1)?))
-        })
-        .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?
-        .collect::<Vec<_>>();
+    Ok(())
+}
 
-    let now = Utc::now();
+/// Sucht den Benutzer zu einem (noch gültigen) Refresh-Token
+pub fn get_user_from_refresh_token(
+    konfiguration: &Konfiguration,
+    refresh_token: &str,
+) -> Result<BenutzerInfo, String> {
+    let conn = verbindung_oeffnen(konfiguration, MountPoint::Local)
+        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
 
-    for t in tokens {
-        let t = t
-            .as_ref()
-            .ok()
-            .and_then(|(t, g)| Some((t, DateTime::parse_from_rfc3339(&g).ok()?)));
+    let (id, refresh_gueltig_bis) = conn
+        .query_row(
+            "SELECT benutzer, refresh_gueltig_bis FROM sessions WHERE refresh_token_hash = ?1",
+            rusqlite::params![hash_token(refresh_token)],
+            |row| Ok((row.get::<usize, i32>(0)?, row.get::<usize, String>(1)?)),
+        )
+        .map_err(|_| format!("Ungültiges Refresh-Token"))?;
 
-        let (token, gueltig_bis) = match t {
-            Some((t, g)) => (t, g),
-            None => continue,
-        };
+    let refresh_gueltig_bis = DateTime::parse_from_rfc3339(&refresh_gueltig_bis)
+        .map_err(|_| format!("Ungültiges Refresh-Token"))?;
 
-        if !(now > gueltig_bis) {
-            return Ok((info, token.clone(), gueltig_bis.into()));
-        }
+    if Utc::now() > refresh_gueltig_bis {
+        return Err(format!("Refresh-Token abgelaufen"));
     }
 
-    Err(None)
+    get_benutzer_by_id(&conn, id)
 }
 
-pub fn insert_token_into_sessions(
-    mount_point: MountPoint,
-    email: &str,
-    token: &str,
-    gueltig_bis: &DateTime<Utc>,
+/// Ersetzt Token und Refresh-Token einer Session (das alte Refresh-Token wird ungültig)
+pub fn session_erneuern(
+    conn: &Connection,
+    refresh_token_hash_alt: &str,
+    session: &SessionNeu,
 ) -> Result<(), String> {
-    let conn = Connection::open(get_db_path(mount_point))
-        .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+    let geaendert = conn
+        .execute(
+            "UPDATE sessions SET token_hash = ?1, refresh_token_hash = ?2, gueltig_bis = ?3, refresh_gueltig_bis = ?4
+            WHERE refresh_token_hash = ?5 AND refresh_gueltig_bis > ?6",
+            rusqlite::params![
+                session.token_hash,
+                session.refresh_token_hash,
+                session.gueltig_bis.to_rfc3339(),
+                session.refresh_gueltig_bis.to_rfc3339(),
+                refresh_token_hash_alt,
+                Utc::now().to_rfc3339(),
+            ],
+        )
+        .map_err(|e| format!("Fehler beim Erneuern der Session: {e}"))?;
 
-    let mut stmt = conn
-        .prepare("SELECT id, name, email, rechte, password_hashed FROM benutzer WHERE email = ?1")
-        .map_err(|e| format!("Fehler beim Auslesen der Benutzerdaten 9"))?;
+    if geaendert == 0 {
+        return Err(format!("Ungültiges Refresh-Token"));
+    }
 
-    let benutzer = stmt
-        .query_map(rusqlite::params![email], |row| {
-            Ok((
-                row.get::<usize, i32>(0)?,
-                row.get::<usize, String>(1)?,
-                row.get::<usize, String>(2)?,
-                row.get::<usize, String>(3)?,
-                row.get::<usize, Vec<u8>>(4)?,
-            ))
-        })
-        .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?
-        .collect::<Vec<_>>();
+    Ok(())
+}
 
-    let (id, name, email, rechte, pw) = match benutzer.get(0) {
-        Some(Ok(s)) => s,
-        _ => {
-            return Err(format!(
-                "Kein Benutzerkonto für angegebene E-Mail-Adresse vorhanden"
-            ));
-        }
-    };
+/// Beendet Sessions eines Benutzers anhand ihrer ID
+pub fn sessions_beenden(conn: &Connection, email: &str, ids: &[String]) -> Result<(), String> {
+    for id in ids {
+        conn.execute(
+            "DELETE FROM sessions WHERE id = ?1 AND benutzer = (SELECT id FROM benutzer WHERE email = ?2)",
+            rusqlite::params![id, email],
+        )
+        .map_err(|e| format!("Fehler beim Beenden der Session: {e}"))?;
+    }
+
+    Ok(())
+}
 
+/// Beendet die Session mit dem angegebenen Token (Logout)
+pub fn session_abmelden(conn: &Connection, token_hash: &str) -> Result<(), String> {
     conn.execute(
-        "INSERT INTO sessions (benutzer, token, gueltig_bis) VALUES (?1, ?2, ?3)",
-        rusqlite::params![id, token, gueltig_bis.to_rfc3339()],
+        "DELETE FROM sessions WHERE token_hash = ?1",
+        rusqlite::params![token_hash],
     )
-    .map_err(|e| format!("Fehler beim Einfügen von Token in Sessions: {e}"))?;
+    .map_err(|e| format!("Fehler beim Abmelden: {e}"))?;
 
     Ok(())
 }
 
-pub fn get_public_key(email: &str, fingerprint: &str) -> Result<String, String> {
-    let conn = Connection::open(get_db_path(MountPoint::Local))
+/// Löscht alle Sessions, deren Refresh-Token abgelaufen ist
+pub fn sessions_bereinigen(
+    konfiguration: &Konfiguration,
+    mount_point: MountPoint,
+) -> Result<usize, String> {
+    let conn = verbindung_oeffnen(konfiguration, mount_point)
+        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+
+    conn.execute(
+        "DELETE FROM sessions WHERE refresh_gueltig_bis < ?1",
+        rusqlite::params![Utc::now().to_rfc3339()],
+    )
+    .map_err(|e| format!("Fehler beim Bereinigen der Sessions: {e}"))
+}
+
+pub fn get_sessions_fuer_benutzer(
+    konfiguration: &Konfiguration,
+    benutzer: &BenutzerInfo,
+) -> Result<Vec<SessionInfo>, String> {
+    let conn = verbindung_oeffnen(konfiguration, MountPoint::Local)
+        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, erstellt_am, gueltig_bis, refresh_gueltig_bis, beschreibung
+            FROM sessions WHERE benutzer = ?1 AND refresh_gueltig_bis > ?2
+            ORDER BY id DESC",
+        )
+        .map_err(|e| format!("Fehler beim Auslesen der Sessions: {e}"))?;
+
+    let sessions = stmt
+        .query_map(
+            rusqlite::params![benutzer.id, Utc::now().to_rfc3339()],
+            |row| {
+                Ok(SessionInfo {
+                    id: row.get(0)?,
+                    erstellt_am: row.get(1)?,
+                    gueltig_bis: row.get(2)?,
+                    refresh_gueltig_bis: row.get(3)?,
+                    beschreibung: row.get::<usize, Option<String>>(4)?.unwrap_or_default(),
+                })
+            },
+        )
+        .map_err(|e| format!("Fehler beim Auslesen der Sessions: {e}"))?
+        .filter_map(|r| r.ok())
+        .collect();
+
+    Ok(sessions)
+}
+
+pub fn get_public_key(
+    konfiguration: &Konfiguration,
+    email: &str,
+    fingerprint: &str,
+) -> Result<String, String> {
+    let conn = verbindung_oeffnen(konfiguration, MountPoint::Local)
         .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
 
     let mut stmt = conn
@@ -2251,39 +3237,72 @@ pub fn get_public_key(email: &str, fingerprint: &str) -> Result<String, String>
     }
 }
 
-pub fn delete_user(mount_point: MountPoint, email: &str) -> Result<(), String> {
-    let mut conn = Connection::open(get_db_path(mount_point))
-        .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+pub fn delete_user(conn: &Connection, email: &str) -> Result<(), String> {
+    conn.execute(
+        "DELETE FROM sessions WHERE benutzer IN (SELECT id FROM benutzer WHERE email = ?1)",
+        rusqlite::params![email],
+    )
+    .map_err(|e| format!("Fehler beim Löschen von Benutzer: {e}"))?;
 
-    let tx = conn
-        .transaction()
-        .map_err(|e| format!("Fehler beim Löschen von Benutzer: {e}"))?;
+    conn.execute(
+        "DELETE FROM api_schluessel WHERE benutzer IN (SELECT id FROM benutzer WHERE email = ?1)",
+        rusqlite::params![email],
+    )
+    .map_err(|e| format!("Fehler beim Löschen von Benutzer: {e}"))?;
+
+    conn.execute(
+        "DELETE FROM zwei_faktor WHERE benutzer IN (SELECT id FROM benutzer WHERE email = ?1)",
+        rusqlite::params![email],
+    )
+    .map_err(|e| format!("Fehler beim Löschen von Benutzer: {e}"))?;
+
+    conn.execute(
+        "DELETE FROM wiederherstellungscodes WHERE benutzer IN (SELECT id FROM benutzer WHERE email = ?1)",
+        rusqlite::params![email],
+    )
+    .map_err(|e| format!("Fehler beim Löschen von Benutzer: {e}"))?;
+
+    conn.execute(
+        "DELETE FROM passwort_reset WHERE benutzer IN (SELECT id FROM benutzer WHERE email = ?1)",
+        rusqlite::params![email],
+    )
+    .map_err(|e| format!("Fehler beim Löschen von Benutzer: {e}"))?;
 
-    tx.execute(
+    conn.execute(
         "DELETE FROM benutzer WHERE email = ?1",
         rusqlite::params![email],
     )
     .map_err(|e| format!("Fehler beim Löschen von Benutzer: {e}"))?;
 
-    tx.execute(
+    conn.execute(
         "DELETE FROM publickeys WHERE email = ?1",
         rusqlite::params![email],
     )
     .map_err(|e| format!("Fehler beim Löschen von Benutzer: {e}"))?;
 
-    tx.commit()
-        .map_err(|e| format!("Fehler beim Löschen von Benutzer: {e}"))?;
-
     Ok(())
 }
 
-pub fn create_abo(
-    mount_point: MountPoint,
+/// Geprüfte Angaben eines neuen Abonnements
+pub struct AboAngaben {
+    amtsgericht: String,
+    bezirk: String,
+    blatt: AbonnementInfoBlattNr,
+    detailstufe: AboDetailstufe,
+    abteilungen: Vec<String>,
+    aenderungsarten: Vec<crate::aenderungen::AenderungsArt>,
+    flurstueck: Option<String>,
+}
+
+/// Prüft die Angaben eines neuen Abonnements, ohne es anzulegen
+pub fn abo_pruefen(
     typ: &str,
     blatt: &str,
-    text: &str,
-    aktenzeichen: Option<&str>,
-) -> Result<(), String> {
+    detailstufe: Option<&str>,
+    abteilungen: Option<&str>,
+    aenderungsarten: Option<&str>,
+    flurstueck: Option<&str>,
+) -> Result<AboAngaben, String> {
     match typ {
         "email" | "webhook" => {}
         _ => {
@@ -2291,6 +3310,16 @@ pub fn create_abo(
         }
     }
 
+    let detailstufe = match detailstufe {
+        Some(d) => AboDetailstufe::from_str(d)
+            .ok_or_else(|| format!("Ungültige Detailstufe: {d} (\"standard\" oder \"details\")"))?,
+        None => AboDetailstufe::default(),
+    };
+
+    let abteilungen = crate::aenderungen::abteilungen_lesen(abteilungen.unwrap_or_default())?;
+    let aenderungsarten =
+        crate::aenderungen::aenderungsarten_lesen(aenderungsarten.unwrap_or_default())?;
+
     let blatt_split = blatt
         .split("/")
         .map(|s| s.trim().to_string())
@@ -2321,29 +3350,100 @@ pub fn create_abo(
         }
     };
 
-    let conn = Connection::open(get_db_path(mount_point))
-        .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+    // Bezirk "*" = alle Bezirke des Amtsgerichts
+    if bezirk == "*" && b != AbonnementInfoBlattNr::Alle {
+        return Err(format!("Abonnement {blatt}: ohne Bezirk nur für alle Blätter (*) möglich"));
+    }
+
+    let flurstueck = match flurstueck.map(|f| f.trim()).filter(|f| !f.is_empty()) {
+        Some(f) => {
+            if crate::aenderungen::flurstueck_normalisieren(f).is_none() {
+                return Err(format!("Ungültiges Flurstück: {f} (Gemarkung/Flur/Flurstück)"));
+            }
+            if b != AbonnementInfoBlattNr::Alle {
+                return Err(format!("Abonnement für Flurstück {f} nur für alle Blätter (*) möglich"));
+            }
+            Some(f.to_string())
+        }
+        None => None,
+    };
+
+    Ok(AboAngaben {
+        amtsgericht,
+        bezirk,
+        blatt: b,
+        detailstufe,
+        abteilungen,
+        aenderungsarten,
+        flurstueck,
+    })
+}
+
+pub fn create_abo(
+    conn: &Connection,
+    typ: &str,
+    blatt: &str,
+    text: &str,
+    aktenzeichen: Option<&str>,
+    besitzer: Option<&str>,
+    webhook_secret: Option<&str>,
+    detailstufe: Option<&str>,
+    abteilungen: Option<&str>,
+    aenderungsarten: Option<&str>,
+    flurstueck: Option<&str>,
+    id: Option<&str>,
+) -> Result<(), String> {
+    let AboAngaben {
+        amtsgericht,
+        bezirk,
+        blatt: b,
+        detailstufe,
+        abteilungen,
+        aenderungsarten,
+        flurstueck,
+    } = abo_pruefen(typ, blatt, detailstufe, abteilungen, aenderungsarten, flurstueck)?;
+
+    let id = match id {
+        Some(s) => s.to_string(),
+        None => uuid::Uuid::new_v4().to_string(),
+    };
+
+    let besitzer = match (besitzer, typ) {
+        (Some(b), _) => Some(b.to_string()),
+        (None, "email") => Some(text.to_string()),
+        (None, _) => None,
+    };
 
-    let id = uuid::Uuid::new_v4().to_string();
+    let webhook_secret = match (webhook_secret, typ) {
+        (Some(w), "webhook") => Some(w.to_string()),
+        (None, "webhook") => Some(crate::signatur::schluessel_generieren()),
+        _ => None,
+    };
 
     conn.execute(
-        "INSERT INTO abonnements (id, typ, text, amtsgericht, bezirk, blatt, aktenzeichen) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+        "INSERT INTO abonnements (id, typ, text, amtsgericht, bezirk, blatt, aktenzeichen, besitzer, webhook_secret, detailstufe, abteilungen, aenderungsarten, flurstueck) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
         rusqlite::params![id, typ, text, amtsgericht, bezirk, match b {
             AbonnementInfoBlattNr::Alle => "*".to_string(),
             AbonnementInfoBlattNr::Exakt(i) => i.to_string(),
-        }, aktenzeichen.map(|s| s.to_string())],
+        }, aktenzeichen.map(|s| s.to_string()), besitzer, webhook_secret, detailstufe.as_str(),
+        abteilungen.join(","),
+        aenderungsarten.iter().map(|a| a.as_str()).collect::<Vec<_>>().join(","),
+        flurstueck],
     ).map_err(|e| format!("Fehler beim Einfügen von {blatt} in Abonnements: {e}"))?;
 
     Ok(())
 }
 
-pub fn get_abos_fuer_benutzer(benutzer: &BenutzerInfo) -> Result<Vec<AbonnementInfo>, String> {
-    let conn = Connection::open(get_db_path(MountPoint::Local))
+pub fn get_abos_fuer_benutzer(
+    konfiguration: &Konfiguration,
+    benutzer: &BenutzerInfo,
+) -> Result<Vec<AbonnementInfo>, String> {
+    let conn = verbindung_oeffnen(konfiguration, MountPoint::Local)
         .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
 
     let mut stmt = conn
         .prepare(
-            "SELECT id, typ, amtsgericht, bezirk, blatt, aktenzeichen FROM abonnements WHERE text = ?1",
+            "SELECT id, text, amtsgericht, bezirk, blatt, aktenzeichen, detailstufe, abteilungen, aenderungsarten, flurstueck, pausiert FROM abonnements WHERE text = ?1 OR besitzer = ?1",
         )
         .map_err(|e| format!("Fehler beim Auslesen der Abonnements"))?;
 
@@ -2356,6 +3456,11 @@ pub fn get_abos_fuer_benutzer(benutzer: &BenutzerInfo) -> Result<Vec<AbonnementI
                 row.get::<usize, String>(3)?,
                 row.get::<usize, String>(4)?,
                 row.get::<usize, Option<String>>(5)?,
+                row.get::<usize, String>(6)?,
+                row.get::<usize, String>(7)?,
+                row.get::<usize, String>(8)?,
+                row.get::<usize, Option<String>>(9)?,
+                row.get::<usize, i32>(10)?,
             ))
         })
         .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
@@ -2363,7 +3468,7 @@ pub fn get_abos_fuer_benutzer(benutzer: &BenutzerInfo) -> Result<Vec<AbonnementI
     let mut bz = Vec::new();
 
     for a in abos {
-        if let Ok((id, typ, amtsgericht, bezirk, blatt, aktenzeichen)) = a {
+        if let Ok((id, text, amtsgericht, bezirk, blatt, aktenzeichen, detailstufe, abteilungen, aenderungsarten, flurstueck, pausiert)) = a {
             bz.push(AbonnementInfo {
                 id: id, 
                 amtsgericht: amtsgericht.clone(),
@@ -2372,8 +3477,13 @@ pub fn get_abos_fuer_benutzer(benutzer: &BenutzerInfo) -> Result<Vec<AbonnementI
                     "*" => AbonnementInfoBlattNr::Alle,
                     i => AbonnementInfoBlattNr::Exakt(i.parse::<i32>().map_err(|e| format!("Fehler beim Einfügen von {blatt} in Abonnements: {e}"))?),
                 },
-                text: benutzer.email.to_string(),
+                text: text,
                 aktenzeichen: aktenzeichen.as_ref().map(|s| s.to_string()),
+                detailstufe: AboDetailstufe::from_str(&detailstufe).unwrap_or_default(),
+                abteilungen: crate::aenderungen::abteilungen_lesen(&abteilungen).unwrap_or_default(),
+                aenderungsarten: crate::aenderungen::aenderungsarten_lesen(&aenderungsarten).unwrap_or_default(),
+                flurstueck: flurstueck,
+                pausiert: pausiert != 0,
             });
         }
     }
@@ -2381,15 +3491,175 @@ pub fn get_abos_fuer_benutzer(benutzer: &BenutzerInfo) -> Result<Vec<AbonnementI
     Ok(bz)
 }
 
-pub fn get_email_abos(blatt: &str) -> Result<Vec<AbonnementInfo>, String> {
-    get_abos_inner("email", blatt)
+/// Einzelnes Abonnement mit Typ, Besitzer und Webhook-Secret
+#[derive(Debug, Clone, PartialEq)]
+pub struct AboEintrag {
+    pub typ: String,
+    pub besitzer: Option<String>,
+    pub webhook_secret: Option<String>,
+    pub info: AbonnementInfo,
+}
+
+impl AboEintrag {
+    /// Ob das Abonnement dem Benutzer gehört (Besitzer oder eigene E-Mail)
+    pub fn gehoert_zu(&self, benutzer: &BenutzerInfo) -> bool {
+        self.besitzer.as_deref() == Some(benutzer.email.as_str())
+            || (self.typ == "email" && self.info.text == benutzer.email)
+    }
+}
+
+const ABO_EINTRAG_SPALTEN: &str = "id, typ, text, amtsgericht, bezirk, blatt, aktenzeichen, besitzer, webhook_secret, detailstufe, abteilungen, aenderungsarten, flurstueck, pausiert";
+
+fn abo_eintrag_aus_zeile(row: &rusqlite::Row) -> rusqlite::Result<AboEintrag> {
+    let blatt = row.get::<usize, String>(5)?;
+    let detailstufe = row.get::<usize, String>(9)?;
+    let abteilungen = row.get::<usize, String>(10)?;
+    let aenderungsarten = row.get::<usize, String>(11)?;
+
+    Ok(AboEintrag {
+        typ: row.get(1)?,
+        besitzer: row.get(7)?,
+        webhook_secret: row.get(8)?,
+        info: AbonnementInfo {
+            id: row.get(0)?,
+            amtsgericht: row.get(3)?,
+            grundbuchbezirk: row.get(4)?,
+            blatt: match blatt.as_str() {
+                "*" => AbonnementInfoBlattNr::Alle,
+                i => AbonnementInfoBlattNr::Exakt(i.parse::<i32>().map_err(|e| {
+                    rusqlite::Error::FromSqlConversionFailure(5, rusqlite::types::Type::Text, Box::new(e))
+                })?),
+            },
+            text: row.get(2)?,
+            aktenzeichen: row.get(6)?,
+            detailstufe: AboDetailstufe::from_str(&detailstufe).unwrap_or_default(),
+            abteilungen: crate::aenderungen::abteilungen_lesen(&abteilungen).unwrap_or_default(),
+            aenderungsarten: crate::aenderungen::aenderungsarten_lesen(&aenderungsarten).unwrap_or_default(),
+            flurstueck: row.get(12)?,
+            pausiert: row.get::<usize, i32>(13)? != 0,
+        },
+    })
+}
+
+pub fn get_abo(konfiguration: &Konfiguration, id: &str) -> Result<Option<AboEintrag>, String> {
+    let conn = verbindung_oeffnen(konfiguration, MountPoint::Local)
+        .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+
+    conn.query_row(
+        &format!("SELECT {ABO_EINTRAG_SPALTEN} FROM abonnements WHERE id = ?1"),
+        rusqlite::params![id],
+        abo_eintrag_aus_zeile,
+    )
+    .optional()
+    .map_err(|e| format!("Fehler beim Auslesen von Abonnement {id}: {e}"))
+}
+
+/// Eigene Abonnements des Benutzers (Besitzer oder eigene E-Mail), siehe `AboEintrag::gehoert_zu`
+pub fn get_abo_eintraege_fuer_benutzer(
+    konfiguration: &Konfiguration,
+    benutzer: &BenutzerInfo,
+) -> Result<Vec<AboEintrag>, String> {
+    let conn = verbindung_oeffnen(konfiguration, MountPoint::Local)
+        .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {ABO_EINTRAG_SPALTEN} FROM abonnements
+            WHERE (typ = 'email' AND text = ?1) OR besitzer = ?1
+            ORDER BY amtsgericht, bezirk, blatt"
+        ))
+        .map_err(|e| format!("Fehler beim Auslesen der Abonnements: {e}"))?;
+
+    let abos = stmt
+        .query_map(rusqlite::params![benutzer.email], abo_eintrag_aus_zeile)
+        .map_err(|e| format!("Fehler beim Auslesen der Abonnements: {e}"))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Fehler beim Auslesen der Abonnements: {e}"))?;
+
+    Ok(abos)
+}
+
+/// Ändert Aktenzeichen (leer = keins) und / oder Pausierung eines Abonnements
+pub fn abo_aendern(
+    conn: &Connection,
+    id: &str,
+    aktenzeichen: Option<&str>,
+    pausiert: Option<bool>,
+) -> Result<(), String> {
+    if let Some(aktenzeichen) = aktenzeichen {
+        let aktenzeichen = Some(aktenzeichen.trim()).filter(|s| !s.is_empty());
+        let geaendert = conn
+            .execute(
+                "UPDATE abonnements SET aktenzeichen = ?1 WHERE id = ?2",
+                rusqlite::params![aktenzeichen, id],
+            )
+            .map_err(|e| format!("Fehler beim Ändern von Abonnement {id}: {e}"))?;
+        if geaendert == 0 {
+            return Err(format!("Kein Abonnement mit ID {id} gefunden"));
+        }
+    }
+
+    if let Some(pausiert) = pausiert {
+        let geaendert = conn
+            .execute(
+                "UPDATE abonnements SET pausiert = ?1 WHERE id = ?2",
+                rusqlite::params![pausiert as i32, id],
+            )
+            .map_err(|e| format!("Fehler beim Ändern von Abonnement {id}: {e}"))?;
+        if geaendert == 0 {
+            return Err(format!("Kein Abonnement mit ID {id} gefunden"));
+        }
+    }
+
+    Ok(())
+}
+
+pub fn abo_webhook_secret_setzen(
+    conn: &Connection,
+    id: &str,
+    webhook_secret: &str,
+) -> Result<(), String> {
+    let geaendert = conn
+        .execute(
+            "UPDATE abonnements SET webhook_secret = ?1 WHERE id = ?2 AND typ = 'webhook'",
+            rusqlite::params![webhook_secret, id],
+        )
+        .map_err(|e| format!("Fehler beim Ändern von Abonnement {id}: {e}"))?;
+
+    if geaendert == 0 {
+        return Err(format!("Kein Webhook-Abonnement mit ID {id} gefunden"));
+    }
+
+    Ok(())
+}
+
+pub fn get_email_abos(
+    konfiguration: &Konfiguration,
+    blatt: &str,
+    flurstuecke: &BTreeSet<String>,
+) -> Result<Vec<AbonnementInfo>, String> {
+    get_abos_inner(konfiguration, "email", blatt, flurstuecke)
 }
 
-pub fn get_webhook_abos(blatt: &str) -> Result<Vec<AbonnementInfo>, String> {
-    get_abos_inner("webhook", blatt)
+pub fn get_webhook_abos(
+    konfiguration: &Konfiguration,
+    blatt: &str,
+    flurstuecke: &BTreeSet<String>,
+) -> Result<Vec<AbonnementInfo>, String> {
+    get_abos_inner(konfiguration, "webhook", blatt, flurstuecke)
 }
 
-fn get_abos_inner(typ: &'static str, blatt: &str) -> Result<Vec<AbonnementInfo>, String> {
+/// Abonnements, die bei einer Änderung von `blatt` benachrichtigt werden:
+/// exakte Abos auf das Blatt, Abos auf den ganzen Bezirk (Blatt "*") bzw.
+/// das ganze Amtsgericht (Bezirk "*") sowie Abos auf Flurstücke, die vor
+/// oder nach der Änderung auf dem Blatt stehen (`flurstuecke`, normalisiert).
+/// Abos, deren Besitzer das Blatt nicht (mehr) lesen darf, werden übersprungen.
+fn get_abos_inner(
+    konfiguration: &Konfiguration,
+    typ: &'static str,
+    blatt: &str,
+    flurstuecke: &BTreeSet<String>,
+) -> Result<Vec<AbonnementInfo>, String> {
     let blatt_split = blatt
         .split("/")
         .map(|s| s.trim().to_string())
@@ -2422,13 +3692,24 @@ fn get_abos_inner(typ: &'static str, blatt: &str) -> Result<Vec<AbonnementInfo>,
 
     println!("get_abos_inner {blatt_split:?} {amtsgericht} // {bezirk} // {b:?}");
 
-    let conn = Connection::open(get_db_path(MountPoint::Local))
+    let conn = verbindung_oeffnen(konfiguration, MountPoint::Local)
         .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
 
     let mut bz = Vec::new();
 
+    let land = get_gemarkungen(konfiguration)?
+        .into_iter()
+        .find(|(_, ag, bz)| *ag == amtsgericht && *bz == bezirk)
+        .map(|(land, _, _)| land)
+        .unwrap_or_default();
+    let blatt_nr = match &b {
+        AbonnementInfoBlattNr::Exakt(i) => i.to_string(),
+        AbonnementInfoBlattNr::Alle => "*".to_string(),
+    };
+    let mut leserechte = BTreeMap::<String, Option<Leserechte>>::new();
+
     let mut stmt = conn
-    .prepare("SELECT id, text, aktenzeichen FROM abonnements WHERE typ = ?1 AND amtsgericht = ?2 AND bezirk = ?3")
+    .prepare("SELECT id, text, aktenzeichen, detailstufe, abteilungen, aenderungsarten, blatt, flurstueck, besitzer FROM abonnements WHERE typ = ?1 AND amtsgericht = ?2 AND (bezirk = ?3 OR bezirk = '*') AND pausiert = 0")
     .map_err(|e| format!("Fehler beim Auslesen der Bezirke"))?;
 
     println!("alle {:?}", &[typ.to_string(), amtsgericht.clone(), bezirk.clone()]);
@@ -2439,6 +3720,12 @@ fn get_abos_inner(typ: &'static str, blatt: &str) -> Result<Vec<AbonnementInfo>,
             row.get::<usize, String>(0)?,
             row.get::<usize, String>(1)?,
             row.get::<usize, Option<String>>(2)?,
+            row.get::<usize, String>(3)?,
+            row.get::<usize, String>(4)?,
+            row.get::<usize, String>(5)?,
+            row.get::<usize, String>(6)?,
+            row.get::<usize, Option<String>>(7)?,
+            row.get::<usize, Option<String>>(8)?,
         ))
     })
     .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
@@ -2446,7 +3733,42 @@ fn get_abos_inner(typ: &'static str, blatt: &str) -> Result<Vec<AbonnementInfo>,
     for a in abos {
         println!("abo {a:?}");
 
-        if let Ok((id, email, aktenzeichen)) = a {
+        if let Ok((id, email, aktenzeichen, detailstufe, abteilungen, aenderungsarten, abo_blatt, flurstueck, besitzer)) = a {
+            let blatt_passt = match (abo_blatt.trim(), &b) {
+                ("*", _) | (_, AbonnementInfoBlattNr::Alle) => true,
+                (s, AbonnementInfoBlattNr::Exakt(i)) => s == i.to_string(),
+            };
+            if !blatt_passt {
+                continue;
+            }
+
+            if let Some(f) = flurstueck.as_deref() {
+                match crate::aenderungen::flurstueck_normalisieren(f) {
+                    Some(f) if flurstuecke.contains(&f) => {}
+                    _ => continue,
+                }
+            }
+
+            // Besitzer ist der Benutzer, der das Abo angelegt hat (ältere E-Mail-Abos: der Empfänger)
+            let besitzer = match besitzer.or(if typ == "email" { Some(email.clone()) } else { None }) {
+                Some(s) => s,
+                None => continue,
+            };
+            let darf_lesen = leserechte
+                .entry(besitzer.clone())
+                .or_insert_with(|| {
+                    get_benutzer_by_email(&conn, &besitzer)
+                        .ok()
+                        .flatten()
+                        .and_then(|b| Leserechte::laden(konfiguration, &b).ok())
+                })
+                .as_ref()
+                .map_or(false, |l| l.erlaubt(&land, &amtsgericht, &bezirk, &blatt_nr));
+            if !darf_lesen {
+                println!("Abo {id}: {besitzer} hat keinen gültigen Zugriff auf {blatt}, wird übersprungen");
+                continue;
+            }
+
             bz.push(AbonnementInfo {
                 id: id, 
                 amtsgericht: amtsgericht.clone(),
@@ -2454,30 +3776,140 @@ fn get_abos_inner(typ: &'static str, blatt: &str) -> Result<Vec<AbonnementInfo>,
                 blatt: b.clone(),
                 text: email.to_string(),
                 aktenzeichen: aktenzeichen.as_ref().map(|s| s.to_string()),
+                detailstufe: AboDetailstufe::from_str(&detailstufe).unwrap_or_default(),
+                abteilungen: crate::aenderungen::abteilungen_lesen(&abteilungen).unwrap_or_default(),
+                aenderungsarten: crate::aenderungen::aenderungsarten_lesen(&aenderungsarten).unwrap_or_default(),
+                flurstueck: flurstueck,
+                pausiert: false,
             });
         }
     }
 
     println!("abos {bz:#?}");
 
-    match b {
-        AbonnementInfoBlattNr::Alle => Ok(bz),
-        AbonnementInfoBlattNr::Exakt(bq) => Ok(bz.into_iter().filter(|a| a.blatt.matches(&bq.to_string())).collect())
-    }
+    Ok(bz)
 }
 
 pub fn delete_abo(
-    mount_point: MountPoint,
+    conn: &Connection,
     id: &str,
 ) -> Result<(), String> {
 
-    let conn = Connection::open(get_db_path(mount_point))
-        .map_err(|e| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
-
     conn.execute(
         "DELETE FROM abonnements WHERE id = ?1",
         rusqlite::params![id],
     ).map_err(|e| format!("Fehler beim Löschen Abonnement {id}: {e}"))?;
 
+    // Nach dem Kündigen keine weiteren Benachrichtigungen mehr senden
+    crate::postausgang::abo_eintraege_loeschen(conn, id)?;
+
+    Ok(())
+}
+
+pub fn abo_detailstufe_setzen(
+    conn: &Connection,
+    ids: &[String],
+    detailstufe: &str,
+) -> Result<(), String> {
+    let detailstufe = AboDetailstufe::from_str(detailstufe)
+        .ok_or_else(|| format!("Ungültige Detailstufe: {detailstufe}"))?;
+
+    for id in ids {
+        conn.execute(
+            "UPDATE abonnements SET detailstufe = ?1 WHERE id = ?2",
+            rusqlite::params![detailstufe.as_str(), id],
+        )
+        .map_err(|e| format!("Fehler beim Ändern von Abonnement {id}: {e}"))?;
+    }
+
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bereich(land: &str, amtsgericht: &str, bezirk: &str, blatt: &str, blatt_bis: Option<&str>) -> ZugriffBereich {
+        ZugriffBereich {
+            land: land.to_string(),
+            amtsgericht: amtsgericht.to_string(),
+            bezirk: bezirk.to_string(),
+            blatt: blatt.to_string(),
+            blatt_bis: blatt_bis.map(|s| s.to_string()),
+        }
+    }
+
+    #[test]
+    fn blatt_nummer_mit_zusatz() {
+        assert_eq!(blatt_nummer("123"), Some(123));
+        assert_eq!(blatt_nummer("123a"), Some(123));
+        assert_eq!(blatt_nummer(" 0042 "), Some(42));
+        assert_eq!(blatt_nummer("a123"), None);
+        assert_eq!(blatt_nummer(""), None);
+        assert_eq!(blatt_nummer("ALLE_BLAETTER"), None);
+    }
+
+    #[test]
+    fn umfasst_einzelnes_blatt() {
+        let z = bereich("Brandenburg", "Prenzlau", "Ludwigsburg", "123a", None);
+        assert!(z.umfasst("Brandenburg", "Prenzlau", "Ludwigsburg", "123a"));
+        assert!(!z.umfasst("Brandenburg", "Prenzlau", "Ludwigsburg", "123"));
+        assert!(!z.umfasst("Brandenburg", "Prenzlau", "Schenkenberg", "123a"));
+        assert!(!z.umfasst("Brandenburg", "Cottbus", "Ludwigsburg", "123a"));
+        assert!(!z.umfasst("Berlin", "Prenzlau", "Ludwigsburg", "123a"));
+    }
+
+    #[test]
+    fn umfasst_bereich() {
+        let z = bereich("Brandenburg", "Prenzlau", "Ludwigsburg", "100", Some("250"));
+        assert!(z.umfasst("Brandenburg", "Prenzlau", "Ludwigsburg", "100"));
+        assert!(z.umfasst("Brandenburg", "Prenzlau", "Ludwigsburg", "123a"));
+        assert!(z.umfasst("Brandenburg", "Prenzlau", "Ludwigsburg", "250"));
+        assert!(z.umfasst("Brandenburg", "Prenzlau", "Ludwigsburg", "250b"));
+        assert!(!z.umfasst("Brandenburg", "Prenzlau", "Ludwigsburg", "99"));
+        assert!(!z.umfasst("Brandenburg", "Prenzlau", "Ludwigsburg", "251"));
+        assert!(!z.umfasst("Brandenburg", "Prenzlau", "Ludwigsburg", "ohne Nummer"));
+        assert!(!z.umfasst("Brandenburg", "Prenzlau", "Schenkenberg", "123"));
+
+        // Ungültige Grenzen umfassen nichts
+        let z = bereich("Brandenburg", "Prenzlau", "Ludwigsburg", "x", Some("250"));
+        assert!(!z.umfasst("Brandenburg", "Prenzlau", "Ludwigsburg", "123"));
+    }
+
+    #[test]
+    fn umfasst_platzhalter() {
+        let z = bereich("Brandenburg", "Prenzlau", "ALLE_GRUNDBUCHBEZIRKE", "ALLE_BLAETTER", None);
+        assert!(z.umfasst("Brandenburg", "Prenzlau", "Ludwigsburg", "1"));
+        assert!(z.umfasst("Brandenburg", "Prenzlau", "Schenkenberg", "123a"));
+        assert!(!z.umfasst("Brandenburg", "Cottbus", "Ludwigsburg", "1"));
+
+        let z = bereich("ALLE_BUNDESLAENDER", "ALLE_AMTSGERICHTE", "ALLE_GRUNDBUCHBEZIRKE", "ALLE_BLAETTER", None);
+        assert!(z.umfasst("Berlin", "Mitte", "Mitte", "7"));
+
+        // Platzhalter gelten nur auf ihrer Ebene
+        let z = bereich("Brandenburg", "ALLE_AMTSGERICHTE", "Ludwigsburg", "1", None);
+        assert!(z.umfasst("Brandenburg", "Prenzlau", "Ludwigsburg", "1"));
+        assert!(!z.umfasst("Brandenburg", "Prenzlau", "Ludwigsburg", "2"));
+    }
+
+    #[test]
+    fn leserechte_erlaubt() {
+        let leserechte = Leserechte {
+            alle: false,
+            zugriffe: vec![
+                bereich("Brandenburg", "Prenzlau", "Ludwigsburg", "100", Some("200")),
+                bereich("Brandenburg", "Prenzlau", "Schenkenberg", "5", None),
+            ],
+        };
+        assert!(leserechte.erlaubt("Brandenburg", "Prenzlau", "Ludwigsburg", "150"));
+        assert!(leserechte.erlaubt("Brandenburg", "Prenzlau", "Schenkenberg", "5"));
+        assert!(!leserechte.erlaubt("Brandenburg", "Prenzlau", "Schenkenberg", "6"));
+        assert!(leserechte.pruefen("Brandenburg", "Prenzlau", "Ludwigsburg", "201").is_err());
+
+        let leserechte = Leserechte {
+            alle: true,
+            zugriffe: Vec::new(),
+        };
+        assert!(leserechte.erlaubt("Berlin", "Mitte", "Mitte", "1"));
+    }
+}
@@ -0,0 +1,331 @@
+//! Einsichtsprotokoll: wer hat wann welches Grundbuchblatt eingesehen
+//!
+//! Protokolliert werden Downloads als .gbx und .pdf, Downloads als .zip über `/konto`
+//! sowie Treffer in der Suche. Optional kann der Benutzer ein Aktenzeichen und einen
+//! Zweck angeben (`?aktenzeichen=...&zweck=...`). Damit das Protokollieren den Download
+//! weder verzögert noch verhindert, landen die Einsichten zuerst in einer lokalen
+//! Warteschlange (`einsichten.jsonl`), die alle `UEBERTRAGUNG_INTERVALL_SEKUNDEN` gesammelt
+//! über den Sync-Server geschrieben wird. Nach `einsichtsprotokoll_aufbewahrung_tage`
+//! werden die Einträge gelöscht.
+
+use crate::api::commit::DbChangeOp;
+use crate::config::Konfiguration;
+use crate::models::{BenutzerInfo, MountPoint};
+use crate::AppState;
+use chrono::{Duration, Utc};
+use rusqlite::Connection;
+use serde_derive::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// Abstand, in dem die lokale Warteschlange an den Sync-Server übertragen wird
+pub const UEBERTRAGUNG_INTERVALL_SEKUNDEN: u64 = 10;
+
+const WARTESCHLANGE_DATEI: &str = "einsichten.jsonl";
+
+/// Verhindert, dass die Warteschlange während des Anhängens umbenannt wird
+static WARTESCHLANGE: Mutex<()> = Mutex::new(());
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum EinsichtTyp {
+    #[serde(rename = "gbx")]
+    Gbx,
+    #[serde(rename = "pdf")]
+    Pdf,
+    #[serde(rename = "zip")]
+    Zip,
+    #[serde(rename = "suchtreffer")]
+    Suchtreffer,
+}
+
+impl EinsichtTyp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EinsichtTyp::Gbx => "gbx",
+            EinsichtTyp::Pdf => "pdf",
+            EinsichtTyp::Zip => "zip",
+            EinsichtTyp::Suchtreffer => "suchtreffer",
+        }
+    }
+}
+
+/// Einsicht in ein einzelnes Grundbuchblatt
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Einsicht {
+    pub land: String,
+    pub amtsgericht: String,
+    pub bezirk: String,
+    pub blatt: String,
+    pub typ: EinsichtTyp,
+    pub aktenzeichen: Option<String>,
+    pub zweck: Option<String>,
+}
+
+/// Optionale Angaben des Benutzers, warum das Blatt eingesehen wird
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EinsichtZweck {
+    pub aktenzeichen: Option<String>,
+    pub zweck: Option<String>,
+}
+
+impl EinsichtZweck {
+    pub fn einsicht(
+        &self,
+        typ: EinsichtTyp,
+        land: &str,
+        amtsgericht: &str,
+        bezirk: &str,
+        blatt: &str,
+    ) -> Einsicht {
+        let nicht_leer = |s: &Option<String>| {
+            s.as_ref()
+                .map(|s| s.trim().to_string())
+                .filter(|s| !s.is_empty())
+        };
+        Einsicht {
+            land: land.to_string(),
+            amtsgericht: amtsgericht.to_string(),
+            bezirk: bezirk.to_string(),
+            blatt: blatt.to_string(),
+            typ,
+            aktenzeichen: nicht_leer(&self.aktenzeichen),
+            zweck: nicht_leer(&self.zweck),
+        }
+    }
+}
+
+/// Einsichten eines Benutzers, eine Zeile in der lokalen Warteschlange
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EinsichtenNeu {
+    pub benutzer: String,
+    pub zeitpunkt: String,
+    pub einsichten: Vec<Einsicht>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EinsichtEintrag {
+    pub id: i64,
+    pub zeitpunkt: String,
+    pub benutzer: String,
+    #[serde(flatten)]
+    pub einsicht: Einsicht,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct EinsichtFilter {
+    pub land: Option<String>,
+    pub amtsgericht: Option<String>,
+    pub bezirk: Option<String>,
+    pub blatt: Option<String>,
+    /// E-Mail des Benutzers, der das Blatt eingesehen hat
+    pub benutzer: Option<String>,
+    /// Einträge ab diesem Zeitpunkt (RFC 3339)
+    pub von: Option<String>,
+    /// Einträge bis zu diesem Zeitpunkt (RFC 3339)
+    pub bis: Option<String>,
+    /// Maximale Anzahl der Einträge (neueste zuerst)
+    pub limit: Option<usize>,
+}
+
+pub fn einsichten_einfuegen(conn: &Connection, eintraege: &[EinsichtenNeu]) -> Result<(), String> {
+    for eintrag in eintraege {
+        for e in eintrag.einsichten.iter() {
+            conn.execute(
+                "INSERT INTO einsichten (zeitpunkt, benutzer, land, amtsgericht, bezirk, blatt, typ, aktenzeichen, zweck)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                rusqlite::params![
+                    eintrag.zeitpunkt,
+                    eintrag.benutzer,
+                    e.land,
+                    e.amtsgericht,
+                    e.bezirk,
+                    e.blatt,
+                    e.typ.as_str(),
+                    e.aktenzeichen,
+                    e.zweck,
+                ],
+            )
+            .map_err(|e| format!("Fehler beim Protokollieren der Einsicht: {e}"))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn warteschlange_pfad(konfiguration: &Konfiguration) -> PathBuf {
+    Path::new(&konfiguration.get_base_path(MountPoint::Local)).join(WARTESCHLANGE_DATEI)
+}
+
+/// Hängt Einsichten an die lokale Warteschlange an (eine JSON-Zeile pro Aufruf)
+pub fn einreihen(konfiguration: &Konfiguration, eintrag: &EinsichtenNeu) -> Result<(), String> {
+    let zeile = serde_json::to_string(eintrag).map_err(|e| format!("{e}"))?;
+    let pfad = warteschlange_pfad(konfiguration);
+
+    let _lock = WARTESCHLANGE
+        .lock()
+        .map_err(|_| format!("Warteschlange für Einsichten nicht verfügbar"))?;
+    let mut datei = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&pfad)
+        .map_err(|e| format!("Konnte {} nicht öffnen: {e}", pfad.display()))?;
+    writeln!(datei, "{zeile}").map_err(|e| format!("Konnte {} nicht schreiben: {e}", pfad.display()))
+}
+
+/// Überträgt alle gesammelten Einsichten in einer Änderung an den Sync-Server.
+/// Schlägt die Übertragung fehl, bleiben die Einträge für den nächsten Versuch erhalten.
+pub async fn warteschlange_uebertragen(app_state: &AppState) -> Result<usize, String> {
+    let pfad = warteschlange_pfad(&app_state.konfiguration());
+    let in_uebertragung = pfad.with_extension("jsonl.uebertragen");
+
+    {
+        let _lock = WARTESCHLANGE
+            .lock()
+            .map_err(|_| format!("Warteschlange für Einsichten nicht verfügbar"))?;
+        if !in_uebertragung.exists() && pfad.exists() {
+            std::fs::rename(&pfad, &in_uebertragung)
+                .map_err(|e| format!("Konnte {} nicht umbenennen: {e}", pfad.display()))?;
+        }
+    }
+
+    let inhalt = match std::fs::read_to_string(&in_uebertragung) {
+        Ok(o) => o,
+        Err(_) => return Ok(0),
+    };
+
+    let eintraege = inhalt
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .filter_map(|l| match serde_json::from_str::<EinsichtenNeu>(l) {
+            Ok(o) => Some(o),
+            Err(e) => {
+                log::error!("Ungültige Zeile in {}: {e}", in_uebertragung.display());
+                None
+            }
+        })
+        .collect::<Vec<_>>();
+
+    let anzahl = eintraege.iter().map(|e| e.einsichten.len()).sum();
+    if !eintraege.is_empty() {
+        crate::api::write_to_root_db(
+            DbChangeOp::EinsichtenProtokollieren { eintraege },
+            "system",
+            app_state,
+        )
+        .await?;
+    }
+
+    std::fs::remove_file(&in_uebertragung)
+        .map_err(|e| format!("Konnte {} nicht löschen: {e}", in_uebertragung.display()))?;
+
+    Ok(anzahl)
+}
+
+/// Löscht Einträge, die älter als die konfigurierte Aufbewahrungsdauer sind
+pub fn einsichten_bereinigen(
+    konfiguration: &Konfiguration,
+    mount_point: MountPoint,
+    aufbewahrung_tage: u32,
+) -> Result<usize, String> {
+    if aufbewahrung_tage == 0 {
+        return Ok(0);
+    }
+
+    let conn = crate::db::verbindung_oeffnen(konfiguration, mount_point)
+        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+
+    let grenze = Utc::now() - Duration::days(aufbewahrung_tage as i64);
+
+    conn.execute(
+        "DELETE FROM einsichten WHERE zeitpunkt < ?1",
+        rusqlite::params![grenze.to_rfc3339()],
+    )
+    .map_err(|e| format!("Fehler beim Bereinigen des Einsichtsprotokolls: {e}"))
+}
+
+/// Liest das Einsichtsprotokoll (neueste Einträge zuerst). Administratoren sehen
+/// alle Einträge, andere Benutzer nur Einträge zu Blättern, auf die sie einen
+/// genehmigten Zugriff haben, sowie ihre eigenen Einsichten.
+pub fn get_einsichten(
+    konfiguration: &Konfiguration,
+    benutzer: &BenutzerInfo,
+    filter: &EinsichtFilter,
+) -> Result<Vec<EinsichtEintrag>, String> {
+    let conn = crate::db::verbindung_oeffnen(konfiguration, MountPoint::Local)
+        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, zeitpunkt, benutzer, land, amtsgericht, bezirk, blatt, typ, aktenzeichen, zweck
+            FROM einsichten
+            WHERE (?1 IS NULL OR land = ?1)
+            AND (?2 IS NULL OR amtsgericht = ?2)
+            AND (?3 IS NULL OR bezirk = ?3)
+            AND (?4 IS NULL OR blatt = ?4)
+            AND (?5 IS NULL OR benutzer = ?5)
+            AND (?6 IS NULL OR zeitpunkt >= ?6)
+            AND (?7 IS NULL OR zeitpunkt <= ?7)
+            ORDER BY id DESC",
+        )
+        .map_err(|e| format!("Fehler beim Auslesen des Einsichtsprotokolls: {e}"))?;
+
+    let eintraege = stmt
+        .query_map(
+            rusqlite::params![
+                filter.land,
+                filter.amtsgericht,
+                filter.bezirk,
+                filter.blatt,
+                filter.benutzer,
+                filter.von,
+                filter.bis,
+            ],
+            |row| {
+                let typ = match row.get::<usize, String>(7)?.as_str() {
+                    "gbx" => EinsichtTyp::Gbx,
+                    "pdf" => EinsichtTyp::Pdf,
+                    "zip" => EinsichtTyp::Zip,
+                    _ => EinsichtTyp::Suchtreffer,
+                };
+                Ok(EinsichtEintrag {
+                    id: row.get(0)?,
+                    zeitpunkt: row.get(1)?,
+                    benutzer: row.get(2)?,
+                    einsicht: Einsicht {
+                        land: row.get(3)?,
+                        amtsgericht: row.get(4)?,
+                        bezirk: row.get(5)?,
+                        blatt: row.get(6)?,
+                        typ,
+                        aktenzeichen: row.get(8)?,
+                        zweck: row.get(9)?,
+                    },
+                })
+            },
+        )
+        .map_err(|e| format!("Fehler beim Auslesen des Einsichtsprotokolls: {e}"))?
+        .filter_map(|r| r.ok());
+
+    let limit = filter.limit.unwrap_or(usize::MAX);
+
+    if benutzer.hat_berechtigung(crate::rollen::Berechtigung::ProtokolleLesen) {
+        return Ok(eintraege.take(limit).collect());
+    }
+
+    let zugriffe = crate::db::get_zugriffe(konfiguration, benutzer)?;
+
+    Ok(eintraege
+        .filter(|e| {
+            e.benutzer == benutzer.email
+                || crate::db::benutzer_hat_zugriff_auf_blatt(
+                    &zugriffe,
+                    &e.einsicht.land,
+                    &e.einsicht.amtsgericht,
+                    &e.einsicht.bezirk,
+                    &e.einsicht.blatt,
+                )
+        })
+        .take(limit)
+        .collect())
+}
@@ -1,10 +1,22 @@
-use crate::models::{AbonnementInfo, MountPoint};
+use crate::aenderungen::AenderungsDetails;
+use crate::config::Konfiguration;
+use crate::models::{AboBenachrichtigung, AbonnementInfo, MountPoint};
+use chrono::{DateTime, Utc};
 use lettre::{
     message::{header, MultiPart, SinglePart},
     Message, SmtpTransport, Transport,
 };
 use serde_derive::{Deserialize, Serialize};
 use crate::models::AbonnementInfoBlattNr;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Globale Einstellung: Benachrichtigung von Administratoren über neue
+/// Zugriffsanfragen, `sofort`, `taeglich` (Zusammenfassung) oder `aus`
+pub const ZUGRIFF_BENACHRICHTIGUNG_EINSTELLUNG: &str = "email.zugriff.benachrichtigung";
+
+/// Benutzer-Einstellung: E-Mails zu Abonnements `sofort`, `taeglich` oder
+/// `woechentlich` (Zusammenfassung, siehe postausgang.rs)
+pub const ABO_BENACHRICHTIGUNG_EINSTELLUNG: &str = "email.abo.benachrichtigung";
 
 // Um die E-Mails zu verschicken, brauchen wir Zugriff
 // zu einem Server. Die Daten werden beim Start des Servers
@@ -28,9 +40,27 @@ pub struct AboWebhookInfo {
     pub webhook: String,
     pub aktenzeichen: Option<String>,
     pub aenderungs_id: String,
+    /// `aenderung` oder `test`
+    #[serde(default)]
+    pub ereignis: String,
+    /// Eindeutig je Zustellung, bleibt bei Wiederholungen gleich
+    #[serde(default)]
+    pub zustellungs_id: String,
+    /// Nur bei Abos auf ein Flurstück ("Gemarkung/Flur/Flurstück")
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flurstueck: Option<String>,
+    /// Nur bei Abos mit Detailstufe `details`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub details: Option<AenderungsDetails>,
 }
 
+/// Webhook-Ereignis: Änderung an einem abonnierten Blatt
+pub const WEBHOOK_EREIGNIS_AENDERUNG: &str = "aenderung";
+/// Webhook-Ereignis: Testzustellung aus dem Konto bzw. der API
+pub const WEBHOOK_EREIGNIS_TEST: &str = "test";
+
 pub fn send_email(
+    konfiguration: &Konfiguration,
     to: &str,
     subject: &str,
     html: &str,
@@ -41,7 +71,7 @@ pub fn send_email(
     use lettre::transport::smtp::PoolConfig;
 
     println!("send_email...");
-    let smtp_config = crate::db::get_email_config()?;
+    let smtp_config = crate::db::get_email_config(konfiguration)?;
 
     let from = format!("Digitales Grundbuch <{}>", smtp_config.email);
     println!("smtp config = {:#?}", smtp_config);
@@ -95,12 +125,13 @@ pub fn send_email(
 }
 
 pub fn send_zugriff_gewaehrt_email(
+    konfiguration: &Konfiguration,
     to: &str,
     zugriff_id: &str,
     // (Land, Amtsgericht, Blatt, Nr.)
     grundbuecher: &[(String, String, String, String)],
 ) -> Result<(), String> {
-    let server_url = crate::db::get_server_address(MountPoint::Local)?;
+    let server_url = crate::db::get_server_address(konfiguration, MountPoint::Local)?;
 
     let mut gb_short = grundbuecher
         .first()
@@ -177,9 +208,10 @@ Die Einstellung für Benachrichtigungen können Sie in Ihrem Konto über \"Einst
 
     // Die meisten SMTP-Server überprüfen die Sender-Adresse,
     // E-Mail muss mit den Login-Daten übereinstimmen
-    let smtp_config = crate::db::get_email_config()?;
+    let smtp_config = crate::db::get_email_config(konfiguration)?;
 
     send_email(
+        konfiguration,
         to,
         &format!("Ihr Zugriff auf Grundbuch {gb_short} wurde gewährt"),
         &html,
@@ -189,13 +221,600 @@ Die Einstellung für Benachrichtigungen können Sie in Ihrem Konto über \"Einst
     Ok(())
 }
 
-pub fn send_zugriff_abgelehnt_email(to: &str) -> Result<(), String> {
-    let server_url = crate::db::get_server_address(MountPoint::Local)?;
+pub fn send_passwort_reset_email(
+    konfiguration: &Konfiguration,
+    to: &str,
+    token: &str,
+    gueltig_bis: &DateTime<Utc>,
+) -> Result<(), String> {
+    let server_url = crate::db::get_server_address(konfiguration, MountPoint::Local)?;
+    let link = format!("{server_url}/passwort-zuruecksetzen?token={token}");
+    let gueltig_bis = gueltig_bis.format("%d.%m.%Y %H:%M UTC");
+
+    let html = format!("<!DOCTYPE html>
+    <html lang=\"de\">
+    <head>
+        <meta charset=\"UTF-8\">
+        <meta name=\"viewport\" content=\"width=device-width, initial-scale=1.0\">
+        <title>Passwort zurücksetzen</title>
+    </head>
+    <body>
+        <div style=\"width: 800px; margin: 0 auto;\">
+          
+            <h4>Passwort zurücksetzen</h4>
+            
+            <p>Guten Tag,</p>
+            
+            <p>für Ihr Konto wurde das Zurücksetzen des Passworts angefordert.</p>
+            
+            <p>Um ein neues Passwort festzulegen, folgen Sie bitte dem folgenden Link (gültig bis {gueltig_bis}):</p>
+            <a href=\"{link}\">{link}</a>
+            <br/>
+            
+            <br/>
+
+            <p>Der Link kann nur einmal verwendet werden. Nach dem Zurücksetzen werden alle angemeldeten Sitzungen beendet.</p>
+            <p>Falls Sie das Zurücksetzen nicht angefordert haben, können Sie diese E-Mail ignorieren.</p>
+        </div>
+    </body>
+    </html>");
+
+    let plaintext = format!(
+        "Guten Tag,
+
+für Ihr Konto wurde das Zurücksetzen des Passworts angefordert.
+
+Um ein neues Passwort festzulegen, folgen Sie bitte dem folgenden Link (gültig bis {gueltig_bis}):
+{link}
+
+Der Link kann nur einmal verwendet werden. Nach dem Zurücksetzen werden alle angemeldeten Sitzungen beendet.
+Falls Sie das Zurücksetzen nicht angefordert haben, können Sie diese E-Mail ignorieren."
+    );
+
+    send_email(konfiguration, to, "Passwort zurücksetzen", &html, &plaintext)
+}
+
+/// Bittet den Antragsteller, seine E-Mail-Adresse über einen signierten Link zu bestätigen.
+/// Erst danach wird die Anfrage den Administratoren angezeigt.
+pub fn send_zugriff_bestaetigung_email(
+    konfiguration: &Konfiguration,
+    to: &str,
+    anfrage_id: &str,
+    gueltig_bis: Option<&DateTime<Utc>>,
+    zugriffe: &[crate::db::ZugriffInfo],
+) -> Result<(), String> {
+    let server_url = crate::db::get_server_address(konfiguration, MountPoint::Local)?;
+    let bis = gueltig_bis
+        .map(|d| format!("{}", d.timestamp()))
+        .unwrap_or_default();
+    let signatur = crate::signatur::signieren(
+        konfiguration,
+        crate::signatur::ZWECK_ZUGRIFF_BESTAETIGEN,
+        &[anfrage_id, &bis],
+    )?;
+    let link = format!(
+        "{server_url}/zugriff/status?anfrage={anfrage_id}&bis={bis}&signatur={signatur}"
+    );
+    let gueltigkeit = match gueltig_bis {
+        Some(d) => format!(" (gültig bis {})", d.format("%d.%m.%Y %H:%M UTC")),
+        None => String::new(),
+    };
+
+    let eintraege = zugriffe
+        .iter()
+        .map(|z| {
+            format!(
+                "Amtsgericht {}, Grundbuch von {} Blatt {}",
+                z.amtsgericht,
+                z.bezirk,
+                z.get_bereich().blatt_text()
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let gb_list_plain = eintraege.join("\r\n");
+    let gb_list = eintraege
+        .iter()
+        .map(|e| format!("<li>{}</li>", html_escape(e)))
+        .collect::<Vec<_>>()
+        .join("\r\n");
+
+    let html = format!("<!DOCTYPE html>
+    <html lang=\"de\">
+    <head>
+        <meta charset=\"UTF-8\">
+        <meta name=\"viewport\" content=\"width=device-width, initial-scale=1.0\">
+        <title>Bitte bestätigen Sie Ihre Zugriffsanfrage</title>
+    </head>
+    <body>
+        <div style=\"width: 800px; margin: 0 auto;\">
+          
+            <h4>Bitte bestätigen Sie Ihre Zugriffsanfrage</h4>
+            
+            <p>Guten Tag,</p>
+            
+            <p>mit dieser E-Mail-Adresse wurde Zugriff auf die folgenden Grundbücher angefragt:</p>
+            
+            <ul>
+                {gb_list}
+            </ul>
+            
+            <p>Die Anfrage wird erst bearbeitet, nachdem Sie sie über den folgenden Link bestätigt haben{gueltigkeit}.
+            Über denselben Link können Sie danach jederzeit den Bearbeitungsstand einsehen:</p>
+            <a href=\"{link}\">{link}</a>
+            <br/>
+            
+            <br/>
+
+            <p>Bitte geben Sie diesen Link nicht weiter.
+            Falls Sie keinen Zugriff angefragt haben, können Sie diese E-Mail ignorieren.</p>
+        </div>
+    </body>
+    </html>");
+
+    let plaintext = format!(
+        "Guten Tag,
+
+mit dieser E-Mail-Adresse wurde Zugriff auf die folgenden Grundbücher angefragt:
+
+{gb_list_plain}
+
+Die Anfrage wird erst bearbeitet, nachdem Sie sie über den folgenden Link bestätigt haben{gueltigkeit}.
+Über denselben Link können Sie danach jederzeit den Bearbeitungsstand einsehen:
+{link}
+
+Bitte geben Sie diesen Link nicht weiter.
+Falls Sie keinen Zugriff angefragt haben, können Sie diese E-Mail ignorieren."
+    );
+
+    send_email(konfiguration, to, "Bitte bestätigen Sie Ihre Zugriffsanfrage", &html, &plaintext)
+}
+
+/// Eingangsbestätigung für den Antragsteller mit Link zur Statusseite der Anfrage
+pub fn send_zugriff_anfrage_eingegangen_email(
+    konfiguration: &Konfiguration,
+    to: &str,
+    anfrage_id: &str,
+    zugriffe: &[crate::db::ZugriffInfo],
+) -> Result<(), String> {
+    let server_url = crate::db::get_server_address(konfiguration, MountPoint::Local)?;
+    let link = format!("{server_url}/zugriff/status?anfrage={anfrage_id}");
+
+    let eintraege = zugriffe
+        .iter()
+        .map(|z| {
+            format!(
+                "Amtsgericht {}, Grundbuch von {} Blatt {}",
+                z.amtsgericht,
+                z.bezirk,
+                z.get_bereich().blatt_text()
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let gb_list_plain = eintraege.join("\r\n");
+    let gb_list = eintraege
+        .iter()
+        .map(|e| format!("<li>{}</li>", html_escape(e)))
+        .collect::<Vec<_>>()
+        .join("\r\n");
+
+    let html = format!("<!DOCTYPE html>
+    <html lang=\"de\">
+    <head>
+        <meta charset=\"UTF-8\">
+        <meta name=\"viewport\" content=\"width=device-width, initial-scale=1.0\">
+        <title>Ihre Zugriffsanfrage ist eingegangen</title>
+    </head>
+    <body>
+        <div style=\"width: 800px; margin: 0 auto;\">
+          
+            <h4>Ihre Zugriffsanfrage ist eingegangen</h4>
+            
+            <p>Guten Tag,</p>
+            
+            <p>Ihre Anfrage auf Zugriff auf die folgenden Grundbücher ist eingegangen:</p>
+            
+            <ul>
+                {gb_list}
+            </ul>
+            
+            <p>Den Bearbeitungsstand können Sie jederzeit über den folgenden Link einsehen.
+            Dort können Sie noch nicht bearbeitete Anfragen auch zurückziehen:</p>
+            <a href=\"{link}\">{link}</a>
+            <br/>
+            
+            <br/>
+
+            <p>Bitte geben Sie diesen Link nicht weiter.</p>
+        </div>
+    </body>
+    </html>");
+
+    let plaintext = format!(
+        "Guten Tag,
+
+Ihre Anfrage auf Zugriff auf die folgenden Grundbücher ist eingegangen:
+
+{gb_list_plain}
+
+Den Bearbeitungsstand können Sie jederzeit über den folgenden Link einsehen.
+Dort können Sie noch nicht bearbeitete Anfragen auch zurückziehen:
+{link}
+
+Bitte geben Sie diesen Link nicht weiter."
+    );
+
+    send_email(konfiguration, to, "Ihre Zugriffsanfrage ist eingegangen", &html, &plaintext)
+}
+
+/// Erinnerung an bald ablaufende Zugriffe, mit Link zum Beantragen der Verlängerung
+pub fn send_zugriff_ablauf_erinnerung_email(
+    konfiguration: &Konfiguration,
+    to: &str,
+    zugriffe: &[crate::db::ZugriffInfo],
+) -> Result<(), String> {
+    let server_url = crate::db::get_server_address(konfiguration, MountPoint::Local)?;
+
+    let eintraege = zugriffe
+        .iter()
+        .map(|z| {
+            let gueltig_bis = z
+                .gueltig_bis
+                .as_deref()
+                .and_then(|d| DateTime::parse_from_rfc3339(d).ok())
+                .map(|d| d.format("%d.%m.%Y").to_string())
+                .unwrap_or_default();
+            let signatur = crate::signatur::signieren(
+                konfiguration,
+                crate::signatur::ZWECK_ZUGRIFF_VERLAENGERN,
+                &[&z.id],
+            )?;
+            let link = format!("{server_url}/zugriff?verlaengern={}&signatur={signatur}", z.id);
+            let text = format!(
+                "Amtsgericht {}, Grundbuch von {} Blatt {} (gültig bis {gueltig_bis})",
+                z.amtsgericht,
+                z.bezirk,
+                z.get_bereich().blatt_text()
+            );
+            Ok((text, link))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    if eintraege.is_empty() {
+        return Ok(());
+    }
+
+    let gb_list_plain = eintraege
+        .iter()
+        .map(|(text, link)| format!("{text}\r\nVerlängerung beantragen: {link}"))
+        .collect::<Vec<_>>()
+        .join("\r\n\r\n");
+
+    let gb_list = eintraege
+        .iter()
+        .map(|(text, link)| format!("<li>{text}<br/><a href=\"{link}\">Verlängerung beantragen</a></li>"))
+        .collect::<Vec<_>>()
+        .join("\r\n");
+
+    let html = format!("<!DOCTYPE html>
+    <html lang=\"de\">
+    <head>
+        <meta charset=\"UTF-8\">
+        <meta name=\"viewport\" content=\"width=device-width, initial-scale=1.0\">
+        <title>Ihr Zugriff auf Grundbücher läuft bald ab</title>
+    </head>
+    <body>
+        <div style=\"width: 800px; margin: 0 auto;\">
+          
+            <h4>Ihr Zugriff auf Grundbücher läuft bald ab</h4>
+            
+            <p>Guten Tag,</p>
+            
+            <p>Ihr Zugriff auf die folgenden Grundbücher ist befristet und läuft bald ab:</p>
+            
+            <ul>
+                {gb_list}
+            </ul>
+            
+            <p>Falls Sie den Zugriff weiterhin benötigen, können Sie über den jeweiligen Link eine Verlängerung beantragen.
+            Die Verlängerung muss erneut genehmigt werden.</p>
+            <p>Benötigen Sie den Zugriff nicht mehr, können Sie diese E-Mail ignorieren.</p>
+        </div>
+    </body>
+    </html>");
+
+    let plaintext = format!(
+        "Guten Tag,
+
+Ihr Zugriff auf die folgenden Grundbücher ist befristet und läuft bald ab:
+
+{gb_list_plain}
+
+Falls Sie den Zugriff weiterhin benötigen, können Sie über den jeweiligen Link eine Verlängerung beantragen.
+Die Verlängerung muss erneut genehmigt werden.
+Benötigen Sie den Zugriff nicht mehr, können Sie diese E-Mail ignorieren."
+    );
+
+    send_email(
+        konfiguration,
+        to,
+        "Ihr Zugriff auf Grundbücher läuft bald ab",
+        &html,
+        &plaintext,
+    )
+}
+
+/// Sendet Erinnerungen für Zugriffe, die innerhalb der nächsten `tage` Tage
+/// ablaufen (eine E-Mail pro Empfänger) und gibt die Anzahl der E-Mails zurück
+pub fn zugriff_erinnerungen_senden(
+    konfiguration: &Konfiguration,
+    mount_point: MountPoint,
+    tage: u32,
+) -> Result<usize, String> {
+    use std::collections::BTreeMap;
+
+    if tage == 0 {
+        return Ok(0);
+    }
+
+    let jetzt = Utc::now();
+    let bis = jetzt
+        .checked_add_signed(chrono::Duration::days(tage as i64))
+        .unwrap_or(jetzt);
+
+    let mut nach_email = BTreeMap::<String, Vec<crate::db::ZugriffInfo>>::new();
+    for z in crate::db::get_ablaufende_zugriffe(konfiguration, mount_point, &jetzt.to_rfc3339(), &bis.to_rfc3339())? {
+        nach_email.entry(z.email.clone()).or_default().push(z);
+    }
+
+    let mut gesendet = 0;
+    for (email, zugriffe) in nach_email.iter() {
+        if let Err(e) = send_zugriff_ablauf_erinnerung_email(konfiguration, email, zugriffe) {
+            println!("Fehler beim Senden der Zugriffs-Erinnerung an {email}: {e}");
+            continue;
+        }
+        let ids = zugriffe.iter().map(|z| z.id.clone()).collect::<Vec<_>>();
+        crate::db::zugriff_erinnerung_gesendet(konfiguration, mount_point, &ids, &jetzt.to_rfc3339())?;
+        gesendet += 1;
+    }
+
+    Ok(gesendet)
+}
+
+pub fn send_zugriff_abgelehnt_email(
+    konfiguration: &Konfiguration,
+    to: &str,
+    // (Land, Amtsgericht, Bezirk, Blatt)
+    grundbuecher: &[(String, String, String, String)],
+    grund: Option<&str>,
+) -> Result<(), String> {
+    let server_url = crate::db::get_server_address(konfiguration, MountPoint::Local)?;
+
+    let gb_list_plain = grundbuecher
+        .iter()
+        .map(|(_, a, g, b)| format!("Amtsgericht {a}, Grundbuch von {g} Blatt {b}"))
+        .collect::<Vec<_>>()
+        .join("\r\n");
+
+    let gb_list = grundbuecher
+        .iter()
+        .map(|(_, a, g, b)| format!("<li>Amtsgericht {a}, Grundbuch von {g} Blatt {b}</li>"))
+        .collect::<Vec<_>>()
+        .join("\r\n");
+
+    let grund = grund.map(|s| s.trim()).filter(|s| !s.is_empty());
+    let grund_html = grund
+        .map(|g| format!("<p>Begründung:</p>\r\n            <p>{}</p>", html_escape(g)))
+        .unwrap_or_default();
+    let grund_plain = grund
+        .map(|g| format!("Begründung:\r\n{g}\r\n"))
+        .unwrap_or_default();
+
+    let html = format!("<!DOCTYPE html>
+    <html lang=\"de\">
+    <head>
+        <meta charset=\"UTF-8\">
+        <meta name=\"viewport\" content=\"width=device-width, initial-scale=1.0\">
+        <title>Ihre Zugriffsanfrage wurde abgelehnt</title>
+    </head>
+    <body>
+        <div style=\"width: 800px; margin: 0 auto;\">
+          
+            <h4>Ihre Zugriffsanfrage wurde abgelehnt</h4>
+            
+            <p>Guten Tag,</p>
+            
+            <p>Ihre Anfrage auf Zugriff auf die folgenden Grundbücher wurde abgelehnt:</p>
+            
+            <ul>
+                {gb_list}
+            </ul>
+            
+            {grund_html}
+
+            <p>Sie können unter <a href=\"{server_url}/zugriff\">{server_url}/zugriff</a> eine neue Anfrage stellen.</p>
+        </div>
+    </body>
+    </html>");
+
+    let plaintext = format!(
+        "Guten Tag,
+
+Ihre Anfrage auf Zugriff auf die folgenden Grundbücher wurde abgelehnt:
+
+{gb_list_plain}
+
+{grund_plain}
+Sie können unter {server_url}/zugriff eine neue Anfrage stellen."
+    );
+
+    send_email(konfiguration, to, "Ihre Zugriffsanfrage wurde abgelehnt", &html, &plaintext)
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Wert der Einstellung `email.zugriff.benachrichtigung` (Standard: `sofort`)
+pub fn get_zugriff_benachrichtigung(konfiguration: &Konfiguration) -> String {
+    crate::db::get_globale_einstellungen(konfiguration, MountPoint::Local)
+        .unwrap_or_default()
+        .into_values()
+        .find(|(k, _)| k == ZUGRIFF_BENACHRICHTIGUNG_EINSTELLUNG)
+        .map(|(_, v)| v.trim().to_lowercase())
+        .unwrap_or_else(|| "sofort".to_string())
+}
+
+/// Benachrichtigt alle Benutzer mit der Berechtigung `zugriffe-verwalten` über
+/// neue Zugriffsanfragen. Fehler beim Senden an einzelne Empfänger werden nur
+/// protokolliert.
+pub fn send_zugriff_anfragen_email(
+    konfiguration: &Konfiguration,
+    zugriffe: &[crate::db::ZugriffInfo],
+    zusammenfassung: bool,
+) -> Result<(), String> {
+    if zugriffe.is_empty() {
+        return Ok(());
+    }
+
+    let server_url = crate::db::get_server_address(konfiguration, MountPoint::Local)?;
+    let empfaenger = crate::rollen::get_benutzer_mit_berechtigung(
+        konfiguration,
+        crate::rollen::Berechtigung::ZugriffeVerwalten,
+    )?;
+
+    let betreff = if zusammenfassung {
+        format!("Zusammenfassung: {} offene Zugriffsanfrage(n)", zugriffe.len())
+    } else {
+        format!("Neue Zugriffsanfrage von {}", zugriffe[0].name)
+    };
+
+    let eintraege = zugriffe
+        .iter()
+        .map(|z| {
+            let typ = z.typ.replace('"', "");
+            let grund = z.grund.clone().unwrap_or_default();
+            format!(
+                "{} <{}> ({typ}): Amtsgericht {}, Grundbuch von {} Blatt {}{}",
+                z.name,
+                z.email,
+                z.amtsgericht,
+                z.bezirk,
+                z.get_bereich().blatt_text(),
+                if grund.trim().is_empty() { String::new() } else { format!(", Grund: {}", grund.trim()) },
+            )
+        })
+        .collect::<Vec<_>>();
+
+    let gb_list_plain = eintraege.join("\r\n");
+    let gb_list = eintraege
+        .iter()
+        .map(|e| format!("<li>{}</li>", html_escape(e)))
+        .collect::<Vec<_>>()
+        .join("\r\n");
+
+    let einleitung = if zusammenfassung {
+        "seit der letzten Zusammenfassung sind die folgenden Zugriffsanfragen eingegangen:"
+    } else {
+        "die folgenden Zugriffsanfragen sind eingegangen:"
+    };
+
+    // Name des Anfragenden stammt aus dem öffentlichen Formular
+    let betreff_html = html_escape(&betreff);
+
+    let html = format!("<!DOCTYPE html>
+    <html lang=\"de\">
+    <head>
+        <meta charset=\"UTF-8\">
+        <meta name=\"viewport\" content=\"width=device-width, initial-scale=1.0\">
+        <title>{betreff_html}</title>
+    </head>
+    <body>
+        <div style=\"width: 800px; margin: 0 auto;\">
+          
+            <h4>{betreff_html}</h4>
+            
+            <p>Guten Tag,</p>
+            
+            <p>{einleitung}</p>
+            
+            <ul>
+                {gb_list}
+            </ul>
+            
+            <p>Anfragen genehmigen oder ablehnen: <a href=\"{server_url}/konto\">{server_url}/konto</a> (Bereich \"Zugriffe\")</p>
+            <br/>
+
+            <p>Die Benachrichtigung kann in den Einstellungen über \"{ZUGRIFF_BENACHRICHTIGUNG_EINSTELLUNG}\" (sofort, taeglich, aus) angepasst werden.</p>
+        </div>
+    </body>
+    </html>");
+
+    let plaintext = format!(
+        "Guten Tag,
+
+{einleitung}
+
+{gb_list_plain}
+
+Anfragen genehmigen oder ablehnen: {server_url}/konto (Bereich \"Zugriffe\")
+
+Die Benachrichtigung kann in den Einstellungen über \"{ZUGRIFF_BENACHRICHTIGUNG_EINSTELLUNG}\" (sofort, taeglich, aus) angepasst werden."
+    );
+
+    for (_, email) in empfaenger.iter() {
+        if let Err(e) = send_email(konfiguration, email, &betreff, &html, &plaintext) {
+            println!("Fehler beim Senden der Zugriffs-Benachrichtigung an {email}: {e}");
+        }
+    }
 
     Ok(())
 }
 
-pub fn send_change_email(abo: &AbonnementInfo, commit_id: &str) -> Result<(), String> {
+/// Sendet bei Einstellung `taeglich` höchstens einmal pro Tag eine Zusammenfassung
+/// aller neuen Zugriffsanfragen und gibt die Anzahl der enthaltenen Anfragen zurück
+pub fn zugriff_zusammenfassung_senden(
+    konfiguration: &Konfiguration,
+    mount_point: MountPoint,
+) -> Result<usize, String> {
+    if get_zugriff_benachrichtigung(konfiguration) != "taeglich" {
+        return Ok(0);
+    }
+
+    let jetzt = Utc::now();
+    let letzte = crate::db::get_letzte_admin_benachrichtigung(konfiguration, mount_point)?
+        .and_then(|d| DateTime::parse_from_rfc3339(&d).ok());
+    if let Some(letzte) = letzte {
+        if jetzt.signed_duration_since(letzte) < chrono::Duration::days(1) {
+            return Ok(0);
+        }
+    }
+
+    let zugriffe = crate::db::get_unbenachrichtigte_zugriff_anfragen(konfiguration, mount_point)?;
+    if zugriffe.is_empty() {
+        return Ok(0);
+    }
+
+    send_zugriff_anfragen_email(konfiguration, &zugriffe, true)?;
+
+    let ids = zugriffe.iter().map(|z| z.id.clone()).collect::<Vec<_>>();
+    let conn = crate::db::verbindung_oeffnen(konfiguration, mount_point)
+        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+    crate::db::zugriffe_admin_benachrichtigt(&conn, &ids, &jetzt.to_rfc3339())?;
+
+    Ok(zugriffe.len())
+}
+
+pub fn send_change_email(
+    konfiguration: &Konfiguration,
+    abo: &AbonnementInfo,
+    commit_id: &str,
+    details: Option<&AenderungsDetails>,
+) -> Result<(), String> {
     let AbonnementInfo {
         id,
         amtsgericht,
@@ -203,6 +822,7 @@ pub fn send_change_email(abo: &AbonnementInfo, commit_id: &str) -> Result<(), St
         text,
         grundbuchbezirk,
         aktenzeichen,
+        ..
     } = abo;
 
     let blatt = match blatt {
@@ -212,7 +832,9 @@ pub fn send_change_email(abo: &AbonnementInfo, commit_id: &str) -> Result<(), St
     let aktenzeichen = aktenzeichen.clone().unwrap_or_default(); // TODO
     let email = text;
     let email_url = urlencoding::encode(text);
-    let server_url = crate::db::get_server_address(MountPoint::Local)?;
+    let server_url = crate::db::get_server_address(konfiguration, MountPoint::Local)?;
+    let details_html = details.map(get_details_html).unwrap_or_default();
+    let details_plain = details.map(get_details_plaintext).unwrap_or_default();
 
     let html = format!("<!DOCTYPE html>
     <html lang=\"de\">
@@ -234,7 +856,7 @@ pub fn send_change_email(abo: &AbonnementInfo, commit_id: &str) -> Result<(), St
                 <li>Amtsgericht {amtsgericht}, Bezirk {grundbuchbezirk}, Blatt {blatt}</li>
                 <li>Ihr Zeichen: {aktenzeichen}</li>
             </ul>
-            
+            {details_html}
             <p>Um die volle Grundbuchänderung in PDF-Form einzusehen, folgen Sie bitten dem folgenden Link:</p>
             <a href=\"{server_url}/aenderung/pdf/{commit_id}\">{server_url}/aenderung/pdf/{commit_id}</a>
             <br/>
@@ -251,7 +873,7 @@ in den folgenden Grundbuchblättern sind Änderungen vorgenommen worden:
 
 Amtsgericht {amtsgericht}, Bezirk {grundbuchbezirk}, Blatt {blatt}
 Ihr Zeichen: {aktenzeichen}
-
+{details_plain}
 Um die volle Grundbuchänderung in PDF-Form einzusehen, folgen Sie bitten dem folgenden Link:
 {server_url}/aenderung/pdf/{commit_id}?email={email_url}
 
@@ -266,6 +888,7 @@ Um das Abonnement zu kündigen, klicken Sie bitte hier:
     let to = email;
 
     send_email(
+        konfiguration,
         to,
         &format!(
             "Grundbuchänderung in {grundbuchbezirk} Blatt {blatt} (Aktenzeichen {aktenzeichen})"
@@ -277,8 +900,218 @@ Um das Abonnement zu kündigen, klicken Sie bitte hier:
     Ok(())
 }
 
-pub async fn send_change_webhook(abo: &AbonnementInfo, commit_id: &str) -> Result<(), String> {
-    let server_url = crate::db::get_server_address(MountPoint::Local)?;
+/// Fasst mehrere Grundbuchänderungen in einer E-Mail zusammen, gruppiert nach
+/// Aktenzeichen und Blatt
+pub fn send_abo_zusammenfassung_email(
+    konfiguration: &Konfiguration,
+    to: &str,
+    benachrichtigung: AboBenachrichtigung,
+    // (Abonnement, Änderungs-ID, Details)
+    aenderungen: &[(AbonnementInfo, String, Option<AenderungsDetails>)],
+) -> Result<(), String> {
+    if aenderungen.is_empty() {
+        return Ok(());
+    }
+
+    let server_url = crate::db::get_server_address(konfiguration, MountPoint::Local)?;
+    let email_url = urlencoding::encode(to);
+
+    let titel = match benachrichtigung {
+        AboBenachrichtigung::Taeglich => "Tägliche Zusammenfassung",
+        AboBenachrichtigung::Woechentlich => "Wöchentliche Zusammenfassung",
+        AboBenachrichtigung::Sofort => "Zusammenfassung",
+    };
+
+    // (Aktenzeichen, Blatt) => (Abo-IDs, Änderungs-ID => Details)
+    let mut gruppen = BTreeMap::<
+        (String, String),
+        (BTreeSet<String>, BTreeMap<String, Option<AenderungsDetails>>),
+    >::new();
+    for (abo, aenderungs_id, details) in aenderungen.iter() {
+        let blatt = format!(
+            "Amtsgericht {}, Bezirk {}, Blatt {}",
+            abo.amtsgericht,
+            abo.grundbuchbezirk,
+            abo.blatt.to_string()
+        );
+        let gruppe = gruppen
+            .entry((abo.aktenzeichen.clone().unwrap_or_default(), blatt))
+            .or_default();
+        gruppe.0.insert(abo.id.clone());
+        gruppe.1.insert(aenderungs_id.clone(), details.clone());
+    }
+
+    let anzahl = gruppen.values().map(|(_, a)| a.len()).sum::<usize>();
+    let betreff = format!("{titel}: {anzahl} Grundbuchänderung(en)");
+
+    let mut liste_html = String::new();
+    let mut liste_plain = String::new();
+    for ((aktenzeichen, blatt), (abo_ids, aenderungen)) in gruppen.iter() {
+        let zeichen = if aktenzeichen.trim().is_empty() {
+            "ohne Aktenzeichen".to_string()
+        } else {
+            format!("Ihr Zeichen: {}", aktenzeichen.trim())
+        };
+
+        liste_html.push_str(&format!(
+            "<h5>{} ({})</h5>\r\n            <ul>\r\n",
+            html_escape(blatt),
+            html_escape(&zeichen)
+        ));
+        liste_plain.push_str(&format!("\n{blatt} ({zeichen})\n"));
+
+        for (commit_id, details) in aenderungen.iter() {
+            liste_html.push_str(&format!(
+                "                <li><a href=\"{server_url}/aenderung/pdf/{commit_id}\">Änderung {commit_id}</a>{}</li>\r\n",
+                details.as_ref().map(get_details_html).unwrap_or_default()
+            ));
+            liste_plain.push_str(&format!(
+                "- Änderung {commit_id}: {server_url}/aenderung/pdf/{commit_id}?email={email_url}\n{}",
+                details.as_ref().map(get_details_plaintext).unwrap_or_default()
+            ));
+        }
+
+        liste_html.push_str("            </ul>\r\n");
+        for id in abo_ids.iter() {
+            liste_html.push_str(&format!(
+                "            <p>Abonnement kündigen: <a href=\"{server_url}/abo-loeschen/{id}\">{server_url}/abo-loeschen/{id}</a></p>\r\n"
+            ));
+            liste_plain.push_str(&format!("Abonnement kündigen: {server_url}/abo-loeschen/{id}\n"));
+        }
+    }
+
+    let html = format!("<!DOCTYPE html>
+    <html lang=\"de\">
+    <head>
+        <meta charset=\"UTF-8\">
+        <meta name=\"viewport\" content=\"width=device-width, initial-scale=1.0\">
+        <title>{betreff}</title>
+    </head>
+    <body>
+        <div style=\"width: 800px; margin: 0 auto;\">
+          
+            <h4>{betreff}</h4>
+            
+            <p>Guten Tag,</p>
+            
+            <p>in den folgenden Grundbuchblättern sind seit der letzten Benachrichtigung Änderungen vorgenommen worden:</p>
+            
+            {liste_html}
+            <br/>
+            
+            <p>Sie wurden benachrichtigt, da Sie diese Grundbuchblätter abonniert haben.</p>
+            <p>Ob Sie sofort, täglich oder wöchentlich benachrichtigt werden, können Sie in Ihrem Konto unter \"Abonnements\" ändern.</p>
+        </div>
+    </body>
+    </html>");
+
+    let plaintext = format!("Guten Tag,
+
+in den folgenden Grundbuchblättern sind seit der letzten Benachrichtigung Änderungen vorgenommen worden:
+{liste_plain}
+Sie wurden benachrichtigt, da Sie diese Grundbuchblätter abonniert haben.
+Ob Sie sofort, täglich oder wöchentlich benachrichtigt werden, können Sie in Ihrem Konto unter \"Abonnements\" ändern.
+    ");
+
+    send_email(konfiguration, to, &betreff, &html, &plaintext)
+}
+
+/// Änderungen je Abteilung als HTML (für `send_change_email`)
+fn get_details_html(details: &AenderungsDetails) -> String {
+    let mut html = format!(
+        "<p><strong>{}</strong> (von {})</p>\r\n",
+        html_escape(&details.titel),
+        html_escape(&details.autor.name)
+    );
+    for zeile in details.beschreibung.iter() {
+        html.push_str(&format!("            <p>{}</p>\r\n", html_escape(zeile)));
+    }
+    for (abteilung, a) in details.abteilungen.iter() {
+        html.push_str(&format!(
+            "            <p>{}:</p>\r\n            <ul>\r\n",
+            crate::aenderungen::get_abteilung_name(abteilung)
+        ));
+        for e in a.hinzugefuegt.iter() {
+            html.push_str(&format!("                <li>Neu: {}</li>\r\n", html_escape(&e.text)));
+        }
+        for e in a.geaendert.iter() {
+            html.push_str(&format!(
+                "                <li>{}: {}<br/>vorher: {}</li>\r\n",
+                if e.geroetet { "Gerötet" } else { "Geändert" },
+                html_escape(&e.neu),
+                html_escape(&e.alt)
+            ));
+        }
+        for e in a.entfernt.iter() {
+            html.push_str(&format!("                <li>Entfernt: {}</li>\r\n", html_escape(&e.text)));
+        }
+        html.push_str("            </ul>\r\n");
+    }
+    html
+}
+
+/// Änderungen je Abteilung als Text (für `send_change_email`)
+fn get_details_plaintext(details: &AenderungsDetails) -> String {
+    let mut text = format!("\n{} (von {})\n", details.titel, details.autor.name);
+    for zeile in details.beschreibung.iter() {
+        text.push_str(&format!("{zeile}\n"));
+    }
+    for (abteilung, a) in details.abteilungen.iter() {
+        text.push_str(&format!("\n{}:\n", crate::aenderungen::get_abteilung_name(abteilung)));
+        for e in a.hinzugefuegt.iter() {
+            text.push_str(&format!("- Neu: {}\n", e.text));
+        }
+        for e in a.geaendert.iter() {
+            text.push_str(&format!(
+                "- {}: {}\n  vorher: {}\n",
+                if e.geroetet { "Gerötet" } else { "Geändert" },
+                e.neu,
+                e.alt
+            ));
+        }
+        for e in a.entfernt.iter() {
+            text.push_str(&format!("- Entfernt: {}\n", e.text));
+        }
+    }
+    text
+}
+
+pub async fn send_change_webhook(
+    konfiguration: &Konfiguration,
+    abo: &AbonnementInfo,
+    commit_id: &str,
+    zustellungs_id: &str,
+    details: Option<&AenderungsDetails>,
+) -> Result<(), String> {
+    send_webhook(konfiguration, abo, commit_id, WEBHOOK_EREIGNIS_AENDERUNG, zustellungs_id, details).await
+}
+
+/// Sendet eine Testzustellung (nicht über den Postausgang), gibt die
+/// Zustellungs-ID zurück
+pub async fn send_test_webhook(
+    konfiguration: &Konfiguration,
+    abo: &AbonnementInfo,
+) -> Result<String, String> {
+    let zustellungs_id = uuid::Uuid::new_v4().to_string();
+    send_webhook(konfiguration, abo, "", WEBHOOK_EREIGNIS_TEST, &zustellungs_id, None).await?;
+    Ok(zustellungs_id)
+}
+
+async fn send_webhook(
+    konfiguration: &Konfiguration,
+    abo: &AbonnementInfo,
+    commit_id: &str,
+    ereignis: &str,
+    zustellungs_id: &str,
+    details: Option<&AenderungsDetails>,
+) -> Result<(), String> {
+    let server_url = crate::db::get_server_address(konfiguration, MountPoint::Local)?;
+
+    // Secret bei jedem Versuch neu lesen, damit Wiederholungen nach
+    // einer Erneuerung bereits mit dem neuen Secret signiert werden
+    let secret = crate::db::get_abo(konfiguration, &abo.id)?
+        .ok_or_else(|| format!("Webhook-Abonnement {} existiert nicht mehr", abo.id))?
+        .webhook_secret;
 
     let abo_info = AboWebhookInfo {
         server_url: server_url.to_string(),
@@ -288,14 +1121,28 @@ pub async fn send_change_webhook(abo: &AbonnementInfo, commit_id: &str) -> Resul
         webhook: abo.text.clone(),
         aktenzeichen: abo.aktenzeichen.clone(),
         aenderungs_id: commit_id.to_string(),
+        ereignis: ereignis.to_string(),
+        zustellungs_id: zustellungs_id.to_string(),
+        flurstueck: abo.flurstueck.clone(),
+        details: details.cloned(),
     };
 
-    let client = reqwest::Client::new();
-    let _ = client
-        .post(&abo.text)
-        .json(&abo_info)
-        .send()
-        .await
-        .map_err(|e| format!("{e}"))?;
-    Ok(())
+    let body = serde_json::to_vec(&abo_info).map_err(|e| format!("{e}"))?;
+    let zeitstempel = Utc::now().timestamp();
+    let mut header = vec![
+        ("Content-Type", "application/json".to_string()),
+        ("X-DGB-Ereignis", ereignis.to_string()),
+        ("X-DGB-Zustellung", zustellungs_id.to_string()),
+        ("X-DGB-Zeitstempel", zeitstempel.to_string()),
+    ];
+
+    // Vor der Migration auf Version 18 angelegte Webhooks haben kein Secret
+    // und bleiben unsigniert, bis das Secret erneuert wird
+    if let Some(secret) = secret.as_deref() {
+        let signatur = crate::signatur::webhook_signieren(secret, zeitstempel, &body)?;
+        header.push(("X-DGB-Signatur", format!("sha256={signatur}")));
+    }
+
+    // Antworten mit Fehlercode gelten als fehlgeschlagen und werden wiederholt
+    crate::webhook::senden(konfiguration, &abo.text, &header, body).await
 }
@@ -14,7 +14,8 @@
 //! durchsuchen kann.
 
 use crate::{
-    models::{get_data_dir, get_index_dir, PdfFile},
+    config::Konfiguration,
+    models::PdfFile,
     MountPoint,
 };
 use std::path::Path;
@@ -48,11 +49,11 @@ pub fn schema_commits() -> Schema {
     schema_builder.build()
 }
 
-pub fn index_all() -> Result<(), String> {
+pub fn index_all(konfiguration: &Konfiguration) -> Result<(), String> {
     use git2::{ObjectType, Repository, TreeWalkMode, TreeWalkResult};
     use std::collections::BTreeSet;
 
-    let data_path = get_data_dir(MountPoint::Local);
+    let data_path = konfiguration.get_data_dir(MountPoint::Local);
 
     if !Path::new(&data_path).exists() {
         return Ok(()); // nichts zu tun
@@ -70,9 +71,9 @@ pub fn index_all() -> Result<(), String> {
         .map(|rl| (rl.id_new(), rl.id_old()))
         .collect::<Vec<_>>();
 
-    let _ = std::fs::create_dir_all(get_index_dir());
+    let _ = std::fs::create_dir_all(konfiguration.get_index_dir());
     let mut commits =
-        match std::fs::read_to_string(Path::new(&get_index_dir()).join(CACHE_FILE)).ok() {
+        match std::fs::read_to_string(Path::new(&konfiguration.get_index_dir()).join(CACHE_FILE)).ok() {
             None => commits.clone(),
             Some(s) => commits
                 .iter()
@@ -83,10 +84,10 @@ pub fn index_all() -> Result<(), String> {
 
     commits.reverse();
 
-    let data_dir = get_data_dir(MountPoint::Local);
+    let data_dir = konfiguration.get_data_dir(MountPoint::Local);
     let data_dir = Path::new(&data_dir);
 
-    let (grundbuch_schema, grundbuch_index) = get_grundbuch_index()
+    let (grundbuch_schema, grundbuch_index) = get_grundbuch_index(konfiguration)
         .map_err(|e| format!("Fehler in Index / Schema \"grundbuch\": {e}"))?;
 
     let mut index_writer = grundbuch_index
@@ -95,7 +96,7 @@ pub fn index_all() -> Result<(), String> {
 
     let len = commits.len();
 
-    let gemarkungen = crate::db::get_gemarkungen().unwrap_or_default();
+    let gemarkungen = crate::db::get_gemarkungen(konfiguration).unwrap_or_default();
     let mut dateien_indexed = BTreeSet::new();
 
     for (i, (new, _)) in commits.into_iter().enumerate() {
@@ -165,7 +166,7 @@ pub fn index_all() -> Result<(), String> {
             .map_err(|e| format!("Fehler bei index.commit({new}): {e}"))?;
 
         let _ = std::fs::write(
-            Path::new(&get_index_dir()).join(CACHE_FILE),
+            Path::new(&konfiguration.get_index_dir()).join(CACHE_FILE),
             &format!("{new}").as_bytes(),
         );
     }
@@ -175,10 +176,10 @@ pub fn index_all() -> Result<(), String> {
     Ok(())
 }
 
-pub fn get_grundbuch_index() -> Result<(Schema, Index), String> {
+pub fn get_grundbuch_index(konfiguration: &Konfiguration) -> Result<(Schema, Index), String> {
     use tantivy::directory::MmapDirectory;
 
-    let index_dir = get_index_dir();
+    let index_dir = konfiguration.get_index_dir();
     let schema = schema_grundbuch();
 
     let grundbuch_index_dir = Path::new(&index_dir).join("grundbuch");
@@ -191,10 +192,10 @@ pub fn get_grundbuch_index() -> Result<(Schema, Index), String> {
     Ok((schema, index))
 }
 
-pub fn get_commit_index() -> Result<Index, String> {
+pub fn get_commit_index(konfiguration: &Konfiguration) -> Result<Index, String> {
     use tantivy::directory::MmapDirectory;
 
-    let index_dir = get_index_dir();
+    let index_dir = konfiguration.get_index_dir();
     let schema = schema_commits();
 
     let commits_index_dir = Path::new(&index_dir).join("commits");
@@ -214,8 +215,6 @@ pub fn add_grundbuchblatt_zu_index(
     index_writer: &IndexWriter,
     schema: &Schema,
 ) -> Result<(), String> {
-    use crate::models::BvEintrag;
-
     let file_name = format!(
         "{}/{}_{}.gbx",
         pdf.analysiert.titelblatt.amtsgericht,
@@ -261,68 +260,8 @@ pub fn add_grundbuchblatt_zu_index(
 
     let _ = index_writer.delete_term(Term::from_field_text(id, &blatt_id));
 
-    for bv in pdf.analysiert.bestandsverzeichnis.eintraege.iter() {
-        match bv {
-            BvEintrag::Flurstueck(bvf) => {
-                let mut doc = Document::default();
-
-                doc.add_text(id, &blatt_id);
-                doc.add_text(land, land_str);
-                doc.add_text(amtsgericht, &pdf.analysiert.titelblatt.amtsgericht);
-                doc.add_text(grundbuch_von, &pdf.analysiert.titelblatt.grundbuch_von);
-                doc.add_text(blatt, format!("{}", pdf.analysiert.titelblatt.blatt));
-                doc.add_text(abteilung, "bv");
-                doc.add_text(lfd_nr, format!("{}", bvf.lfd_nr));
-                doc.add_text(
-                    text,
-                    format!(
-                        "BV lfd. Nr. {}, Gemarkung {} Flur {} Flurstück {}: {}Größe: {} m²",
-                        bvf.lfd_nr,
-                        bvf.gemarkung
-                            .clone()
-                            .unwrap_or(pdf.analysiert.titelblatt.grundbuch_von.clone()),
-                        bvf.flur,
-                        bvf.flurstueck,
-                        bvf.bezeichnung
-                            .as_ref()
-                            .map(|s| s.text_clean() + ", ")
-                            .unwrap_or_default(),
-                        bvf.groesse.get_m2(),
-                    ),
-                );
-
-                index_writer
-                    .add_document(doc)
-                    .map_err(|e| format!("Konnte Dokument nicht indexieren: {file_name}: {e}"))?;
-            }
-            BvEintrag::Recht(bvr) => {
-                let mut doc = Document::default();
-
-                doc.add_text(id, &blatt_id);
-                doc.add_text(land, land_str);
-                doc.add_text(amtsgericht, &pdf.analysiert.titelblatt.amtsgericht);
-                doc.add_text(grundbuch_von, &pdf.analysiert.titelblatt.grundbuch_von);
-                doc.add_text(blatt, format!("{}", pdf.analysiert.titelblatt.blatt));
-                doc.add_text(abteilung, "bv-herrschvermerke");
-                doc.add_text(lfd_nr, format!("{}", bvr.lfd_nr));
-                doc.add_text(
-                    text,
-                    format!(
-                        "BV lfd. Nr. {} (zu lfd. Nr. {}): {}",
-                        bvr.lfd_nr,
-                        bvr.zu_nr.text_clean(),
-                        bvr.text.text_clean(),
-                    ),
-                );
-
-                index_writer
-                    .add_document(doc)
-                    .map_err(|e| format!("Konnte Dokument nicht indexieren: {file_name}: {e}"))?;
-            }
-        }
-    }
-
-    for bvz in pdf.analysiert.bestandsverzeichnis.zuschreibungen.iter() {
+    // Einträge wie in den Änderungsdetails für Abonnenten, siehe aenderungen.rs
+    for eintrag in crate::aenderungen::get_eintraege(pdf) {
         let mut doc = Document::default();
 
         doc.add_text(id, &blatt_id);
@@ -330,280 +269,9 @@ pub fn add_grundbuchblatt_zu_index(
         doc.add_text(amtsgericht, &pdf.analysiert.titelblatt.amtsgericht);
         doc.add_text(grundbuch_von, &pdf.analysiert.titelblatt.grundbuch_von);
         doc.add_text(blatt, format!("{}", pdf.analysiert.titelblatt.blatt));
-        doc.add_text(abteilung, "bv-zuschreibungen");
-        doc.add_text(lfd_nr, bvz.bv_nr.lines().join(" "));
-        doc.add_text(
-            text,
-            format!(
-                "BV-Zuschreibung zu lfd. Nr. {}: {}",
-                bvz.bv_nr.lines().join(" "),
-                bvz.text.text_clean()
-            ),
-        );
-
-        index_writer
-            .add_document(doc)
-            .map_err(|e| format!("Konnte Dokument nicht indexieren: {file_name}: {e}"))?;
-    }
-
-    for bva in pdf.analysiert.bestandsverzeichnis.abschreibungen.iter() {
-        let mut doc = Document::default();
-
-        doc.add_text(id, &blatt_id);
-        doc.add_text(land, land_str);
-        doc.add_text(amtsgericht, &pdf.analysiert.titelblatt.amtsgericht);
-        doc.add_text(grundbuch_von, &pdf.analysiert.titelblatt.grundbuch_von);
-        doc.add_text(blatt, format!("{}", pdf.analysiert.titelblatt.blatt));
-        doc.add_text(abteilung, "bv-abschreibungen");
-        doc.add_text(lfd_nr, bva.bv_nr.lines().join(" "));
-        doc.add_text(
-            text,
-            format!(
-                "BV-Abschreibung zu lfd. Nr. {}: {}",
-                bva.bv_nr.lines().join(" "),
-                bva.text.text_clean()
-            ),
-        );
-
-        index_writer
-            .add_document(doc)
-            .map_err(|e| format!("Konnte Dokument nicht indexieren: {file_name}: {e}"))?;
-    }
-
-    for abt1 in pdf.analysiert.abt1.eintraege.iter() {
-        let mut doc = Document::default();
-
-        doc.add_text(id, &blatt_id);
-        doc.add_text(land, land_str);
-        doc.add_text(amtsgericht, &pdf.analysiert.titelblatt.amtsgericht);
-        doc.add_text(grundbuch_von, &pdf.analysiert.titelblatt.grundbuch_von);
-        doc.add_text(blatt, format!("{}", pdf.analysiert.titelblatt.blatt));
-        doc.add_text(abteilung, "abt1");
-        doc.add_text(lfd_nr, format!("{}", abt1.get_lfd_nr()));
-        doc.add_text(
-            text,
-            format!(
-                "Abteilung 1, lfd. Nr. {}: {}",
-                abt1.get_lfd_nr(),
-                abt1.get_eigentuemer().text_clean()
-            ),
-        );
-
-        index_writer
-            .add_document(doc)
-            .map_err(|e| format!("Konnte Dokument nicht indexieren: {file_name}: {e}"))?;
-    }
-
-    for abt1 in pdf.analysiert.abt1.grundlagen_eintragungen.iter() {
-        let mut doc = Document::default();
-
-        doc.add_text(id, &blatt_id);
-        doc.add_text(land, land_str);
-        doc.add_text(amtsgericht, &pdf.analysiert.titelblatt.amtsgericht);
-        doc.add_text(grundbuch_von, &pdf.analysiert.titelblatt.grundbuch_von);
-        doc.add_text(blatt, format!("{}", pdf.analysiert.titelblatt.blatt));
-        doc.add_text(abteilung, "abt1-grundlagen-eintragungen");
-        doc.add_text(lfd_nr, format!("{}", abt1.bv_nr.lines().join(" ")));
-        doc.add_text(
-            text,
-            format!(
-                "Abteilung 1, lfd. Nr. {}: {}",
-                abt1.bv_nr.lines().join(" "),
-                abt1.text.text_clean()
-            ),
-        );
-
-        index_writer
-            .add_document(doc)
-            .map_err(|e| format!("Konnte Dokument nicht indexieren: {file_name}: {e}"))?;
-    }
-
-    for bvz in pdf.analysiert.abt1.veraenderungen.iter() {
-        let mut doc = Document::default();
-
-        doc.add_text(id, &blatt_id);
-        doc.add_text(land, land_str);
-        doc.add_text(amtsgericht, &pdf.analysiert.titelblatt.amtsgericht);
-        doc.add_text(grundbuch_von, &pdf.analysiert.titelblatt.grundbuch_von);
-        doc.add_text(blatt, format!("{}", pdf.analysiert.titelblatt.blatt));
-        doc.add_text(abteilung, "abt1-veraenderungen");
-        doc.add_text(lfd_nr, bvz.lfd_nr.lines().join(" "));
-        doc.add_text(
-            text,
-            format!(
-                "Abteilung 1 Veränderung von lfd. Nr. {}: {}",
-                bvz.lfd_nr.lines().join(" "),
-                bvz.text.text_clean()
-            ),
-        );
-
-        index_writer
-            .add_document(doc)
-            .map_err(|e| format!("Konnte Dokument nicht indexieren: {file_name}: {e}"))?;
-    }
-
-    for bva in pdf.analysiert.abt1.loeschungen.iter() {
-        let mut doc = Document::default();
-
-        doc.add_text(id, &blatt_id);
-        doc.add_text(land, land_str);
-        doc.add_text(amtsgericht, &pdf.analysiert.titelblatt.amtsgericht);
-        doc.add_text(grundbuch_von, &pdf.analysiert.titelblatt.grundbuch_von);
-        doc.add_text(blatt, format!("{}", pdf.analysiert.titelblatt.blatt));
-        doc.add_text(abteilung, "abt1-loeschungen");
-        doc.add_text(lfd_nr, bva.lfd_nr.lines().join(" "));
-        doc.add_text(
-            text,
-            format!(
-                "Abteilung 1 Löschung von lfd. Nr. {}: {}",
-                bva.lfd_nr.lines().join(" "),
-                bva.text.text_clean()
-            ),
-        );
-
-        index_writer
-            .add_document(doc)
-            .map_err(|e| format!("Konnte Dokument nicht indexieren: {file_name}: {e}"))?;
-    }
-
-    for abt2 in pdf.analysiert.abt2.eintraege.iter() {
-        let mut doc = Document::default();
-
-        doc.add_text(id, &blatt_id);
-        doc.add_text(land, land_str);
-        doc.add_text(amtsgericht, &pdf.analysiert.titelblatt.amtsgericht);
-        doc.add_text(grundbuch_von, &pdf.analysiert.titelblatt.grundbuch_von);
-        doc.add_text(blatt, format!("{}", pdf.analysiert.titelblatt.blatt));
-        doc.add_text(abteilung, "abt2");
-        doc.add_text(lfd_nr, format!("{}", abt2.lfd_nr));
-        doc.add_text(
-            text,
-            format!(
-                "Abteilung 2, lfd. Nr. {}: {}",
-                abt2.lfd_nr,
-                abt2.text.text_clean()
-            ),
-        );
-
-        index_writer
-            .add_document(doc)
-            .map_err(|e| format!("Konnte Dokument nicht indexieren: {file_name}: {e}"))?;
-    }
-
-    for bvz in pdf.analysiert.abt2.veraenderungen.iter() {
-        let mut doc = Document::default();
-
-        doc.add_text(id, &blatt_id);
-        doc.add_text(land, land_str);
-        doc.add_text(amtsgericht, &pdf.analysiert.titelblatt.amtsgericht);
-        doc.add_text(grundbuch_von, &pdf.analysiert.titelblatt.grundbuch_von);
-        doc.add_text(blatt, format!("{}", pdf.analysiert.titelblatt.blatt));
-        doc.add_text(abteilung, "abt2-veraenderungen");
-        doc.add_text(lfd_nr, bvz.lfd_nr.lines().join(" "));
-        doc.add_text(
-            text,
-            format!(
-                "Abteilung 2 Veränderung von lfd. Nr. {}: {}",
-                bvz.lfd_nr.lines().join(" "),
-                bvz.text.text_clean()
-            ),
-        );
-
-        index_writer
-            .add_document(doc)
-            .map_err(|e| format!("Konnte Dokument nicht indexieren: {file_name}: {e}"))?;
-    }
-
-    for bva in pdf.analysiert.abt2.loeschungen.iter() {
-        let mut doc = Document::default();
-
-        doc.add_text(id, &blatt_id);
-        doc.add_text(land, land_str);
-        doc.add_text(amtsgericht, &pdf.analysiert.titelblatt.amtsgericht);
-        doc.add_text(grundbuch_von, &pdf.analysiert.titelblatt.grundbuch_von);
-        doc.add_text(blatt, format!("{}", pdf.analysiert.titelblatt.blatt));
-        doc.add_text(abteilung, "abt2-loeschungen");
-        doc.add_text(lfd_nr, bva.lfd_nr.lines().join(" "));
-        doc.add_text(
-            text,
-            format!(
-                "Abteilung 2 Löschung von lfd. Nr. {}: {}",
-                bva.lfd_nr.lines().join(" "),
-                bva.text.text_clean()
-            ),
-        );
-
-        index_writer
-            .add_document(doc)
-            .map_err(|e| format!("Konnte Dokument nicht indexieren: {file_name}: {e}"))?;
-    }
-
-    for abt3 in pdf.analysiert.abt3.eintraege.iter() {
-        let mut doc = Document::default();
-
-        doc.add_text(id, &blatt_id);
-        doc.add_text(land, land_str);
-        doc.add_text(amtsgericht, &pdf.analysiert.titelblatt.amtsgericht);
-        doc.add_text(grundbuch_von, &pdf.analysiert.titelblatt.grundbuch_von);
-        doc.add_text(blatt, format!("{}", pdf.analysiert.titelblatt.blatt));
-        doc.add_text(abteilung, "abt3");
-        doc.add_text(lfd_nr, format!("{}", abt3.lfd_nr));
-        doc.add_text(
-            text,
-            format!(
-                "Abteilung 3, lfd. Nr. {}: {}",
-                abt3.lfd_nr,
-                abt3.text.text_clean()
-            ),
-        );
-
-        index_writer
-            .add_document(doc)
-            .map_err(|e| format!("Konnte Dokument nicht indexieren: {file_name}: {e}"))?;
-    }
-
-    for bvz in pdf.analysiert.abt3.veraenderungen.iter() {
-        let mut doc = Document::default();
-
-        doc.add_text(id, &blatt_id);
-        doc.add_text(land, land_str);
-        doc.add_text(amtsgericht, &pdf.analysiert.titelblatt.amtsgericht);
-        doc.add_text(grundbuch_von, &pdf.analysiert.titelblatt.grundbuch_von);
-        doc.add_text(blatt, format!("{}", pdf.analysiert.titelblatt.blatt));
-        doc.add_text(abteilung, "abt3-veraenderungen");
-        doc.add_text(lfd_nr, bvz.lfd_nr.lines().join(" "));
-        doc.add_text(
-            text,
-            format!(
-                "Abteilung 3 Veränderung von lfd. Nr. {}: {}",
-                bvz.lfd_nr.lines().join(" "),
-                bvz.text.text_clean()
-            ),
-        );
-
-        index_writer
-            .add_document(doc)
-            .map_err(|e| format!("Konnte Dokument nicht indexieren: {file_name}: {e}"))?;
-    }
-
-    for bva in pdf.analysiert.abt3.loeschungen.iter() {
-        let mut doc = Document::default();
-
-        doc.add_text(id, &blatt_id);
-        doc.add_text(land, land_str);
-        doc.add_text(amtsgericht, &pdf.analysiert.titelblatt.amtsgericht);
-        doc.add_text(grundbuch_von, &pdf.analysiert.titelblatt.grundbuch_von);
-        doc.add_text(blatt, format!("{}", pdf.analysiert.titelblatt.blatt));
-        doc.add_text(abteilung, "abt3-loeschungen");
-        doc.add_text(lfd_nr, bva.lfd_nr.lines().join(" "));
-        doc.add_text(
-            text,
-            format!(
-                "Abteilung 3 Löschung von lfd. Nr. {}: {}",
-                bva.lfd_nr.lines().join(" "),
-                bva.text.text_clean()
-            ),
-        );
+        doc.add_text(abteilung, &eintrag.abteilung);
+        doc.add_text(lfd_nr, &eintrag.lfd_nr);
+        doc.add_text(text, &eintrag.text);
 
         index_writer
             .add_document(doc)
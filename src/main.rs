@@ -31,7 +31,8 @@
 //!
 //! - `benutzer-neu`: Legt einen neuen Benutzer an, wichtig sind hierbei die `--rechte`: `"gast"` kann nur lesen,
 //!   braucht aber keinen privaten Schlüssel dafür (nur E-Mail und Passwort). `"bearbeiter"` dagegen können, sowohl
-//!   lesen als auch Daten bearbeiten und bekommen hierfür eine Schlüsseldatei.
+//!   lesen als auch Daten bearbeiten und bekommen hierfür eine Schlüsseldatei. `--rechte` ist der Name einer
+//!   Rolle aus der Tabelle `rollen` (`admin`, `bearbeiter`, `mitarbeiter`, `gast` oder selbst angelegt).
 //!
 //! - `benutzer-loeschen`: Löscht einen Benutzer (Zugangsdaten werden vernichtet).
 //!
@@ -45,29 +46,52 @@
 //!
 //! - `abo-loeschen`: Löscht das angegebene Abonnement.
 //!
+//! - `backup --out backup.tar.zst`: Sichert Datenbank, Git-Repository und Suchindex inkl.
+//!   Manifest mit Prüfsummen, optional verschlüsselt (`--empfaenger schluessel.asc`).
+//!
+//! - `restore --datei backup.tar.zst`: Prüft und stellt eine Sicherung wieder her
+//!   (Server vorher stoppen, `--nur-pruefen` kontrolliert nur die Prüfsummen).
+//!
+//! - `db-migrate`: Führt ausstehende Migrationen der Benutzer-Datenbank aus
+//!   (`--dry-run` zeigt sie nur an). Der Sync-Server migriert beim Start automatisch.
+//!
+use crate::config::{Konfiguration, KonfigurationArgs};
 use crate::k8s::k8s_get_acme_config;
 use crate::{db::GpgPublicKeyPair, models::MountPoint};
 use actix_web::{web::JsonConfig, App, HttpServer};
 use clap::Parser;
 use futures::StreamExt;
-use models::get_data_dir;
 use rustls_acme::caches::DirCache;
 use rustls_acme::AcmeConfig;
 use serde_derive::{Deserialize, Serialize};
 use std::path::PathBuf;
 use std::sync::{Arc, Mutex};
 
+pub mod aenderungen;
 pub mod api;
+pub mod api_schluessel;
+pub mod audit;
+pub mod backup;
 pub mod cli;
+pub mod config;
 pub mod db;
+pub mod einsicht;
 pub mod email;
 pub mod index;
 pub mod k8s;
+pub mod migration;
 pub mod models;
+pub mod oidc;
 pub mod pdf;
 pub mod pgp;
+pub mod postausgang;
+pub mod ratenbegrenzung;
+pub mod rollen;
+pub mod signatur;
 pub mod suche;
+pub mod webhook;
 pub mod zip;
+pub mod zwei_faktor;
 
 #[derive(Debug, Clone)]
 pub struct AppState {
@@ -79,24 +103,27 @@ impl AppState {
         self.data
             .lock()
             .ok()
-            .map(|l| l.sync_server)
+            .map(|l| l.konfiguration.sync_modus)
             .unwrap_or(false)
     }
     pub fn k8s_aktiv(&self) -> bool {
         self.data.lock().ok().map(|l| l.k8s_aktiv).unwrap_or(false)
     }
+    pub fn konfiguration(&self) -> Arc<Konfiguration> {
+        match self.data.lock() {
+            Ok(l) => l.konfiguration.clone(),
+            Err(e) => e.into_inner().konfiguration.clone(),
+        }
+    }
 }
 
 /// Server-interne Konfiguration, geladen beim Server-Start
 #[derive(Debug, Clone, PartialEq)]
 pub struct AppStateData {
-    /// Ob dieser Server im Sync-Modus läuft (und daher
-    /// Schreibrechte auf /mnt/data/files hat) oder nur Lesezugriff
-    pub sync_server: bool,
     /// Ob der Server im Kubernetes-Cluster läuft
     pub k8s_aktiv: bool,
-    /// Mount des k8s-PersistenVolume zum Synchronisieren zwischen Servern
-    pub remote_mount: String,
+    /// Konfiguration (Pfade, Ports, Sync-Modus, ACME), siehe `config.rs`
+    pub konfiguration: Arc<Konfiguration>,
 }
 
 /// Server für .gbx-Dateien, läuft auf 127.0.0.1:8080
@@ -106,6 +133,9 @@ struct Args {
     /// Befehl zum Bearbeiten der Datenbark (kein Befehl = Server startet)
     #[clap(subcommand)]
     action: ArgAction,
+
+    #[clap(flatten)]
+    konfiguration: KonfigurationArgs,
 }
 
 #[derive(clap::Subcommand, Clone, Debug, PartialEq)]
@@ -115,7 +145,7 @@ pub enum ArgAction {
         /// IP-Adresse, die der Server verwenden soll
         #[clap(long, default_value = "127.0.0.1")]
         ip: String,
-        /// Port des Servers (default 8080)
+        /// Port des Servers (default: `ports.http` aus der Konfiguration)
         #[clap(long)]
         port: Option<u16>,
     },
@@ -125,6 +155,12 @@ pub enum ArgAction {
     SyncDb,
     /// Git-Repository von Sync-Server lesen
     Sync,
+    /// Ausstehende Migrationen der Benutzer-Datenbank ausführen (--dry-run)
+    DbMigrate(DbMigrateArgs),
+    /// Sicherung von Datenbank, Git-Repository und Index erstellen (--out, --empfaenger)
+    Backup(BackupArgs),
+    /// Sicherung wiederherstellen (--datei, --schluessel, --nur-pruefen)
+    Restore(RestoreArgs),
     /// Suche nach Suchbegriff in momentan vorhandenem Index
     Suche { begriff: String },
     /// Neuen GPG-Schluessel generieren (--name, --email, --dir)
@@ -145,6 +181,39 @@ pub enum ArgAction {
     AboLoeschen(AboLoeschenArgs),
 }
 
+#[derive(clap::Parser, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[clap(author, version, about, long_about = None)]
+pub struct DbMigrateArgs {
+    /// Nur ausstehende Migrationen anzeigen, ohne die Datenbank zu verändern
+    #[clap(long)]
+    pub dry_run: bool,
+}
+
+#[derive(clap::Parser, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[clap(author, version, about, long_about = None)]
+pub struct BackupArgs {
+    /// Ausgabedatei (z.B. backup.tar.zst)
+    #[clap(short, long)]
+    pub out: PathBuf,
+    /// Öffentlicher PGP-Schlüssel, für den das Backup verschlüsselt werden soll
+    #[clap(short, long)]
+    pub empfaenger: Option<PathBuf>,
+}
+
+#[derive(clap::Parser, Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[clap(author, version, about, long_about = None)]
+pub struct RestoreArgs {
+    /// Backup-Datei (.tar.zst, ggf. verschlüsselt)
+    #[clap(short, long)]
+    pub datei: PathBuf,
+    /// Privater PGP-Schlüssel zum Entschlüsseln
+    #[clap(short, long)]
+    pub schluessel: Option<PathBuf>,
+    /// Nur Prüfsummen kontrollieren, nichts ersetzen
+    #[clap(long)]
+    pub nur_pruefen: bool,
+}
+
 #[derive(clap::Parser, Debug, Clone, PartialEq, Serialize, Deserialize)]
 #[clap(author, version, about, long_about = None)]
 pub struct SchluesselNeuArgs {
@@ -174,7 +243,7 @@ pub struct BenutzerNeuArgsCli {
     #[clap(short, long)]
     pub passwort: String,
 
-    /// Rechte (Typ) des neuen Benutzers
+    /// Rolle des neuen Benutzers (siehe Tabelle `rollen`)
     #[clap(short, long, default_value = "gast")]
     pub rechte: String,
 
@@ -291,7 +360,9 @@ pub struct AboNeuArgs {
     pub typ: String,
 
     /// Name des Amtsgerichts / Gemarkung / Blatts des neuen Abos,
-    /// getrennt mit Schrägstrich ("Prenzlau / Ludwigsburg / 254")
+    /// getrennt mit Schrägstrich ("Prenzlau / Ludwigsburg / 254"),
+    /// "*" für alle Blätter im Bezirk bzw. "Prenzlau / * / *" für
+    /// das ganze Amtsgericht
     #[clap(short, long)]
     pub blatt: String,
 
@@ -302,6 +373,46 @@ pub struct AboNeuArgs {
     /// Aktenzeichen für das neue Abo
     #[clap(short, long)]
     pub aktenzeichen: Option<String>,
+
+    /// E-Mail des Benutzers, dem das Abo gehört (bei E-Mail-Abos
+    /// standardmäßig die E-Mail selbst)
+    #[clap(long)]
+    #[serde(default)]
+    pub besitzer: Option<String>,
+
+    /// Secret zum Signieren der Webhook-Zustellungen (wird bei
+    /// Webhook-Abos erzeugt, falls leer)
+    #[clap(skip)]
+    #[serde(default)]
+    pub webhook_secret: Option<String>,
+
+    /// Umfang der Benachrichtigungen ("standard" oder "details")
+    #[clap(long)]
+    #[serde(default)]
+    pub detailstufe: Option<String>,
+
+    /// Nur bei Änderungen in diesen Abteilungen benachrichtigen,
+    /// kommagetrennt ("bv", "abt1", "abt2", "abt3", leer = alle)
+    #[clap(long)]
+    #[serde(default)]
+    pub abteilungen: Option<String>,
+
+    /// Nur bei diesen Arten von Änderungen benachrichtigen, kommagetrennt
+    /// ("neu", "loeschung", "veraenderung", leer = alle)
+    #[clap(long)]
+    #[serde(default)]
+    pub aenderungsarten: Option<String>,
+
+    /// Flurstück "Gemarkung/Flur/Flurstück" ("Schenkenberg/1/26"), das Abo
+    /// folgt dem Flurstück in jedes Blatt (nur mit Blatt "*")
+    #[clap(long)]
+    #[serde(default)]
+    pub flurstueck: Option<String>,
+
+    /// ID des neuen Abos (wird erzeugt, falls leer)
+    #[clap(skip)]
+    #[serde(default)]
+    pub id: Option<String>,
 }
 
 #[derive(clap::Parser, Debug, Clone, PartialEq, Serialize, Deserialize)]
@@ -312,7 +423,7 @@ pub struct AboLoeschenArgs {
     pub id: String,
 }
 
-pub fn process_action(action: &ArgAction) -> Result<(), String> {
+pub fn process_action(konfiguration: &Konfiguration, action: &ArgAction) -> Result<(), String> {
     use self::ArgAction::*;
     match action {
         Start { ip, port } => {
@@ -324,14 +435,14 @@ pub fn process_action(action: &ArgAction) -> Result<(), String> {
                 .map_err(|e| format!("tokio: {e}"))?;
 
             runtime.block_on(async move {
-                let app_state = load_app_state().await;
+                let app_state = load_app_state(konfiguration).await;
                 let _ = init(&app_state).await?;
+                starte_hintergrund_aufgaben(&app_state);
                 if !app_state.sync_server() {
-                    let acme_config = k8s_get_acme_config().await.ok().and_then(|o| o);
-                    let acme_args = std::env::var("ACME_CONFIG")
-                        .ok()
-                        .and_then(|s| serde_json::from_str(&s).ok())
-                        .or_else(|| acme_config);
+                    let acme_args = match app_state.konfiguration().acme.clone() {
+                        Some(s) => Some(s),
+                        None => k8s_get_acme_config().await.ok().and_then(|o| o),
+                    };
                     if acme_args.is_none() {
                         println!(
                             "ACME_ARGS fehlt, Beispiel: {}",
@@ -348,7 +459,7 @@ pub fn process_action(action: &ArgAction) -> Result<(), String> {
                 }
             })
         }
-        Indexiere => crate::index::index_all(),
+        Indexiere => crate::index::index_all(konfiguration),
         SyncDb => {
             let _ = init_logger()?;
 
@@ -358,7 +469,7 @@ pub fn process_action(action: &ArgAction) -> Result<(), String> {
                 .map_err(|e| format!("tokio: {e}"))?;
 
             runtime.block_on(async move {
-                crate::cli::pull_db_cli().await?;
+                crate::cli::pull_db_cli(konfiguration).await?;
                 Ok(())
             })
         }
@@ -371,12 +482,15 @@ pub fn process_action(action: &ArgAction) -> Result<(), String> {
                 .map_err(|e| format!("tokio: {e}"))?;
 
             runtime.block_on(async move {
-                crate::cli::pull().await?;
+                crate::cli::pull(konfiguration).await?;
                 Ok(())
             })
         }
+        DbMigrate(a) => crate::cli::db_migrate(konfiguration, a).map_err(|e| format!("{e}")),
+        Backup(a) => crate::backup::backup_erstellen(konfiguration, a),
+        Restore(a) => crate::backup::backup_wiederherstellen(konfiguration, a),
         Suche { begriff } => {
-            let suchergebnisse = crate::suche::suche_in_index(&begriff)?;
+            let suchergebnisse = crate::suche::suche_in_index(konfiguration, &begriff)?;
             println!("{:#?}", suchergebnisse);
             Ok(())
         }
@@ -388,7 +502,7 @@ pub fn process_action(action: &ArgAction) -> Result<(), String> {
                 .map_err(|e| format!("tokio: {e}"))?;
 
             runtime.block_on(async move {
-                crate::cli::create_user_cli(a)
+                crate::cli::create_user_cli(konfiguration, a)
                     .await
                     .map_err(|e| format!("{e}"))
             })
@@ -400,7 +514,7 @@ pub fn process_action(action: &ArgAction) -> Result<(), String> {
                 .map_err(|e| format!("tokio: {e}"))?;
 
             runtime.block_on(async move {
-                crate::cli::delete_user_cli(a)
+                crate::cli::delete_user_cli(konfiguration, a)
                     .await
                     .map_err(|e| format!("{e}"))
             })
@@ -412,7 +526,7 @@ pub fn process_action(action: &ArgAction) -> Result<(), String> {
                 .map_err(|e| format!("tokio: {e}"))?;
 
             runtime.block_on(async move {
-                crate::cli::create_bezirk_cli(a)
+                crate::cli::create_bezirk_cli(konfiguration, a)
                     .await
                     .map_err(|e| format!("{e}"))
             })
@@ -424,7 +538,7 @@ pub fn process_action(action: &ArgAction) -> Result<(), String> {
                 .map_err(|e| format!("tokio: {e}"))?;
 
             runtime.block_on(async move {
-                crate::cli::delete_bezirk_cli(a)
+                crate::cli::delete_bezirk_cli(konfiguration, a)
                     .await
                     .map_err(|e| format!("{e}"))
             })
@@ -436,7 +550,7 @@ pub fn process_action(action: &ArgAction) -> Result<(), String> {
                 .map_err(|e| format!("tokio: {e}"))?;
 
             runtime.block_on(async move {
-                crate::cli::create_abo_cli(a)
+                crate::cli::create_abo_cli(konfiguration, a)
                     .await
                     .map_err(|e| format!("{e}"))
             })
@@ -448,7 +562,7 @@ pub fn process_action(action: &ArgAction) -> Result<(), String> {
                 .map_err(|e| format!("tokio: {e}"))?;
 
             runtime.block_on(async move {
-                crate::cli::delete_abo_cli(a)
+                crate::cli::delete_abo_cli(konfiguration, a)
                     .await
                     .map_err(|e| format!("{e}"))
             })
@@ -457,9 +571,10 @@ pub fn process_action(action: &ArgAction) -> Result<(), String> {
 }
 
 async fn init(app_state: &AppState) -> Result<(), String> {
-    use crate::models::{get_db_path, get_index_dir};
     use git2::Repository;
 
+    let konfiguration = app_state.konfiguration();
+
     if app_state.k8s_aktiv() && !app_state.sync_server() {
         // Warte bis sync-server online ist
         let mut timeout = 0;
@@ -475,16 +590,16 @@ async fn init(app_state: &AppState) -> Result<(), String> {
         let sync_server_ip = crate::k8s::get_sync_server_ip().await?;
         println!("OK: dgb-sync = {sync_server_ip}");
 
-        let database_bytes = crate::db::get_db_bytes().await?;
-        let _ = std::fs::create_dir_all(get_data_dir(MountPoint::Local));
-        let _ = std::fs::create_dir_all(get_index_dir());
+        let database_bytes = crate::db::get_db_bytes(&konfiguration).await?;
+        let _ = std::fs::create_dir_all(konfiguration.get_data_dir(MountPoint::Local));
+        let _ = std::fs::create_dir_all(konfiguration.get_index_dir());
 
         println!("dgb-server: Datenbank erstellt");
-        std::fs::write(get_db_path(MountPoint::Local), database_bytes)
+        std::fs::write(konfiguration.get_db_path(MountPoint::Local), database_bytes)
             .map_err(|e| format!("Fehler in copy_database:\r\n{e}"))?;
 
-        let data_local = get_data_dir(MountPoint::Local);
-        let data_remote = format!("git://{sync_server_ip}:9418/");
+        let data_local = konfiguration.get_data_dir(MountPoint::Local);
+        let data_remote = format!("git://{sync_server_ip}:{}/", konfiguration.ports.git);
 
         println!("dgb-server git clone {data_remote:?} {data_local:?}");
         let _ = git2::Repository::clone(&data_remote, &data_local).map_err(|e| {
@@ -494,12 +609,12 @@ async fn init(app_state: &AppState) -> Result<(), String> {
     } else if app_state.k8s_aktiv() && app_state.sync_server() {
         println!(
             "dgb-sync: erstelle Datenbank in {:?}",
-            get_db_path(MountPoint::Remote)
+            konfiguration.get_db_path(MountPoint::Remote)
         );
-        crate::db::create_database(MountPoint::Remote)
+        crate::db::create_database(&konfiguration, MountPoint::Remote)
             .map_err(|e| format!("Fehler in create_database:\r\n{e}"))?;
 
-        let data_dir = get_data_dir(MountPoint::Remote);
+        let data_dir = konfiguration.get_data_dir(MountPoint::Remote);
         println!("dgb-sync: erstelle /data dir in {data_dir:?}");
         let _ = std::fs::create_dir_all(&data_dir);
         println!("dgb-sync: initialisiere repo in {data_dir:?}");
@@ -508,19 +623,153 @@ async fn init(app_state: &AppState) -> Result<(), String> {
             Err(_) => Repository::init(&data_dir).map_err(|e| format!("{e}"))?,
         };
     } else {
-        crate::db::create_database(MountPoint::Local)
+        crate::db::create_database(&konfiguration, MountPoint::Local)
             .map_err(|e| format!("Fehler in create_database:\r\n{e}"))?;
     }
 
     Ok(())
 }
 
-async fn load_app_state() -> AppState {
+/// Regelmäßige Aufräumarbeiten, laufen nur auf dem Server, der die Datenbank
+/// schreibt (Sync-Server bzw. lokaler Server ohne k8s)
+fn starte_hintergrund_aufgaben(app_state: &AppState) {
+    // Einsichten sammelt jeder Pod lokal, übertragen werden sie gebündelt
+    let app_state_einsichten = app_state.clone();
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+            crate::einsicht::UEBERTRAGUNG_INTERVALL_SEKUNDEN,
+        ));
+        loop {
+            interval.tick().await;
+            if let Err(e) =
+                crate::einsicht::warteschlange_uebertragen(&app_state_einsichten).await
+            {
+                println!("Fehler beim Übertragen des Einsichtsprotokolls: {e}");
+            }
+        }
+    });
+
+    if app_state.k8s_aktiv() && !app_state.sync_server() {
+        return;
+    }
+
+    let mount_point = if app_state.k8s_aktiv() {
+        MountPoint::Remote
+    } else {
+        MountPoint::Local
+    };
+    let konfiguration = app_state.konfiguration();
+    let postausgang = konfiguration.postausgang;
+    let app_state_stuendlich = app_state.clone();
+    let app_state_postausgang = app_state.clone();
+
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(60 * 60));
+        loop {
+            interval.tick().await;
+            match crate::einsicht::einsichten_bereinigen(
+                &konfiguration,
+                mount_point,
+                konfiguration.einsichtsprotokoll_aufbewahrung_tage,
+            ) {
+                Ok(n) if n > 0 => println!("Einsichtsprotokoll: {n} alte Einträge gelöscht"),
+                Ok(_) => {}
+                Err(e) => println!("Fehler beim Bereinigen des Einsichtsprotokolls: {e}"),
+            }
+            match crate::db::sessions_bereinigen(&konfiguration, mount_point) {
+                Ok(n) if n > 0 => println!("Sessions: {n} abgelaufene Sessions gelöscht"),
+                Ok(_) => {}
+                Err(e) => println!("Fehler beim Bereinigen der Sessions: {e}"),
+            }
+            match crate::db::passwort_reset_bereinigen(&konfiguration, mount_point) {
+                Ok(n) if n > 0 => println!("Passwort-Tokens: {n} alte Tokens gelöscht"),
+                Ok(_) => {}
+                Err(e) => println!("Fehler beim Bereinigen der Passwort-Tokens: {e}"),
+            }
+            match crate::ratenbegrenzung::bereinigen(&konfiguration, mount_point) {
+                Ok(n) if n > 0 => println!("Ratenbegrenzung: {n} alte Einträge gelöscht"),
+                Ok(_) => {}
+                Err(e) => println!("Fehler beim Bereinigen der Ratenbegrenzung: {e}"),
+            }
+            match crate::db::unbestaetigte_zugriffe_bereinigen(
+                &konfiguration,
+                mount_point,
+                konfiguration.zugriff_bestaetigung_stunden,
+            ) {
+                Ok(n) if n > 0 => println!("Zugriffe: {n} unbestätigte Anfragen gelöscht"),
+                Ok(_) => {}
+                Err(e) => println!("Fehler beim Bereinigen unbestätigter Anfragen: {e}"),
+            }
+            // SmtpTransport blockiert, daher nicht im async-Task selbst senden
+            let erinnerung_tage = konfiguration.zugriff_erinnerung_tage;
+            let konfiguration_smtp = konfiguration.clone();
+            match tokio::task::spawn_blocking(move || {
+                crate::email::zugriff_erinnerungen_senden(
+                    &konfiguration_smtp,
+                    mount_point,
+                    erinnerung_tage,
+                )
+            })
+            .await
+            .map_err(|e| format!("{e}"))
+            .and_then(|r| r)
+            {
+                Ok(n) if n > 0 => println!("Zugriffe: {n} Erinnerungen vor Ablauf gesendet"),
+                Ok(_) => {}
+                Err(e) => println!("Fehler beim Senden der Zugriffs-Erinnerungen: {e}"),
+            }
+            let konfiguration_smtp = konfiguration.clone();
+            match tokio::task::spawn_blocking(move || {
+                crate::email::zugriff_zusammenfassung_senden(&konfiguration_smtp, mount_point)
+            })
+            .await
+            .map_err(|e| format!("{e}"))
+            .and_then(|r| r)
+            {
+                Ok(n) if n > 0 => println!("Zugriffe: Zusammenfassung über {n} Anfragen gesendet"),
+                Ok(_) => {}
+                Err(e) => println!("Fehler beim Senden der Zugriffs-Zusammenfassung: {e}"),
+            }
+            match crate::postausgang::zusammenfassungen_senden(mount_point, &app_state_stuendlich)
+                .await
+            {
+                Ok(n) if n > 0 => println!("Postausgang: {n} Zusammenfassungen gesendet"),
+                Ok(_) => {}
+                Err(e) => println!("Fehler beim Senden der Zusammenfassungen: {e}"),
+            }
+            match crate::postausgang::bereinigen(
+                &konfiguration,
+                mount_point,
+                konfiguration.postausgang.aufbewahrung_tage,
+            ) {
+                Ok(n) if n > 0 => println!("Postausgang: {n} gesendete Einträge gelöscht"),
+                Ok(_) => {}
+                Err(e) => println!("Fehler beim Bereinigen des Postausgangs: {e}"),
+            }
+        }
+    });
+
+    // Benachrichtigungen an Abonnenten (E-Mails und Webhooks)
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(std::time::Duration::from_secs(
+            postausgang.intervall_sekunden.max(1),
+        ));
+        loop {
+            interval.tick().await;
+            match crate::postausgang::verarbeiten(mount_point, &app_state_postausgang).await {
+                Ok(n) if n > 0 => println!("Postausgang: {n} Benachrichtigungen gesendet"),
+                Ok(_) => {}
+                Err(e) => println!("Fehler beim Verarbeiten des Postausgangs: {e}"),
+            }
+        }
+    });
+}
+
+async fn load_app_state(konfiguration: &Konfiguration) -> AppState {
     AppState {
         data: Arc::new(Mutex::new(AppStateData {
-            sync_server: std::env::var("SYNC_MODE") == Ok("1".to_string()),
-            remote_mount: std::env::var("REMOTE_MOUNT").unwrap_or("/mnt/data/files".to_string()),
             k8s_aktiv: crate::k8s::is_running_in_k8s().await,
+            konfiguration: Arc::new(konfiguration.clone()),
         })),
     }
 }
@@ -582,13 +831,14 @@ pub fn get_css() -> String {
 async fn startup_sync_server(ip: &str, app_state: AppState) -> std::io::Result<()> {
     use std::path::Path;
 
-    println!(
-        "dgb-sync: starte git daemon --base-path={}",
-        get_data_dir(MountPoint::Remote)
-    );
+    let konfiguration = app_state.konfiguration();
+    let data_dir = konfiguration.get_data_dir(MountPoint::Remote);
+    let git_port = konfiguration.ports.git;
+
+    println!("dgb-sync: starte git daemon --base-path={data_dir} --port={git_port}");
 
     std::thread::spawn(move || {
-        let git_daemon_ok_file = Path::new(&get_data_dir(MountPoint::Remote))
+        let git_daemon_ok_file = Path::new(&data_dir)
             .join(".git")
             .join("git-daemon-export-ok");
 
@@ -598,8 +848,9 @@ async fn startup_sync_server(ip: &str, app_state: AppState) -> std::io::Result<(
         let _ = std::process::Command::new("git")
             .arg("daemon")
             .arg("--reuseaddr")
-            .arg(&format!("--base-path={}", get_data_dir(MountPoint::Remote)))
-            .arg(&get_data_dir(MountPoint::Remote))
+            .arg(&format!("--port={git_port}"))
+            .arg(&format!("--base-path={data_dir}"))
+            .arg(&data_dir)
             .output()
             .expect("could not spawm git daemon");
     });
@@ -611,8 +862,9 @@ async fn startup_sync_server(ip: &str, app_state: AppState) -> std::io::Result<(
         println!("{}\t{}", p.name, p.ip);
     }
 
+    let port_intern = konfiguration.ports.intern;
     println!(
-        "\r\ndgb-sync: starte sync server (port 8081, endpoint = /commit, /db, /get-db /ping)"
+        "\r\ndgb-sync: starte sync server (port {port_intern}, endpoint = /commit, /db, /get-db /ping)"
     );
 
     HttpServer::new(move || {
@@ -635,7 +887,7 @@ async fn startup_sync_server(ip: &str, app_state: AppState) -> std::io::Result<(
             .service(crate::api::commit::get_db)
             .service(crate::api::commit::ping)
     })
-    .bind((ip, 8081))?
+    .bind((ip, port_intern))?
     .run()
     .await
 }
@@ -719,7 +971,8 @@ async fn startup_http_server(
         Some(rustls_config)
     });
 
-    let port = port.unwrap_or(8080);
+    let ports = app_state.konfiguration().ports;
+    let port = port.unwrap_or(ports.http);
     println!("dgb-server starte auf port {port}");
     let app_state_clone = app_state.clone();
     let a = async move {
@@ -737,10 +990,19 @@ async fn startup_http_server(
                 .wrap(cors)
                 .service(crate::api::index::status)
                 .service(crate::api::index::zugriff)
+                .service(crate::api::index::zugriff_status)
                 .service(crate::api::index::zugriff_post)
                 .service(crate::api::index::api)
                 .service(crate::api::login::login_get)
                 .service(crate::api::login::login_post)
+                .service(crate::api::login::login_refresh)
+                .service(crate::api::login::logout)
+                .service(crate::api::login::oidc_login)
+                .service(crate::api::login::oidc_callback)
+                .service(crate::api::login::passwort_vergessen_get)
+                .service(crate::api::login::passwort_vergessen_post)
+                .service(crate::api::login::passwort_zuruecksetzen_get)
+                .service(crate::api::login::passwort_zuruecksetzen_post)
                 .service(crate::api::konto::konto_get)
                 .service(crate::api::konto::konto_post)
                 .service(crate::api::konto::konto_post_neu)
@@ -753,6 +1015,20 @@ async fn startup_http_server(
                 .service(crate::api::upload::upload)
                 .service(crate::api::abo::abo_neu)
                 .service(crate::api::abo::abo_loeschen)
+                .service(crate::api::abo::abo_webhook_secret_erneuern)
+                .service(crate::api::abo::abo_webhook_testen)
+                .service(crate::api::abo::abos_liste)
+                .service(crate::api::abo::abos_neu)
+                .service(crate::api::abo::abos_aendern)
+                .service(crate::api::abo::abos_loeschen)
+                .service(crate::api::abo::abos_zustellungen)
+                .service(crate::api::api_schluessel::api_schluessel_liste)
+                .service(crate::api::api_schluessel::api_schluessel_neu)
+                .service(crate::api::audit::audit_log)
+                .service(crate::api::rollen::rollen_get)
+                .service(crate::api::rollen::rollen_post)
+                .service(crate::api::rollen::rollen_delete)
+                .service(crate::api::einsicht::einsichtsprotokoll)
         })
         .bind((ip, port))?
         .run()
@@ -773,7 +1049,7 @@ async fn startup_http_server(
                 .service(crate::api::pull::pull)
                 .service(crate::api::pull::pull_db)
         })
-        .bind((ip, 8081))?
+        .bind((ip, ports.intern))?
         .run()
         .await
     };
@@ -794,10 +1070,19 @@ async fn startup_http_server(
                 .wrap(cors)
                 .service(crate::api::index::status)
                 .service(crate::api::index::zugriff)
+                .service(crate::api::index::zugriff_status)
                 .service(crate::api::index::zugriff_post)
                 .service(crate::api::index::api)
                 .service(crate::api::login::login_get)
                 .service(crate::api::login::login_post)
+                .service(crate::api::login::login_refresh)
+                .service(crate::api::login::logout)
+                .service(crate::api::login::oidc_login)
+                .service(crate::api::login::oidc_callback)
+                .service(crate::api::login::passwort_vergessen_get)
+                .service(crate::api::login::passwort_vergessen_post)
+                .service(crate::api::login::passwort_zuruecksetzen_get)
+                .service(crate::api::login::passwort_zuruecksetzen_post)
                 .service(crate::api::konto::konto_get)
                 .service(crate::api::konto::konto_post)
                 .service(crate::api::konto::konto_post_neu)
@@ -810,13 +1095,27 @@ async fn startup_http_server(
                 .service(crate::api::upload::upload)
                 .service(crate::api::abo::abo_neu)
                 .service(crate::api::abo::abo_loeschen)
+                .service(crate::api::abo::abo_webhook_secret_erneuern)
+                .service(crate::api::abo::abo_webhook_testen)
+                .service(crate::api::abo::abos_liste)
+                .service(crate::api::abo::abos_neu)
+                .service(crate::api::abo::abos_aendern)
+                .service(crate::api::abo::abos_loeschen)
+                .service(crate::api::abo::abos_zustellungen)
+                .service(crate::api::api_schluessel::api_schluessel_liste)
+                .service(crate::api::api_schluessel::api_schluessel_neu)
+                .service(crate::api::audit::audit_log)
+                .service(crate::api::rollen::rollen_get)
+                .service(crate::api::rollen::rollen_post)
+                .service(crate::api::rollen::rollen_delete)
+                .service(crate::api::einsicht::einsichtsprotokoll)
         });
 
         if let Some(q) = rustls_config {
-            println!("dgb-server starte auf port 443");
-            s.bind_rustls((ip, 443), q)?.run().await
+            println!("dgb-server starte auf port {}", ports.https);
+            s.bind_rustls((ip, ports.https), q)?.run().await
         } else {
-            s.bind((ip, 8082))?.run().await
+            s.bind((ip, ports.http_ohne_tls))?.run().await
         }
     };
 
@@ -844,8 +1143,16 @@ fn init_logger() -> Result<(), String> {
 }
 
 fn main() -> std::io::Result<()> {
-    let action = Args::parse().action;
-    match process_action(&action) {
+    let args = Args::parse();
+    let action = args.action;
+    let konfiguration = match Konfiguration::laden(&args.konfiguration) {
+        Ok(o) => o,
+        Err(e) => {
+            println!("Fehler beim Laden der Konfiguration:\r\n{e}");
+            return Ok(());
+        }
+    };
+    match process_action(&konfiguration, &action) {
         Ok(_) => Ok(()),
         Err(e) => {
             println!("Fehler: {action:?}:\r\n{e}");
@@ -0,0 +1,588 @@
+//! Versionierte Migrationen für die Benutzer-Datenbank
+//!
+//! Jede Migration hat eine fortlaufende Nummer und wird genau einmal
+//! ausgeführt. Die bereits ausgeführten Migrationen stehen in der Tabelle
+//! `schema_version`. Neue Spalten oder Tabellen werden ausschließlich als
+//! neue Migration am Ende von `MIGRATIONEN` hinzugefügt, bestehende
+//! Migrationen dürfen nicht mehr verändert werden.
+//!
+//! Der Sync-Server (bzw. der lokale Server ohne k8s) führt ausstehende
+//! Migrationen beim Start automatisch aus, die anderen Pods kopieren die
+//! bereits migrierte Datenbank vom Sync-Server.
+
+use chrono::Utc;
+use rusqlite::Connection;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Migration {
+    pub version: u32,
+    pub beschreibung: &'static str,
+    pub sql: &'static str,
+}
+
+pub const MIGRATIONEN: &[Migration] = &[
+    Migration {
+        version: 1,
+        beschreibung: "Ausgangsschema",
+        sql: "
+            CREATE TABLE IF NOT EXISTS zugriffe (
+                id              STRING PRIMARY KEY UNIQUE NOT NULL,
+                name            VARCHAR(255) NOT NULL,
+                email           VARCHAR(255) NOT NULL,
+                typ             VARCHAR(50) NOT NULL,
+                grund           STRING,
+                land            STRING NOT NULL,
+                amtsgericht     STRING NOT NULL,
+                bezirk          STRING NOT NULL,
+                blatt           STRING NOT NULL,
+                angefragt       STRING NOT NULL,
+                gewaehrt_von    STRING,
+                abgelehnt_von   STRING,
+                am              STRING
+            );
+
+            CREATE TABLE IF NOT EXISTS benutzer (
+                id              INTEGER PRIMARY KEY AUTOINCREMENT,
+                email           VARCHAR(255) UNIQUE NOT NULL,
+                name            VARCHAR(255) NOT NULL,
+                rechte          VARCHAR(255) NOT NULL,
+                password_hashed BLOB
+            );
+
+            CREATE TABLE IF NOT EXISTS sessions (
+                benutzer        INTEGER,
+                token           VARCHAR(1024) UNIQUE NOT NULL,
+                gueltig_bis     VARCHAR(255) NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS bezirke (
+                id              VARCHAR(255) NOT NULL,
+                land            VARCHAR(255) NOT NULL,
+                amtsgericht     VARCHAR(255) NOT NULL,
+                bezirk          VARCHAR(255) NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS abonnements (
+                id               VARCHAR(255) NOT NULL,
+                typ              VARCHAR(50) NOT NULL,
+                text             VARCHAR(1023) NOT NULL,
+                amtsgericht      VARCHAR(255) NOT NULL,
+                bezirk           VARCHAR(255) NOT NULL,
+                blatt            VARCHAR(255) NOT NULL,
+                aktenzeichen     VARCHAR(1023)
+            );
+
+            CREATE TABLE IF NOT EXISTS publickeys (
+                email           VARCHAR(255) NOT NULL,
+                pubkey          TEXT NOT NULL,
+                fingerprint     VARCHAR(2048) NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS einstellungen (
+                id              VARCHAR(255) NOT NULL,
+                benutzer        INTEGER NOT NULL,
+                einstellung     VARCHAR(1023) NOT NULL,
+                wert            STRING NOT NULL
+            );
+
+            CREATE TABLE IF NOT EXISTS grundbuecher (
+                land            VARCHAR(255) NOT NULL,
+                amtsgericht     VARCHAR(1024) NOT NULL,
+                bezirk          VARCHAR(1024) NOT NULL,
+                blatt           VARCHAR(20) NOT NULL
+            );
+        ",
+    },
+    Migration {
+        version: 2,
+        beschreibung: "Primärschlüssel, Fremdschlüssel und Indizes",
+        sql: "
+            CREATE TABLE sessions_neu (
+                id              INTEGER PRIMARY KEY AUTOINCREMENT,
+                benutzer        INTEGER NOT NULL REFERENCES benutzer(id) ON DELETE CASCADE,
+                token           VARCHAR(1024) UNIQUE NOT NULL,
+                gueltig_bis     VARCHAR(255) NOT NULL
+            );
+            INSERT OR IGNORE INTO sessions_neu (benutzer, token, gueltig_bis)
+                SELECT benutzer, token, gueltig_bis FROM sessions
+                WHERE benutzer IN (SELECT id FROM benutzer);
+            DROP TABLE sessions;
+            ALTER TABLE sessions_neu RENAME TO sessions;
+            CREATE INDEX sessions_benutzer ON sessions(benutzer);
+
+            CREATE TABLE bezirke_neu (
+                id              VARCHAR(255) PRIMARY KEY NOT NULL,
+                land            VARCHAR(255) NOT NULL,
+                amtsgericht     VARCHAR(255) NOT NULL,
+                bezirk          VARCHAR(255) NOT NULL
+            );
+            INSERT OR IGNORE INTO bezirke_neu (id, land, amtsgericht, bezirk)
+                SELECT id, land, amtsgericht, bezirk FROM bezirke;
+            DROP TABLE bezirke;
+            ALTER TABLE bezirke_neu RENAME TO bezirke;
+            CREATE INDEX bezirke_amtsgericht ON bezirke(land, amtsgericht, bezirk);
+
+            CREATE TABLE abonnements_neu (
+                id               VARCHAR(255) PRIMARY KEY NOT NULL,
+                typ              VARCHAR(50) NOT NULL,
+                text             VARCHAR(1023) NOT NULL,
+                amtsgericht      VARCHAR(255) NOT NULL,
+                bezirk           VARCHAR(255) NOT NULL,
+                blatt            VARCHAR(255) NOT NULL,
+                aktenzeichen     VARCHAR(1023)
+            );
+            INSERT OR IGNORE INTO abonnements_neu (id, typ, text, amtsgericht, bezirk, blatt, aktenzeichen)
+                SELECT id, typ, text, amtsgericht, bezirk, blatt, aktenzeichen FROM abonnements;
+            DROP TABLE abonnements;
+            ALTER TABLE abonnements_neu RENAME TO abonnements;
+            CREATE INDEX abonnements_blatt ON abonnements(typ, amtsgericht, bezirk);
+            CREATE INDEX abonnements_text ON abonnements(text);
+
+            CREATE TABLE publickeys_neu (
+                email           VARCHAR(255) PRIMARY KEY NOT NULL REFERENCES benutzer(email) ON DELETE CASCADE,
+                pubkey          TEXT NOT NULL,
+                fingerprint     VARCHAR(2048) NOT NULL
+            );
+            INSERT OR IGNORE INTO publickeys_neu (email, pubkey, fingerprint)
+                SELECT email, pubkey, fingerprint FROM publickeys
+                WHERE rowid IN (SELECT MAX(rowid) FROM publickeys GROUP BY email);
+            DROP TABLE publickeys;
+            ALTER TABLE publickeys_neu RENAME TO publickeys;
+            CREATE INDEX publickeys_fingerprint ON publickeys(fingerprint);
+
+            CREATE TABLE grundbuecher_neu (
+                land            VARCHAR(255) NOT NULL,
+                amtsgericht     VARCHAR(1024) NOT NULL,
+                bezirk          VARCHAR(1024) NOT NULL,
+                blatt           VARCHAR(20) NOT NULL,
+                PRIMARY KEY (land, amtsgericht, bezirk, blatt)
+            );
+            INSERT OR IGNORE INTO grundbuecher_neu (land, amtsgericht, bezirk, blatt)
+                SELECT land, amtsgericht, bezirk, blatt FROM grundbuecher;
+            DROP TABLE grundbuecher;
+            ALTER TABLE grundbuecher_neu RENAME TO grundbuecher;
+
+            CREATE INDEX IF NOT EXISTS einstellungen_benutzer ON einstellungen(benutzer, einstellung);
+            CREATE INDEX IF NOT EXISTS zugriffe_email ON zugriffe(email);
+        ",
+    },
+    Migration {
+        version: 3,
+        beschreibung: "Audit-Log",
+        sql: "
+            CREATE TABLE audit_log (
+                id              INTEGER PRIMARY KEY AUTOINCREMENT,
+                zeitpunkt       VARCHAR(255) NOT NULL,
+                akteur          VARCHAR(255) NOT NULL,
+                operation       VARCHAR(255) NOT NULL,
+                ziel            TEXT NOT NULL,
+                daten           TEXT NOT NULL
+            );
+            CREATE INDEX audit_log_zeitpunkt ON audit_log(zeitpunkt);
+            CREATE INDEX audit_log_akteur ON audit_log(akteur);
+            CREATE INDEX audit_log_operation ON audit_log(operation);
+
+            CREATE TRIGGER audit_log_kein_update BEFORE UPDATE ON audit_log
+            BEGIN
+                SELECT RAISE(ABORT, 'audit_log darf nicht verändert werden');
+            END;
+            CREATE TRIGGER audit_log_kein_delete BEFORE DELETE ON audit_log
+            BEGIN
+                SELECT RAISE(ABORT, 'audit_log darf nicht gelöscht werden');
+            END;
+        ",
+    },
+    Migration {
+        version: 4,
+        beschreibung: "Einsichtsprotokoll",
+        sql: "
+            CREATE TABLE einsichten (
+                id              INTEGER PRIMARY KEY AUTOINCREMENT,
+                zeitpunkt       VARCHAR(255) NOT NULL,
+                benutzer        VARCHAR(255) NOT NULL,
+                land            VARCHAR(255) NOT NULL,
+                amtsgericht     VARCHAR(1024) NOT NULL,
+                bezirk          VARCHAR(1024) NOT NULL,
+                blatt           VARCHAR(20) NOT NULL,
+                typ             VARCHAR(50) NOT NULL,
+                aktenzeichen    VARCHAR(1023),
+                zweck           TEXT
+            );
+            CREATE INDEX einsichten_blatt ON einsichten(land, amtsgericht, bezirk, blatt);
+            CREATE INDEX einsichten_benutzer ON einsichten(benutzer);
+            CREATE INDEX einsichten_zeitpunkt ON einsichten(zeitpunkt);
+        ",
+    },
+    Migration {
+        version: 5,
+        beschreibung: "Sessions mit gehashten Tokens und Refresh-Tokens",
+        // Bestehende Sessions enthalten Klartext-Tokens und werden verworfen
+        sql: "
+            DROP TABLE sessions;
+            CREATE TABLE sessions (
+                id                  INTEGER PRIMARY KEY AUTOINCREMENT,
+                benutzer            INTEGER NOT NULL REFERENCES benutzer(id) ON DELETE CASCADE,
+                token_hash          VARCHAR(64) UNIQUE NOT NULL,
+                refresh_token_hash  VARCHAR(64) UNIQUE NOT NULL,
+                erstellt_am         VARCHAR(255) NOT NULL,
+                gueltig_bis         VARCHAR(255) NOT NULL,
+                refresh_gueltig_bis VARCHAR(255) NOT NULL,
+                beschreibung        VARCHAR(1023)
+            );
+            CREATE INDEX sessions_benutzer ON sessions(benutzer);
+            CREATE INDEX sessions_refresh_gueltig_bis ON sessions(refresh_gueltig_bis);
+        ",
+    },
+    Migration {
+        version: 6,
+        beschreibung: "API-Schlüssel",
+        sql: "
+            CREATE TABLE api_schluessel (
+                id              VARCHAR(36) PRIMARY KEY NOT NULL,
+                benutzer        INTEGER NOT NULL REFERENCES benutzer(id) ON DELETE CASCADE,
+                name            VARCHAR(255) NOT NULL,
+                schluessel_hash VARCHAR(64) UNIQUE NOT NULL,
+                scopes          VARCHAR(255) NOT NULL,
+                bereiche        TEXT NOT NULL,
+                erstellt_am     VARCHAR(255) NOT NULL,
+                gueltig_bis     VARCHAR(255),
+                zuletzt_benutzt VARCHAR(255)
+            );
+            CREATE INDEX api_schluessel_benutzer ON api_schluessel(benutzer);
+        ",
+    },
+    Migration {
+        version: 7,
+        beschreibung: "Zwei-Faktor-Authentifizierung",
+        sql: "
+            CREATE TABLE zwei_faktor (
+                benutzer        INTEGER PRIMARY KEY NOT NULL REFERENCES benutzer(id) ON DELETE CASCADE,
+                geheimnis       VARCHAR(64) NOT NULL,
+                aktiviert       BOOLEAN NOT NULL DEFAULT 0,
+                erstellt_am     VARCHAR(255) NOT NULL
+            );
+            CREATE TABLE wiederherstellungscodes (
+                id              INTEGER PRIMARY KEY AUTOINCREMENT,
+                benutzer        INTEGER NOT NULL REFERENCES benutzer(id) ON DELETE CASCADE,
+                code_hash       VARCHAR(64) NOT NULL
+            );
+            CREATE INDEX wiederherstellungscodes_benutzer ON wiederherstellungscodes(benutzer);
+        ",
+    },
+    Migration {
+        version: 8,
+        beschreibung: "Passwort zurücksetzen",
+        sql: "
+            CREATE TABLE passwort_reset (
+                id              INTEGER PRIMARY KEY AUTOINCREMENT,
+                benutzer        INTEGER NOT NULL REFERENCES benutzer(id) ON DELETE CASCADE,
+                token_hash      VARCHAR(64) UNIQUE NOT NULL,
+                erstellt_am     VARCHAR(255) NOT NULL,
+                gueltig_bis     VARCHAR(255) NOT NULL,
+                benutzt_am      VARCHAR(255)
+            );
+            CREATE INDEX passwort_reset_benutzer ON passwort_reset(benutzer);
+        ",
+    },
+    Migration {
+        version: 9,
+        beschreibung: "Ratenbegrenzung für Anmeldung und Zugriffsanfragen",
+        sql: "
+            CREATE TABLE fehlanmeldungen (
+                id              INTEGER PRIMARY KEY AUTOINCREMENT,
+                email           VARCHAR(1023) NOT NULL,
+                ip              VARCHAR(255) NOT NULL,
+                zeitpunkt       VARCHAR(255) NOT NULL
+            );
+            CREATE INDEX fehlanmeldungen_email ON fehlanmeldungen(email, zeitpunkt);
+            CREATE INDEX fehlanmeldungen_ip ON fehlanmeldungen(ip, zeitpunkt);
+            CREATE TABLE kontosperren (
+                email           VARCHAR(1023) PRIMARY KEY NOT NULL,
+                gesperrt_am     VARCHAR(255) NOT NULL,
+                gesperrt_bis    VARCHAR(255)
+            );
+            CREATE TABLE zugriff_anfragen (
+                id              INTEGER PRIMARY KEY AUTOINCREMENT,
+                email           VARCHAR(1023) NOT NULL,
+                ip              VARCHAR(255) NOT NULL,
+                zeitpunkt       VARCHAR(255) NOT NULL,
+                anzahl          INTEGER NOT NULL
+            );
+            CREATE INDEX zugriff_anfragen_email ON zugriff_anfragen(email, zeitpunkt);
+            CREATE INDEX zugriff_anfragen_ip ON zugriff_anfragen(ip, zeitpunkt);
+        ",
+    },
+    Migration {
+        version: 10,
+        beschreibung: "Verknüpfung von Benutzern mit OpenID-Connect-Konten",
+        sql: "
+            ALTER TABLE benutzer ADD COLUMN oidc_subjekt VARCHAR(1023);
+            CREATE UNIQUE INDEX benutzer_oidc_subjekt ON benutzer(oidc_subjekt);
+        ",
+    },
+    Migration {
+        version: 11,
+        beschreibung: "Rollen mit Berechtigungen",
+        sql: "
+            CREATE TABLE rollen (
+                name            VARCHAR(255) PRIMARY KEY NOT NULL,
+                bezeichnung     VARCHAR(1023) NOT NULL,
+                berechtigungen  TEXT NOT NULL
+            );
+            INSERT INTO rollen (name, bezeichnung, berechtigungen) VALUES
+                ('admin', 'Administrator', ''),
+                ('bearbeiter', 'Bearbeiter', 'grundbuch-bearbeiten'),
+                ('mitarbeiter', 'Mitarbeiter', ''),
+                ('gast', 'Gast', '');
+        ",
+    },
+    Migration {
+        version: 12,
+        beschreibung: "Befristete und widerrufbare Zugriffe",
+        sql: "
+            ALTER TABLE zugriffe ADD COLUMN gueltig_bis STRING;
+            ALTER TABLE zugriffe ADD COLUMN widerrufen_von STRING;
+            ALTER TABLE zugriffe ADD COLUMN widerrufen_am STRING;
+            ALTER TABLE zugriffe ADD COLUMN erinnerung_gesendet STRING;
+        ",
+    },
+    Migration {
+        version: 13,
+        beschreibung: "Zugriffe auf Bereiche von Blättern",
+        sql: "
+            ALTER TABLE zugriffe ADD COLUMN blatt_bis STRING;
+        ",
+    },
+    Migration {
+        version: 14,
+        beschreibung: "Ablehnungsgrund und Benachrichtigung über neue Zugriffsanfragen",
+        sql: "
+            ALTER TABLE zugriffe ADD COLUMN ablehnungsgrund STRING;
+            ALTER TABLE zugriffe ADD COLUMN admin_benachrichtigt STRING;
+        ",
+    },
+    Migration {
+        version: 15,
+        beschreibung: "Statusseite und Zurückziehen von Zugriffsanfragen",
+        sql: "
+            ALTER TABLE zugriffe ADD COLUMN anfrage_id STRING;
+            ALTER TABLE zugriffe ADD COLUMN zurueckgezogen_am STRING;
+            CREATE INDEX zugriffe_anfrage_id ON zugriffe(anfrage_id);
+        ",
+    },
+    Migration {
+        version: 16,
+        beschreibung: "Bestätigung von Zugriffsanfragen per E-Mail (Double-Opt-In)",
+        sql: "
+            ALTER TABLE zugriffe ADD COLUMN bestaetigt_am STRING;
+            UPDATE zugriffe SET bestaetigt_am = angefragt;
+        ",
+    },
+    Migration {
+        version: 17,
+        beschreibung: "Postausgang für E-Mails und Webhooks mit Wiederholungen",
+        sql: "
+            CREATE TABLE postausgang (
+                id                  VARCHAR(255) PRIMARY KEY NOT NULL,
+                typ                 VARCHAR(255) NOT NULL,
+                ziel                VARCHAR(1023) NOT NULL,
+                abo_id              VARCHAR(255),
+                aenderungs_id       VARCHAR(255),
+                daten               TEXT NOT NULL,
+                status              VARCHAR(255) NOT NULL,
+                versuche            INTEGER NOT NULL DEFAULT 0,
+                naechster_versuch   VARCHAR(255) NOT NULL,
+                letzter_fehler      TEXT,
+                erstellt_am         VARCHAR(255) NOT NULL,
+                gesendet_am         VARCHAR(255)
+            );
+            CREATE INDEX postausgang_faellig ON postausgang(status, naechster_versuch);
+        ",
+    },
+    Migration {
+        version: 18,
+        beschreibung: "Besitzer und Secret für signierte Webhooks bei Abonnements",
+        sql: "
+            ALTER TABLE abonnements ADD COLUMN besitzer VARCHAR(1023);
+            ALTER TABLE abonnements ADD COLUMN webhook_secret VARCHAR(255);
+            UPDATE abonnements SET besitzer = text WHERE typ = 'email';
+
+            -- Besitzer bestehender Webhooks ist, wer sie laut Audit-Log angelegt hat,
+            -- falls das eindeutig ein Benutzer war (sonst nur für Administratoren sichtbar)
+            UPDATE abonnements SET besitzer = (
+                SELECT MIN(a.akteur) FROM audit_log a
+                WHERE a.operation = 'abo-neu'
+                    AND json_extract(a.daten, '$.AboNeu.typ') = 'webhook'
+                    AND json_extract(a.daten, '$.AboNeu.text') = abonnements.text
+                    AND a.akteur IN (SELECT email FROM benutzer)
+                HAVING COUNT(DISTINCT a.akteur) = 1
+            ) WHERE typ = 'webhook';
+
+            -- webhook_secret bleibt bei bestehenden Webhooks leer: ein hier erzeugtes
+            -- Secret hätte niemand je gesehen. Sie werden unsigniert zugestellt, bis
+            -- der Besitzer oder ein Administrator das Secret erneuert.
+        ",
+    },
+    Migration {
+        version: 19,
+        beschreibung: "Detailstufe der Benachrichtigungen je Abonnement",
+        sql: "
+            ALTER TABLE abonnements ADD COLUMN detailstufe VARCHAR(255) NOT NULL DEFAULT 'standard';
+        ",
+    },
+    Migration {
+        version: 20,
+        beschreibung: "Filter nach Abteilung und Art der Änderung je Abonnement",
+        sql: "
+            ALTER TABLE abonnements ADD COLUMN abteilungen VARCHAR(255) NOT NULL DEFAULT '';
+            ALTER TABLE abonnements ADD COLUMN aenderungsarten VARCHAR(255) NOT NULL DEFAULT '';
+        ",
+    },
+    Migration {
+        version: 21,
+        beschreibung: "Abonnements für Flurstücke (Bezirk \"*\" = ganzes Amtsgericht)",
+        sql: "
+            ALTER TABLE abonnements ADD COLUMN flurstueck VARCHAR(1023);
+        ",
+    },
+    Migration {
+        version: 22,
+        beschreibung: "Pausierte Abonnements, Zustellungen je Abonnement",
+        sql: "
+            ALTER TABLE abonnements ADD COLUMN pausiert INTEGER NOT NULL DEFAULT 0;
+            CREATE INDEX postausgang_abo ON postausgang(abo_id, erstellt_am);
+        ",
+    },
+    Migration {
+        version: 23,
+        beschreibung: "Zuletzt benutzter TOTP-Zeitschritt (Schutz vor Wiederverwendung)",
+        sql: "
+            ALTER TABLE zwei_faktor ADD COLUMN letzter_schritt INTEGER;
+        ",
+    },
+    Migration {
+        version: 24,
+        beschreibung: "Ratenbegrenzung für /passwort-vergessen",
+        sql: "
+            CREATE TABLE passwort_vergessen_anfragen (
+                id              INTEGER PRIMARY KEY AUTOINCREMENT,
+                email           VARCHAR(1023) NOT NULL,
+                ip              VARCHAR(255) NOT NULL,
+                zeitpunkt       VARCHAR(255) NOT NULL
+            );
+            CREATE INDEX passwort_vergessen_anfragen_email ON passwort_vergessen_anfragen(email, zeitpunkt);
+            CREATE INDEX passwort_vergessen_anfragen_ip ON passwort_vergessen_anfragen(ip, zeitpunkt);
+        ",
+    },
+];
+
+/// Tabellen, die Migration 2 per `INSERT OR IGNORE` neu aufbaut - doppelte
+/// Zeilen (und Sessions ohne Benutzer) gehen dabei verloren
+const MIGRATION_2_NEU_AUFGEBAUT: &[&str] = &[
+    "sessions",
+    "bezirke",
+    "abonnements",
+    "publickeys",
+    "grundbuecher",
+];
+
+fn zeilen_zaehlen(conn: &Connection, tabelle: &str) -> Result<usize, rusqlite::Error> {
+    conn.query_row(&format!("SELECT COUNT(*) FROM {tabelle}"), [], |row| {
+        row.get::<usize, usize>(0)
+    })
+}
+
+/// Gibt die Anzahl der Zeilen zurück, deren Fremdschlüssel auf keinen
+/// existierenden Eintrag verweisen (`PRAGMA foreign_key_check`)
+pub fn fremdschluessel_pruefen(conn: &Connection) -> Result<usize, rusqlite::Error> {
+    let mut stmt = conn.prepare("PRAGMA foreign_key_check")?;
+    let mut rows = stmt.query([])?;
+    let mut anzahl = 0;
+    while rows.next()?.is_some() {
+        anzahl += 1;
+    }
+    Ok(anzahl)
+}
+
+/// Gibt die momentane Schema-Version zurück (0 = leere / unversionierte Datenbank)
+pub fn get_schema_version(conn: &Connection) -> Result<u32, rusqlite::Error> {
+    let tabelle_existiert = conn.query_row(
+        "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'schema_version'",
+        [],
+        |row| row.get::<usize, u32>(0),
+    )? > 0;
+
+    if !tabelle_existiert {
+        return Ok(0);
+    }
+
+    conn.query_row(
+        "SELECT COALESCE(MAX(version), 0) FROM schema_version",
+        [],
+        |row| row.get::<usize, u32>(0),
+    )
+}
+
+/// Gibt alle Migrationen zurück, die auf der Datenbank noch nicht ausgeführt wurden
+pub fn get_ausstehende_migrationen(
+    conn: &Connection,
+) -> Result<Vec<&'static Migration>, rusqlite::Error> {
+    let version = get_schema_version(conn)?;
+    Ok(MIGRATIONEN.iter().filter(|m| m.version > version).collect())
+}
+
+/// Führt alle ausstehenden Migrationen aus (jede in einer eigenen Transaktion)
+/// und gibt die ausgeführten Migrationen zurück. Bei `dry_run = true` wird
+/// nichts geschrieben.
+pub fn migrieren(
+    conn: &mut Connection,
+    dry_run: bool,
+) -> Result<Vec<&'static Migration>, rusqlite::Error> {
+    let ausstehend = get_ausstehende_migrationen(conn)?;
+    if dry_run || ausstehend.is_empty() {
+        return Ok(ausstehend);
+    }
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS schema_version (
+            version         INTEGER PRIMARY KEY NOT NULL,
+            beschreibung    VARCHAR(1023) NOT NULL,
+            angewendet_am   VARCHAR(255) NOT NULL
+        )",
+        [],
+    )?;
+
+    for migration in ausstehend.iter() {
+        let tx = conn.transaction()?;
+        let vorher = if migration.version == 2 {
+            MIGRATION_2_NEU_AUFGEBAUT
+                .iter()
+                .map(|tabelle| Ok((*tabelle, zeilen_zaehlen(&tx, tabelle)?)))
+                .collect::<Result<Vec<_>, rusqlite::Error>>()?
+        } else {
+            Vec::new()
+        };
+        tx.execute_batch(migration.sql)?;
+        for (tabelle, anzahl_vorher) in vorher {
+            let anzahl_nachher = zeilen_zaehlen(&tx, tabelle)?;
+            if anzahl_nachher < anzahl_vorher {
+                println!(
+                    "Migration {:03}: {} Zeilen in {tabelle} verworfen (Duplikate oder ohne Benutzer)",
+                    migration.version,
+                    anzahl_vorher - anzahl_nachher
+                );
+            }
+        }
+        tx.execute(
+            "INSERT INTO schema_version (version, beschreibung, angewendet_am) VALUES (?1, ?2, ?3)",
+            rusqlite::params![
+                migration.version,
+                migration.beschreibung,
+                Utc::now().to_rfc3339()
+            ],
+        )?;
+        tx.commit()?;
+    }
+
+    Ok(ausstehend)
+}
@@ -1,8 +1,9 @@
 //! Datenmodelle, die vom Server verarbeitet werden
 
 pub use gbx::*;
+use crate::aenderungen::{AenderungsArt, AenderungsDetails};
 use serde_derive::{Deserialize, Serialize};
-use std::path::Path;
+use std::collections::BTreeSet;
 
 #[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
 pub enum MountPoint {
@@ -10,52 +11,6 @@ pub enum MountPoint {
     Remote,
 }
 
-pub fn get_local_path() -> String {
-    std::env::current_exe()
-        .unwrap()
-        .parent()
-        .unwrap()
-        .join("local")
-        .to_str()
-        .unwrap_or_default()
-        .to_string()
-}
-
-pub fn get_remote_path() -> String {
-    std::env::var("REMOTE_MOUNT_POINT").unwrap_or("/mnt/data/files".to_string())
-}
-
-pub fn get_base_path(mount_point: MountPoint) -> String {
-    match mount_point {
-        MountPoint::Local => get_local_path(),
-        MountPoint::Remote => get_remote_path(),
-    }
-}
-
-pub fn get_db_path(mount_point: MountPoint) -> String {
-    Path::new(&get_base_path(mount_point))
-        .join("benutzer.sqlite.db")
-        .to_str()
-        .unwrap_or_default()
-        .to_string()
-}
-
-pub fn get_data_dir(mount_point: MountPoint) -> String {
-    Path::new(&get_base_path(mount_point))
-        .join("data")
-        .to_str()
-        .unwrap_or_default()
-        .to_string()
-}
-
-pub fn get_index_dir() -> String {
-    Path::new(&get_base_path(MountPoint::Local))
-        .join("index")
-        .to_str()
-        .unwrap_or_default()
-        .to_string()
-}
-
 #[derive(Debug, Clone, PartialEq, Eq, Ord, PartialOrd, Hash, Serialize, Deserialize)]
 #[serde(untagged)]
 pub enum StringOrUsize {
@@ -78,6 +33,10 @@ pub struct BenutzerInfo {
     pub rechte: String,
     pub name: String,
     pub email: String,
+    /// Gesetzt, wenn sich der Benutzer mit einem API-Schlüssel statt einer Session angemeldet hat
+    pub api_schluessel: Option<crate::api_schluessel::ApiSchluesselInfo>,
+    /// Berechtigungen der Rolle `rechte`, werden beim Laden des Benutzers einmal ausgelesen
+    pub berechtigungen: std::collections::BTreeSet<crate::rollen::Berechtigung>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
@@ -88,6 +47,132 @@ pub struct AbonnementInfo {
     pub blatt: AbonnementInfoBlattNr,
     pub text: String,
     pub aktenzeichen: Option<String>,
+    #[serde(default)]
+    pub detailstufe: AboDetailstufe,
+    /// Nur Änderungen in diesen Abteilungen (`bv`, `abt1`, `abt2`, `abt3`), leer = alle
+    #[serde(default)]
+    pub abteilungen: Vec<String>,
+    /// Nur diese Arten von Änderungen, leer = alle
+    #[serde(default)]
+    pub aenderungsarten: Vec<AenderungsArt>,
+    /// Flurstück `Gemarkung/Flur/Flurstück`, das Abo gilt für jedes Blatt, in dem
+    /// es (vor oder nach der Änderung) eingetragen ist
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flurstueck: Option<String>,
+    /// Pausierte Abos werden bei Änderungen nicht benachrichtigt
+    #[serde(default)]
+    pub pausiert: bool,
+}
+
+impl AbonnementInfo {
+    /// Ob eine der Änderungen (Abteilung, Art) zu den Filtern des Abos passt,
+    /// siehe `crate::aenderungen::get_aenderungsarten`
+    pub fn filter_passt(&self, aenderungen: &BTreeSet<(String, AenderungsArt)>) -> bool {
+        if self.abteilungen.is_empty() && self.aenderungsarten.is_empty() {
+            return true;
+        }
+        aenderungen.iter().any(|(abteilung, art)| {
+            (self.abteilungen.is_empty() || self.abteilungen.contains(abteilung))
+                && (self.aenderungsarten.is_empty() || self.aenderungsarten.contains(art))
+        })
+    }
+
+    /// Details ohne die Abteilungen, die das Abo nicht abonniert hat
+    /// (z.B. bleiben bei `abt2` nur `abt2`, `abt2-veraenderungen` und `abt2-loeschungen`)
+    pub fn details_filtern(&self, details: &AenderungsDetails) -> AenderungsDetails {
+        let mut details = details.clone();
+        if !self.abteilungen.is_empty() {
+            details.abteilungen.retain(|abteilung, _| {
+                self.abteilungen
+                    .iter()
+                    .any(|a| a == crate::aenderungen::get_hauptabteilung(abteilung))
+            });
+        }
+        details
+    }
+}
+
+/// Umfang der Benachrichtigung bei einer Änderung
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum AboDetailstufe {
+    /// Nur Blatt und Änderungs-ID
+    #[serde(rename = "standard")]
+    Standard,
+    /// Zusätzlich Titel, Beschreibung, Autor und geänderte Einträge je Abteilung
+    #[serde(rename = "details")]
+    Details,
+}
+
+impl Default for AboDetailstufe {
+    fn default() -> Self {
+        AboDetailstufe::Standard
+    }
+}
+
+impl AboDetailstufe {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AboDetailstufe::Standard => "standard",
+            AboDetailstufe::Details => "details",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.trim() {
+            "standard" => Some(AboDetailstufe::Standard),
+            "details" => Some(AboDetailstufe::Details),
+            _ => None,
+        }
+    }
+}
+
+/// Benutzer-Einstellung `email.abo.benachrichtigung`: wann E-Mails zu
+/// Abonnements gesendet werden
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum AboBenachrichtigung {
+    /// Eine E-Mail je Änderung und Blatt
+    #[serde(rename = "sofort")]
+    Sofort,
+    /// Zusammenfassung höchstens einmal pro Tag
+    #[serde(rename = "taeglich")]
+    Taeglich,
+    /// Zusammenfassung höchstens einmal pro Woche
+    #[serde(rename = "woechentlich")]
+    Woechentlich,
+}
+
+impl Default for AboBenachrichtigung {
+    fn default() -> Self {
+        AboBenachrichtigung::Sofort
+    }
+}
+
+impl AboBenachrichtigung {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AboBenachrichtigung::Sofort => "sofort",
+            AboBenachrichtigung::Taeglich => "taeglich",
+            AboBenachrichtigung::Woechentlich => "woechentlich",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s.trim() {
+            "sofort" => Some(AboBenachrichtigung::Sofort),
+            "taeglich" => Some(AboBenachrichtigung::Taeglich),
+            "woechentlich" => Some(AboBenachrichtigung::Woechentlich),
+            _ => None,
+        }
+    }
+
+    /// Abstand zwischen zwei Zusammenfassungen, `None` bei `sofort`
+    pub fn get_intervall_tage(&self) -> Option<i64> {
+        match self {
+            AboBenachrichtigung::Sofort => None,
+            AboBenachrichtigung::Taeglich => Some(1),
+            AboBenachrichtigung::Woechentlich => Some(7),
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
@@ -0,0 +1,512 @@
+//! Anmeldung über OpenID Connect (Authorization Code Flow mit PKCE)
+//!
+//! `/login/oidc` leitet zum Anbieter weiter, `state`, `nonce` und der
+//! PKCE-Verifier liegen bis zum Callback in einem HttpOnly-Cookie (damit
+//! funktioniert der Ablauf auch, wenn der Callback auf einem anderen Pod
+//! ankommt). Im Callback wird der Code direkt beim Token-Endpunkt eingelöst.
+//! Da das ID-Token über eine TLS-Verbindung direkt vom Anbieter kommt, werden
+//! nur `iss`, `aud`, `exp` und `nonce` geprüft, nicht die Signatur
+//! (OpenID Connect Core 1.0, Abschnitt 3.1.3.7).
+//!
+//! Benutzer werden über das `sub`-Claim verknüpft (`benutzer.oidc_subjekt`),
+//! beim ersten Login über die E-Mail-Adresse, sofern der Anbieter sie als
+//! bestätigt meldet (`email_verified`). Konten mit Passwort oder Administratorrechten
+//! werden nicht automatisch verknüpft. Unbekannte Benutzer werden automatisch
+//! angelegt (ohne Passwort). Die Rechte werden bei jedem Login aus den Gruppen
+//! des Benutzers (`gruppen_claim`) übernommen.
+//!
+//! Kontosperren gelten auch hier. Die Zwei-Faktor-Authentifizierung kann der
+//! Anbieter nur übernehmen, wenn `zwei_faktor_beim_anbieter` gesetzt ist,
+//! sonst wird die Anmeldung für Konten mit aktivierter oder vorgeschriebener
+//! 2FA abgelehnt.
+
+use crate::api::commit::DbChangeOp;
+use crate::config::{Konfiguration, OidcKonfiguration};
+use crate::models::{BenutzerInfo, MountPoint};
+use crate::AppState;
+use data_encoding::BASE64URL_NOPAD;
+use rand::Rng;
+use rusqlite::{Connection, OptionalExtension};
+use serde_derive::Deserialize;
+use sha2::{Digest, Sha256};
+
+/// Cookie, in dem die begonnene Anmeldung bis zum Callback gespeichert wird
+pub const COOKIE_NAME: &str = "OidcAnmeldung";
+/// Gültigkeit einer begonnenen Anmeldung in Minuten
+pub const ANMELDUNG_GUELTIGKEIT_MINUTEN: i64 = 10;
+
+/// Auszug aus `/.well-known/openid-configuration`
+#[derive(Debug, Clone, Deserialize)]
+pub struct OidcDiscovery {
+    pub issuer: String,
+    pub authorization_endpoint: String,
+    pub token_endpoint: String,
+    #[serde(default)]
+    pub token_endpoint_auth_methods_supported: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+struct TokenAntwort {
+    id_token: String,
+}
+
+/// Zustand zwischen Weiterleitung und Callback
+#[derive(Debug, Clone, PartialEq)]
+pub struct OidcAnmeldung {
+    pub state: String,
+    pub nonce: String,
+    pub code_verifier: String,
+}
+
+impl OidcAnmeldung {
+    pub fn generieren() -> Self {
+        Self {
+            state: zufall(),
+            nonce: zufall(),
+            code_verifier: zufall(),
+        }
+    }
+
+    pub fn to_cookie_wert(&self) -> String {
+        format!("{}.{}.{}", self.state, self.nonce, self.code_verifier)
+    }
+
+    pub fn from_cookie_wert(s: &str) -> Option<Self> {
+        let mut teile = s.split('.');
+        let anmeldung = Self {
+            state: teile.next()?.to_string(),
+            nonce: teile.next()?.to_string(),
+            code_verifier: teile.next()?.to_string(),
+        };
+        if teile.next().is_some() {
+            return None;
+        }
+        Some(anmeldung)
+    }
+}
+
+/// Vom Anbieter bestätigter Benutzer
+#[derive(Debug, Clone, PartialEq)]
+pub struct OidcBenutzer {
+    pub subjekt: String,
+    pub email: String,
+    /// `email_verified` ist im ID-Token gesetzt und `true`
+    pub email_bestaetigt: bool,
+    pub name: String,
+    pub gruppen: Vec<String>,
+}
+
+/// 256 Bit, Base64-URL ohne Padding
+fn zufall() -> String {
+    let bytes: [u8; 32] = rand::thread_rng().gen();
+    BASE64URL_NOPAD.encode(&bytes)
+}
+
+pub async fn get_discovery(k: &OidcKonfiguration) -> Result<OidcDiscovery, String> {
+    let aussteller = k.aussteller.trim_end_matches('/');
+    let url = format!("{aussteller}/.well-known/openid-configuration");
+
+    let discovery = reqwest::get(&url)
+        .await
+        .map_err(|e| format!("OpenID-Connect-Anbieter nicht erreichbar: {e}"))?
+        .json::<OidcDiscovery>()
+        .await
+        .map_err(|e| format!("Ungültige OpenID-Konfiguration von {url}: {e}"))?;
+
+    if discovery.issuer.trim_end_matches('/') != aussteller {
+        return Err(format!(
+            "Aussteller {} stimmt nicht mit der Konfiguration ({aussteller}) überein",
+            discovery.issuer
+        ));
+    }
+
+    Ok(discovery)
+}
+
+pub fn get_redirect_url(
+    konfiguration: &Konfiguration,
+    k: &OidcKonfiguration,
+) -> Result<String, String> {
+    match k.redirect_url.as_ref() {
+        Some(s) => Ok(s.clone()),
+        None => {
+            let server_url = crate::db::get_server_address(konfiguration, MountPoint::Local)?;
+            Ok(format!(
+                "{}/login/oidc/callback",
+                server_url.trim_end_matches('/')
+            ))
+        }
+    }
+}
+
+/// URL, zu der der Browser für die Anmeldung weitergeleitet wird
+pub fn get_autorisierung_url(
+    k: &OidcKonfiguration,
+    discovery: &OidcDiscovery,
+    anmeldung: &OidcAnmeldung,
+    redirect_url: &str,
+) -> String {
+    let challenge = BASE64URL_NOPAD.encode(&Sha256::digest(anmeldung.code_verifier.as_bytes()));
+    let trenner = if discovery.authorization_endpoint.contains('?') {
+        '&'
+    } else {
+        '?'
+    };
+
+    format!(
+        "{}{trenner}response_type=code&client_id={}&redirect_uri={}&scope={}&state={}&nonce={}&code_challenge={challenge}&code_challenge_method=S256",
+        discovery.authorization_endpoint,
+        urlencoding::encode(&k.client_id),
+        urlencoding::encode(redirect_url),
+        urlencoding::encode(&k.scopes.join(" ")),
+        anmeldung.state,
+        anmeldung.nonce,
+    )
+}
+
+/// Löst den Code beim Token-Endpunkt ein und prüft das ID-Token
+pub async fn code_einloesen(
+    k: &OidcKonfiguration,
+    discovery: &OidcDiscovery,
+    code: &str,
+    anmeldung: &OidcAnmeldung,
+    redirect_url: &str,
+) -> Result<OidcBenutzer, String> {
+    let mut form = vec![
+        ("grant_type", "authorization_code"),
+        ("code", code),
+        ("redirect_uri", redirect_url),
+        ("code_verifier", anmeldung.code_verifier.as_str()),
+    ];
+
+    // Standard laut Spezifikation ist client_secret_basic
+    let basic = discovery.token_endpoint_auth_methods_supported.is_empty()
+        || discovery
+            .token_endpoint_auth_methods_supported
+            .iter()
+            .any(|m| m == "client_secret_basic");
+
+    let mut anfrage = reqwest::Client::new().post(&discovery.token_endpoint);
+    if basic {
+        anfrage = anfrage.basic_auth(
+            urlencoding::encode(&k.client_id),
+            Some(urlencoding::encode(&k.client_secret)),
+        );
+    } else {
+        form.push(("client_id", k.client_id.as_str()));
+        form.push(("client_secret", k.client_secret.as_str()));
+    }
+
+    let antwort = anfrage
+        .form(&form)
+        .send()
+        .await
+        .map_err(|e| format!("Token-Endpunkt nicht erreichbar: {e}"))?;
+
+    if !antwort.status().is_success() {
+        let status = antwort.status();
+        let text = antwort.text().await.unwrap_or_default();
+        return Err(format!("Token-Endpunkt antwortete mit {status}: {text}"));
+    }
+
+    let token = antwort
+        .json::<TokenAntwort>()
+        .await
+        .map_err(|e| format!("Ungültige Antwort vom Token-Endpunkt: {e}"))?;
+
+    id_token_pruefen(k, discovery, &token.id_token, &anmeldung.nonce)
+}
+
+fn id_token_pruefen(
+    k: &OidcKonfiguration,
+    discovery: &OidcDiscovery,
+    id_token: &str,
+    nonce: &str,
+) -> Result<OidcBenutzer, String> {
+    use serde_json::Value;
+
+    let payload = id_token
+        .split('.')
+        .nth(1)
+        .ok_or(format!("Ungültiges ID-Token"))?;
+    let payload = BASE64URL_NOPAD
+        .decode(payload.trim_end_matches('=').as_bytes())
+        .map_err(|e| format!("Ungültiges ID-Token: {e}"))?;
+    let claims: Value =
+        serde_json::from_slice(&payload).map_err(|e| format!("Ungültiges ID-Token: {e}"))?;
+
+    let claim = |name: &str| claims.get(name).and_then(|v| v.as_str());
+
+    if claim("iss") != Some(discovery.issuer.as_str()) {
+        return Err(format!("ID-Token: ungültiger Aussteller"));
+    }
+
+    let aud_gueltig = match claims.get("aud") {
+        Some(Value::String(s)) => *s == k.client_id,
+        Some(Value::Array(a)) => a.iter().any(|v| v.as_str() == Some(k.client_id.as_str())),
+        _ => false,
+    };
+    if !aud_gueltig {
+        return Err(format!("ID-Token: ungültige Zielgruppe (aud)"));
+    }
+
+    let exp = claims.get("exp").and_then(|v| v.as_i64()).unwrap_or(0);
+    if exp < chrono::Utc::now().timestamp() {
+        return Err(format!("ID-Token ist abgelaufen"));
+    }
+
+    if claim("nonce") != Some(nonce) {
+        return Err(format!("ID-Token: ungültige nonce"));
+    }
+
+    let subjekt = claim("sub").ok_or(format!("ID-Token enthält kein sub"))?;
+    let email = claim("email").ok_or(format!(
+        "ID-Token enthält keine E-Mail-Adresse (Scope \"email\" angefordert?)"
+    ))?;
+    let email_bestaetigt = claims.get("email_verified").and_then(|v| v.as_bool()) == Some(true);
+    let name = claim("name")
+        .or(claim("preferred_username"))
+        .unwrap_or(email);
+
+    // Verschachtelte Claims, z.B. "realm_access.roles"
+    let gruppen = k
+        .gruppen_claim
+        .split('.')
+        .try_fold(&claims, |v, teil| v.get(teil));
+    let gruppen = match gruppen {
+        Some(Value::Array(a)) => a
+            .iter()
+            .filter_map(|v| v.as_str().map(|s| s.to_string()))
+            .collect(),
+        Some(Value::String(s)) => vec![s.clone()],
+        _ => Vec::new(),
+    };
+
+    Ok(OidcBenutzer {
+        subjekt: subjekt.to_string(),
+        email: email.trim().to_string(),
+        email_bestaetigt,
+        name: name.trim().to_string(),
+        gruppen,
+    })
+}
+
+/// Rolle mit den meisten Berechtigungen, die einer der Gruppen zugeordnet ist
+pub fn rechte_zuordnen(
+    konfiguration: &Konfiguration,
+    k: &OidcKonfiguration,
+    gruppen: &[String],
+) -> Option<String> {
+    gruppen
+        .iter()
+        .filter_map(|g| k.rechte.get(g))
+        .filter_map(|r| crate::rollen::get_rolle(konfiguration, r).ok().flatten())
+        .max_by_key(|r| r.berechtigungen.len())
+        .map(|r| r.name)
+}
+
+/// Lokaler Benutzer zu einem OIDC-Konto
+struct VerknuepfterBenutzer {
+    email: String,
+    rechte: String,
+    oidc_subjekt: Option<String>,
+    hat_passwort: bool,
+}
+
+/// Sucht den Benutzer zuerst über das Subjekt, dann über die E-Mail-Adresse
+/// (nur wenn der Anbieter sie bestätigt hat)
+fn get_verknuepften_benutzer(
+    konfiguration: &Konfiguration,
+    oidc_benutzer: &OidcBenutzer,
+) -> Result<Option<VerknuepfterBenutzer>, String> {
+    let conn = crate::db::verbindung_oeffnen(konfiguration, MountPoint::Local)
+        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+
+    let row = |row: &rusqlite::Row| {
+        Ok(VerknuepfterBenutzer {
+            email: row.get(0)?,
+            rechte: row.get(1)?,
+            oidc_subjekt: row.get(2)?,
+            hat_passwort: row.get(3)?,
+        })
+    };
+
+    let benutzer = conn
+        .query_row(
+            "SELECT email, rechte, oidc_subjekt, password_hashed IS NOT NULL FROM benutzer WHERE oidc_subjekt = ?1",
+            rusqlite::params![oidc_benutzer.subjekt],
+            row,
+        )
+        .optional()
+        .map_err(|e| format!("Fehler beim Auslesen der Benutzerdaten: {e}"))?;
+
+    if benutzer.is_some() {
+        return Ok(benutzer);
+    }
+
+    if !oidc_benutzer.email_bestaetigt {
+        return Err(format!(
+            "E-Mail-Adresse {} ist beim Anbieter nicht bestätigt",
+            oidc_benutzer.email
+        ));
+    }
+
+    conn.query_row(
+        "SELECT email, rechte, oidc_subjekt, password_hashed IS NOT NULL FROM benutzer WHERE LOWER(email) = LOWER(?1)",
+        rusqlite::params![oidc_benutzer.email],
+        row,
+    )
+    .optional()
+    .map_err(|e| format!("Fehler beim Auslesen der Benutzerdaten: {e}"))
+}
+
+/// Verknüpft bzw. legt den Benutzer an und gleicht die Rechte ab,
+/// gibt die E-Mail des lokalen Benutzers zurück
+pub async fn benutzer_anmelden(
+    k: &OidcKonfiguration,
+    oidc_benutzer: &OidcBenutzer,
+    app_state: &AppState,
+) -> Result<String, String> {
+    let konfiguration = app_state.konfiguration();
+    let gruppen_rechte = rechte_zuordnen(&konfiguration, k, &oidc_benutzer.gruppen);
+
+    let (email, rechte) = match get_verknuepften_benutzer(&konfiguration, oidc_benutzer)? {
+        Some(b) => {
+            match b.oidc_subjekt.as_ref() {
+                Some(s) if *s != oidc_benutzer.subjekt => {
+                    return Err(format!(
+                        "Das Benutzerkonto {} ist bereits mit einem anderen Konto des Anbieters verknüpft",
+                        b.email
+                    ));
+                }
+                Some(_) => {}
+                // Ein bestehendes Konto, das der Benutzer selbst angelegt haben könnte,
+                // soll nicht allein über die E-Mail-Adresse übernommen werden können
+                None if b.hat_passwort || b.rechte == crate::rollen::ADMIN_ROLLE => {
+                    return Err(format!(
+                        "Das Benutzerkonto {} hat ein Passwort oder Administratorrechte und wird nicht automatisch verknüpft, bitte mit Passwort anmelden",
+                        b.email
+                    ));
+                }
+                None => {}
+            }
+            crate::ratenbegrenzung::kontosperre_pruefen(&konfiguration, &b.email)?;
+            // Ohne passende Gruppe bleiben die bisherigen Rechte erhalten
+            let rechte = gruppen_rechte.unwrap_or(b.rechte.clone());
+            if b.oidc_subjekt.is_some() && rechte == b.rechte {
+                return Ok(b.email);
+            }
+            (b.email, rechte)
+        }
+        None => {
+            let standard_rechte = k
+                .standard_rechte
+                .clone()
+                .filter(|r| crate::rollen::get_rolle(&konfiguration, r).ok().flatten().is_some());
+            let rechte = gruppen_rechte.or(standard_rechte).ok_or(format!(
+                "Für {} ist keine Berechtigung für das Digitale Grundbuch hinterlegt",
+                oidc_benutzer.email
+            ))?;
+            crate::ratenbegrenzung::kontosperre_pruefen(&konfiguration, &oidc_benutzer.email)?;
+            (oidc_benutzer.email.clone(), rechte)
+        }
+    };
+
+    crate::api::write_to_root_db(
+        DbChangeOp::OidcBenutzerAnmelden {
+            email: email.clone(),
+            name: oidc_benutzer.name.clone(),
+            rechte,
+            subjekt: oidc_benutzer.subjekt.clone(),
+        },
+        &email,
+        app_state,
+    )
+    .await?;
+
+    Ok(email)
+}
+
+/// Prüft nach der Anmeldung beim Anbieter die lokale Zwei-Faktor-Authentifizierung:
+/// Ohne `zwei_faktor_beim_anbieter` wird die Anmeldung für Konten mit aktivierter
+/// oder vorgeschriebener 2FA abgelehnt, da hier kein Code abgefragt werden kann.
+pub fn zwei_faktor_pruefen(
+    konfiguration: &Konfiguration,
+    k: &OidcKonfiguration,
+    email: &str,
+) -> Result<(), String> {
+    if k.zwei_faktor_beim_anbieter {
+        return Ok(());
+    }
+
+    let benutzer = get_benutzer_info(konfiguration, email)?;
+    if crate::zwei_faktor::get_zwei_faktor(konfiguration, &benutzer).map_or(false, |z| z.aktiviert)
+        || crate::zwei_faktor::ist_pflicht(konfiguration, &benutzer)
+    {
+        return Err(format!(
+            "Für das Benutzerkonto {email} ist die Zwei-Faktor-Authentifizierung aktiviert oder vorgeschrieben, eine Anmeldung über den Anbieter ist deshalb nicht möglich"
+        ));
+    }
+
+    Ok(())
+}
+
+/// Übernimmt der Anbieter den zweiten Faktor für diesen Benutzer? Gilt nur für
+/// verknüpfte Konten ohne Passwort, die sich also nur über den Anbieter anmelden können.
+pub fn zwei_faktor_beim_anbieter(konfiguration: &Konfiguration, benutzer: &BenutzerInfo) -> bool {
+    let aktiv = konfiguration
+        .oidc
+        .as_ref()
+        .map_or(false, |k| k.zwei_faktor_beim_anbieter);
+    if !aktiv {
+        return false;
+    }
+
+    let conn = match crate::db::verbindung_oeffnen(konfiguration, MountPoint::Local) {
+        Ok(o) => o,
+        Err(_) => return false,
+    };
+
+    conn.query_row(
+        "SELECT oidc_subjekt IS NOT NULL AND password_hashed IS NULL FROM benutzer WHERE id = ?1",
+        rusqlite::params![benutzer.id],
+        |row| row.get::<usize, bool>(0),
+    )
+    .unwrap_or(false)
+}
+
+fn get_benutzer_info(konfiguration: &Konfiguration, email: &str) -> Result<BenutzerInfo, String> {
+    let conn = crate::db::verbindung_oeffnen(konfiguration, MountPoint::Local)
+        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+
+    crate::db::get_benutzer_by_email(&conn, email)?
+        .ok_or(format!("Benutzer {email} nicht gefunden"))
+}
+
+/// Aktualisiert Rechte und Verknüpfung oder legt den Benutzer ohne Passwort an
+pub fn oidc_benutzer_speichern(
+    conn: &Connection,
+    email: &str,
+    name: &str,
+    rechte: &str,
+    subjekt: &str,
+) -> Result<(), String> {
+    let geaendert = conn
+        .execute(
+            "UPDATE benutzer SET rechte = ?1, oidc_subjekt = ?2 WHERE email = ?3",
+            rusqlite::params![rechte, subjekt, email],
+        )
+        .map_err(|e| format!("Fehler beim Verknüpfen des Benutzers: {e}"))?;
+
+    if geaendert > 0 {
+        return Ok(());
+    }
+
+    conn.execute(
+        "INSERT INTO benutzer (name, email, rechte, password_hashed, oidc_subjekt) VALUES (?1, ?2, ?3, NULL, ?4)",
+        rusqlite::params![name, email, rechte, subjekt],
+    )
+    .map_err(|e| format!("Fehler beim Anlegen des Benutzers: {e}"))?;
+
+    Ok(())
+}
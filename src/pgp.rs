@@ -1,9 +1,13 @@
 use std::io::{self, Write};
 
 use sequoia_openpgp::cert::prelude::*;
+use sequoia_openpgp::crypto::SessionKey;
+use sequoia_openpgp::packet::{PKESK, SKESK};
 use sequoia_openpgp::parse::PacketParser;
 use sequoia_openpgp::parse::{stream::*, Parse};
 use sequoia_openpgp::policy::Policy;
+use sequoia_openpgp::serialize::stream::{Encryptor, LiteralWriter, Message};
+use sequoia_openpgp::types::SymmetricAlgorithm;
 
 pub fn parse_cert(cert: &[u8]) -> Result<sequoia_openpgp::Cert, String> {
     let ppr = PacketParser::from_bytes(cert).map_err(|e| format!("{e}"))?;
@@ -40,6 +44,99 @@ pub fn verify(
     Ok(())
 }
 
+/// Gibt einen Writer zurück, der alle Daten für den Empfänger verschlüsselt
+/// in `sink` schreibt. Der Writer muss mit `.finalize()` abgeschlossen werden.
+pub fn encrypt<'a>(
+    p: &'a dyn Policy,
+    sink: &'a mut (dyn Write + Send + Sync),
+    recipient: &'a sequoia_openpgp::Cert,
+) -> sequoia_openpgp::Result<Message<'a>> {
+    let recipients = recipient
+        .keys()
+        .with_policy(p, None)
+        .supported()
+        .alive()
+        .revoked(false)
+        .for_storage_encryption()
+        .collect::<Vec<_>>();
+
+    if recipients.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Schlüssel {} hat keinen Unterschlüssel zur Verschlüsselung",
+            recipient.fingerprint()
+        ));
+    }
+
+    let message = Message::new(sink);
+    let message = Encryptor::for_recipients(message, recipients).build()?;
+    LiteralWriter::new(message).build()
+}
+
+/// Gibt einen Reader zurück, der die Nachricht mit dem privaten Schlüssel entschlüsselt
+pub fn decrypt<'a, R: io::Read + Send + Sync + 'a>(
+    p: &'a dyn Policy,
+    source: R,
+    secret: &'a sequoia_openpgp::Cert,
+) -> sequoia_openpgp::Result<Decryptor<'a, DecryptHelper<'a>>> {
+    let helper = DecryptHelper { policy: p, secret };
+    DecryptorBuilder::from_reader(source)?.with_policy(p, None, helper)
+}
+
+pub struct DecryptHelper<'a> {
+    policy: &'a dyn Policy,
+    secret: &'a sequoia_openpgp::Cert,
+}
+
+impl<'a> VerificationHelper for DecryptHelper<'a> {
+    fn get_certs(
+        &mut self,
+        _ids: &[sequoia_openpgp::KeyHandle],
+    ) -> sequoia_openpgp::Result<Vec<sequoia_openpgp::Cert>> {
+        Ok(Vec::new())
+    }
+
+    fn check(&mut self, _structure: MessageStructure) -> sequoia_openpgp::Result<()> {
+        // Verschlüsselte Backups sind nicht signiert
+        Ok(())
+    }
+}
+
+impl<'a> DecryptionHelper for DecryptHelper<'a> {
+    fn decrypt<D>(
+        &mut self,
+        pkesks: &[PKESK],
+        _skesks: &[SKESK],
+        sym_algo: Option<SymmetricAlgorithm>,
+        mut decrypt: D,
+    ) -> sequoia_openpgp::Result<Option<sequoia_openpgp::Fingerprint>>
+    where
+        D: FnMut(SymmetricAlgorithm, &SessionKey) -> bool,
+    {
+        let keys = self
+            .secret
+            .keys()
+            .unencrypted_secret()
+            .with_policy(self.policy, None)
+            .for_storage_encryption()
+            .collect::<Vec<_>>();
+
+        for ka in keys {
+            let mut pair = ka.key().clone().into_keypair()?;
+            for pkesk in pkesks {
+                if let Some((algo, session_key)) = pkesk.decrypt(&mut pair, sym_algo) {
+                    if decrypt(algo, &session_key) {
+                        return Ok(Some(ka.fingerprint()));
+                    }
+                }
+            }
+        }
+
+        Err(anyhow::anyhow!(
+            "Kein passender privater Schlüssel zum Entschlüsseln gefunden"
+        ))
+    }
+}
+
 struct Helper<'a> {
     cert: &'a sequoia_openpgp::Cert,
 }
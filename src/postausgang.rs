@@ -0,0 +1,576 @@
+//! Postausgang für Benachrichtigungen über Grundbuchänderungen
+//!
+//! `commit_changes` verschickt E-Mails und Webhooks an Abonnenten nicht selbst,
+//! sondern reiht sie über `write_to_root_db` in die Tabelle `postausgang` ein.
+//! Ein Hintergrund-Task auf dem Server, der die Datenbank schreibt, sendet alle
+//! fälligen Einträge. Schlägt das Senden fehl, wird es nach einer Wartezeit
+//! wiederholt, die sich mit jedem Fehlversuch verdoppelt. Nach `max_versuche`
+//! Versuchen bleibt der Eintrag als `fehlgeschlagen` liegen und kann unter
+//! `/konto` ("Benachrichtigungen") erneut gesendet werden.
+//!
+//! E-Mails an Benutzer mit der Einstellung `email.abo.benachrichtigung` =
+//! `taeglich` bzw. `woechentlich` werden als `gesammelt` eingereiht und von
+//! `zusammenfassungen_senden` in einer E-Mail zusammengefasst.
+//!
+//! Gelesen wird direkt aus `mount_point`, das Ergebnis eines Sendeversuchs wird
+//! dagegen über `write_to_root_db` gespeichert, damit alle Pods den aktuellen
+//! Status unter `/konto` und `GET /abos/{id}/zustellungen` anzeigen.
+
+use crate::aenderungen::AenderungsDetails;
+use crate::api::commit::DbChangeOp;
+use crate::config::{Konfiguration, PostausgangKonfiguration};
+use crate::models::{AboBenachrichtigung, AbonnementInfo, MountPoint};
+use crate::AppState;
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::Connection;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+pub const STATUS_OFFEN: &str = "offen";
+pub const STATUS_GESENDET: &str = "gesendet";
+/// Maximale Anzahl an Versuchen erreicht, wird nur manuell erneut gesendet
+pub const STATUS_FEHLGESCHLAGEN: &str = "fehlgeschlagen";
+/// Wartet auf die nächste Zusammenfassung (tägliche / wöchentliche E-Mail)
+pub const STATUS_GESAMMELT: &str = "gesammelt";
+
+/// Maximale Anzahl an Einträgen pro Durchlauf des Hintergrund-Tasks
+const MAX_EINTRAEGE_PRO_DURCHLAUF: usize = 100;
+
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
+pub enum PostausgangTyp {
+    #[serde(rename = "email")]
+    Email,
+    #[serde(rename = "webhook")]
+    Webhook,
+}
+
+impl PostausgangTyp {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            PostausgangTyp::Email => "email",
+            PostausgangTyp::Webhook => "webhook",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        match s {
+            "email" => Some(PostausgangTyp::Email),
+            "webhook" => Some(PostausgangTyp::Webhook),
+            _ => None,
+        }
+    }
+}
+
+/// Neue Benachrichtigung, wird mit `DbChangeOp::PostausgangEinreihen` gespeichert
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PostausgangNeu {
+    pub id: String,
+    pub typ: PostausgangTyp,
+    pub abo: AbonnementInfo,
+    pub aenderungs_id: String,
+    /// Nur bei Abos mit Detailstufe `details`
+    #[serde(default)]
+    pub details: Option<AenderungsDetails>,
+}
+
+impl PostausgangNeu {
+    pub fn neu(
+        typ: PostausgangTyp,
+        abo: &AbonnementInfo,
+        aenderungs_id: &str,
+        details: Option<&AenderungsDetails>,
+    ) -> Self {
+        Self {
+            id: crate::db::generate_uuid(),
+            typ,
+            abo: abo.clone(),
+            aenderungs_id: aenderungs_id.to_string(),
+            details: details.cloned(),
+        }
+    }
+}
+
+/// Inhalt der Spalte `daten`: das Abo und ggf. die Details der Änderung
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+struct PostausgangDaten {
+    #[serde(flatten)]
+    abo: AbonnementInfo,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    details: Option<AenderungsDetails>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PostausgangEintrag {
+    pub id: String,
+    pub typ: String,
+    /// E-Mail-Adresse bzw. URL des Webhooks
+    pub ziel: String,
+    pub abo_id: Option<String>,
+    pub aenderungs_id: Option<String>,
+    /// `AbonnementInfo` (und ggf. `details`) als JSON
+    pub daten: String,
+    pub status: String,
+    pub versuche: u32,
+    pub naechster_versuch: String,
+    pub letzter_fehler: Option<String>,
+    pub erstellt_am: String,
+    pub gesendet_am: Option<String>,
+}
+
+const EINTRAG_SPALTEN: &str = "id, typ, ziel, abo_id, aenderungs_id, daten, status, versuche, naechster_versuch, letzter_fehler, erstellt_am, gesendet_am";
+
+fn eintrag_aus_zeile(r: &rusqlite::Row) -> rusqlite::Result<PostausgangEintrag> {
+    Ok(PostausgangEintrag {
+        id: r.get(0)?,
+        typ: r.get(1)?,
+        ziel: r.get(2)?,
+        abo_id: r.get(3)?,
+        aenderungs_id: r.get(4)?,
+        daten: r.get(5)?,
+        status: r.get(6)?,
+        versuche: r.get(7)?,
+        naechster_versuch: r.get(8)?,
+        letzter_fehler: r.get(9)?,
+        erstellt_am: r.get(10)?,
+        gesendet_am: r.get(11)?,
+    })
+}
+
+pub fn einreihen(
+    conn: &Connection,
+    eintraege: &[PostausgangNeu],
+    datum: &str,
+) -> Result<(), String> {
+    for e in eintraege.iter() {
+        let status = match e.typ {
+            PostausgangTyp::Email => match crate::db::get_abo_benachrichtigung(conn, &e.abo.text) {
+                AboBenachrichtigung::Sofort => STATUS_OFFEN,
+                AboBenachrichtigung::Taeglich | AboBenachrichtigung::Woechentlich => {
+                    STATUS_GESAMMELT
+                }
+            },
+            PostausgangTyp::Webhook => STATUS_OFFEN,
+        };
+        conn.execute(
+            "INSERT OR IGNORE INTO postausgang
+            (id, typ, ziel, abo_id, aenderungs_id, daten, status, versuche, naechster_versuch, erstellt_am)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, 0, ?8, ?8)",
+            rusqlite::params![
+                e.id,
+                e.typ.as_str(),
+                e.abo.text,
+                e.abo.id,
+                e.aenderungs_id,
+                serde_json::to_string(&PostausgangDaten {
+                    abo: e.abo.clone(),
+                    details: e.details.clone(),
+                })
+                .unwrap_or_default(),
+                status,
+                datum,
+            ],
+        )
+        .map_err(|e| format!("Fehler beim Einreihen der Benachrichtigung: {e}"))?;
+    }
+
+    Ok(())
+}
+
+/// Setzt Einträge zurück, sodass sie beim nächsten Durchlauf (mit allen Versuchen) gesendet werden
+pub fn erneut_senden(conn: &Connection, ids: &[String], datum: &str) -> Result<(), String> {
+    for id in ids.iter() {
+        conn.execute(
+            "UPDATE postausgang SET status = ?1, versuche = 0, naechster_versuch = ?2, gesendet_am = NULL
+            WHERE id = ?3",
+            rusqlite::params![STATUS_OFFEN, datum, id],
+        )
+        .map_err(|e| format!("Fehler beim erneuten Senden: {e}"))?;
+    }
+
+    Ok(())
+}
+
+/// Speichert das Ergebnis eines Sendeversuchs für alle `ids`
+pub fn ergebnis_setzen(
+    conn: &Connection,
+    ids: &[String],
+    status: &str,
+    versuche: u32,
+    naechster_versuch: &str,
+    letzter_fehler: Option<&str>,
+    gesendet_am: Option<&str>,
+) -> Result<(), String> {
+    for id in ids.iter() {
+        conn.execute(
+            "UPDATE postausgang SET status = ?1, versuche = ?2, naechster_versuch = ?3, letzter_fehler = ?4, gesendet_am = ?5
+            WHERE id = ?6",
+            rusqlite::params![status, versuche, naechster_versuch, letzter_fehler, gesendet_am, id],
+        )
+        .map_err(|e| format!("Fehler beim Speichern des Sendeergebnisses: {e}"))?;
+    }
+
+    Ok(())
+}
+
+/// Löscht noch nicht gesendete Einträge eines gelöschten Abonnements
+pub fn abo_eintraege_loeschen(conn: &Connection, abo_id: &str) -> Result<(), String> {
+    conn.execute(
+        "DELETE FROM postausgang WHERE abo_id = ?1 AND status IN (?2, ?3)",
+        rusqlite::params![abo_id, STATUS_OFFEN, STATUS_GESAMMELT],
+    )
+    .map_err(|e| format!("Fehler beim Löschen der Benachrichtigungen für Abonnement {abo_id}: {e}"))?;
+
+    Ok(())
+}
+
+/// Neueste Einträge zuerst (für `/konto`)
+pub fn get_eintraege(
+    konfiguration: &Konfiguration,
+    limit: usize,
+) -> Result<Vec<PostausgangEintrag>, String> {
+    let conn = crate::db::verbindung_oeffnen(konfiguration, MountPoint::Local)
+        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {EINTRAG_SPALTEN} FROM postausgang ORDER BY erstellt_am DESC LIMIT ?1"
+        ))
+        .map_err(|e| format!("Fehler beim Auslesen des Postausgangs: {e}"))?;
+
+    let eintraege = stmt
+        .query_map(rusqlite::params![limit as i64], eintrag_aus_zeile)
+        .map_err(|e| format!("Fehler beim Auslesen des Postausgangs: {e}"))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Fehler beim Auslesen des Postausgangs: {e}"))?;
+
+    Ok(eintraege)
+}
+
+/// Zustellung an ein Abonnement (ohne Inhalt), für `GET /abos/{id}/zustellungen`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AboZustellung {
+    /// Bei Webhooks identisch mit `zustellungs_id` im Webhook-JSON
+    pub id: String,
+    pub typ: String,
+    pub aenderungs_id: Option<String>,
+    pub status: String,
+    pub versuche: u32,
+    pub naechster_versuch: String,
+    pub letzter_fehler: Option<String>,
+    pub erstellt_am: String,
+    pub gesendet_am: Option<String>,
+}
+
+/// Neueste Zustellungen an ein Abonnement zuerst
+pub fn get_zustellungen_fuer_abo(
+    konfiguration: &Konfiguration,
+    abo_id: &str,
+    limit: usize,
+) -> Result<Vec<AboZustellung>, String> {
+    let conn = crate::db::verbindung_oeffnen(konfiguration, MountPoint::Local)
+        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {EINTRAG_SPALTEN} FROM postausgang WHERE abo_id = ?1
+            ORDER BY erstellt_am DESC LIMIT ?2"
+        ))
+        .map_err(|e| format!("Fehler beim Auslesen des Postausgangs: {e}"))?;
+
+    let eintraege = stmt
+        .query_map(rusqlite::params![abo_id, limit as i64], eintrag_aus_zeile)
+        .map_err(|e| format!("Fehler beim Auslesen des Postausgangs: {e}"))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Fehler beim Auslesen des Postausgangs: {e}"))?;
+
+    Ok(eintraege
+        .into_iter()
+        .map(|e| AboZustellung {
+            id: e.id,
+            typ: e.typ,
+            aenderungs_id: e.aenderungs_id,
+            status: e.status,
+            versuche: e.versuche,
+            naechster_versuch: e.naechster_versuch,
+            letzter_fehler: e.letzter_fehler,
+            erstellt_am: e.erstellt_am,
+            gesendet_am: e.gesendet_am,
+        })
+        .collect())
+}
+
+fn get_faellige(
+    konfiguration: &Konfiguration,
+    mount_point: MountPoint,
+    jetzt: &str,
+) -> Result<Vec<PostausgangEintrag>, String> {
+    let conn = crate::db::verbindung_oeffnen(konfiguration, mount_point)
+        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {EINTRAG_SPALTEN} FROM postausgang
+            WHERE status = ?1 AND naechster_versuch <= ?2
+            ORDER BY naechster_versuch LIMIT ?3"
+        ))
+        .map_err(|e| format!("Fehler beim Auslesen des Postausgangs: {e}"))?;
+
+    let eintraege = stmt
+        .query_map(
+            rusqlite::params![STATUS_OFFEN, jetzt, MAX_EINTRAEGE_PRO_DURCHLAUF as i64],
+            eintrag_aus_zeile,
+        )
+        .map_err(|e| format!("Fehler beim Auslesen des Postausgangs: {e}"))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Fehler beim Auslesen des Postausgangs: {e}"))?;
+
+    Ok(eintraege)
+}
+
+/// Wartezeit vor dem nächsten Versuch nach `versuche` Fehlversuchen
+fn wartezeit(konfiguration: &PostausgangKonfiguration, versuche: u32) -> Duration {
+    let faktor = 2_i64.saturating_pow(versuche.saturating_sub(1).min(30));
+    let sekunden = konfiguration
+        .wartezeit_sekunden
+        .saturating_mul(faktor)
+        .min(konfiguration.max_wartezeit_minuten.saturating_mul(60));
+    Duration::seconds(sekunden)
+}
+
+async fn ergebnis_speichern(
+    konfiguration: &PostausgangKonfiguration,
+    eintrag: &PostausgangEintrag,
+    ergebnis: &Result<(), String>,
+    app_state: &AppState,
+) -> Result<(), String> {
+    let jetzt = Utc::now();
+    let versuche = eintrag.versuche + 1;
+
+    let (status, naechster_versuch, gesendet_am) = match ergebnis {
+        Ok(()) => (STATUS_GESENDET, jetzt, Some(jetzt.to_rfc3339())),
+        Err(_) => {
+            let status = if versuche >= konfiguration.max_versuche {
+                STATUS_FEHLGESCHLAGEN
+            } else {
+                STATUS_OFFEN
+            };
+            (status, jetzt + wartezeit(konfiguration, versuche), None)
+        }
+    };
+
+    crate::api::write_to_root_db(
+        DbChangeOp::PostausgangErgebnisSpeichern {
+            ids: vec![eintrag.id.clone()],
+            status: status.to_string(),
+            versuche,
+            naechster_versuch: naechster_versuch.to_rfc3339(),
+            letzter_fehler: ergebnis.as_ref().err().cloned(),
+            gesendet_am,
+        },
+        "system",
+        app_state,
+    )
+    .await
+}
+
+async fn senden(konfiguration: &Konfiguration, eintrag: &PostausgangEintrag) -> Result<(), String> {
+    let PostausgangDaten { abo, details } = serde_json::from_str(&eintrag.daten)
+        .map_err(|e| format!("Ungültige Daten im Postausgang: {e}"))?;
+    let aenderungs_id = eintrag.aenderungs_id.clone().unwrap_or_default();
+
+    match PostausgangTyp::from_str(&eintrag.typ) {
+        Some(PostausgangTyp::Webhook) => {
+            crate::email::send_change_webhook(
+                konfiguration,
+                &abo,
+                &aenderungs_id,
+                &eintrag.id,
+                details.as_ref(),
+            )
+            .await
+        }
+        // SmtpTransport blockiert, daher nicht im async-Task selbst senden
+        Some(PostausgangTyp::Email) => {
+            let konfiguration = konfiguration.clone();
+            tokio::task::spawn_blocking(move || {
+                crate::email::send_change_email(&konfiguration, &abo, &aenderungs_id, details.as_ref())
+            })
+            .await
+            .map_err(|e| format!("{e}"))?
+        }
+        None => Err(format!("Unbekannter Typ \"{}\"", eintrag.typ)),
+    }
+}
+
+/// Sendet alle fälligen Einträge, gibt die Anzahl der erfolgreich gesendeten zurück
+pub async fn verarbeiten(mount_point: MountPoint, app_state: &AppState) -> Result<usize, String> {
+    let konfiguration = app_state.konfiguration();
+    let faellig = get_faellige(&konfiguration, mount_point, &Utc::now().to_rfc3339())?;
+    let mut gesendet = 0;
+
+    for eintrag in faellig.iter() {
+        let ergebnis = senden(&konfiguration, eintrag).await;
+        match ergebnis.as_ref() {
+            Ok(()) => gesendet += 1,
+            Err(e) => println!(
+                "Postausgang: Senden an {} fehlgeschlagen (Versuch {}): {e}",
+                eintrag.ziel,
+                eintrag.versuche + 1
+            ),
+        }
+        ergebnis_speichern(&konfiguration.postausgang, eintrag, &ergebnis, app_state).await?;
+    }
+
+    Ok(gesendet)
+}
+
+/// Sendet je Empfänger eine Zusammenfassung aller gesammelten E-Mails, sobald
+/// die älteste davon einen Tag (`taeglich`) bzw. eine Woche (`woechentlich`)
+/// alt ist. Gibt die Anzahl der gesendeten Zusammenfassungen zurück.
+pub async fn zusammenfassungen_senden(
+    mount_point: MountPoint,
+    app_state: &AppState,
+) -> Result<usize, String> {
+    let konfiguration = app_state.konfiguration();
+    let conn = crate::db::verbindung_oeffnen(&konfiguration, mount_point)
+        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+
+    let gesammelt = {
+        let mut stmt = conn
+            .prepare(&format!(
+                "SELECT {EINTRAG_SPALTEN} FROM postausgang WHERE status = ?1 ORDER BY erstellt_am"
+            ))
+            .map_err(|e| format!("Fehler beim Auslesen des Postausgangs: {e}"))?;
+
+        let eintraege = stmt
+            .query_map(rusqlite::params![STATUS_GESAMMELT], eintrag_aus_zeile)
+            .map_err(|e| format!("Fehler beim Auslesen des Postausgangs: {e}"))?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Fehler beim Auslesen des Postausgangs: {e}"))?;
+
+        eintraege
+    };
+
+    let mut nach_empfaenger = BTreeMap::<String, Vec<PostausgangEintrag>>::new();
+    for e in gesammelt {
+        nach_empfaenger.entry(e.ziel.clone()).or_default().push(e);
+    }
+
+    let nach_empfaenger = nach_empfaenger
+        .into_iter()
+        .map(|(empfaenger, eintraege)| {
+            let benachrichtigung = crate::db::get_abo_benachrichtigung(&conn, &empfaenger);
+            (empfaenger, benachrichtigung, eintraege)
+        })
+        .collect::<Vec<_>>();
+    drop(conn);
+
+    let jetzt = Utc::now();
+    let mut gesendet = 0;
+
+    for (empfaenger, benachrichtigung, eintraege) in nach_empfaenger {
+
+        // Nach einem Wechsel auf "sofort" wird der Rest direkt gesendet
+        let aelteste = eintraege
+            .iter()
+            .filter_map(|e| DateTime::parse_from_rfc3339(&e.erstellt_am).ok())
+            .min();
+        let faellig = match (benachrichtigung.get_intervall_tage(), aelteste) {
+            (Some(tage), Some(aelteste)) => {
+                jetzt.signed_duration_since(aelteste) >= Duration::days(tage)
+            }
+            _ => true,
+        };
+        let wartet = eintraege
+            .iter()
+            .any(|e| e.naechster_versuch.as_str() > jetzt.to_rfc3339().as_str());
+        if !faellig || wartet {
+            continue;
+        }
+
+        let aenderungen = eintraege
+            .iter()
+            .filter_map(|e| {
+                let daten = serde_json::from_str::<PostausgangDaten>(&e.daten).ok()?;
+                Some((
+                    daten.abo,
+                    e.aenderungs_id.clone().unwrap_or_default(),
+                    daten.details,
+                ))
+            })
+            .collect::<Vec<_>>();
+
+        // SmtpTransport blockiert, daher nicht im async-Task selbst senden
+        let email = empfaenger.clone();
+        let konfiguration_smtp = konfiguration.clone();
+        let ergebnis = tokio::task::spawn_blocking(move || {
+            crate::email::send_abo_zusammenfassung_email(
+                &konfiguration_smtp,
+                &email,
+                benachrichtigung,
+                &aenderungen,
+            )
+        })
+        .await
+        .map_err(|e| format!("{e}"))
+        .and_then(|r| r);
+
+        // Fehlversuche zählen für alle enthaltenen Einträge
+        let versuche = eintraege.iter().map(|e| e.versuche).max().unwrap_or(0) + 1;
+        let (status, naechster_versuch, gesendet_am) = match ergebnis.as_ref() {
+            Ok(()) => {
+                gesendet += 1;
+                (STATUS_GESENDET, jetzt, Some(jetzt.to_rfc3339()))
+            }
+            Err(e) => {
+                println!(
+                    "Postausgang: Zusammenfassung an {empfaenger} fehlgeschlagen (Versuch {versuche}): {e}"
+                );
+                let status = if versuche >= konfiguration.postausgang.max_versuche {
+                    STATUS_FEHLGESCHLAGEN
+                } else {
+                    STATUS_GESAMMELT
+                };
+                (status, jetzt + wartezeit(&konfiguration.postausgang, versuche), None)
+            }
+        };
+
+        crate::api::write_to_root_db(
+            DbChangeOp::PostausgangErgebnisSpeichern {
+                ids: eintraege.iter().map(|e| e.id.clone()).collect(),
+                status: status.to_string(),
+                versuche,
+                naechster_versuch: naechster_versuch.to_rfc3339(),
+                letzter_fehler: ergebnis.as_ref().err().cloned(),
+                gesendet_am,
+            },
+            "system",
+            app_state,
+        )
+        .await?;
+    }
+
+    Ok(gesendet)
+}
+
+/// Löscht gesendete Einträge, die älter als `aufbewahrung_tage` sind (`0` = unbegrenzt)
+pub fn bereinigen(
+    konfiguration: &Konfiguration,
+    mount_point: MountPoint,
+    aufbewahrung_tage: u32,
+) -> Result<usize, String> {
+    if aufbewahrung_tage == 0 {
+        return Ok(0);
+    }
+
+    let conn = crate::db::verbindung_oeffnen(konfiguration, mount_point)
+        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+
+    let grenze = Utc::now() - Duration::days(aufbewahrung_tage as i64);
+
+    conn.execute(
+        "DELETE FROM postausgang WHERE status = ?1 AND gesendet_am < ?2",
+        rusqlite::params![STATUS_GESENDET, grenze.to_rfc3339()],
+    )
+    .map_err(|e| format!("Fehler beim Bereinigen des Postausgangs: {e}"))
+}
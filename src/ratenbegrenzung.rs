@@ -0,0 +1,709 @@
+//! Ratenbegrenzung für `/login`, `/zugriff` und `/passwort-vergessen`
+//!
+//! Fehlgeschlagene Anmeldungen werden pro E-Mail und pro IP in der
+//! Benutzerdatenbank gespeichert (über `write_to_root_db`, damit alle Pods
+//! dieselben Zähler sehen). Ab `verzoegerung_ab_fehlversuchen` Fehlversuchen
+//! innerhalb von `zeitraum_minuten` muss vor dem nächsten Versuch gewartet werden,
+//! die Wartezeit verdoppelt sich mit jedem weiteren Fehlversuch. Ab
+//! `sperre_ab_fehlversuchen` wird das Konto gesperrt, bis die Sperre abläuft
+//! oder ein Administrator das Konto entsperrt. Eine erfolgreiche Anmeldung
+//! setzt die Fehlversuche des Kontos zurück.
+//!
+//! Anonyme Zugriffsanfragen werden pro E-Mail und IP auf eine Anzahl
+//! angefragter Zeilen (Blätter, Bereiche oder Platzhalter) pro Tag begrenzt, Anfragen auf `/passwort-vergessen`
+//! pro E-Mail und IP auf eine Anzahl pro Stunde. Beide werden ebenfalls in der
+//! Benutzerdatenbank gezählt.
+
+use crate::api::commit::DbChangeOp;
+use crate::config::{Konfiguration, RatenbegrenzungKonfiguration};
+use crate::models::MountPoint;
+use crate::AppState;
+use actix_web::HttpRequest;
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::{Connection, OptionalExtension};
+use std::collections::BTreeMap;
+use std::net::IpAddr;
+
+/// IP-Adresse des Clients. `Forwarded` bzw. `X-Forwarded-For` werden nur
+/// ausgewertet, wenn die Anfrage von einem der `vertrauenswuerdige_proxies`
+/// kommt. Verwendet wird dann die letzte Adresse der Kette, die nicht selbst
+/// ein vertrauenswürdiger Proxy ist (die vorderen Einträge kann der Client fälschen).
+pub fn get_ip(konfiguration: &Konfiguration, req: &HttpRequest) -> String {
+    let proxies = &konfiguration.ratenbegrenzung.vertrauenswuerdige_proxies;
+    let ist_proxy = |ip: IpAddr| proxies.iter().any(|netz| im_netz(ip, netz));
+
+    let peer = match req.peer_addr() {
+        Some(s) => s.ip(),
+        None => return "unbekannt".to_string(),
+    };
+
+    if !ist_proxy(peer) {
+        return peer.to_string();
+    }
+
+    let mut kette = Vec::new();
+    for wert in req.headers().get_all("forwarded") {
+        let wert = wert.to_str().unwrap_or_default();
+        for teil in wert.split(|c| c == ',' || c == ';') {
+            let (name, adresse) = match teil.split_once('=') {
+                Some(s) => s,
+                None => continue,
+            };
+            if name.trim().eq_ignore_ascii_case("for") {
+                kette.push(forwarded_adresse_parsen(adresse));
+            }
+        }
+    }
+    if kette.is_empty() {
+        for wert in req.headers().get_all("x-forwarded-for") {
+            let wert = wert.to_str().unwrap_or_default();
+            kette.extend(wert.split(',').map(forwarded_adresse_parsen));
+        }
+    }
+
+    for ip in kette.into_iter().rev() {
+        match ip {
+            Some(ip) if ist_proxy(ip) => continue,
+            Some(ip) => return ip.to_string(),
+            // Unlesbarer Eintrag hinter dem Proxy: nicht weiter zurückverfolgen
+            None => break,
+        }
+    }
+
+    peer.to_string()
+}
+
+/// `1.2.3.4`, `1.2.3.4:80`, `"[::1]:80"` oder `::1`
+fn forwarded_adresse_parsen(s: &str) -> Option<IpAddr> {
+    let s = s.trim().trim_matches('"');
+    if let Ok(ip) = s.parse::<IpAddr>() {
+        return Some(ip);
+    }
+    if let Ok(addr) = s.parse::<std::net::SocketAddr>() {
+        return Some(addr.ip());
+    }
+    s.trim_start_matches('[').split(']').next()?.parse().ok()
+}
+
+/// Prüft, ob `netz` eine gültige IP-Adresse oder ein Netz in CIDR-Schreibweise ist
+pub fn netz_pruefen(netz: &str) -> bool {
+    netz_parsen(netz).is_some()
+}
+
+fn netz_parsen(netz: &str) -> Option<(IpAddr, u32)> {
+    let (adresse, praefix) = match netz.trim().split_once('/') {
+        Some((a, p)) => (a, Some(p)),
+        None => (netz.trim(), None),
+    };
+    let adresse = adresse.parse::<IpAddr>().ok()?;
+    let max = if adresse.is_ipv4() { 32 } else { 128 };
+    let praefix = match praefix {
+        Some(p) => p.trim().parse::<u32>().ok().filter(|p| *p <= max)?,
+        None => max,
+    };
+    Some((adresse, praefix))
+}
+
+fn im_netz(ip: IpAddr, netz: &str) -> bool {
+    let (basis, praefix) = match netz_parsen(netz) {
+        Some(s) => s,
+        None => return false,
+    };
+
+    let ip = match ip {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(ip),
+        ip => ip,
+    };
+
+    match (ip, basis) {
+        (IpAddr::V4(ip), IpAddr::V4(basis)) => {
+            let maske = u32::MAX.checked_shl(32 - praefix).unwrap_or(0);
+            u32::from(ip) & maske == u32::from(basis) & maske
+        }
+        (IpAddr::V6(ip), IpAddr::V6(basis)) => {
+            let maske = u128::MAX.checked_shl(128 - praefix).unwrap_or(0);
+            u128::from(ip) & maske == u128::from(basis) & maske
+        }
+        _ => false,
+    }
+}
+
+/// E-Mail-Adressen werden ohne Groß- / Kleinschreibung gezählt
+fn normalisieren(email: &str) -> String {
+    email.trim().to_lowercase()
+}
+
+fn parse_zeitpunkt(s: &str) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc3339(s)
+        .ok()
+        .map(|d| d.with_timezone(&Utc))
+}
+
+/// Wartezeit nach `fehlversuche` Fehlversuchen
+fn verzoegerung(k: &RatenbegrenzungKonfiguration, fehlversuche: u32) -> Duration {
+    if fehlversuche < k.verzoegerung_ab_fehlversuchen.max(1) {
+        return Duration::zero();
+    }
+    let exponent = (fehlversuche - k.verzoegerung_ab_fehlversuchen.max(1)).min(30);
+    let sekunden = k
+        .verzoegerung_sekunden
+        .saturating_mul(1_i64 << exponent)
+        .min(k.max_verzoegerung_sekunden);
+    Duration::seconds(sekunden)
+}
+
+/// Anzahl und Zeitpunkt des letzten Fehlversuchs seit `seit`
+/// (`spalte` ist entweder `email` oder `ip`)
+fn get_fehlversuche(
+    conn: &Connection,
+    spalte: &str,
+    wert: &str,
+    seit: &DateTime<Utc>,
+) -> Result<(u32, Option<DateTime<Utc>>), String> {
+    conn.query_row(
+        &format!(
+            "SELECT COUNT(*), MAX(zeitpunkt) FROM fehlanmeldungen WHERE {spalte} = ?1 AND zeitpunkt > ?2"
+        ),
+        rusqlite::params![wert, seit.to_rfc3339()],
+        |row| {
+            Ok((
+                row.get::<usize, u32>(0)?,
+                row.get::<usize, Option<String>>(1)?,
+            ))
+        },
+    )
+    .map(|(anzahl, letzter)| (anzahl, letzter.as_deref().and_then(parse_zeitpunkt)))
+    .map_err(|e| format!("Fehler beim Auslesen der Fehlanmeldungen: {e}"))
+}
+
+/// `None` = nicht gesperrt, `Some(None)` = gesperrt bis zur Entsperrung
+fn get_kontosperre(
+    conn: &Connection,
+    email: &str,
+) -> Result<Option<Option<DateTime<Utc>>>, String> {
+    let sperre = conn
+        .query_row(
+            "SELECT gesperrt_bis FROM kontosperren WHERE email = ?1",
+            rusqlite::params![normalisieren(email)],
+            |row| row.get::<usize, Option<String>>(0),
+        )
+        .optional()
+        .map_err(|e| format!("Fehler beim Auslesen der Kontosperren: {e}"))?;
+
+    Ok(match sperre {
+        None => None,
+        Some(None) => Some(None),
+        Some(Some(bis)) => match parse_zeitpunkt(&bis) {
+            Some(bis) if bis <= Utc::now() => None,
+            bis => Some(bis),
+        },
+    })
+}
+
+/// Momentan gesperrte Konten (E-Mail => gesperrt bis, leer = unbegrenzt)
+pub fn get_kontosperren(
+    konfiguration: &Konfiguration,
+    mount_point: MountPoint,
+) -> Result<BTreeMap<String, String>, String> {
+    let conn = crate::db::verbindung_oeffnen(konfiguration, mount_point)
+        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+
+    let mut stmt = conn
+        .prepare(
+            "SELECT email, gesperrt_bis FROM kontosperren
+            WHERE gesperrt_bis IS NULL OR gesperrt_bis > ?1",
+        )
+        .map_err(|e| format!("Fehler beim Auslesen der Kontosperren: {e}"))?;
+
+    let sperren = stmt
+        .query_map(rusqlite::params![Utc::now().to_rfc3339()], |row| {
+            Ok((
+                row.get::<usize, String>(0)?,
+                row.get::<usize, Option<String>>(1)?.unwrap_or_default(),
+            ))
+        })
+        .map_err(|e| format!("Fehler beim Auslesen der Kontosperren: {e}"))?
+        .filter_map(|r| r.ok())
+        .collect();
+
+    Ok(sperren)
+}
+
+/// Gibt einen Fehler zurück, wenn das Konto momentan gesperrt ist
+pub fn kontosperre_pruefen(konfiguration: &Konfiguration, email: &str) -> Result<(), String> {
+    let conn = crate::db::verbindung_oeffnen(konfiguration, MountPoint::Local)
+        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+
+    match get_kontosperre(&conn, email)? {
+        None => {}
+        Some(None) => {
+            return Err(format!(
+                "Das Konto wurde nach zu vielen Fehlversuchen gesperrt, bitte wenden Sie sich an einen Administrator"
+            ));
+        }
+        Some(Some(bis)) => {
+            return Err(format!(
+                "Das Konto ist nach zu vielen Fehlversuchen bis {} gesperrt",
+                bis.format("%d.%m.%Y %H:%M UTC")
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Prüft vor der Passwortprüfung, ob für E-Mail und IP eine Anmeldung erlaubt ist
+pub fn anmeldung_pruefen(
+    konfiguration: &Konfiguration,
+    email: &str,
+    ip: &str,
+) -> Result<(), String> {
+    let k = &konfiguration.ratenbegrenzung;
+    let jetzt = Utc::now();
+
+    kontosperre_pruefen(konfiguration, email)?;
+
+    let conn = crate::db::verbindung_oeffnen(konfiguration, MountPoint::Local)
+        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+    let seit = jetzt - Duration::minutes(k.zeitraum_minuten);
+    let email = normalisieren(email);
+    for (spalte, wert) in [("email", email.as_str()), ("ip", ip)] {
+        let (anzahl, letzter) = get_fehlversuche(&conn, spalte, wert, &seit)?;
+        let letzter = match letzter {
+            Some(s) => s,
+            None => continue,
+        };
+        let warten_bis = letzter + verzoegerung(k, anzahl);
+        if warten_bis > jetzt {
+            return Err(format!(
+                "Zu viele Fehlversuche, bitte in {} Sekunden erneut versuchen",
+                (warten_bis - jetzt).num_seconds().max(1)
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Speichert einen Fehlversuch und sperrt das Konto, wenn
+/// `sperre_ab_fehlversuchen` erreicht ist
+pub async fn fehlanmeldung_protokollieren(
+    email: &str,
+    ip: &str,
+    app_state: &AppState,
+) -> Result<(), String> {
+    let konfiguration = app_state.konfiguration();
+    let k = &konfiguration.ratenbegrenzung;
+    let jetzt = Utc::now();
+    let email = normalisieren(email);
+
+    let sperren = if k.sperre_ab_fehlversuchen == 0 {
+        false
+    } else {
+        let conn = crate::db::verbindung_oeffnen(&konfiguration, MountPoint::Local)
+            .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+        let seit = jetzt - Duration::minutes(k.zeitraum_minuten);
+        let (anzahl, _) = get_fehlversuche(&conn, "email", &email, &seit)?;
+        anzahl + 1 >= k.sperre_ab_fehlversuchen
+    };
+
+    let gesperrt_bis = if sperren && k.sperrdauer_minuten > 0 {
+        Some((jetzt + Duration::minutes(k.sperrdauer_minuten)).to_rfc3339())
+    } else {
+        None
+    };
+
+    crate::api::write_to_root_db(
+        DbChangeOp::FehlanmeldungProtokollieren {
+            email: email.clone(),
+            ip: ip.to_string(),
+            zeitpunkt: jetzt.to_rfc3339(),
+            sperren,
+            gesperrt_bis,
+        },
+        &email,
+        app_state,
+    )
+    .await
+}
+
+/// Setzt nach einer erfolgreichen Anmeldung die Fehlversuche des Kontos zurück
+pub async fn anmeldung_erfolgreich(email: &str, app_state: &AppState) -> Result<(), String> {
+    let email = normalisieren(email);
+
+    let conn = crate::db::verbindung_oeffnen(&app_state.konfiguration(), MountPoint::Local)
+        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+    let anzahl = conn
+        .query_row(
+            "SELECT COUNT(*) FROM fehlanmeldungen WHERE email = ?1",
+            rusqlite::params![email],
+            |row| row.get::<usize, u32>(0),
+        )
+        .map_err(|e| format!("Fehler beim Auslesen der Fehlanmeldungen: {e}"))?;
+    if anzahl == 0 {
+        return Ok(());
+    }
+
+    crate::api::write_to_root_db(
+        DbChangeOp::FehlanmeldungenZuruecksetzen {
+            email: email.clone(),
+        },
+        &email,
+        app_state,
+    )
+    .await
+}
+
+/// Prüft, ob E-Mail und IP heute noch `anzahl` Zeilen anfragen dürfen
+pub fn zugriff_anfrage_pruefen(
+    konfiguration: &Konfiguration,
+    email: &str,
+    ip: &str,
+    anzahl: usize,
+) -> Result<(), String> {
+    let conn = crate::db::verbindung_oeffnen(konfiguration, MountPoint::Local)
+        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+    zugriff_anfragen_zaehlen(&conn, &konfiguration.ratenbegrenzung, email, ip, anzahl)
+}
+
+fn zugriff_anfragen_zaehlen(
+    conn: &Connection,
+    k: &RatenbegrenzungKonfiguration,
+    email: &str,
+    ip: &str,
+    anzahl: usize,
+) -> Result<(), String> {
+    let seit = (Utc::now() - Duration::days(1)).to_rfc3339();
+    let email = normalisieren(email);
+
+    for (spalte, wert, max) in [
+        ("email", email.as_str(), k.zugriff_anfragen_pro_email_tag),
+        ("ip", ip, k.zugriff_anfragen_pro_ip_tag),
+    ] {
+        let bisher = conn
+            .query_row(
+                &format!(
+                    "SELECT COALESCE(SUM(anzahl), 0) FROM zugriff_anfragen WHERE {spalte} = ?1 AND zeitpunkt > ?2"
+                ),
+                rusqlite::params![wert, seit],
+                |row| row.get::<usize, i64>(0),
+            )
+            .map_err(|e| format!("Fehler beim Auslesen der Zugriffsanfragen: {e}"))?;
+
+        if bisher.max(0) as usize + anzahl > max as usize {
+            return Err(format!(
+                "Zu viele Zugriffsanfragen: Es können höchstens {max} Blätter oder Bereiche pro Tag angefragt werden"
+            ));
+        }
+    }
+
+    Ok(())
+}
+
+/// Prüft, ob E-Mail und IP in der letzten Stunde noch eine weitere
+/// Anfrage auf `/passwort-vergessen` stellen dürfen
+pub fn passwort_vergessen_pruefen(
+    konfiguration: &Konfiguration,
+    email: &str,
+    ip: &str,
+) -> Result<(), String> {
+    let k = &konfiguration.ratenbegrenzung;
+    let conn = crate::db::verbindung_oeffnen(konfiguration, MountPoint::Local)
+        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+    let seit = (Utc::now() - Duration::hours(1)).to_rfc3339();
+    let email = normalisieren(email);
+
+    for (spalte, wert, max) in [
+        ("ip", ip, k.passwort_vergessen_pro_ip_stunde),
+        ("email", email.as_str(), k.passwort_vergessen_pro_email_stunde),
+    ] {
+        let bisher = conn
+            .query_row(
+                &format!(
+                    "SELECT COUNT(*) FROM passwort_vergessen_anfragen WHERE {spalte} = ?1 AND zeitpunkt > ?2"
+                ),
+                rusqlite::params![wert, seit],
+                |row| row.get::<usize, u32>(0),
+            )
+            .map_err(|e| format!("Fehler beim Auslesen der Passwort-Anfragen: {e}"))?;
+
+        if bisher >= max {
+            return Err(format!("Zu viele Anfragen für {spalte} {wert}"));
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn passwort_vergessen_protokollieren(
+    email: &str,
+    ip: &str,
+    app_state: &AppState,
+) -> Result<(), String> {
+    let email = normalisieren(email);
+    crate::api::write_to_root_db(
+        DbChangeOp::PasswortVergessenProtokollieren {
+            email: email.clone(),
+            ip: ip.to_string(),
+            zeitpunkt: Utc::now().to_rfc3339(),
+        },
+        &email,
+        app_state,
+    )
+    .await
+}
+
+pub async fn zugriff_anfrage_protokollieren(
+    email: &str,
+    ip: &str,
+    anzahl: usize,
+    app_state: &AppState,
+) -> Result<(), String> {
+    let email = normalisieren(email);
+    crate::api::write_to_root_db(
+        DbChangeOp::ZugriffAnfrageProtokollieren {
+            email: email.clone(),
+            ip: ip.to_string(),
+            zeitpunkt: Utc::now().to_rfc3339(),
+            anzahl,
+        },
+        &email,
+        app_state,
+    )
+    .await
+}
+
+pub fn fehlanmeldung_einfuegen(
+    conn: &Connection,
+    email: &str,
+    ip: &str,
+    zeitpunkt: &str,
+    sperren: bool,
+    gesperrt_bis: Option<&str>,
+) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO fehlanmeldungen (email, ip, zeitpunkt) VALUES (?1, ?2, ?3)",
+        rusqlite::params![email, ip, zeitpunkt],
+    )
+    .map_err(|e| format!("Fehler beim Speichern der Fehlanmeldung: {e}"))?;
+
+    if sperren {
+        conn.execute(
+            "INSERT OR REPLACE INTO kontosperren (email, gesperrt_am, gesperrt_bis) VALUES (?1, ?2, ?3)",
+            rusqlite::params![email, zeitpunkt, gesperrt_bis],
+        )
+        .map_err(|e| format!("Fehler beim Sperren des Kontos: {e}"))?;
+    }
+
+    Ok(())
+}
+
+pub fn fehlanmeldungen_loeschen(conn: &Connection, email: &str) -> Result<(), String> {
+    conn.execute(
+        "DELETE FROM fehlanmeldungen WHERE email = ?1",
+        rusqlite::params![email],
+    )
+    .map_err(|e| format!("Fehler beim Zurücksetzen der Fehlanmeldungen: {e}"))?;
+
+    Ok(())
+}
+
+/// Hebt die Sperre der Konten auf und setzt deren Fehlversuche zurück
+pub fn konten_entsperren(conn: &Connection, emails: &[String]) -> Result<(), String> {
+    for email in emails {
+        let email = normalisieren(email);
+        conn.execute(
+            "DELETE FROM kontosperren WHERE email = ?1",
+            rusqlite::params![email],
+        )
+        .map_err(|e| format!("Fehler beim Entsperren des Kontos: {e}"))?;
+        conn.execute(
+            "DELETE FROM fehlanmeldungen WHERE email = ?1",
+            rusqlite::params![email],
+        )
+        .map_err(|e| format!("Fehler beim Entsperren des Kontos: {e}"))?;
+    }
+
+    Ok(())
+}
+
+pub fn zugriff_anfrage_einfuegen(
+    conn: &Connection,
+    email: &str,
+    ip: &str,
+    zeitpunkt: &str,
+    anzahl: usize,
+) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO zugriff_anfragen (email, ip, zeitpunkt, anzahl) VALUES (?1, ?2, ?3, ?4)",
+        rusqlite::params![email, ip, zeitpunkt, anzahl as i64],
+    )
+    .map_err(|e| format!("Fehler beim Speichern der Zugriffsanfrage: {e}"))?;
+
+    Ok(())
+}
+
+pub fn passwort_vergessen_einfuegen(
+    conn: &Connection,
+    email: &str,
+    ip: &str,
+    zeitpunkt: &str,
+) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO passwort_vergessen_anfragen (email, ip, zeitpunkt) VALUES (?1, ?2, ?3)",
+        rusqlite::params![email, ip, zeitpunkt],
+    )
+    .map_err(|e| format!("Fehler beim Speichern der Passwort-Anfrage: {e}"))?;
+
+    Ok(())
+}
+
+/// Löscht Fehlanmeldungen und Anfragen, die nicht mehr gezählt werden,
+/// sowie abgelaufene Kontosperren
+pub fn bereinigen(konfiguration: &Konfiguration, mount_point: MountPoint) -> Result<usize, String> {
+    let k = &konfiguration.ratenbegrenzung;
+    let conn = crate::db::verbindung_oeffnen(konfiguration, mount_point)
+        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+
+    let jetzt = Utc::now();
+    let mut geloescht = 0;
+
+    geloescht += conn
+        .execute(
+            "DELETE FROM fehlanmeldungen WHERE zeitpunkt < ?1",
+            rusqlite::params![(jetzt - Duration::minutes(k.zeitraum_minuten)).to_rfc3339()],
+        )
+        .map_err(|e| format!("Fehler beim Bereinigen der Fehlanmeldungen: {e}"))?;
+
+    geloescht += conn
+        .execute(
+            "DELETE FROM passwort_vergessen_anfragen WHERE zeitpunkt < ?1",
+            rusqlite::params![(jetzt - Duration::hours(1)).to_rfc3339()],
+        )
+        .map_err(|e| format!("Fehler beim Bereinigen der Passwort-Anfragen: {e}"))?;
+
+    geloescht += conn
+        .execute(
+            "DELETE FROM zugriff_anfragen WHERE zeitpunkt < ?1",
+            rusqlite::params![(jetzt - Duration::days(1)).to_rfc3339()],
+        )
+        .map_err(|e| format!("Fehler beim Bereinigen der Zugriffsanfragen: {e}"))?;
+
+    // Unbefristete Sperren für E-Mails ohne Benutzerkonto laufen nach einem Tag ab
+    geloescht += conn
+        .execute(
+            "DELETE FROM kontosperren WHERE gesperrt_bis < ?1
+            OR (email NOT IN (SELECT LOWER(email) FROM benutzer) AND gesperrt_am < ?2)",
+            rusqlite::params![jetzt.to_rfc3339(), (jetzt - Duration::days(1)).to_rfc3339()],
+        )
+        .map_err(|e| format!("Fehler beim Bereinigen der Kontosperren: {e}"))?;
+
+    Ok(geloescht)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn forwarded_adressen() {
+        assert_eq!(forwarded_adresse_parsen("1.2.3.4"), Some(ip("1.2.3.4")));
+        assert_eq!(forwarded_adresse_parsen(" 1.2.3.4:8080 "), Some(ip("1.2.3.4")));
+        assert_eq!(forwarded_adresse_parsen("\"[2001:db8::1]:443\""), Some(ip("2001:db8::1")));
+        assert_eq!(forwarded_adresse_parsen("\"[2001:db8::1]\""), Some(ip("2001:db8::1")));
+        assert_eq!(forwarded_adresse_parsen("2001:db8::1"), Some(ip("2001:db8::1")));
+        assert_eq!(forwarded_adresse_parsen("unknown"), None);
+        assert_eq!(forwarded_adresse_parsen("_versteckt"), None);
+        assert_eq!(forwarded_adresse_parsen(""), None);
+    }
+
+    #[test]
+    fn netze() {
+        assert!(netz_pruefen("10.0.0.0/8"));
+        assert!(netz_pruefen("10.0.0.1"));
+        assert!(netz_pruefen("fd00::/8"));
+        assert!(netz_pruefen("0.0.0.0/0"));
+        assert!(!netz_pruefen("10.0.0.0/33"));
+        assert!(!netz_pruefen("fd00::/129"));
+        assert!(!netz_pruefen("10.0.0.0/x"));
+        assert!(!netz_pruefen("proxy.local"));
+    }
+
+    #[test]
+    fn adressen_im_netz() {
+        assert!(im_netz(ip("10.1.2.3"), "10.0.0.0/8"));
+        assert!(!im_netz(ip("11.1.2.3"), "10.0.0.0/8"));
+        assert!(im_netz(ip("172.16.5.4"), "172.16.0.0/12"));
+        assert!(!im_netz(ip("172.32.0.1"), "172.16.0.0/12"));
+        assert!(im_netz(ip("192.168.0.7"), "192.168.0.7"));
+        assert!(!im_netz(ip("192.168.0.8"), "192.168.0.7"));
+        assert!(im_netz(ip("8.8.8.8"), "0.0.0.0/0"));
+        assert!(im_netz(ip("fd00::1"), "fd00::/8"));
+        assert!(!im_netz(ip("fe80::1"), "fd00::/8"));
+        assert!(!im_netz(ip("10.0.0.1"), "ungültig"));
+    }
+
+    #[test]
+    fn ipv4_mapped_im_ipv4_netz() {
+        assert!(im_netz(ip("::ffff:10.1.2.3"), "10.0.0.0/8"));
+        assert!(!im_netz(ip("::ffff:11.1.2.3"), "10.0.0.0/8"));
+        // IPv4- und IPv6-Netze werden sonst nicht vermischt
+        assert!(!im_netz(ip("10.1.2.3"), "::/0"));
+        assert!(!im_netz(ip("fd00::1"), "0.0.0.0/0"));
+    }
+
+    #[test]
+    fn verzoegerung_verdoppelt_sich() {
+        let k = RatenbegrenzungKonfiguration::default();
+        assert_eq!(verzoegerung(&k, 0), Duration::zero());
+        assert_eq!(verzoegerung(&k, 2), Duration::zero());
+        assert_eq!(verzoegerung(&k, 3), Duration::seconds(2));
+        assert_eq!(verzoegerung(&k, 4), Duration::seconds(4));
+        assert_eq!(verzoegerung(&k, 5), Duration::seconds(8));
+        assert_eq!(verzoegerung(&k, 20), Duration::seconds(300));
+        assert_eq!(verzoegerung(&k, u32::MAX), Duration::seconds(300));
+    }
+
+    fn zugriff_anfragen_db() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE zugriff_anfragen (
+                id              INTEGER PRIMARY KEY AUTOINCREMENT,
+                email           VARCHAR(1023) NOT NULL,
+                ip              VARCHAR(255) NOT NULL,
+                zeitpunkt       VARCHAR(255) NOT NULL,
+                anzahl          INTEGER NOT NULL
+            );",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn bereich_zaehlt_als_eine_anfrage() {
+        let k = RatenbegrenzungKonfiguration::default();
+        let conn = zugriff_anfragen_db();
+        let jetzt = Utc::now().to_rfc3339();
+
+        // Blatt 100 bis 250 umfasst 151 Blätter, ist aber nur eine Zeile
+        let blaetter = vec![crate::db::ZugriffBereich {
+            land: "Brandenburg".to_string(),
+            amtsgericht: "Prenzlau".to_string(),
+            bezirk: "Ludwigsburg".to_string(),
+            blatt: "100".to_string(),
+            blatt_bis: Some("250".to_string()),
+        }];
+        assert!(zugriff_anfragen_zaehlen(&conn, &k, "a@example.com", "1.2.3.4", blaetter.len()).is_ok());
+        zugriff_anfrage_einfuegen(&conn, "a@example.com", "1.2.3.4", &jetzt, blaetter.len()).unwrap();
+
+        // Bis zur Grenze pro E-Mail weitere Anfragen möglich
+        let frei = k.zugriff_anfragen_pro_email_tag as usize - 1;
+        assert!(zugriff_anfragen_zaehlen(&conn, &k, "a@example.com", "1.2.3.4", frei).is_ok());
+        assert!(zugriff_anfragen_zaehlen(&conn, &k, "a@example.com", "1.2.3.4", frei + 1).is_err());
+        assert!(zugriff_anfragen_zaehlen(&conn, &k, "b@example.com", "1.2.3.4", frei + 1).is_ok());
+    }
+}
@@ -0,0 +1,364 @@
+//! Rollen und Berechtigungen
+//!
+//! `benutzer.rechte` enthält den Namen einer Rolle aus der Tabelle `rollen`,
+//! jede Rolle hat eine Menge von Berechtigungen. Handler prüfen nur
+//! Berechtigungen (`BenutzerInfo::berechtigung_pruefen`), nie Rollennamen,
+//! so dass eine neue Rolle nur in der Datenbank angelegt werden muss.
+//! Administratoren können Rollen unter `/konto` ("Rollen") oder über
+//! `/rollen` anpassen.
+//!
+//! Die Rolle `admin` hat immer alle Berechtigungen und kann weder geändert
+//! noch gelöscht werden, damit sich Administratoren nicht aussperren können.
+
+use crate::config::Konfiguration;
+use crate::models::{BenutzerInfo, MountPoint};
+use rusqlite::{Connection, OptionalExtension};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+/// Rolle mit allen Berechtigungen
+pub const ADMIN_ROLLE: &str = "admin";
+/// Rolle für neue Benutzer, falls nichts anderes angegeben ist
+pub const STANDARD_ROLLE: &str = "gast";
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Berechtigung {
+    /// Änderungen an Grundbuchblättern hochladen (im Rahmen der Zugriffe)
+    #[serde(rename = "grundbuch-bearbeiten")]
+    GrundbuchBearbeiten,
+    /// Alle Grundbuchblätter lesen, unabhängig von gewährten Zugriffen
+    #[serde(rename = "grundbuecher-alle-lesen")]
+    GrundbuecherAlleLesen,
+    /// Abonnements aller Benutzer sehen und verwalten, Benachrichtigungen erneut senden
+    #[serde(rename = "abos-verwalten")]
+    AbosVerwalten,
+    /// Zugriffsanfragen genehmigen und ablehnen, Zugriffe widerrufen
+    #[serde(rename = "zugriffe-verwalten")]
+    ZugriffeVerwalten,
+    /// Benutzer, Schlüssel, Kontosperren und API-Schlüssel anderer Benutzer verwalten
+    #[serde(rename = "benutzer-verwalten")]
+    BenutzerVerwalten,
+    #[serde(rename = "bezirke-verwalten")]
+    BezirkeVerwalten,
+    /// Globale Einstellungen bearbeiten
+    #[serde(rename = "konfiguration-bearbeiten")]
+    KonfigurationBearbeiten,
+    /// Audit-Log und Einsichtsprotokoll aller Benutzer lesen
+    #[serde(rename = "protokolle-lesen")]
+    ProtokolleLesen,
+    #[serde(rename = "rollen-verwalten")]
+    RollenVerwalten,
+}
+
+impl Berechtigung {
+    pub const ALLE: [Berechtigung; 9] = [
+        Berechtigung::GrundbuchBearbeiten,
+        Berechtigung::GrundbuecherAlleLesen,
+        Berechtigung::AbosVerwalten,
+        Berechtigung::ZugriffeVerwalten,
+        Berechtigung::BenutzerVerwalten,
+        Berechtigung::BezirkeVerwalten,
+        Berechtigung::KonfigurationBearbeiten,
+        Berechtigung::ProtokolleLesen,
+        Berechtigung::RollenVerwalten,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Berechtigung::GrundbuchBearbeiten => "grundbuch-bearbeiten",
+            Berechtigung::GrundbuecherAlleLesen => "grundbuecher-alle-lesen",
+            Berechtigung::AbosVerwalten => "abos-verwalten",
+            Berechtigung::ZugriffeVerwalten => "zugriffe-verwalten",
+            Berechtigung::BenutzerVerwalten => "benutzer-verwalten",
+            Berechtigung::BezirkeVerwalten => "bezirke-verwalten",
+            Berechtigung::KonfigurationBearbeiten => "konfiguration-bearbeiten",
+            Berechtigung::ProtokolleLesen => "protokolle-lesen",
+            Berechtigung::RollenVerwalten => "rollen-verwalten",
+        }
+    }
+
+    pub fn from_str(s: &str) -> Option<Self> {
+        Self::ALLE.iter().copied().find(|b| b.as_str() == s.trim())
+    }
+
+    pub fn beschreibung(&self) -> &'static str {
+        match self {
+            Berechtigung::GrundbuchBearbeiten => "Grundbuchblätter bearbeiten",
+            Berechtigung::GrundbuecherAlleLesen => "Alle Grundbuchblätter lesen",
+            Berechtigung::AbosVerwalten => "Abonnements aller Benutzer verwalten, Benachrichtigungen erneut senden",
+            Berechtigung::ZugriffeVerwalten => "Zugriffe genehmigen, ablehnen und widerrufen",
+            Berechtigung::BenutzerVerwalten => "Benutzer verwalten",
+            Berechtigung::BezirkeVerwalten => "Bezirke verwalten",
+            Berechtigung::KonfigurationBearbeiten => "Globale Einstellungen bearbeiten",
+            Berechtigung::ProtokolleLesen => "Audit-Log und Einsichtsprotokoll lesen",
+            Berechtigung::RollenVerwalten => "Rollen und Berechtigungen verwalten",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Rolle {
+    pub name: String,
+    pub bezeichnung: String,
+    pub berechtigungen: BTreeSet<Berechtigung>,
+}
+
+fn berechtigungen_parsen(s: &str) -> BTreeSet<Berechtigung> {
+    s.split(',').filter_map(Berechtigung::from_str).collect()
+}
+
+fn berechtigungen_als_text(b: &BTreeSet<Berechtigung>) -> String {
+    b.iter().map(|b| b.as_str()).collect::<Vec<_>>().join(",")
+}
+
+fn rolle_aus_zeile(row: &rusqlite::Row) -> rusqlite::Result<Rolle> {
+    let name: String = row.get(0)?;
+    let berechtigungen = if name == ADMIN_ROLLE {
+        Berechtigung::ALLE.iter().copied().collect()
+    } else {
+        berechtigungen_parsen(&row.get::<usize, String>(2)?)
+    };
+    Ok(Rolle {
+        name,
+        bezeichnung: row.get(1)?,
+        berechtigungen,
+    })
+}
+
+pub fn get_rollen(konfiguration: &Konfiguration) -> Result<Vec<Rolle>, String> {
+    let conn = crate::db::verbindung_oeffnen(konfiguration, MountPoint::Local)
+        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+
+    let mut stmt = conn
+        .prepare("SELECT name, bezeichnung, berechtigungen FROM rollen ORDER BY name")
+        .map_err(|e| format!("Fehler beim Auslesen der Rollen: {e}"))?;
+
+    let rollen = stmt
+        .query_map(rusqlite::params![], rolle_aus_zeile)
+        .map_err(|e| format!("Fehler beim Auslesen der Rollen: {e}"))?
+        .filter_map(|r| r.ok())
+        .collect();
+
+    Ok(rollen)
+}
+
+pub fn get_rolle(konfiguration: &Konfiguration, name: &str) -> Result<Option<Rolle>, String> {
+    let conn = crate::db::verbindung_oeffnen(konfiguration, MountPoint::Local)
+        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+
+    conn.query_row(
+        "SELECT name, bezeichnung, berechtigungen FROM rollen WHERE name = ?1",
+        rusqlite::params![name],
+        rolle_aus_zeile,
+    )
+    .optional()
+    .map_err(|e| format!("Fehler beim Auslesen der Rollen: {e}"))
+}
+
+/// Berechtigungen der Rolle `rechte` (leer, wenn die Rolle nicht existiert)
+pub fn get_berechtigungen(conn: &Connection, rechte: &str) -> BTreeSet<Berechtigung> {
+    conn.query_row(
+        "SELECT name, bezeichnung, berechtigungen FROM rollen WHERE name = ?1",
+        rusqlite::params![rechte],
+        rolle_aus_zeile,
+    )
+    .map(|r| r.berechtigungen)
+    .unwrap_or_default()
+}
+
+/// Legt eine Rolle an oder ersetzt Bezeichnung und Berechtigungen
+pub fn rolle_speichern(conn: &Connection, rolle: &Rolle) -> Result<(), String> {
+    let name = rolle.name.trim();
+    if name.is_empty()
+        || !name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+    {
+        return Err(format!(
+            "Ungültiger Rollenname \"{name}\" (erlaubt: a-z, 0-9 und -)"
+        ));
+    }
+    if name == ADMIN_ROLLE {
+        return Err(format!("Die Rolle \"{ADMIN_ROLLE}\" kann nicht verändert werden"));
+    }
+
+    conn.execute(
+        "INSERT OR REPLACE INTO rollen (name, bezeichnung, berechtigungen) VALUES (?1, ?2, ?3)",
+        rusqlite::params![
+            name,
+            rolle.bezeichnung.trim(),
+            berechtigungen_als_text(&rolle.berechtigungen)
+        ],
+    )
+    .map_err(|e| format!("Fehler beim Speichern der Rolle: {e}"))?;
+
+    Ok(())
+}
+
+/// Löscht Rollen, die keinem Benutzer mehr zugeordnet sind
+pub fn rollen_loeschen(conn: &Connection, namen: &[String]) -> Result<(), String> {
+    for name in namen.iter() {
+        if name == ADMIN_ROLLE || name == STANDARD_ROLLE {
+            return Err(format!("Die Rolle \"{name}\" kann nicht gelöscht werden"));
+        }
+
+        let benutzer: usize = conn
+            .query_row(
+                "SELECT COUNT(*) FROM benutzer WHERE rechte = ?1",
+                rusqlite::params![name],
+                |row| row.get(0),
+            )
+            .map_err(|e| format!("Fehler beim Löschen der Rollen: {e}"))?;
+
+        if benutzer > 0 {
+            return Err(format!(
+                "Die Rolle \"{name}\" ist noch {benutzer} Benutzer(n) zugeordnet"
+            ));
+        }
+
+        conn.execute("DELETE FROM rollen WHERE name = ?1", rusqlite::params![name])
+            .map_err(|e| format!("Fehler beim Löschen der Rollen: {e}"))?;
+    }
+
+    Ok(())
+}
+
+/// Bezeichnung der Rolle eines Benutzers, z.B. für die Statusseite von
+/// Zugriffsanfragen (ohne die E-Mail des Bearbeiters offenzulegen)
+pub fn get_rollen_bezeichnung_fuer_benutzer(
+    konfiguration: &Konfiguration,
+    email: &str,
+) -> Option<String> {
+    let conn = crate::db::verbindung_oeffnen(konfiguration, MountPoint::Local).ok()?;
+    let rechte: String = conn
+        .query_row(
+            "SELECT rechte FROM benutzer WHERE email = ?1",
+            rusqlite::params![email],
+            |row| row.get(0),
+        )
+        .ok()?;
+    get_rolle(konfiguration, &rechte).ok().flatten().map(|r| r.bezeichnung)
+}
+
+/// (Name, E-Mail) aller Benutzer, deren Rolle die Berechtigung hat
+pub fn get_benutzer_mit_berechtigung(
+    konfiguration: &Konfiguration,
+    berechtigung: Berechtigung,
+) -> Result<Vec<(String, String)>, String> {
+    let rollen = get_rollen(konfiguration)?
+        .into_iter()
+        .filter(|r| r.name == ADMIN_ROLLE || r.berechtigungen.contains(&berechtigung))
+        .map(|r| r.name)
+        .collect::<BTreeSet<_>>();
+
+    let conn = crate::db::verbindung_oeffnen(konfiguration, MountPoint::Local)
+        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+
+    let mut stmt = conn
+        .prepare("SELECT name, email, rechte FROM benutzer ORDER BY email")
+        .map_err(|e| format!("Fehler beim Auslesen der Benutzer: {e}"))?;
+
+    let benutzer = stmt
+        .query_map(rusqlite::params![], |row| {
+            Ok((
+                row.get::<usize, String>(0)?,
+                row.get::<usize, String>(1)?,
+                row.get::<usize, String>(2)?,
+            ))
+        })
+        .map_err(|e| format!("Fehler beim Auslesen der Benutzer: {e}"))?
+        .filter_map(|r| r.ok())
+        .filter(|(_, _, rechte)| rollen.contains(rechte))
+        .map(|(name, email, _)| (name, email))
+        .collect();
+
+    Ok(benutzer)
+}
+
+impl BenutzerInfo {
+    pub fn hat_berechtigung(&self, berechtigung: Berechtigung) -> bool {
+        self.rechte == ADMIN_ROLLE || self.berechtigungen.contains(&berechtigung)
+    }
+
+    /// Zentrale Prüfung für alle Handler
+    pub fn berechtigung_pruefen(&self, berechtigung: Berechtigung) -> Result<(), String> {
+        if self.hat_berechtigung(berechtigung) {
+            Ok(())
+        } else {
+            Err(format!(
+                "Benutzer ist nicht berechtigt: \"{}\" ist für die Rolle \"{}\" nicht freigegeben",
+                berechtigung.beschreibung(),
+                self.rechte
+            ))
+        }
+    }
+
+    /// Rollen dürfen nur vergeben oder geändert werden, wenn der Benutzer alle
+    /// ihre Berechtigungen selbst hat (Administratoren haben alle)
+    pub fn rolle_vergeben_pruefen(&self, rolle: &Rolle) -> Result<(), String> {
+        let fehlend = self.fehlende_berechtigungen(rolle);
+
+        if fehlend.is_empty() {
+            Ok(())
+        } else {
+            Err(format!(
+                "Die Rolle \"{}\" enthält Berechtigungen, die die eigene Rolle nicht hat: {}",
+                rolle.name,
+                fehlend.join(", ")
+            ))
+        }
+    }
+
+    /// Andere Benutzer dürfen nur gelöscht, umgestuft oder zurückgesetzt werden,
+    /// wenn der Benutzer alle Berechtigungen ihrer aktuellen Rolle selbst hat
+    pub fn benutzer_bearbeiten_pruefen(
+        &self,
+        konfiguration: &Konfiguration,
+        emails: &[String],
+    ) -> Result<(), String> {
+        let conn = crate::db::verbindung_oeffnen(konfiguration, MountPoint::Local)
+            .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+
+        for email in emails {
+            let rechte = conn
+                .query_row(
+                    "SELECT rechte FROM benutzer WHERE email = ?1",
+                    rusqlite::params![email],
+                    |row| row.get::<usize, String>(0),
+                )
+                .optional()
+                .map_err(|e| format!("Fehler beim Auslesen der Benutzer: {e}"))?;
+
+            // Unbekannte Benutzer: die Änderung selbst läuft ins Leere
+            let rechte = match rechte {
+                Some(s) => s,
+                None => continue,
+            };
+
+            let rolle = Rolle {
+                berechtigungen: get_berechtigungen(&conn, &rechte),
+                name: rechte,
+                bezeichnung: String::new(),
+            };
+            let fehlend = self.fehlende_berechtigungen(&rolle);
+            if !fehlend.is_empty() {
+                return Err(format!(
+                    "Der Benutzer \"{email}\" hat die Rolle \"{}\" mit Berechtigungen, die die eigene Rolle nicht hat: {}",
+                    rolle.name,
+                    fehlend.join(", ")
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn fehlende_berechtigungen(&self, rolle: &Rolle) -> Vec<&'static str> {
+        rolle
+            .berechtigungen
+            .iter()
+            .filter(|b| !self.hat_berechtigung(**b))
+            .map(|b| b.beschreibung())
+            .collect()
+    }
+}
@@ -0,0 +1,129 @@
+//! Signierte Links (HMAC-SHA256)
+//!
+//! Links in E-Mails, die eine Aktion auslösen (z.B. die Bestätigung einer
+//! Zugriffsanfrage), enthalten eine Signatur über die Parameter und ein
+//! Ablaufdatum. Der Schlüssel liegt in der globalen Einstellung
+//! `server.signatur.secret` und wird beim ersten Start zufällig erzeugt,
+//! damit alle Pods denselben Schlüssel verwenden.
+//!
+//! Webhook-Zustellungen werden dagegen mit dem Secret des jeweiligen
+//! Abonnements signiert (siehe `webhook_signieren`), damit Empfänger die
+//! Signatur prüfen können, ohne den Server-Schlüssel zu kennen.
+
+use crate::config::Konfiguration;
+use crate::models::MountPoint;
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use sha2::Sha256;
+
+/// Globale Einstellung: Schlüssel für signierte Links (hex)
+pub const SCHLUESSEL_EINSTELLUNG: &str = "server.signatur.secret";
+
+/// Zweck der Signatur im Bestätigungslink einer Zugriffsanfrage
+pub const ZWECK_ZUGRIFF_BESTAETIGEN: &str = "zugriff-bestaetigen";
+
+/// Zweck der Signatur im Verlängerungslink der Ablauf-Erinnerung
+pub const ZWECK_ZUGRIFF_VERLAENGERN: &str = "zugriff-verlaengern";
+
+/// Neuer Schlüssel (256 Bit, hex)
+pub fn schluessel_generieren() -> String {
+    let bytes: [u8; 32] = rand::thread_rng().gen();
+    data_encoding::HEXLOWER.encode(&bytes)
+}
+
+fn get_mac(konfiguration: &Konfiguration) -> Result<Hmac<Sha256>, String> {
+    let schluessel = crate::db::get_globale_einstellungen(konfiguration, MountPoint::Local)?
+        .into_values()
+        .find(|(k, _)| k == SCHLUESSEL_EINSTELLUNG)
+        .map(|(_, v)| v)
+        .unwrap_or_default();
+
+    if schluessel.trim().is_empty() {
+        return Err(format!("Kein Schlüssel für signierte Links eingestellt"));
+    }
+
+    Hmac::<Sha256>::new_from_slice(schluessel.trim().as_bytes())
+        .map_err(|e| format!("Ungültiger Schlüssel für signierte Links: {e}"))
+}
+
+/// Signiert `zweck` und `werte` (mit `:` verbunden), Ergebnis hex
+pub fn signieren(
+    konfiguration: &Konfiguration,
+    zweck: &str,
+    werte: &[&str],
+) -> Result<String, String> {
+    let mut mac = get_mac(konfiguration)?;
+    mac.update(nachricht(zweck, werte).as_bytes());
+    Ok(data_encoding::HEXLOWER.encode(&mac.finalize().into_bytes()))
+}
+
+/// Prüft eine mit `signieren` erzeugte Signatur (Vergleich in konstanter Zeit)
+pub fn pruefen(konfiguration: &Konfiguration, zweck: &str, werte: &[&str], signatur: &str) -> bool {
+    let signatur = match data_encoding::HEXLOWER_PERMISSIVE.decode(signatur.trim().as_bytes()) {
+        Ok(o) => o,
+        Err(_) => return false,
+    };
+    let mut mac = match get_mac(konfiguration) {
+        Ok(o) => o,
+        Err(_) => return false,
+    };
+    mac.update(nachricht(zweck, werte).as_bytes());
+    mac.verify_slice(&signatur).is_ok()
+}
+
+/// Signatur einer Webhook-Zustellung: HMAC-SHA256 mit dem Secret des
+/// Abonnements über `"{zeitstempel}.{body}"`, Ergebnis hex
+pub fn webhook_signieren(secret: &str, zeitstempel: i64, body: &[u8]) -> Result<String, String> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.trim().as_bytes())
+        .map_err(|e| format!("Ungültiges Webhook-Secret: {e}"))?;
+    mac.update(zeitstempel.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    Ok(data_encoding::HEXLOWER.encode(&mac.finalize().into_bytes()))
+}
+
+fn nachricht(zweck: &str, werte: &[&str]) -> String {
+    let mut n = zweck.to_string();
+    for w in werte {
+        n.push(':');
+        n.push_str(w);
+    }
+    n
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn webhook_signatur_wie_api_md() {
+        // hmac.new(b"geheim", b"1767225600." + body, hashlib.sha256).hexdigest()
+        let body = br#"{"ereignis":"test"}"#;
+        assert_eq!(
+            webhook_signieren("geheim", 1767225600, body).unwrap(),
+            "07ea0c814fa6e6e566929689796c6f792d441412360dec39056acc16b05e6159"
+        );
+    }
+
+    #[test]
+    fn webhook_signatur_abhaengig_von_zeitstempel_und_body() {
+        let body = br#"{"ereignis":"test"}"#;
+        let signatur = webhook_signieren("geheim", 1767225600, body).unwrap();
+        assert_ne!(webhook_signieren("geheim", 1767225601, body).unwrap(), signatur);
+        assert_ne!(webhook_signieren("anderes", 1767225600, body).unwrap(), signatur);
+        assert_ne!(
+            webhook_signieren("geheim", 1767225600, br#"{"ereignis":"aenderung"}"#).unwrap(),
+            signatur
+        );
+    }
+
+    #[test]
+    fn nachricht_mit_zweck() {
+        assert_eq!(nachricht(ZWECK_ZUGRIFF_BESTAETIGEN, &["abc", "123"]), "zugriff-bestaetigen:abc:123");
+        assert_eq!(nachricht(ZWECK_ZUGRIFF_VERLAENGERN, &["abc"]), "zugriff-verlaengern:abc");
+        assert_ne!(
+            nachricht(ZWECK_ZUGRIFF_BESTAETIGEN, &["abc"]),
+            nachricht(ZWECK_ZUGRIFF_VERLAENGERN, &["abc"])
+        );
+    }
+}
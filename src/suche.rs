@@ -1,5 +1,6 @@
 //! Suchfunktion: durchsucht den momentanen Index
 
+use crate::config::Konfiguration;
 use serde_derive::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
@@ -28,12 +29,12 @@ pub struct SuchErgebnisGrundbuch {
     pub text: String,
 }
 
-pub fn suche_in_index(s: &str) -> Result<SuchErgebnisse, String> {
+pub fn suche_in_index(konfiguration: &Konfiguration, s: &str) -> Result<SuchErgebnisse, String> {
     use tantivy::collector::TopDocs;
     use tantivy::query::QueryParser;
     use tantivy::{DocAddress, Score};
 
-    let (schema, index) = crate::index::get_grundbuch_index()
+    let (schema, index) = crate::index::get_grundbuch_index(konfiguration)
         .map_err(|e| format!("Suche: Konnte Index nicht erzeugen: {e}"))?;
 
     let land = schema
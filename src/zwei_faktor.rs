@@ -0,0 +1,413 @@
+//! Zwei-Faktor-Authentifizierung per TOTP (RFC 6238)
+//!
+//! Der Benutzer richtet unter `/konto` ein Geheimnis ein (als `otpauth://`-URI für
+//! Authenticator-Apps) und aktiviert es mit einem ersten gültigen Code. Bei der
+//! Aktivierung werden Wiederherstellungscodes erzeugt, die jeweils einmal statt
+//! eines TOTP-Codes verwendet werden können. Gespeichert werden nur deren Hashes.
+//! Jeder TOTP-Code wird nur einmal akzeptiert: gespeichert wird der zuletzt
+//! benutzte Zeitschritt, Codes aus diesem oder früheren Zeitschritten sind ungültig.
+//!
+//! In der globalen Einstellung `sicherheit.2fa.pflicht` können Administratoren
+//! eine kommagetrennte Liste von Rechten (z.B. `admin,bearbeiter`) angeben, für
+//! die die Zwei-Faktor-Authentifizierung vorgeschrieben ist.
+
+use crate::api::commit::DbChangeOp;
+use crate::config::Konfiguration;
+use crate::models::{BenutzerInfo, MountPoint};
+use crate::AppState;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use rusqlite::Connection;
+use sha1::Sha1;
+
+/// Globale Einstellung: Rechte, für die 2FA vorgeschrieben ist
+pub const PFLICHT_EINSTELLUNG: &str = "sicherheit.2fa.pflicht";
+
+const AUSSTELLER: &str = "dgb-server";
+const ZEITSCHRITT_SEKUNDEN: i64 = 30;
+const STELLEN: u32 = 6;
+/// Erlaubte Abweichung in Zeitschritten (Uhrzeit des Telefons)
+const TOLERANZ_SCHRITTE: i64 = 1;
+const ANZAHL_WIEDERHERSTELLUNGSCODES: usize = 10;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ZweiFaktor {
+    /// Base32-kodiertes Geheimnis
+    pub geheimnis: String,
+    pub aktiviert: bool,
+    pub wiederherstellungscodes_uebrig: usize,
+    /// Zeitschritt des zuletzt akzeptierten TOTP-Codes
+    pub letzter_schritt: Option<i64>,
+}
+
+/// Womit der zweite Faktor bestätigt wurde
+#[derive(Debug, Clone, PartialEq)]
+pub enum ZweiterFaktor {
+    /// Zeitschritt des TOTP-Codes, muss danach gespeichert werden
+    Totp(i64),
+    /// Hash des benutzten Wiederherstellungscodes, muss danach gelöscht werden
+    Wiederherstellungscode(String),
+}
+
+/// Neues Geheimnis (160 Bit, Base32 ohne Padding)
+pub fn geheimnis_generieren() -> String {
+    let bytes: [u8; 20] = rand::thread_rng().gen();
+    data_encoding::BASE32_NOPAD.encode(&bytes)
+}
+
+pub fn otpauth_uri(email: &str, geheimnis: &str) -> String {
+    format!(
+        "otpauth://totp/{AUSSTELLER}:{}?secret={geheimnis}&issuer={AUSSTELLER}&algorithm=SHA1&digits={STELLEN}&period={ZEITSCHRITT_SEKUNDEN}",
+        urlencoding::encode(email)
+    )
+}
+
+/// HOTP (RFC 4226) mit HMAC-SHA1
+fn hotp(schluessel: &[u8], zaehler: u64) -> u32 {
+    let mut mac = match Hmac::<Sha1>::new_from_slice(schluessel) {
+        Ok(o) => o,
+        Err(_) => return u32::MAX,
+    };
+    mac.update(&zaehler.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binaer = ((hash[offset] as u32 & 0x7f) << 24)
+        | ((hash[offset + 1] as u32) << 16)
+        | ((hash[offset + 2] as u32) << 8)
+        | (hash[offset + 3] as u32);
+
+    binaer % 10_u32.pow(STELLEN)
+}
+
+/// Prüft einen TOTP-Code zum Zeitpunkt `unix_zeit` (± `TOLERANZ_SCHRITTE`) und gibt
+/// den passenden Zeitschritt zurück. Schritte bis einschließlich `letzter_schritt`
+/// wurden bereits benutzt und werden abgelehnt.
+pub fn code_pruefen(
+    geheimnis: &str,
+    code: &str,
+    unix_zeit: i64,
+    letzter_schritt: Option<i64>,
+) -> Option<i64> {
+    let code = code.trim().replace(' ', "");
+    if code.len() != STELLEN as usize {
+        return None;
+    }
+    let code = code.parse::<u32>().ok()?;
+    let schluessel = data_encoding::BASE32_NOPAD.decode(geheimnis.as_bytes()).ok()?;
+
+    let schritt = unix_zeit / ZEITSCHRITT_SEKUNDEN;
+    (-TOLERANZ_SCHRITTE..=TOLERANZ_SCHRITTE)
+        .map(|d| schritt + d)
+        .filter(|z| letzter_schritt.map_or(true, |l| *z > l))
+        .find(|z| u64::try_from(*z).map_or(false, |z| hotp(&schluessel, z) == code))
+}
+
+/// Erzeugt neue Wiederherstellungscodes im Format `xxxxx-xxxxx`
+pub fn wiederherstellungscodes_generieren() -> Vec<String> {
+    const ZEICHEN: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+    let mut rng = rand::thread_rng();
+    (0..ANZAHL_WIEDERHERSTELLUNGSCODES)
+        .map(|_| {
+            let mut code = (0..10)
+                .map(|_| ZEICHEN[rng.gen_range(0..ZEICHEN.len())] as char)
+                .collect::<String>();
+            code.insert(5, '-');
+            code
+        })
+        .collect()
+}
+
+pub fn wiederherstellungscode_hash(code: &str) -> String {
+    crate::db::hash_token(&code.trim().to_lowercase())
+}
+
+/// Ist für die Rechte des Benutzers 2FA vorgeschrieben?
+pub fn ist_pflicht(konfiguration: &Konfiguration, benutzer: &BenutzerInfo) -> bool {
+    crate::db::get_globale_einstellungen(konfiguration, MountPoint::Local)
+        .unwrap_or_default()
+        .values()
+        .filter(|(k, _)| k == PFLICHT_EINSTELLUNG)
+        .any(|(_, v)| v.split(',').any(|r| r.trim() == benutzer.rechte))
+}
+
+/// 2FA ist vorgeschrieben, aber noch nicht aktiviert (und wird nicht vom
+/// OpenID-Connect-Anbieter übernommen)
+pub fn einrichtung_erforderlich(konfiguration: &Konfiguration, benutzer: &BenutzerInfo) -> bool {
+    ist_pflicht(konfiguration, benutzer)
+        && !get_zwei_faktor(konfiguration, benutzer).map_or(false, |z| z.aktiviert)
+        && !crate::oidc::zwei_faktor_beim_anbieter(konfiguration, benutzer)
+}
+
+pub fn get_zwei_faktor(
+    konfiguration: &Konfiguration,
+    benutzer: &BenutzerInfo,
+) -> Option<ZweiFaktor> {
+    let conn = crate::db::verbindung_oeffnen(konfiguration, MountPoint::Local).ok()?;
+
+    let (geheimnis, aktiviert, letzter_schritt) = conn
+        .query_row(
+            "SELECT geheimnis, aktiviert, letzter_schritt FROM zwei_faktor WHERE benutzer = ?1",
+            rusqlite::params![benutzer.id],
+            |row| {
+                Ok((
+                    row.get::<usize, String>(0)?,
+                    row.get::<usize, bool>(1)?,
+                    row.get::<usize, Option<i64>>(2)?,
+                ))
+            },
+        )
+        .ok()?;
+
+    let wiederherstellungscodes_uebrig = conn
+        .query_row(
+            "SELECT COUNT(*) FROM wiederherstellungscodes WHERE benutzer = ?1",
+            rusqlite::params![benutzer.id],
+            |row| row.get::<usize, i64>(0),
+        )
+        .unwrap_or(0) as usize;
+
+    Some(ZweiFaktor {
+        geheimnis,
+        aktiviert,
+        wiederherstellungscodes_uebrig,
+        letzter_schritt,
+    })
+}
+
+/// Prüft einen TOTP-Code oder Wiederherstellungscode des Benutzers
+pub fn zweiten_faktor_pruefen(
+    konfiguration: &Konfiguration,
+    benutzer: &BenutzerInfo,
+    code: &str,
+) -> Result<ZweiterFaktor, String> {
+    let zf = get_zwei_faktor(konfiguration, benutzer)
+        .filter(|z| z.aktiviert)
+        .ok_or(format!("Zwei-Faktor-Authentifizierung ist nicht aktiviert"))?;
+
+    if let Some(schritt) = code_pruefen(&zf.geheimnis, code, Utc::now().timestamp(), zf.letzter_schritt) {
+        return Ok(ZweiterFaktor::Totp(schritt));
+    }
+
+    let conn = crate::db::verbindung_oeffnen(konfiguration, MountPoint::Local)
+        .map_err(|_| format!("Fehler bei Verbindung zur Benutzerdatenbank"))?;
+
+    let hash = wiederherstellungscode_hash(code);
+    let vorhanden = conn
+        .query_row(
+            "SELECT COUNT(*) FROM wiederherstellungscodes WHERE benutzer = ?1 AND code_hash = ?2",
+            rusqlite::params![benutzer.id, hash],
+            |row| row.get::<usize, i64>(0),
+        )
+        .unwrap_or(0);
+
+    if vorhanden > 0 {
+        Ok(ZweiterFaktor::Wiederherstellungscode(hash))
+    } else {
+        Err(format!("Ungültiger Code"))
+    }
+}
+
+/// Markiert den geprüften Faktor als benutzt (TOTP-Zeitschritt bzw. Wiederherstellungscode).
+/// Schlägt fehl, wenn der Code inzwischen schon einmal benutzt wurde.
+pub async fn zweiten_faktor_verbrauchen(
+    benutzer: &BenutzerInfo,
+    faktor: ZweiterFaktor,
+    app_state: &AppState,
+) -> Result<(), String> {
+    let change = match faktor {
+        ZweiterFaktor::Totp(schritt) => DbChangeOp::TotpSchrittBenutzen {
+            email: benutzer.email.clone(),
+            schritt,
+        },
+        ZweiterFaktor::Wiederherstellungscode(code_hash) => DbChangeOp::WiederherstellungscodeBenutzen {
+            email: benutzer.email.clone(),
+            code_hash,
+        },
+    };
+
+    crate::api::write_to_root_db(change, &benutzer.email, app_state).await
+}
+
+fn get_benutzer_id(conn: &Connection, email: &str) -> Result<i32, String> {
+    conn.query_row(
+        "SELECT id FROM benutzer WHERE email = ?1",
+        rusqlite::params![email],
+        |row| row.get::<usize, i32>(0),
+    )
+    .map_err(|_| format!("Kein Benutzerkonto für angegebene E-Mail-Adresse vorhanden"))
+}
+
+/// Speichert ein neues (noch nicht aktiviertes) Geheimnis
+pub fn zwei_faktor_einrichten(conn: &Connection, email: &str, geheimnis: &str) -> Result<(), String> {
+    let id = get_benutzer_id(conn, email)?;
+
+    conn.execute(
+        "INSERT OR REPLACE INTO zwei_faktor (benutzer, geheimnis, aktiviert, erstellt_am) VALUES (?1, ?2, 0, ?3)",
+        rusqlite::params![id, geheimnis, Utc::now().to_rfc3339()],
+    )
+    .map_err(|e| format!("Fehler beim Einrichten der Zwei-Faktor-Authentifizierung: {e}"))?;
+
+    Ok(())
+}
+
+/// Aktiviert 2FA und ersetzt alle Wiederherstellungscodes
+pub fn zwei_faktor_aktivieren(
+    conn: &Connection,
+    email: &str,
+    code_hashes: &[String],
+) -> Result<(), String> {
+    let id = get_benutzer_id(conn, email)?;
+
+    let geaendert = conn
+        .execute(
+            "UPDATE zwei_faktor SET aktiviert = 1 WHERE benutzer = ?1",
+            rusqlite::params![id],
+        )
+        .map_err(|e| format!("Fehler beim Aktivieren der Zwei-Faktor-Authentifizierung: {e}"))?;
+
+    if geaendert == 0 {
+        return Err(format!("Zwei-Faktor-Authentifizierung wurde noch nicht eingerichtet"));
+    }
+
+    conn.execute(
+        "DELETE FROM wiederherstellungscodes WHERE benutzer = ?1",
+        rusqlite::params![id],
+    )
+    .map_err(|e| format!("Fehler beim Speichern der Wiederherstellungscodes: {e}"))?;
+
+    for hash in code_hashes {
+        conn.execute(
+            "INSERT INTO wiederherstellungscodes (benutzer, code_hash) VALUES (?1, ?2)",
+            rusqlite::params![id, hash],
+        )
+        .map_err(|e| format!("Fehler beim Speichern der Wiederherstellungscodes: {e}"))?;
+    }
+
+    Ok(())
+}
+
+pub fn zwei_faktor_deaktivieren(conn: &Connection, email: &str) -> Result<(), String> {
+    let id = get_benutzer_id(conn, email)?;
+
+    for sql in [
+        "DELETE FROM wiederherstellungscodes WHERE benutzer = ?1",
+        "DELETE FROM zwei_faktor WHERE benutzer = ?1",
+    ] {
+        conn.execute(sql, rusqlite::params![id])
+            .map_err(|e| format!("Fehler beim Deaktivieren der Zwei-Faktor-Authentifizierung: {e}"))?;
+    }
+
+    Ok(())
+}
+
+/// Speichert den Zeitschritt eines benutzten TOTP-Codes (Fehler, wenn er
+/// nicht neuer als der zuletzt gespeicherte ist)
+pub fn totp_schritt_benutzen(conn: &Connection, email: &str, schritt: i64) -> Result<(), String> {
+    let id = get_benutzer_id(conn, email)?;
+
+    let geaendert = conn
+        .execute(
+            "UPDATE zwei_faktor SET letzter_schritt = ?2
+            WHERE benutzer = ?1 AND (letzter_schritt IS NULL OR letzter_schritt < ?2)",
+            rusqlite::params![id, schritt],
+        )
+        .map_err(|e| format!("Fehler beim Speichern des TOTP-Codes: {e}"))?;
+
+    if geaendert == 0 {
+        return Err(format!("Code wurde bereits verwendet"));
+    }
+
+    Ok(())
+}
+
+/// Löscht einen benutzten Wiederherstellungscode (Fehler, wenn er schon benutzt wurde)
+pub fn wiederherstellungscode_benutzen(
+    conn: &Connection,
+    email: &str,
+    code_hash: &str,
+) -> Result<(), String> {
+    let id = get_benutzer_id(conn, email)?;
+
+    let geloescht = conn
+        .execute(
+            "DELETE FROM wiederherstellungscodes WHERE benutzer = ?1 AND code_hash = ?2",
+            rusqlite::params![id, code_hash],
+        )
+        .map_err(|e| format!("Fehler beim Benutzen des Wiederherstellungscodes: {e}"))?;
+
+    if geloescht == 0 {
+        return Err(format!("Wiederherstellungscode wurde bereits benutzt"));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Schlüssel der Testvektoren aus RFC 4226 / RFC 6238 (SHA-1)
+    const RFC_SCHLUESSEL: &[u8] = b"12345678901234567890";
+
+    fn rfc_geheimnis() -> String {
+        data_encoding::BASE32_NOPAD.encode(RFC_SCHLUESSEL)
+    }
+
+    #[test]
+    fn hotp_rfc4226() {
+        let erwartet = [
+            755224, 287082, 359152, 969429, 338314, 254676, 287922, 162583, 399871, 520489,
+        ];
+        for (zaehler, code) in erwartet.iter().enumerate() {
+            assert_eq!(hotp(RFC_SCHLUESSEL, zaehler as u64), *code, "Zähler {zaehler}");
+        }
+    }
+
+    #[test]
+    fn totp_rfc6238() {
+        // RFC 6238 Anhang B, letzte 6 der 8 Stellen
+        for (unix_zeit, code) in [
+            (59_i64, 287082),
+            (1111111109, 81804),
+            (1111111111, 50471),
+            (1234567890, 5924),
+            (2000000000, 279037),
+            (20000000000, 353130),
+        ] {
+            let schritt = unix_zeit / ZEITSCHRITT_SEKUNDEN;
+            assert_eq!(hotp(RFC_SCHLUESSEL, schritt as u64), code, "Zeit {unix_zeit}");
+            assert_eq!(
+                code_pruefen(&rfc_geheimnis(), &format!("{code:06}"), unix_zeit, None),
+                Some(schritt),
+                "Zeit {unix_zeit}"
+            );
+        }
+    }
+
+    #[test]
+    fn code_pruefen_toleranz() {
+        let geheimnis = rfc_geheimnis();
+        // Code aus Schritt 1 (Zeit 30..59)
+        assert_eq!(code_pruefen(&geheimnis, "287082", 59, None), Some(1));
+        assert_eq!(code_pruefen(&geheimnis, "287082", 89, None), Some(1));
+        assert_eq!(code_pruefen(&geheimnis, "287082", 0, None), Some(1));
+        assert_eq!(code_pruefen(&geheimnis, "287082", 100, None), None);
+        assert_eq!(code_pruefen(&geheimnis, " 287 082 ", 59, None), Some(1));
+        assert_eq!(code_pruefen(&geheimnis, "28708", 59, None), None);
+        assert_eq!(code_pruefen(&geheimnis, "2870820", 59, None), None);
+        assert_eq!(code_pruefen(&geheimnis, "abcdef", 59, None), None);
+        assert_eq!(code_pruefen("kein base32!", "287082", 59, None), None);
+    }
+
+    #[test]
+    fn code_pruefen_nur_einmal() {
+        let geheimnis = rfc_geheimnis();
+        assert_eq!(code_pruefen(&geheimnis, "287082", 59, Some(0)), Some(1));
+        assert_eq!(code_pruefen(&geheimnis, "287082", 59, Some(1)), None);
+        assert_eq!(code_pruefen(&geheimnis, "287082", 59, Some(2)), None);
+        // Code des nächsten Schritts ist weiterhin gültig
+        assert_eq!(code_pruefen(&geheimnis, "359152", 59, Some(1)), Some(2));
+    }
+}
@@ -31,32 +31,30 @@ function setKontoDaten(daten) {
     document.getElementById("konto-daten").dataset.kontoDaten = JSON.stringify(daten);
 }
 
-var kontotyp = getKontoDaten().kontotyp;
-var sidebar_items = [];
-if (kontotyp == "admin") {
-    sidebar_items = [
-        "Grundbücher",
-        "Änderungen",
-        "Zugriffe",
-        "Benutzer",
-        "Bezirke",
-        "Abonnements",
-        "Einstellungen",
-    ]
-} else if (kontotyp == "bearbeiter") {
-    sidebar_items = [
-        "Grundbücher",
-        "Änderungen",
-        "Abonnements",
-        "Einstellungen",
-    ]
-} else if (kontotyp == "gast") {
-    sidebar_items = [
-        "Grundbücher",
-        "Abonnements",
-        "Einstellungen",
-    ]
-} 
+var berechtigungen = getKontoDaten().berechtigungen || [];
+
+function hatBerechtigung(berechtigung) {
+    return berechtigungen.indexOf(berechtigung) != -1;
+}
+
+// Titel, Bereich in den Kontodaten, anzeigen
+var sidebar_bereiche = [
+    ["Grundbücher", "blaetter", true],
+    ["Änderungen", "aenderungen", hatBerechtigung("grundbuch-bearbeiten") || hatBerechtigung("grundbuecher-alle-lesen")],
+    ["Zugriffe", "zugriffe", hatBerechtigung("zugriffe-verwalten")],
+    ["Benutzer", "benutzer", hatBerechtigung("benutzer-verwalten")],
+    ["Bezirke", "bezirke", hatBerechtigung("bezirke-verwalten")],
+    ["Abonnements", "abonnements", true],
+    ["Einstellungen", "meine-kontodaten", true],
+    ["Protokoll", "audit-log", hatBerechtigung("protokolle-lesen")],
+    ["Einsichten", "einsichtsprotokoll", true],
+    ["Sitzungen", "sitzungen", true],
+    ["API-Schlüssel", "api-schluessel", true],
+    ["Zwei-Faktor", "zwei-faktor", true],
+    ["Rollen", "rollen", hatBerechtigung("rollen-verwalten")],
+    ["Benachrichtigungen", "postausgang", hatBerechtigung("abos-verwalten")],
+].filter(function(b) { return b[2]; });
+var sidebar_items = sidebar_bereiche.map(function(b) { return b[0]; });
 
 var active_sidebar = 0;
 var filter_by = null;
@@ -64,49 +62,8 @@ var sort_by = null;
 var selected = [];
 
 function getActiveSectionName() {
-    if (kontotyp == "admin") {
-        if (active_sidebar == 0) {
-            return "blaetter";
-        } else if (active_sidebar == 1) {
-            return "aenderungen";
-        } else if (active_sidebar == 2) {
-            return "zugriffe";
-        } else if (active_sidebar == 3) {
-            return "benutzer";
-        } else if (active_sidebar == 4) {
-            return "bezirke";
-        } else if (active_sidebar == 5) {
-            return "abonnements";
-        } else if (active_sidebar == 6) {
-            return "meine-kontodaten";
-        } else {
-            return "";
-        }
-    } else if (kontotyp == "gast") {
-        if (active_sidebar == 0) {
-            return "blaetter";
-        } else if (active_sidebar == 1) {
-            return "abonnements";
-        } else if (active_sidebar == 2) {
-            return "meine-kontodaten";
-        } else {
-            return "";
-        }
-    } else if (kontotyp == "bearbeiter") {
-        if (active_sidebar == 0) {
-            return "blaetter";
-        } else if (active_sidebar == 1) {
-            return "aenderungen";
-        } else if (active_sidebar == 2) {
-            return "abonnements";
-        }  else if (active_sidebar == 3) {
-            return "meine-kontodaten";
-        } else {
-            return "";
-        }
-    } else {
-        return "";
-    }
+    var bereich = sidebar_bereiche[active_sidebar];
+    return bereich ? bereich[1] : "";
 }
 
 function updateFilter(target) {
@@ -180,20 +137,29 @@ function selectAllVisible() {
         var e = keys[i];
         var row = activeSection.daten[e];
         if (!rowIsValid(row, filter_by)) { continue; }
-        if (kontotyp == "admin" && active_section == "aenderungen") {
+        if (active_section == "aenderungen") {
             var aenderung_id = row[0];
             selected.push(aenderung_id);
-        } else if (kontotyp == "admin" && active_section == "zugriffe") {
+        } else if (active_section == "zugriffe") {
             var zugriff_id = row[0];
             selected.push(zugriff_id);
-        } else if (kontotyp == "admin" && active_section == "benutzer") {
+        } else if (active_section == "benutzer") {
             var benutzer_email = row[1];
             selected.push(benutzer_email);
-        } else if (kontotyp == "admin" && active_section == "bezirke") {
+        } else if (active_section == "bezirke") {
             var bezirk_id = row[0];
             selected.push(bezirk_id);
-        } else if (kontotyp == "admin" && active_section == "meine-kontodaten") {
+        } else if (active_section == "meine-kontodaten") {
         
+        } else if (active_section == "rollen") {
+            var rolle_name = row[0];
+            selected.push(rolle_name);
+        } else if (active_section == "sitzungen") {
+            var session_id = row[0];
+            selected.push(session_id);
+        } else if (active_section == "api-schluessel") {
+            var schluessel_id = row[0];
+            selected.push(schluessel_id);
         } else if (active_section == "blaetter") {
             var blatt_id = row[0] + "/" + row[1] + "/" + row[2] + "/" + row[3];
             selected.push(blatt_id);
@@ -210,37 +176,91 @@ function deselectAll() {
 
 function renderHeader(id) {
     var spalten = [];
-    if ((kontotyp == "admin" && id == "aenderungen") ||
-        (kontotyp == "bearbeiter" && id == "aenderungen")) {
+    if (id == "aenderungen") {
         spalten = [
             "Name",
             "Datum / Beschreibung"
         ];
-    } else if (kontotyp == "admin" && id == "zugriffe") {
+    } else if (id == "zugriffe") {
         spalten = [
             "Name",
             "Grund / Typ",
             "Bezirk",
             "Status",
         ];
-    } else if (kontotyp == "admin" && id == "benutzer") {
+    } else if (id == "benutzer") {
         spalten = [
             "Name",
             "E-Mail",
             "Rechte",
             "Schlüssel",
         ];
-    } else if (kontotyp == "admin" && id == "bezirke") {
+    } else if (id == "bezirke") {
         spalten = [
             "Land",
             "Amtsgericht",
             "Bezirk",
         ];
-    } else if (
-        (kontotyp == "admin" && id == "meine-kontodaten") || 
-        (kontotyp == "bearbeiter" && id == "meine-kontodaten") ||
-        (kontotyp == "gast" && id == "meine-kontodaten")
-    ) {
+    } else if (id == "rollen") {
+        spalten = [
+            "Name",
+            "Bezeichnung",
+            "Berechtigungen",
+        ];
+    } else if (id == "audit-log") {
+        spalten = [
+            "Zeitpunkt",
+            "Benutzer",
+            "Operation",
+            "Ziel",
+        ];
+    } else if (id == "postausgang") {
+        spalten = [
+            "Erstellt",
+            "Typ",
+            "Empfänger",
+            "Status",
+            "Versuche",
+            "Nächster Versuch / Gesendet",
+        ];
+    } else if (id == "einsichtsprotokoll") {
+        spalten = [
+            "Zeitpunkt",
+            "Benutzer",
+            "Blatt",
+            "Art",
+            "Aktenzeichen / Zweck",
+        ];
+    } else if (id == "sitzungen") {
+        spalten = [
+            "Angemeldet seit",
+            "Gültig bis",
+            "Verlängerbar bis",
+            "Gerät",
+        ];
+    } else if (hatBerechtigung("benutzer-verwalten") && id == "api-schluessel") {
+        spalten = [
+            "Benutzer",
+            "Name",
+            "Scopes",
+            "Bezirke",
+            "Gültig bis",
+            "Zuletzt benutzt",
+        ];
+    } else if (id == "zwei-faktor") {
+        spalten = [
+            "Einstellung",
+            "Wert",
+        ];
+    } else if (id == "api-schluessel") {
+        spalten = [
+            "Name",
+            "Scopes",
+            "Bezirke",
+            "Gültig bis",
+            "Zuletzt benutzt",
+        ];
+    } else if (id == "meine-kontodaten") {
         spalten = [
             "Einstellung",
             "Wert"
@@ -260,6 +280,8 @@ function renderHeader(id) {
             "Bezirk",
             "Blatt",
             "Aktenzeichen",
+            "Detailstufe",
+            "Filter",
         ];
     }
 
@@ -362,6 +384,23 @@ function renderSidebar() {
 }
 
 function benutzerAbmelden() {
+    var auth = document.getElementById("token-id").dataset.tokenId;
+    if (!auth) {
+        cookiesLoeschen();
+        return;
+    }
+    var http = new XMLHttpRequest();
+    http.open('POST', '/logout', true);
+    http.setRequestHeader('Authorization', 'Bearer ' + auth);
+    http.onreadystatechange = function() {
+        if (http.readyState == 4) {
+            cookiesLoeschen();
+        }
+    }
+    http.send();
+}
+
+function cookiesLoeschen() {
     var cookies = document.cookie.split("; ");
     for (var c = 0; c < cookies.length; c++) {
         var d = window.location.hostname.split(".");
@@ -408,8 +447,7 @@ function renderRows(id) {
         var row_node = document.createElement("div");
         row_node.dataset.index = e;
 
-        if ((kontotyp == "admin" && id == "aenderungen") ||
-           (kontotyp == "bearbeiter" && id == "aenderungen")) {
+        if (id == "aenderungen") {
 
             var aenderung_id = row[0];
             var aenderung_name = row[1];
@@ -495,7 +533,7 @@ function renderRows(id) {
             non_check_node.appendChild(cell_node);
             
             row_node.appendChild(non_check_node);
-        } else if (kontotyp == "admin" && id == "zugriffe") {
+        } else if (id == "zugriffe") {
 
             var zugriff_id = row[0];
             var zugriff_name = row[1];
@@ -512,6 +550,17 @@ function renderRows(id) {
             var gewaehrt_von = row[10];
             var abgelehnt_von = row[11];
             var am = row[12];
+            var gueltig_bis = row[13];
+            var widerrufen_von = row[14];
+            var widerrufen_am = row[15];
+            var blatt_bis = row[16];
+            var blaetter_anzahl = row[17];
+            var ablehnungsgrund = row[18];
+            var zurueckgezogen_am = row[19];
+
+            if (blatt_bis != "") {
+                zugriff_blatt = zugriff_blatt + " - " + blatt_bis;
+            }
 
             var options = { year: '2-digit', month: '2-digit', day: '2-digit', hour: "2-digit", minute: "2-digit", second: "2-digit" };
             var angefragt_date = new Date(Date.parse(angefragt)).toLocaleDateString("de-DE", options);
@@ -519,14 +568,30 @@ function renderRows(id) {
             var status = "Warte auf Zugriff, angefragt am";
             var line2 = angefragt_date;
 
-            if (angefragt != "" && gewaehrt_von != "") {
+            if (zurueckgezogen_am != "") {
+                status = "Vom Antragsteller zurückgezogen";
+                line2 = "am " + new Date(Date.parse(zurueckgezogen_am)).toLocaleDateString("de-DE", options);
+            } else if (angefragt != "" && widerrufen_am != "") {
+                var am = new Date(Date.parse(widerrufen_am)).toLocaleDateString("de-DE", options);
+                status = "Widerrufen von " + widerrufen_von;
+                line2 = "am " + am;
+            } else if (angefragt != "" && gewaehrt_von != "") {
                 var am = new Date(Date.parse(am)).toLocaleDateString("de-DE", options);
                 status = "Gewährt von " + gewaehrt_von;
                 line2 = "am " + am;
+                if (gueltig_bis != "") {
+                    var bis = Date.parse(gueltig_bis);
+                    var bis_date = new Date(bis).toLocaleDateString("de-DE", { year: '2-digit', month: '2-digit', day: '2-digit' });
+                    status = bis < Date.now() ? "Abgelaufen, gewährt von " + gewaehrt_von : status;
+                    line2 += ", gültig bis " + bis_date;
+                }
             } else if (angefragt != "" && abgelehnt_von != "") {
                 var am = new Date(Date.parse(am)).toLocaleDateString("de-DE", options);
                 status = "Abgelehnt von " + abgelehnt_von;
                 line2 = "am " + am;
+                if (ablehnungsgrund != "") {
+                    line2 += ": " + ablehnungsgrund;
+                }
             }
 
             var check_uncheck_all_node_div = document.createElement("div");
@@ -614,6 +679,14 @@ function renderRows(id) {
             cell_text.appendChild(textnode1);
             cell_node.appendChild(cell_text);
 
+            // Anzahl der vorhandenen Blätter, die der Zugriff tatsächlich umfasst (nur bei offenen Anfragen)
+            if (blaetter_anzahl != "") {
+                var cell_text = document.createElement("p");
+                var textnode1 = document.createTextNode("(" + blaetter_anzahl + (blaetter_anzahl == "1" ? " Blatt)" : " Blätter)"));
+                cell_text.appendChild(textnode1);
+                cell_node.appendChild(cell_text);
+            }
+
             non_check_node.appendChild(cell_node);
 
 
@@ -636,12 +709,13 @@ function renderRows(id) {
             non_check_node.appendChild(cell_node);
             row_node.appendChild(non_check_node);
 
-        } else if (kontotyp == "admin" && id == "benutzer") {
+        } else if (id == "benutzer") {
 
             var benutzer_name = row[0]; 
             var benutzer_email = row[1]; 
             var benutzer_rechte = row[2]; 
             var pubkey_fingerprint = row[4]; 
+            var gesperrt_bis = row[5];
 
             var check_uncheck_all_node_div = document.createElement("div");
             check_uncheck_all_node_div.style.flexDirection = "column";
@@ -691,6 +765,14 @@ function renderRows(id) {
             cell_text.appendChild(textnode1);
             cell_node.appendChild(cell_text);
 
+            if (gesperrt_bis) {
+                var cell_text = document.createElement("p");
+                cell_text.style.color = "rgb(185, 14, 14)";
+                var textnode1 = document.createTextNode("Gesperrt bis: " + gesperrt_bis);
+                cell_text.appendChild(textnode1);
+                cell_node.appendChild(cell_text);
+            }
+
             non_check_node.appendChild(cell_node);
 
             var cell_node = document.createElement("div");
@@ -703,11 +785,7 @@ function renderRows(id) {
             cell_text.dataset.id = benutzer_email; 
             cell_text.onchange = function() { benutzerBearbeiten(this); };
 
-            var select_values = [
-                "admin",
-                "bearbeiter",
-                "gast",
-            ];
+            var select_values = Object.keys(getKontoDaten().data["rollen"].daten);
 
             // Create and append the options
             for (var i = 0; i < select_values.length; i++) {
@@ -735,11 +813,15 @@ function renderRows(id) {
             cell_text.dataset.email = benutzer_email;
             cell_text.dataset.name = benutzer_name;
 
-            if (pubkey_fingerprint == "") {
+            // Schlüssel anderer Benutzer darf nur die Rolle "admin" setzen
+            if (pubkey_fingerprint != "") {
+                cell_text.textContent = "Schlüssel " + pubkey_fingerprint.substr(0, 6) + "";
+            } else if (getKontoDaten().rechte == "admin") {
                 cell_text.textContent = "Schlüsselpaar generieren";
                 cell_text.onclick = function() { generiereSchluesselPaar(this); }
             } else {
-                cell_text.textContent = "Schlüssel " + pubkey_fingerprint.substr(0, 6) + "";
+                cell_text.textContent = "Kein Schlüssel";
+                cell_text.disabled = true;
             }
             cell_node.appendChild(cell_text);
 
@@ -747,7 +829,7 @@ function renderRows(id) {
 
             row_node.appendChild(non_check_node);
 
-        } else if (kontotyp == "admin" && id == "bezirke") {
+        } else if (id == "bezirke") {
             
             var bezirk_id = row[0];
             var land = row[1];
@@ -817,6 +899,31 @@ function renderRows(id) {
             non_check_node.appendChild(cell_node);
 
             row_node.appendChild(non_check_node);
+        } else if (id == "audit-log") {
+            // zeitpunkt, akteur, operation, ziel (daten nur als Tooltip)
+            renderTextCells(row_node, row.slice(0, 4));
+            row_node.title = row[4];
+        } else if (id == "rollen") {
+            // name, bezeichnung, berechtigungen
+            renderTextCells(row_node, row, row[0]);
+        } else if (id == "postausgang") {
+            // id, typ, ziel, status, versuche, naechster_versuch, letzter_fehler, erstellt_am, gesendet_am, aenderungs_id
+            var zeitpunkt = row[3] == "gesendet" ? row[8] : (row[3] == "offen" ? row[5] : "");
+            renderTextCells(row_node, [row[7], row[1], row[2], row[3], row[4], zeitpunkt], row[0]);
+            row_node.title = row[6] ? "Letzter Fehler: " + row[6] : "Änderung " + row[9];
+        } else if (id == "einsichtsprotokoll") {
+            // zeitpunkt, benutzer, blatt, typ, aktenzeichen, zweck
+            var az_zweck = [row[4], row[5]].filter(function(s) { return s; }).join(" / ");
+            renderTextCells(row_node, [row[0], row[1], row[2], row[3], az_zweck]);
+        } else if (id == "sitzungen") {
+            // id, erstellt_am, gueltig_bis, refresh_gueltig_bis, beschreibung
+            renderTextCells(row_node, row.slice(1, 5), row[0]);
+        } else if (id == "zwei-faktor") {
+            renderTextCells(row_node, row);
+        } else if (id == "api-schluessel") {
+            // id, benutzer, name, scopes, bereiche, gueltig_bis, zuletzt_benutzt
+            var von = hatBerechtigung("benutzer-verwalten") ? 1 : 2;
+            renderTextCells(row_node, row.slice(von, 7), row[0]);
         } else if (id == "meine-kontodaten") {
 
             var einstellung_id = e;
@@ -961,6 +1068,8 @@ function renderRows(id) {
             var bezirk = row[4];
             var blatt = row[5];
             var aktenzeichen = row[6];
+            var detailstufe = row[7];
+            var filter = row[8];
 
             var check_uncheck_all_node_div = document.createElement("div");
             check_uncheck_all_node_div.style.flexDirection = "column";
@@ -985,7 +1094,7 @@ function renderRows(id) {
 
             var non_check_node = document.createElement("div");
 
-            var values = [typ, text, amtsgericht, bezirk, blatt, aktenzeichen];
+            var values = [typ, text, amtsgericht, bezirk, blatt, aktenzeichen, detailstufe, filter];
 
             for (var q = 0; q < values.length; q++) {
                 var e = values[q];
@@ -1008,7 +1117,7 @@ function renderRows(id) {
         node_data.appendChild(row_node);
     }
 
-    if (kontotyp == "bearbeiter" && id == "meine-kontodaten") {
+    if (id == "meine-kontodaten" && kontoDaten.data["kontodaten-extra"]) {
 
         var pubkey_fingerprint = kontoDaten.data["kontodaten-extra"].daten["konto.publickey"][0];
         var benutzer_email = kontoDaten.data["kontodaten-extra"].daten["konto.email"][0];
@@ -1090,11 +1199,55 @@ function generiereSchluesselPaar(target) {
     }));
 }
 
+// Text-Zellen mit gleicher Breite, Checkbox nur wenn check_id angegeben ist
+function renderTextCells(row_node, cells, check_id) {
+    var check_uncheck_all_node_div = document.createElement("div");
+    check_uncheck_all_node_div.style.flexDirection = "column";
+    check_uncheck_all_node_div.style.padding = "5px 10px";
+    check_uncheck_all_node_div.style.flexGrow = "0";
+    check_uncheck_all_node_div.style.maxWidth = "18px";
+    check_uncheck_all_node_div.style.minWidth = "18px";
+    if (check_id) {
+        var check_node = document.createElement("input");
+        check_node.type = "checkbox";
+        check_node.style.minWidth = "15px";
+        check_node.dataset.id = check_id;
+        check_node.checked = selected.includes(check_id);
+        check_node.addEventListener('change', function(event) {
+            if (event.currentTarget.checked) {
+                addToSelection(event.currentTarget);
+            } else {
+                removeFromSelection(event.currentTarget);
+            }
+        });
+        check_uncheck_all_node_div.appendChild(check_node);
+    }
+    row_node.appendChild(check_uncheck_all_node_div);
+
+    var non_check_node = document.createElement("div");
+    var width = (100 / cells.length) + "%";
+    for (var i = 0; i < cells.length; i++) {
+        var cell_node = document.createElement("div");
+        cell_node.classList.add("row-cell");
+        cell_node.style.width = width;
+        cell_node.style.minWidth = width;
+        cell_node.style.maxWidth = width;
+
+        var cell_text = document.createElement("p");
+        var textnode1 = document.createTextNode(cells[i]);
+        cell_text.appendChild(textnode1);
+        cell_node.appendChild(cell_text);
+
+        non_check_node.appendChild(cell_node);
+    }
+    row_node.appendChild(non_check_node);
+}
+
 function renderActions(id) {
     var actions_data = document.createElement("div");
-    if (kontotyp == "admin" && id == "aenderungen") {
+    if (id == "aenderungen") {
 
-    } else if (kontotyp == "admin" && id == "zugriffe") {
+    } else if (id == "zugriffe") {
         var genehmigen = document.createElement("button");
         genehmigen.textContent = "Zugriff genehmigen";
         genehmigen.onclick = function() { zugriffGenehmigen(); }
@@ -1104,7 +1257,17 @@ function renderActions(id) {
         ablehnen.textContent = "Zugriff ablehnen";
         ablehnen.onclick = function() { zugriffAblehnen(); }
         actions_data.appendChild(ablehnen);
-    } else if (kontotyp == "admin" && id == "benutzer") {
+
+        var widerrufen = document.createElement("button");
+        widerrufen.textContent = "Zugriff widerrufen";
+        widerrufen.onclick = function() { zugriffWiderrufen(); }
+        actions_data.appendChild(widerrufen);
+
+        var gueltigkeit = document.createElement("button");
+        gueltigkeit.textContent = "Gültigkeit ändern";
+        gueltigkeit.onclick = function() { zugriffGueltigkeitAendern(); }
+        actions_data.appendChild(gueltigkeit);
+    } else if (id == "benutzer") {
 
         var change = document.createElement("button");
         change.textContent = "Neuen Benutzer anlegen";
@@ -1115,7 +1278,17 @@ function renderActions(id) {
         loeschen.textContent = "Benutzer löschen";
         loeschen.onclick = function() { benutzerLoeschen(); }
         actions_data.appendChild(loeschen);
-    } else if (kontotyp == "admin" && id == "bezirke") {
+
+        var zwei_faktor = document.createElement("button");
+        zwei_faktor.textContent = "Zwei-Faktor zurücksetzen";
+        zwei_faktor.onclick = function() { postToServer("benutzer-zwei-faktor-zuruecksetzen", selected); }
+        actions_data.appendChild(zwei_faktor);
+
+        var entsperren = document.createElement("button");
+        entsperren.textContent = "Konto entsperren";
+        entsperren.onclick = function() { postToServer("benutzer-entsperren", selected); }
+        actions_data.appendChild(entsperren);
+    } else if (id == "bezirke") {
         var bezirk_new = document.createElement("label");
         bezirk_new.htmlFor = "bezirke-von-csv-laden";
         bezirk_new.textContent = "Bezirke von CSV laden";
@@ -1132,6 +1305,79 @@ function renderActions(id) {
         loeschen.textContent = "Bezirk löschen";
         loeschen.onclick = function() { bezirkLoeschen(); }
         actions_data.appendChild(loeschen);
+    } else if (id == "rollen") {
+        var neu = document.createElement("button");
+        neu.textContent = "Neue Rolle";
+        neu.onclick = function() { rolleBearbeiten(null); }
+        actions_data.appendChild(neu);
+
+        var bearbeiten = document.createElement("button");
+        bearbeiten.textContent = "Berechtigungen bearbeiten";
+        bearbeiten.onclick = function() { rolleBearbeiten(selected[0]); }
+        actions_data.appendChild(bearbeiten);
+
+        var loeschen = document.createElement("button");
+        loeschen.textContent = "Ausgewählte Rollen löschen";
+        loeschen.onclick = function() { postToServer("rolle-loeschen", selected); }
+        actions_data.appendChild(loeschen);
+    } else if (id == "postausgang") {
+        var erneut = document.createElement("button");
+        erneut.textContent = "Ausgewählte erneut senden";
+        erneut.onclick = function() { postToServer("postausgang-erneut-senden", selected); }
+        actions_data.appendChild(erneut);
+    } else if (id == "audit-log") {
+        var export_csv = document.createElement("button");
+        export_csv.textContent = "Gefilterte Einträge als CSV exportieren";
+        export_csv.onclick = function() { auditLogAlsCsv(); }
+        actions_data.appendChild(export_csv);
+    } else if (id == "sitzungen") {
+        var beenden = document.createElement("button");
+        beenden.textContent = "Ausgewählte Sitzungen beenden";
+        beenden.onclick = function() { sitzungenBeenden(); }
+        actions_data.appendChild(beenden);
+
+        var passwort = document.createElement("button");
+        passwort.textContent = "Passwort ändern";
+        passwort.onclick = function() { passwortAendern(); }
+        actions_data.appendChild(passwort);
+    } else if (id == "zwei-faktor") {
+        var status = getKontoDaten().data["zwei-faktor"].extra.status;
+        if (status == "deaktiviert") {
+            var einrichten = document.createElement("button");
+            einrichten.textContent = "Zwei-Faktor-Authentifizierung einrichten";
+            einrichten.onclick = function() { postToServer("zwei-faktor-einrichten", []); }
+            actions_data.appendChild(einrichten);
+        } else if (status == "ausstehend") {
+            var aktivieren = document.createElement("button");
+            aktivieren.textContent = "Mit Code aus der App aktivieren";
+            aktivieren.onclick = function() { zweiFaktorMitCode("zwei-faktor-aktivieren"); }
+            actions_data.appendChild(aktivieren);
+
+            var abbrechen = document.createElement("button");
+            abbrechen.textContent = "Einrichtung abbrechen";
+            abbrechen.onclick = function() { postToServer("zwei-faktor-deaktivieren", []); }
+            actions_data.appendChild(abbrechen);
+        } else if (status == "aktiviert") {
+            var neue_codes = document.createElement("button");
+            neue_codes.textContent = "Neue Wiederherstellungscodes erzeugen";
+            neue_codes.onclick = function() { zweiFaktorMitCode("zwei-faktor-neue-codes"); }
+            actions_data.appendChild(neue_codes);
+
+            var deaktivieren = document.createElement("button");
+            deaktivieren.textContent = "Zwei-Faktor-Authentifizierung deaktivieren";
+            deaktivieren.onclick = function() { zweiFaktorMitCode("zwei-faktor-deaktivieren"); }
+            actions_data.appendChild(deaktivieren);
+        }
+    } else if (id == "api-schluessel") {
+        var neu = document.createElement("button");
+        neu.textContent = "Neuen API-Schlüssel anlegen";
+        neu.onclick = function() { apiSchluesselNeu(); }
+        actions_data.appendChild(neu);
+
+        var loeschen = document.createElement("button");
+        loeschen.textContent = "Ausgewählte API-Schlüssel löschen";
+        loeschen.onclick = function() { apiSchluesselLoeschen(); }
+        actions_data.appendChild(loeschen);
     } else if (id == "blaetter") {
         var herunterladen = document.createElement("button");
         herunterladen.textContent = "Ausgewählte Blätter als .zip herunterladen";
@@ -1147,6 +1393,26 @@ function renderActions(id) {
         loeschen.textContent = "Ausgewählte Abonnements beenden";
         loeschen.onclick = function(){ aboLoeschen(); };
         actions_data.appendChild(loeschen);
+
+        var benachrichtigung = document.createElement("button");
+        benachrichtigung.textContent = "E-Mails: " + getAboBenachrichtigung();
+        benachrichtigung.onclick = function(){ aboBenachrichtigungAendern(); };
+        actions_data.appendChild(benachrichtigung);
+
+        var detailstufe = document.createElement("button");
+        detailstufe.textContent = "Detailstufe ändern";
+        detailstufe.onclick = function(){ aboDetailstufeAendern(); };
+        actions_data.appendChild(detailstufe);
+
+        var testen = document.createElement("button");
+        testen.textContent = "Webhook testen";
+        testen.onclick = function(){ aboWebhookTesten(); };
+        actions_data.appendChild(testen);
+
+        var erneuern = document.createElement("button");
+        erneuern.textContent = "Webhook-Secret erneuern";
+        erneuern.onclick = function(){ aboWebhookSecretErneuern(); };
+        actions_data.appendChild(erneuern);
     }
     return actions_data;
 }
@@ -1167,7 +1433,7 @@ function renderMainTable() {
     node_actions.appendChild(renderActions(active_section));
 }
 
-function postToServer(aktion, daten) {
+function postToServer(aktion, daten, grund) {
     var auth = document.getElementById("token-id").dataset.tokenId;
     if (!auth) {
         return;
@@ -1181,8 +1447,13 @@ function postToServer(aktion, daten) {
             if (object.status == "ok") {
                 setKontoDaten(object);
                 renderMainTable();
+                zeigeWiederherstellungscodes(object);
+                zeigeWebhookInfo(object);
             } else if (object.status == "error") {
                 console.error("" + object.code + ": " + object.text);
+                if (object.code == 2) {
+                    alert(object.text);
+                }
             }
         }
     }
@@ -1190,6 +1461,7 @@ function postToServer(aktion, daten) {
         auth: auth,
         aktion: aktion,
         daten: daten,
+        grund: grund,
     }));
 }
 
@@ -1202,7 +1474,35 @@ function zugriffGenehmigen() {
 }
 
 function zugriffAblehnen() {
-    postToServer("zugriff-ablehnen", selected);
+    if (selected.length == 0) {
+        return;
+    }
+    var grund = prompt("Begründung (optional, wird dem Antragsteller per E-Mail mitgeteilt):", "");
+    if (grund === null) {
+        return;
+    }
+    postToServer("zugriff-ablehnen", selected, grund.trim());
+}
+
+function zugriffWiderrufen() {
+    if (selected.length == 0) {
+        return;
+    }
+    if (!confirm("Ausgewählte Zugriffe wirklich widerrufen?")) {
+        return;
+    }
+    postToServer("zugriff-widerrufen", selected);
+}
+
+function zugriffGueltigkeitAendern() {
+    if (selected.length == 0) {
+        return;
+    }
+    var datum = prompt("Gültig bis (JJJJ-MM-TT, leer = unbegrenzt):", "");
+    if (datum === null) {
+        return;
+    }
+    postToServer("zugriff-gueltigkeit", [datum.trim()].concat(selected));
 }
 
 function blaetterAlsZip() {
@@ -1228,6 +1528,168 @@ function blaetterAlsZip() {
     }));
 }
 
+function auditLogAlsCsv() {
+    var auth = document.getElementById("token-id").dataset.tokenId;
+    if (!auth) {
+        return;
+    }
+    var http = new XMLHttpRequest();
+    http.open('GET', '/audit-log?format=csv', true);
+    http.setRequestHeader('Authorization', 'Bearer ' + auth);
+    http.onreadystatechange = function() {
+        if (http.readyState == 4 && http.status == 200) {
+            // Filter aus dem Suchfeld auch auf den Export anwenden
+            var zeilen = http.responseText.split("\r\n");
+            var gefiltert = [zeilen[0]];
+            for (var i = 1; i < zeilen.length; i++) {
+                if (zeilen[i] && rowIsValid([zeilen[i]], filter_by)) {
+                    gefiltert.push(zeilen[i]);
+                }
+            }
+            downloadBlob(gefiltert.join("\r\n") + "\r\n", "audit-log.csv", 'text/csv');
+        }
+    }
+    http.send();
+}
+
+function sitzungenBeenden() {
+    postToServer("sitzungen-beenden", selected);
+}
+
+function passwortAendern() {
+    var auth = document.getElementById("token-id").dataset.tokenId;
+    if (!auth) {
+        return;
+    }
+    var aktuell = window.prompt("Aktuelles Passwort", "");
+    if (!aktuell) { return; }
+    var neu = window.prompt("Neues Passwort", "");
+    if (!neu) { return; }
+    if (window.prompt("Neues Passwort wiederholen", "") != neu) {
+        alert("Die Passwörter stimmen nicht überein.");
+        return;
+    }
+
+    var http = new XMLHttpRequest();
+    http.open('POST', '/konto', true);
+    http.setRequestHeader('Content-type', 'application/json');
+    http.onreadystatechange = function() {
+        if (http.readyState == 4 && http.status == 200) {
+            var object = JSON.parse(http.responseText);
+            if (object.status == "ok") {
+                // Alle Sitzungen wurden beendet, auch die aktuelle
+                alert("Das Passwort wurde geändert. Bitte melden Sie sich neu an.");
+                cookiesLoeschen();
+            } else if (object.status == "error") {
+                console.error("" + object.code + ": " + object.text);
+                alert(object.text);
+            }
+        }
+    }
+    http.send(JSON.stringify({
+        auth: auth,
+        aktion: "passwort-aendern",
+        daten: [aktuell, neu],
+    }));
+}
+
+function zweiFaktorMitCode(aktion) {
+    var code = window.prompt("Code aus der Authenticator-App (oder Wiederherstellungscode)", "");
+    if (!code) { return; }
+    postToServer(aktion, [code]);
+}
+
+function zeigeWiederherstellungscodes(kontoDaten) {
+    var tabelle = kontoDaten.data["zwei-faktor"];
+    if (!tabelle || !tabelle.extra.wiederherstellungscodes) {
+        return;
+    }
+    alert(
+        "Wiederherstellungscodes (werden nur einmal angezeigt, bitte sicher aufbewahren):\n\n" +
+        tabelle.extra.wiederherstellungscodes.join("\n")
+    );
+}
+
+function zeigeWebhookInfo(kontoDaten) {
+    var tabelle = kontoDaten.data["abonnements"];
+    if (!tabelle) {
+        return;
+    }
+    if (tabelle.extra.webhook_secret) {
+        window.prompt("Webhook-Secret (wird nur einmal angezeigt):", tabelle.extra.webhook_secret);
+    }
+    if (tabelle.extra.webhook_testzustellung) {
+        alert("Testzustellung erfolgreich (Zustellungs-ID " + tabelle.extra.webhook_testzustellung + ")");
+    }
+}
+
+function apiSchluesselNeu() {
+    var auth = document.getElementById("token-id").dataset.tokenId;
+    if (!auth) {
+        return;
+    }
+    var name = window.prompt("Name des API-Schlüssels", "");
+    if (!name) { return; }
+    var scopes = window.prompt("Scopes (read-gbx, read-pdf, search, upload, abo-manage)", "read-gbx, read-pdf, search");
+    if (!scopes) { return; }
+    var bereiche = window.prompt("Nur für Amtsgerichte / Bezirke (z.B. \"Prenzlau, Eberswalde/Angermünde\", leer = alle)", "");
+    var gueltig_bis = window.prompt("Gültig bis (JJJJ-MM-TT, leer = unbegrenzt)", "");
+    var benutzer = null;
+    if (hatBerechtigung("benutzer-verwalten")) {
+        benutzer = window.prompt("Für Benutzer (E-Mail, leer = eigenes Konto)", "");
+    }
+
+    var http = new XMLHttpRequest();
+    http.open('POST', '/api-schluessel', true);
+    http.setRequestHeader('Content-type', 'application/json');
+    http.setRequestHeader('Authorization', 'Bearer ' + auth);
+    http.onreadystatechange = function() {
+        if (http.readyState == 4 && http.status == 200) {
+            var object = JSON.parse(http.responseText);
+            if (object.status == "ok") {
+                window.prompt("API-Schlüssel (wird nur einmal angezeigt):", object.schluessel);
+                window.location.reload();
+            } else if (object.status == "error") {
+                console.error("" + object.code + ": " + object.text);
+                alert(object.text);
+            }
+        }
+    }
+    http.send(JSON.stringify({
+        name: name,
+        scopes: scopes.split(",").map(function(s) { return s.trim(); }).filter(function(s) { return s; }),
+        bereiche: (bereiche || "").split(",").map(function(s) { return s.trim(); }).filter(function(s) { return s; }).map(function(s) {
+            var teile = s.split("/");
+            return { amtsgericht: teile[0].trim(), bezirk: teile.length > 1 ? teile[1].trim() : null };
+        }),
+        gueltig_bis: gueltig_bis || null,
+        benutzer: benutzer || null,
+    }));
+}
+
+function apiSchluesselLoeschen() {
+    postToServer("api-schluessel-loeschen", selected);
+}
+
+function rolleBearbeiten(name) {
+    var rollen = getKontoDaten().data["rollen"];
+    var zeile = name ? rollen.daten[name] : null;
+    if (!name) {
+        name = window.prompt("Name der neuen Rolle (a-z, 0-9 und -)", "");
+        if (!name) { return; }
+    }
+    var bezeichnung = window.prompt("Bezeichnung", zeile ? zeile[1] : "");
+    if (bezeichnung === null) { return; }
+    var berechtigungen = rollen.extra.berechtigungen;
+    var verfuegbar = Object.keys(berechtigungen).map(function(b) {
+        return b + " (" + berechtigungen[b] + ")";
+    }).join("\n");
+    var auswahl = window.prompt("Berechtigungen, durch Komma getrennt:\n" + verfuegbar, zeile ? zeile[2] : "");
+    if (auswahl === null) { return; }
+    var daten = [name, bezeichnung].concat(auswahl.split(",").map(function(s) { return s.trim(); }).filter(function(s) { return s; }));
+    postToServer("rolle-speichern", daten);
+}
+
 function benutzerBearbeiten(target) {
     var value = target.value;
     var s = target.options[target.selectedIndex].text;
@@ -1251,30 +1713,86 @@ function aboNeu() {
     }
 
     var text = null;
-    if (kontotyp == "admin" && typ == "email") {
+    if (hatBerechtigung("abos-verwalten") && typ == "email") {
         text = window.prompt("E-Mail", "");
     } else if (typ == "webhook") {
         text = window.prompt("Webhook-URL", "");
     } else {
         text = "";
     }
-    if (kontotyp == "admin" && !text) { return; }
+    if (hatBerechtigung("abos-verwalten") && !text) { return; }
     
     var amtsgericht = window.prompt("Amtsgericht", "");
     if (!amtsgericht) { return; }
-    var bezirk = window.prompt("Bezirk", "");
+    var bezirk = window.prompt("Bezirk (* = ganzes Amtsgericht)", "");
     if (!bezirk) { return; }
-    var blatt = window.prompt("Blatt", "");
+    var blatt = window.prompt("Blatt (* = alle Blätter)", bezirk == "*" ? "*" : "");
     if (!blatt) { return; }
+    var flurstueck = "";
+    if (blatt == "*") {
+        flurstueck = window.prompt("Nur dieses Flurstück (Gemarkung/Flur/Flurstück; leer = alle)", "");
+        if (flurstueck === null) { return; }
+    }
     var aktenzeichen = window.prompt("Aktenzeichen", "");
     if (!aktenzeichen) { return; }
-    postToServer("abo-neu", [typ, text, amtsgericht, bezirk, blatt, aktenzeichen]);
+    var detailstufe = window.prompt("Detailstufe (\"standard\" oder \"details\" = mit geänderten Einträgen)", "standard");
+    if (!detailstufe) { return; }
+    var abteilungen = window.prompt("Nur Änderungen in diesen Abteilungen (kommagetrennt: bv, abt1, abt2, abt3; leer = alle)", "");
+    if (abteilungen === null) { return; }
+    var aenderungsarten = window.prompt("Nur diese Arten von Änderungen (kommagetrennt: neu, loeschung, veraenderung; leer = alle)", "");
+    if (aenderungsarten === null) { return; }
+    postToServer("abo-neu", [typ, text, amtsgericht, bezirk, blatt, aktenzeichen, detailstufe, abteilungen, aenderungsarten, flurstueck]);
+}
+
+function getAboBenachrichtigung() {
+    var abos = getKontoDaten().data["abonnements"];
+    if (!abos || !abos.extra || !abos.extra.benachrichtigung) {
+        return "sofort";
+    }
+    return abos.extra.benachrichtigung;
+}
+
+function aboBenachrichtigungAendern() {
+    var benachrichtigung = window.prompt(
+        "E-Mails zu Abonnements: \"sofort\", \"taeglich\" oder \"woechentlich\" (Zusammenfassung)",
+        getAboBenachrichtigung()
+    );
+    if (!benachrichtigung) { return; }
+    postToServer("abo-benachrichtigung-aendern", [benachrichtigung]);
+}
+
+function aboDetailstufeAendern() {
+    if (selected.length == 0) {
+        return;
+    }
+    var detailstufe = window.prompt("Detailstufe (\"standard\" oder \"details\" = mit geänderten Einträgen)", "details");
+    if (!detailstufe) { return; }
+    postToServer("abo-detailstufe-aendern", [detailstufe].concat(selected));
 }
 
 function aboLoeschen() {
     postToServer("abo-loeschen", selected);
 }
 
+function aboWebhookTesten() {
+    if (selected.length != 1) {
+        alert("Bitte genau ein Webhook-Abonnement auswählen");
+        return;
+    }
+    postToServer("abo-webhook-testen", selected);
+}
+
+function aboWebhookSecretErneuern() {
+    if (selected.length != 1) {
+        alert("Bitte genau ein Webhook-Abonnement auswählen");
+        return;
+    }
+    if (!confirm("Neues Secret erzeugen? Das bisherige Secret wird sofort ungültig.")) {
+        return;
+    }
+    postToServer("abo-webhook-secret-erneuern", selected);
+}
+
 function benutzerNeu() {
     var name = window.prompt("Name", "");
     if (!name) { return; }
@@ -1320,6 +1838,13 @@ function bezirkLoeschen() {
     postToServer("bezirk-loeschen", selected);
 }
 
+// Vorgeschriebene Zwei-Faktor-Authentifizierung zuerst einrichten
+var zwei_faktor_tabelle = getKontoDaten().data["zwei-faktor"];
+if (zwei_faktor_tabelle && zwei_faktor_tabelle.extra.einrichtung_erforderlich) {
+    active_sidebar = sidebar_items.indexOf("Zwei-Faktor");
+    alert("Für Ihr Benutzerkonto ist die Zwei-Faktor-Authentifizierung vorgeschrieben. Bitte richten Sie sie jetzt ein.");
+}
+
 renderSidebar();
 renderMainTable();
 document.getElementById("main-table-filter").onchange = function() { updateFilter(this); }